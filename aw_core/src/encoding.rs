@@ -1,3 +1,6 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+
 pub fn latin1_to_string(s: &[u8]) -> String {
     s.iter()
         .map(|&c| c as char)
@@ -9,3 +12,106 @@ pub fn latin1_to_string(s: &[u8]) -> String {
 pub fn string_to_latin1(s: &str) -> Vec<u8> {
     s.chars().map(|c| c as u8).collect()
 }
+
+/// Characters for bytes 0x80 through 0x9F in Windows-1252.
+/// Bytes which Windows-1252 leaves undefined keep their Latin-1 meaning.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Single-byte character set used for strings in packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum Codepage {
+    #[default]
+    Latin1 = 0,
+    Windows1252 = 1,
+}
+
+impl Codepage {
+    /// Convert bytes from the wire to a string, stripping any null terminator.
+    pub fn decode(self, s: &[u8]) -> String {
+        match self {
+            Codepage::Latin1 => latin1_to_string(s),
+            Codepage::Windows1252 => s
+                .iter()
+                .map(|&c| match c {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[(c - 0x80) as usize],
+                    _ => c as char,
+                })
+                .collect::<String>()
+                .trim_end_matches('\0')
+                .to_string(),
+        }
+    }
+
+    /// Convert a string to bytes for the wire. Characters which cannot be
+    /// represented become '?'.
+    pub fn encode(self, s: &str) -> Vec<u8> {
+        s.chars().map(|c| self.encode_char(c)).collect()
+    }
+
+    fn encode_char(self, c: char) -> u8 {
+        if self == Codepage::Windows1252 {
+            if let Some(i) = WINDOWS_1252_HIGH.iter().position(|&x| x == c) {
+                return 0x80 + i as u8;
+            }
+            if ('\u{80}'..='\u{9F}').contains(&c) {
+                return b'?';
+            }
+        }
+
+        u8::try_from(c).unwrap_or(b'?')
+    }
+}
+
+static WIRE_CODEPAGE: AtomicU8 = AtomicU8::new(Codepage::Latin1 as u8);
+
+/// Set the codepage used when (de)serializing string packet variables.
+pub fn set_wire_codepage(codepage: Codepage) {
+    WIRE_CODEPAGE.store(codepage as u8, Ordering::Relaxed);
+}
+
+/// The codepage used when (de)serializing string packet variables.
+pub fn wire_codepage() -> Codepage {
+    match WIRE_CODEPAGE.load(Ordering::Relaxed) {
+        1 => Codepage::Windows1252,
+        _ => Codepage::Latin1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_latin1_round_trip() {
+        let s = "Ren\u{e9}e";
+        let bytes = Codepage::Latin1.encode(s);
+        assert_eq!(bytes, b"Ren\xe9e");
+        assert_eq!(Codepage::Latin1.decode(&bytes), s);
+    }
+
+    #[test]
+    pub fn test_windows_1252_round_trip() {
+        let s = "\u{20AC}5 \u{201C}quoted\u{201D} \u{0160}ime";
+        let bytes = Codepage::Windows1252.encode(s);
+        assert_eq!(bytes, b"\x805 \x93quoted\x94 \x8aime");
+        assert_eq!(Codepage::Windows1252.decode(&bytes), s);
+    }
+
+    #[test]
+    pub fn test_unrepresentable() {
+        assert_eq!(Codepage::Latin1.encode("a\u{20AC}b"), b"a?b");
+        assert_eq!(Codepage::Windows1252.encode("a\u{3042}\u{0093}b"), b"a??b");
+    }
+
+    #[test]
+    pub fn test_null_terminator() {
+        assert_eq!(Codepage::Windows1252.decode(b"abc\0"), "abc");
+    }
+}
@@ -1,32 +1,127 @@
-use crate::{AWPacket, AWPacketGroup, AWProtocol, ProtocolMessage};
+use crate::{AWPacket, AWPacketGroup, AWProtocol, ProtocolMessage, TrafficCounters};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Limits on how many messages may wait to be sent on a connection.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueLimits {
+    /// Above this many queued messages, packet groups (list refreshes) are dropped.
+    pub high_water: usize,
+    /// At this many queued messages, all packets are dropped.
+    pub max: usize,
+    /// How long the queue may stay above the high water mark before the
+    /// connection is considered stalled.
+    pub stall_timeout: Duration,
+}
+
+impl Default for QueueLimits {
+    fn default() -> Self {
+        Self {
+            high_water: 256,
+            max: 1024,
+            stall_timeout: Duration::from_secs(30),
+        }
+    }
+}
 
 pub struct AWConnection {
     outbound: Sender<ProtocolMessage>,
     inbound: Receiver<ProtocolMessage>,
     a4_send_key: Vec<u8>,
+    queued: Arc<AtomicUsize>,
+    limits: QueueLimits,
+    backed_up_since: Cell<Option<Instant>>,
+    dropped: Cell<u64>,
+    traffic: Arc<TrafficCounters>,
 }
 
 impl AWConnection {
     pub fn new(protocol: AWProtocol) -> Self {
+        Self::with_queue_limits(protocol, QueueLimits::default())
+    }
+
+    pub fn with_queue_limits(protocol: AWProtocol, limits: QueueLimits) -> Self {
         let a4_send_key = protocol.get_send_key();
+        let queued = protocol.outbound_queue_len();
+        let traffic = protocol.traffic();
         let (outbound, inbound) = protocol.start_process_loop();
 
         Self {
             outbound,
             inbound,
             a4_send_key,
+            queued,
+            limits,
+            backed_up_since: Cell::new(None),
+            dropped: Cell::new(0),
+            traffic,
         }
     }
 
     pub fn send(&self, packet: AWPacket) {
-        self.outbound.send(ProtocolMessage::Packet(packet)).ok();
+        if self.check_queue() >= self.limits.max {
+            self.dropped.set(self.dropped.get() + 1);
+            return;
+        }
+
+        self.queue(ProtocolMessage::Packet(packet));
     }
 
+    /// Send a group of packets. Groups are dropped first when the other end
+    /// is not keeping up, since they carry lists which can be requested again.
     pub fn send_group(&self, packets: AWPacketGroup) {
-        self.outbound
-            .send(ProtocolMessage::PacketGroup(packets.packets))
-            .ok();
+        if self.check_queue() >= self.limits.high_water {
+            self.dropped.set(self.dropped.get() + 1);
+            return;
+        }
+
+        packets.record_sent();
+        self.queue(ProtocolMessage::PacketGroup(packets.packets));
+    }
+
+    fn queue(&self, message: ProtocolMessage) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        if self.outbound.send(message).is_err() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Get the number of queued messages, noting when the queue backs up.
+    fn check_queue(&self) -> usize {
+        let queued = self.queued_len();
+        if queued < self.limits.high_water {
+            self.backed_up_since.set(None);
+        } else if self.backed_up_since.get().is_none() {
+            self.backed_up_since.set(Some(Instant::now()));
+        }
+        queued
+    }
+
+    /// Number of messages waiting to be sent.
+    pub fn queued_len(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Number of messages dropped because the queue was full.
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.get()
+    }
+
+    /// Whether the other end has stopped reading for too long and should be disconnected.
+    pub fn is_stalled(&self) -> bool {
+        self.check_queue();
+        match self.backed_up_since.get() {
+            Some(since) => since.elapsed() >= self.limits.stall_timeout,
+            None => false,
+        }
+    }
+
+    /// Bytes sent and received on this connection.
+    pub fn traffic(&self) -> &TrafficCounters {
+        &self.traffic
     }
 
     pub fn set_recv_key(&self, key: &[u8]) {
@@ -0,0 +1,138 @@
+//! Tools for working out what the packet header fields are for.
+//!
+//! The purpose of header_0 and header_1 is not known. Observed values can be
+//! counted per packet type, and the values sent can be overridden at runtime.
+use crate::net::packet::{AWPacket, PacketType};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Number of times a packet type was seen with a pair of header values.
+#[derive(Debug, Clone)]
+pub struct HeaderObservation {
+    pub direction: Direction,
+    pub opcode: PacketType,
+    pub header_0: u16,
+    pub header_1: u16,
+    pub count: u64,
+}
+
+/// Header values to send for a packet type in place of the usual ones.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeaderOverride {
+    pub header_0: Option<u16>,
+    pub header_1: Option<u16>,
+}
+
+type ObservationKey = (Direction, PacketType, u16, u16);
+
+static OBSERVING: AtomicBool = AtomicBool::new(false);
+static OBSERVED: Mutex<Option<HashMap<ObservationKey, u64>>> = Mutex::new(None);
+static OVERRIDES: Mutex<Option<HashMap<PacketType, HeaderOverride>>> = Mutex::new(None);
+
+/// Turn counting of header values on or off.
+pub fn set_header_observation(enabled: bool) {
+    OBSERVING.store(enabled, Ordering::Relaxed);
+}
+
+pub fn header_observation() -> bool {
+    OBSERVING.load(Ordering::Relaxed)
+}
+
+/// Count the header values of a packet, if observation is turned on.
+pub fn observe_headers(direction: Direction, packet: &AWPacket) {
+    if !header_observation() {
+        return;
+    }
+
+    let key = (
+        direction,
+        packet.get_opcode(),
+        packet.get_header_0(),
+        packet.get_header_1(),
+    );
+    if let Ok(mut observed) = OBSERVED.lock() {
+        *observed
+            .get_or_insert_with(HashMap::new)
+            .entry(key)
+            .or_insert(0) += 1;
+    }
+}
+
+/// Get all header values seen so far, ordered by direction and packet type.
+pub fn observed_headers() -> Vec<HeaderObservation> {
+    let mut result: Vec<HeaderObservation> = match OBSERVED.lock() {
+        Ok(observed) => observed
+            .iter()
+            .flatten()
+            .map(
+                |(&(direction, opcode, header_0, header_1), &count)| HeaderObservation {
+                    direction,
+                    opcode,
+                    header_0,
+                    header_1,
+                    count,
+                },
+            )
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    result.sort_by_key(|x| (x.direction, x.opcode as i16, x.header_0, x.header_1));
+    result
+}
+
+/// Send different header values for a packet type.
+pub fn set_header_override(opcode: PacketType, header_override: HeaderOverride) {
+    if let Ok(mut overrides) = OVERRIDES.lock() {
+        overrides
+            .get_or_insert_with(HashMap::new)
+            .insert(opcode, header_override);
+    }
+}
+
+/// Apply any override set for the packet's type.
+pub fn apply_header_override(packet: &mut AWPacket) {
+    let header_override = match OVERRIDES.lock() {
+        Ok(overrides) => match overrides.as_ref().and_then(|x| x.get(&packet.get_opcode())) {
+            Some(x) => *x,
+            None => return,
+        },
+        Err(_) => return,
+    };
+
+    if let Some(header_0) = header_override.header_0 {
+        packet.set_header_0(header_0);
+    }
+    if let Some(header_1) = header_override.header_1 {
+        packet.set_header_1(header_1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_HEADER_1;
+
+    #[test]
+    pub fn test_header_override() {
+        set_header_override(
+            PacketType::Heartbeat,
+            HeaderOverride {
+                header_0: Some(7),
+                header_1: None,
+            },
+        );
+
+        let mut packet = AWPacket::new(PacketType::Heartbeat);
+        apply_header_override(&mut packet);
+        assert_eq!(packet.get_header_0(), 7);
+        assert_eq!(packet.get_header_1(), DEFAULT_HEADER_1);
+    }
+}
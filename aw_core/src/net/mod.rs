@@ -7,5 +7,14 @@ pub use packet::*;
 mod packet_var;
 pub use packet_var::*;
 
+mod packet_data;
+pub use packet_data::*;
+
+mod headers;
+pub use headers::*;
+
+mod trace;
+pub use trace::*;
+
 mod connection;
 pub use connection::*;
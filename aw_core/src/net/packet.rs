@@ -7,6 +7,10 @@ use flate2::Compression;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use std::io::{Cursor, Read, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Value header_1 has until something sets it.
+pub const DEFAULT_HEADER_1: u16 = 2;
 
 /// Packet which can be sent over an AWProtocol.
 #[derive(Debug, PartialEq, Clone)]
@@ -23,8 +27,8 @@ impl AWPacket {
         Self {
             vars: Vec::new(),
             opcode,
-            header_0: 0, // Defaults to 2 in AW 6
-            header_1: 2, // Defaults to 3 in AW 6
+            header_0: 0,                // Defaults to 2 in AW 6
+            header_1: DEFAULT_HEADER_1, // Defaults to 3 in AW 6
         }
     }
 
@@ -33,6 +37,14 @@ impl AWPacket {
         self.opcode
     }
 
+    pub fn get_header_0(&self) -> u16 {
+        self.header_0
+    }
+
+    pub fn get_header_1(&self) -> u16 {
+        self.header_1
+    }
+
     pub fn set_header_0(&mut self, header_0: u16) {
         self.header_0 = header_0;
     }
@@ -224,9 +236,12 @@ impl AWPacket {
         }
 
         let compressed_data = &data[TagHeader::length()..];
-        let mut decoder = ZlibDecoder::new(compressed_data);
+        let mut decoder = ZlibDecoder::new(compressed_data).take(MAX_DECOMPRESSED_LEN as u64 + 1);
         let mut decompressed_bytes = Vec::<u8>::new();
         match decoder.read_to_end(&mut decompressed_bytes) {
+            Ok(len) if len > MAX_DECOMPRESSED_LEN => {
+                Err("Compressed data expands beyond the maximum length".to_string())
+            }
             Ok(_) => Ok(decompressed_bytes),
             Err(_) => Err("Failed to decode compressed data".to_string()),
         }
@@ -239,6 +254,10 @@ impl AWPacket {
         data = &data[consumed..];
         total_consumed += consumed;
 
+        if header.var_count > MAX_VAR_COUNT {
+            return Err(format!("Packet has too many vars ({})", header.var_count));
+        }
+
         let mut vars = Vec::<AWPacketVar>::with_capacity(header.var_count as usize);
 
         for _ in 0..header.var_count {
@@ -280,6 +299,11 @@ impl AWPacket {
             return Err(DeserializeError::InvalidHeader);
         }
 
+        let serialized_len = usize::from(header.serialized_length);
+        if !(TagHeader::length()..=MAX_PACKET_LEN).contains(&serialized_len) {
+            return Err(DeserializeError::InvalidHeader);
+        }
+
         if header.opcode == -1 && header.header_1 != 0 {
             return Err(DeserializeError::Compressed(
                 header.serialized_length.into(),
@@ -293,20 +317,96 @@ impl AWPacket {
 #[derive(Debug, PartialEq, Clone)]
 pub struct AWPacketGroup {
     pub packets: Vec<AWPacket>,
+    max_len: usize,
+}
+
+/// Largest number of bytes a packet group may serialize to.
+pub const MAX_GROUP_LEN: usize = 0x7FFF;
+
+/// Largest number of bytes a received packet may claim to be.
+pub const MAX_PACKET_LEN: usize = MAX_GROUP_LEN;
+
+/// Largest number of bytes a compressed packet may expand to.
+pub const MAX_DECOMPRESSED_LEN: usize = 0xFFFF;
+
+/// Largest number of vars a received packet may have.
+pub const MAX_VAR_COUNT: u16 = 1024;
+
+/// Smallest target group size. List senders start each new group with a
+/// "more" packet followed by the packet which did not fit, so both must fit.
+pub const MIN_GROUP_LEN: usize = 0x1000;
+
+static GROUP_TARGET_LEN: AtomicUsize = AtomicUsize::new(MAX_GROUP_LEN);
+static GROUPS_SENT: AtomicU64 = AtomicU64::new(0);
+static GROUP_PACKETS_SENT: AtomicU64 = AtomicU64::new(0);
+static GROUP_BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static GROUP_BYTES_WASTED: AtomicU64 = AtomicU64::new(0);
+static GROUP_SPLITS: AtomicU64 = AtomicU64::new(0);
+
+/// Set the size new packet groups are filled to. Smaller groups are sent
+/// sooner, larger groups use fewer transmissions.
+pub fn set_group_target_len(len: usize) {
+    GROUP_TARGET_LEN.store(len.clamp(MIN_GROUP_LEN, MAX_GROUP_LEN), Ordering::Relaxed);
+}
+
+/// The size new packet groups are filled to.
+pub fn group_target_len() -> usize {
+    GROUP_TARGET_LEN.load(Ordering::Relaxed)
+}
+
+/// Totals describing how packet groups have been used since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupStats {
+    /// Groups handed to a connection to be sent
+    pub groups_sent: u64,
+    /// Packets contained in those groups
+    pub packets_sent: u64,
+    /// Serialized size of those groups
+    pub bytes_sent: u64,
+    /// Space left unused in those groups compared to their size limit
+    pub bytes_wasted: u64,
+    /// Packets which did not fit in a group and had to start a new one
+    pub splits: u64,
+}
+
+impl GroupStats {
+    pub fn get() -> Self {
+        Self {
+            groups_sent: GROUPS_SENT.load(Ordering::Relaxed),
+            packets_sent: GROUP_PACKETS_SENT.load(Ordering::Relaxed),
+            bytes_sent: GROUP_BYTES_SENT.load(Ordering::Relaxed),
+            bytes_wasted: GROUP_BYTES_WASTED.load(Ordering::Relaxed),
+            splits: GROUP_SPLITS.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Average serialized size of a group.
+    pub fn average_group_len(&self) -> u64 {
+        self.bytes_sent.checked_div(self.groups_sent).unwrap_or(0)
+    }
 }
 
 impl AWPacketGroup {
     pub fn new() -> Self {
+        Self::with_max_len(group_target_len())
+    }
+
+    /// Create a group which holds at most `max_len` serialized bytes.
+    pub fn with_max_len(max_len: usize) -> Self {
         Self {
             packets: Vec::new(),
+            max_len: max_len.clamp(1, MAX_GROUP_LEN),
         }
     }
+
     pub fn push(&mut self, packet: AWPacket) -> Result<usize, AWPacket> {
         let total_len = self.serialize_len() + packet.serialize_len();
-        if total_len < 0x8000 {
+        // A packet always fits in an empty group so that oversized packets still get sent
+        if total_len <= self.max_len || self.packets.is_empty() && total_len <= MAX_GROUP_LEN {
             self.packets.push(packet);
             Ok(total_len)
         } else {
+            GROUP_SPLITS.fetch_add(1, Ordering::Relaxed);
             Err(packet)
         }
     }
@@ -314,6 +414,15 @@ impl AWPacketGroup {
     pub fn serialize_len(&self) -> usize {
         self.packets.iter().map(|p| p.serialize_len()).sum()
     }
+
+    /// Record that this group is being sent, for GroupStats.
+    pub fn record_sent(&self) {
+        let len = self.serialize_len();
+        GROUPS_SENT.fetch_add(1, Ordering::Relaxed);
+        GROUP_PACKETS_SENT.fetch_add(self.packets.len() as u64, Ordering::Relaxed);
+        GROUP_BYTES_SENT.fetch_add(len as u64, Ordering::Relaxed);
+        GROUP_BYTES_WASTED.fetch_add(self.max_len.saturating_sub(len) as u64, Ordering::Relaxed);
+    }
 }
 
 impl Default for AWPacketGroup {
@@ -395,7 +504,7 @@ impl TagHeader {
 
     pub fn is_valid(&self) -> bool {
         if self.header_1 <= 3 || self.opcode == PacketType::Tunnel as i16 {
-            if self.var_count > 1024 {
+            if self.var_count > MAX_VAR_COUNT {
                 return false;
             } else {
                 if self.header_1 == 0 {
@@ -416,7 +525,7 @@ pub enum DeserializeError {
     Compressed(usize),
 }
 
-#[derive(FromPrimitive, Clone, Copy, Debug, PartialEq)]
+#[derive(FromPrimitive, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum PacketType {
     PublicKeyResponse = 1,
     StreamKeyResponse = 2,
@@ -429,7 +538,7 @@ pub enum PacketType {
     AvatarChange = 10,
     AvatarClick = 11,
     AvatarDelete = 12,
-
+    Botgram = 13,
     Invite = 14,
     BotgramResponse = 15,
 
@@ -574,6 +683,19 @@ pub enum PacketType {
     ObjectQuery = 170,
     LaserBeam = 183,
 
+    // The packets below are not part of the original protocol. They are only
+    // understood by this universe and tools written for it, and are numbered
+    // from 190 so they stay clear of the opcodes known browsers and SDKs use.
+    PresenceQuery = 190,
+    CitizenProfile = 191,
+    GroupQuery = 192,
+    GroupTelegram = 193,
+    GroupChange = 194,
+    CreditQuery = 195,
+    CreditChange = 196,
+    /// An admin disconnecting a session, with `VarID::KickReason` shown to its user
+    SessionKick = 197,
+
     Unknown = 0x7FFF,
 }
 
@@ -590,4 +712,46 @@ mod tests {
         let (deserialized, _) = AWPacket::deserialize(&serialized).unwrap();
         assert!(packet == deserialized);
     }
+
+    #[test]
+    pub fn test_group_max_len() {
+        let mut packet = AWPacket::new(PacketType::UserList);
+        packet.add_var(AWPacketVar::String(
+            VarID::UserListName,
+            "Hello".to_string(),
+        ));
+        let len = packet.serialize_len();
+
+        let mut group = AWPacketGroup::with_max_len(len * 2);
+        assert!(group.push(packet.clone()).is_ok());
+        assert!(group.push(packet.clone()).is_ok());
+        assert!(group.push(packet.clone()).is_err());
+        assert!(group.packets.len() == 2);
+
+        // Packets larger than the limit still fit in an empty group
+        let mut group = AWPacketGroup::with_max_len(1);
+        assert!(group.push(packet.clone()).is_ok());
+        assert!(group.push(packet).is_err());
+    }
+
+    #[test]
+    pub fn test_packet_limits() {
+        let packet = AWPacket::new(PacketType::Address);
+        let mut serialized = packet.serialize().unwrap();
+        assert!(AWPacket::deserialize_check(&serialized).is_ok());
+
+        // Claim to be longer than any packet may be
+        serialized[..2].copy_from_slice(&0xFFFFu16.to_be_bytes());
+        assert!(matches!(
+            AWPacket::deserialize_check(&serialized),
+            Err(DeserializeError::InvalidHeader)
+        ));
+
+        // Compressed data may not expand without limit
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&[0u8; MAX_DECOMPRESSED_LEN + 1]).unwrap();
+        let mut compressed = vec![0u8; TagHeader::length()];
+        compressed.extend(encoder.finish().unwrap());
+        assert!(AWPacket::decompress(&compressed).is_err());
+    }
 }
@@ -0,0 +1,69 @@
+//! Conversion between Rust values and packet variables.
+use crate::net::packet::AWPacket;
+use crate::net::packet_var::VarID;
+
+/// A type which can be stored in a packet variable.
+pub trait PacketVarValue: Sized {
+    fn get_from(packet: &AWPacket, var_id: VarID) -> Option<Self>;
+    fn add_to(&self, packet: &mut AWPacket, var_id: VarID);
+}
+
+impl PacketVarValue for u8 {
+    fn get_from(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_byte(var_id)
+    }
+
+    fn add_to(&self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_byte(var_id, *self);
+    }
+}
+
+impl PacketVarValue for i32 {
+    fn get_from(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_int(var_id)
+    }
+
+    fn add_to(&self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_int(var_id, *self);
+    }
+}
+
+impl PacketVarValue for u32 {
+    fn get_from(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_uint(var_id)
+    }
+
+    fn add_to(&self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_uint(var_id, *self);
+    }
+}
+
+impl PacketVarValue for f32 {
+    fn get_from(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_float(var_id)
+    }
+
+    fn add_to(&self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_float(var_id, *self);
+    }
+}
+
+impl PacketVarValue for String {
+    fn get_from(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_string(var_id)
+    }
+
+    fn add_to(&self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_string(var_id, self.clone());
+    }
+}
+
+impl PacketVarValue for Vec<u8> {
+    fn get_from(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_data(var_id)
+    }
+
+    fn add_to(&self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_data(var_id, self.clone());
+    }
+}
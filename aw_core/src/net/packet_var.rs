@@ -1,6 +1,6 @@
 //! Packet variable (de)serialization for AW
 
-use crate::encoding::{latin1_to_string, string_to_latin1};
+use crate::encoding::wire_codepage;
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
@@ -53,9 +53,21 @@ pub enum VarID {
     AttributeMailCommand = 21,
     AttributePAVObjectPath = 22,
     AttributeUnknownUniverseSetting = 23,
+    AttributeObjectPath = 24,
+    AttributeTexturePath = 25,
+    AttributeMediaProxyURL = 27,
+    AttributeObjectRefresh = 28,
+    AttributePerCitizenPorts = 29,
 
     IdentifyUserIP = 26,
 
+    ConsoleBold = 30,
+    ConsoleItalics = 31,
+    ConsoleRed = 32,
+    ConsoleGreen = 33,
+    ConsoleBlue = 34,
+    ConsoleMessage = 35,
+
     BotgramFromCitizenNumber = 40,
     BotgramFromUsername = 41,
     BotgramMessage = 42,
@@ -114,6 +126,22 @@ pub enum VarID {
     TelegramsMoreRemain = 143,
     TelegramAge = 144,
     TelegramTo = 145,
+
+    JoinCitizenNumber = 150,
+    JoinCitizenName = 151,
+    JoinWorld = 152,
+    JoinX = 153,
+    JoinY = 154,
+    JoinZ = 155,
+    JoinYaw = 156,
+    JoinDeclineReason = 157,
+
+    PresenceOnline = 160,
+    PresenceWorld = 161,
+    PresenceAFK = 162,
+    PresenceBytesSent = 163,
+    PresenceBytesReceived = 164,
+
     UniverseLicense = 171,
     UserListAddress = 176,
     UserListCitizenID = 177,
@@ -137,10 +165,55 @@ pub enum VarID {
     WorldList3DayUnknown = 196,
     WorldListStatus = 197,
     WorldListUsers = 198,
+    WorldListLoad = 199,
+    WorldLoad = 200,
     WorldUsers = 201,
+
+    LoginChallenge = 202,
+    ProfileFieldName = 203,
+    ProfileFieldValue = 204,
+
+    GroupID = 205,
+    GroupName = 206,
+    GroupOwner = 207,
+    GroupMember = 208,
+    GroupRights = 209,
+    GroupAction = 210,
+
     BrowserVersion = 211,
+    URLName = 212,
+    URLTarget = 213,
+
+    CreditBalance = 214,
+    CreditAmount = 215,
+    CreditReason = 216,
+
+    CitizenMultiLogin = 217,
+
+    XferID = 218,
+    XferKind = 219,
+    XferOffset = 220,
+    XferTotalSize = 221,
+    XferData = 222,
+
+    /// Not sent by the original universe; used with `PacketType::SessionKick`
+    KickReason = 223,
+    UpgradeURL = 224,
+
     CAVEnabled = 226,
     CAVTemplate = 227,
+    CAVCitizen = 228,
+    CAVSession = 229,
+    CAVDefinition = 230,
+    CAVTemplateName = 231,
+    CAVTemplateModel = 232,
+    CAVTemplateType = 233,
+    CAVTemplateRating = 234,
+    EjectionAddress = 235,
+    EjectionAddressEnd = 236,
+    EjectionExpiration = 237,
+    EjectionCreation = 238,
+    EjectionComment = 239,
     AFKStatus = 261,
     WorldLicenseVoip = 263,
     WorldLicensePlugins = 264,
@@ -181,7 +254,7 @@ impl AWPacketVar {
             AWPacketVar::Int(_, _) => 4,
             AWPacketVar::Uint(_, _) => 4,
             AWPacketVar::Float(_, _) => 4,
-            AWPacketVar::String(_, string) => string_to_latin1(string).len() + 1,
+            AWPacketVar::String(_, string) => wire_codepage().encode(string).len() + 1,
             AWPacketVar::Data(_, buf) => buf.len(),
         }
     }
@@ -221,7 +294,7 @@ impl AWPacketVar {
                 result.write_f32::<LittleEndian>(*x).unwrap();
             }
             AWPacketVar::String(_, x) => {
-                result.write_all(&string_to_latin1(x)).unwrap();
+                result.write_all(&wire_codepage().encode(x)).unwrap();
                 result.write_all(&[0u8]).unwrap();
             }
             AWPacketVar::Data(_, x) => {
@@ -280,7 +353,7 @@ impl AWPacketVar {
                 reader
                     .read_exact(&mut buf)
                     .map_err(|_| "Could not deserialize String data")?;
-                AWPacketVar::String(var_id, latin1_to_string(&buf))
+                AWPacketVar::String(var_id, wire_codepage().decode(&buf))
             }
             DataType::Data => {
                 let mut buf = vec![0u8; size as usize];
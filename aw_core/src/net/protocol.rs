@@ -1,11 +1,73 @@
 //! Networking protocol implementation
 use crate::crypt_a4::AWCryptA4;
-use crate::net::packet::{AWPacket, DeserializeError, PacketType};
+use crate::net::headers::{apply_header_override, observe_headers, Direction};
+use crate::net::packet::{AWPacket, DeserializeError, PacketType, DEFAULT_HEADER_1};
+use crate::net::trace::trace_packet;
 use crate::ReasonCode;
 use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bytes sent and received on a connection, as they appear on the wire.
+#[derive(Debug, Default)]
+pub struct TrafficCounters {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl TrafficCounters {
+    pub fn bytes_sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+}
+
+fn hex_sample(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Token bucket used to cap the rate data is sent at.
+struct SendRateLimit {
+    bytes_per_sec: u64,
+    allowance: f64,
+    last_update: Instant,
+}
+
+impl SendRateLimit {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            allowance: bytes_per_sec as f64,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Take `len` bytes from the bucket, returning how long to wait before sending them.
+    fn take(&mut self, len: usize) -> Duration {
+        let rate = self.bytes_per_sec as f64;
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
+
+        // Allow bursts of up to one second's worth of data
+        self.allowance = (self.allowance + elapsed * rate).min(rate) - len as f64;
+        if self.allowance >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.allowance / rate)
+        }
+    }
+}
 
 /// State of an instance of the AW protocol.
 pub struct AWProtocol {
@@ -20,14 +82,31 @@ pub struct AWProtocol {
     other_inbound_packets: Option<Receiver<ProtocolMessage>>,
     other_outbound_packets: Option<Sender<ProtocolMessage>>,
     last_packet_type: Option<PacketType>,
+    outbound_queued: Arc<AtomicUsize>,
+    traffic: Arc<TrafficCounters>,
+    send_rate: Option<SendRateLimit>,
+    malformed_score: u32,
+    malformed_limit: u32,
+    peer: Option<SocketAddr>,
 }
 
+/// Score added for each malformed packet. Each good packet takes one point off.
+const MALFORMED_PENALTY: u32 = 10;
+
+/// Default score at which a connection sending malformed packets is dropped.
+pub const DEFAULT_MALFORMED_LIMIT: u32 = 50;
+
+/// Number of bytes of a malformed packet to log.
+const MALFORMED_SAMPLE_LEN: usize = 64;
+
 impl AWProtocol {
     /// Create a new AWProtocol instance given a TCP stream that has already been established.
     pub fn new(stream: TcpStream) -> Self {
         let (outbound_packets_tx, outbound_packets_rx) = channel::<ProtocolMessage>();
         let (inbound_packets_tx, inbound_packets_rx) = channel::<ProtocolMessage>();
 
+        let peer = stream.peer_addr().ok();
+
         Self {
             stream,
             data: Vec::new(),
@@ -40,9 +119,36 @@ impl AWProtocol {
             outbound_packets: outbound_packets_rx,
             other_inbound_packets: Some(inbound_packets_rx),
             other_outbound_packets: Some(outbound_packets_tx),
+            outbound_queued: Arc::new(AtomicUsize::new(0)),
+            traffic: Arc::new(TrafficCounters::default()),
+            send_rate: None,
+            malformed_score: 0,
+            malformed_limit: DEFAULT_MALFORMED_LIMIT,
+            peer,
         }
     }
 
+    /// Set the malformed packet score at which the connection is dropped.
+    pub fn set_malformed_limit(&mut self, limit: u32) {
+        self.malformed_limit = limit;
+    }
+
+    /// Byte counters for this connection.
+    pub fn traffic(&self) -> Arc<TrafficCounters> {
+        self.traffic.clone()
+    }
+
+    /// Limit how many bytes per second may be sent, or None for no limit.
+    pub fn set_send_rate_limit(&mut self, bytes_per_sec: Option<u64>) {
+        self.send_rate = bytes_per_sec.filter(|&x| x > 0).map(SendRateLimit::new);
+    }
+
+    /// Counter of packets waiting to be sent. Whoever queues packets increments it,
+    /// and the process loop decrements it once each has been sent.
+    pub fn outbound_queue_len(&self) -> Arc<AtomicUsize> {
+        self.outbound_queued.clone()
+    }
+
     /// Set the key to receive data (i.e. the key the other end of the connection is using).
     pub fn set_recv_key(&mut self, key: &[u8]) {
         self.recv_cipher = Some(AWCryptA4::from_key(key));
@@ -77,16 +183,23 @@ impl AWProtocol {
     /// Send packets.
     pub fn send(&mut self, packets: &mut [AWPacket], compression: bool) -> Result<(), ReasonCode> {
         for packet in packets.iter_mut() {
+            // Leave header_1 alone if it was set on purpose
             match packet.get_opcode() {
                 PacketType::PublicKeyResponse
                 | PacketType::StreamKeyResponse
                 | PacketType::Attributes
                 | PacketType::Login
-                | PacketType::Tunnel => {
+                | PacketType::Tunnel
+                    if packet.get_header_1() == DEFAULT_HEADER_1 =>
+                {
                     packet.set_header_1(1);
                 }
                 _ => {}
             }
+
+            apply_header_override(packet);
+            observe_headers(Direction::Sent, packet);
+            trace_packet(Direction::Sent, self.peer, packet);
         }
 
         // Serialize one or more packets
@@ -107,10 +220,21 @@ impl AWProtocol {
             bytes_to_send = self.send_cipher.encrypt(&bytes_to_send);
         }
 
+        // Hold back the data if this connection has used its share of bandwidth.
+        if let Some(limit) = &mut self.send_rate {
+            let wait = limit.take(bytes_to_send.len());
+            if !wait.is_zero() {
+                thread::sleep(wait);
+            }
+        }
+
         // Send the serialized packet.
         self.stream
             .write_all(&bytes_to_send)
             .map_err(|_| ReasonCode::SendFailed)?;
+        self.traffic
+            .sent
+            .fetch_add(bytes_to_send.len() as u64, Ordering::Relaxed);
 
         Ok(())
     }
@@ -119,6 +243,10 @@ impl AWProtocol {
     pub fn recv(&mut self) -> Result<usize, String> {
         let mut buf = [0u8; 0x8000];
         if let Ok(bytes_read) = self.stream.read(&mut buf) {
+            self.traffic
+                .received
+                .fetch_add(bytes_read as u64, Ordering::Relaxed);
+
             // Decrypt incoming bytes if we have a key.
             if let Some(cipher) = &mut self.recv_cipher {
                 cipher.decrypt_in_place(&mut buf[..bytes_read]);
@@ -135,12 +263,20 @@ impl AWProtocol {
         }
     }
 
-    fn decompress_packet(&mut self, serialized_len: usize) {
+    fn decompress_packet(&mut self, serialized_len: usize) -> Result<(), String> {
         // Decompress it and replace the front of the recv buf with the decompressed packet.
         let compressed_data = &self.data[..serialized_len];
-        if let Ok(decompressed) = AWPacket::decompress(compressed_data) {
-            self.remove_from_buf(serialized_len);
-            self.insert_into_buf(&decompressed);
+        match AWPacket::decompress(compressed_data) {
+            Ok(decompressed) => {
+                self.remove_from_buf(serialized_len);
+                self.insert_into_buf(&decompressed);
+                Ok(())
+            }
+            Err(why) => {
+                self.malformed_packet(serialized_len, &why)?;
+                self.remove_from_buf(serialized_len);
+                Ok(())
+            }
         }
     }
 
@@ -149,29 +285,62 @@ impl AWProtocol {
             Ok((packet, consumed_bytes)) => {
                 // Successfully deserialized a packet, now remove the data from the recv buf.
                 self.remove_from_buf(consumed_bytes);
+                self.malformed_score = self.malformed_score.saturating_sub(1);
+                observe_headers(Direction::Received, &packet);
+                trace_packet(Direction::Received, self.peer, &packet);
                 return Ok(Some(packet));
             }
-            Err(_) => {
-                // Failed to deserialize packet
-                self.recv()?;
+            Err(why) => {
+                // Failed to deserialize packet, so skip over it
+                self.malformed_packet(serialized_len, &why)?;
+                self.remove_from_buf(serialized_len);
             }
         }
         Ok(None)
     }
 
+    /// Log a sample of a malformed packet and raise the connection's malformed score.
+    /// Returns an error once the score reaches the limit.
+    fn malformed_packet(&mut self, len: usize, why: &str) -> Result<(), String> {
+        let sample = &self.data[..len.min(self.data.len()).min(MALFORMED_SAMPLE_LEN)];
+        eprintln!(
+            "Malformed packet from {:?} ({why}): {}",
+            self.stream.peer_addr().ok(),
+            hex_sample(sample)
+        );
+
+        self.malformed_score += MALFORMED_PENALTY;
+        if self.malformed_score >= self.malformed_limit {
+            return Err("Too many malformed packets.".to_string());
+        }
+        Ok(())
+    }
+
     fn check_and_deserialize_packet(&mut self) -> Result<Option<AWPacket>, String> {
         match AWPacket::deserialize_check(&self.data) {
+            // Have not received the whole packet yet
+            Ok(serialized_len) | Err(DeserializeError::Compressed(serialized_len))
+                if self.data.len() < serialized_len =>
+            {
+                self.recv()?;
+            }
             // Received a packet that appears well formed, attempt to deserialize
             Ok(serialized_len) => {
                 return self.deserialize_packet(serialized_len);
             }
             Err(err) => match err {
-                DeserializeError::Length | DeserializeError::InvalidHeader => {
+                DeserializeError::Length => {
                     self.recv()?;
                 }
+                // There is no way to find where the next packet starts, so give up on the connection.
+                DeserializeError::InvalidHeader => {
+                    let len = self.data.len();
+                    self.malformed_packet(len, "invalid header").ok();
+                    return Err("Received an invalid packet header.".to_string());
+                }
                 // Received a packet that is still compressed.
                 DeserializeError::Compressed(serialized_len) => {
-                    self.decompress_packet(serialized_len);
+                    self.decompress_packet(serialized_len)?;
                 }
             },
         }
@@ -234,12 +403,14 @@ impl AWProtocol {
         if let Ok(message) = self.outbound_packets.try_recv() {
             match message {
                 ProtocolMessage::Packet(packet) => {
+                    self.sent_queued();
                     if self.send(&mut [packet], true).is_err() {
                         self.inbound_packets.send(ProtocolMessage::Disconnect).ok();
                         self.dead = true;
                     }
                 }
                 ProtocolMessage::PacketGroup(mut packets) => {
+                    self.sent_queued();
                     if self.send(&mut packets, true).is_err() {
                         self.inbound_packets.send(ProtocolMessage::Disconnect).ok();
                         self.dead = true;
@@ -260,6 +431,15 @@ impl AWProtocol {
         }
     }
 
+    fn sent_queued(&self) {
+        // Saturate in case packets were sent without going through the counter
+        self.outbound_queued
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                Some(x.saturating_sub(1))
+            })
+            .ok();
+    }
+
     fn handle_inbound_packets(&mut self) {
         if self.needs_action() {
             match self.recv_next_packet() {
@@ -362,4 +542,16 @@ mod tests {
         // The deserialized packet should be the same as the packet originally sent.
         assert!(packet == packet_2);
     }
+
+    #[test]
+    pub fn test_send_rate_limit() {
+        let mut limit = SendRateLimit::new(1000);
+
+        // A full second's worth can be sent immediately
+        assert!(limit.take(1000).is_zero());
+
+        // After that, sending has to wait for the bucket to refill
+        let wait = limit.take(500);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+    }
 }
@@ -0,0 +1,136 @@
+//! A live feed of every packet sent and received, for debugging tools.
+//!
+//! Tracing is off until a tap is set, and costs one atomic load per packet
+//! while it is off.
+use crate::net::headers::Direction;
+use crate::net::packet::AWPacket;
+use crate::net::packet_var::{AWPacketVar, VarID};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A copy of one packet as it crossed the wire, before compression and encryption.
+#[derive(Debug, Clone)]
+pub struct PacketTrace {
+    pub time: SystemTime,
+    pub direction: Direction,
+    pub peer: Option<SocketAddr>,
+    pub data: Vec<u8>,
+}
+
+/// Vars whose values are replaced before a packet reaches the tap.
+const SECRET_VARS: [VarID; 5] = [
+    VarID::CitizenPassword,
+    VarID::CitizenPrivilegePassword,
+    VarID::WorldLicensePassword,
+    VarID::Password,
+    VarID::PrivilegePassword,
+];
+
+static TRACING: AtomicBool = AtomicBool::new(false);
+static TAP: Mutex<Option<Sender<PacketTrace>>> = Mutex::new(None);
+
+/// Send a copy of every packet to `tap`, or stop tracing with None.
+pub fn set_packet_tap(tap: Option<Sender<PacketTrace>>) {
+    if let Ok(mut current) = TAP.lock() {
+        TRACING.store(tap.is_some(), Ordering::Relaxed);
+        *current = tap;
+    }
+}
+
+pub fn packet_tracing() -> bool {
+    TRACING.load(Ordering::Relaxed)
+}
+
+/// Pass a packet to the tap, if there is one.
+pub fn trace_packet(direction: Direction, peer: Option<SocketAddr>, packet: &AWPacket) {
+    if !packet_tracing() {
+        return;
+    }
+
+    let data = match redact(packet).serialize() {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    let trace = PacketTrace {
+        time: SystemTime::now(),
+        direction,
+        peer,
+        data,
+    };
+
+    if let Ok(mut tap) = TAP.lock() {
+        // Nobody is listening any more
+        if tap.as_ref().is_some_and(|tx| tx.send(trace).is_err()) {
+            *tap = None;
+            TRACING.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Copy a packet with any passwords it carries blanked out.
+fn redact(packet: &AWPacket) -> AWPacket {
+    let mut redacted = AWPacket::new(packet.get_opcode());
+    redacted.set_header_0(packet.get_header_0());
+    redacted.set_header_1(packet.get_header_1());
+
+    for var in packet.get_vars() {
+        let var_id = var.get_var_id();
+        if SECRET_VARS.contains(&var_id) {
+            redacted.add_var(AWPacketVar::String(var_id, "(redacted)".to_string()));
+        } else {
+            redacted.add_var(var.clone());
+        }
+    }
+
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::packet::PacketType;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    pub fn test_packet_tap() {
+        let packet = AWPacket::new(PacketType::Heartbeat);
+
+        let (tx, rx) = channel();
+        set_packet_tap(Some(tx));
+        trace_packet(Direction::Sent, None, &packet);
+        set_packet_tap(None);
+        trace_packet(Direction::Sent, None, &packet);
+
+        // Other tests may be sending packets over real connections at the same time
+        let traces: Vec<PacketTrace> = rx.try_iter().filter(|x| x.peer.is_none()).collect();
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].direction, Direction::Sent);
+        assert_eq!(traces[0].data, packet.serialize().unwrap());
+    }
+
+    #[test]
+    pub fn test_redact() {
+        let mut packet = AWPacket::new(PacketType::Login);
+        packet.add_string(VarID::LoginUsername, "Someone".to_string());
+        packet.add_string(VarID::Password, "hunter2".to_string());
+        packet.add_string(VarID::PrivilegePassword, "hunter3".to_string());
+
+        let redacted = redact(&packet);
+        assert_eq!(redacted.get_opcode(), PacketType::Login);
+        assert_eq!(
+            redacted.get_string(VarID::LoginUsername).as_deref(),
+            Some("Someone")
+        );
+        assert_eq!(
+            redacted.get_string(VarID::Password).as_deref(),
+            Some("(redacted)")
+        );
+        assert_eq!(
+            redacted.get_string(VarID::PrivilegePassword).as_deref(),
+            Some("(redacted)")
+        );
+    }
+}
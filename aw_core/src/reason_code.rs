@@ -1,4 +1,6 @@
-#[derive(Debug, PartialEq, Eq)]
+use num_derive::FromPrimitive;
+
+#[derive(FromPrimitive, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ReasonCode {
     Success = 0,
     CitizenshipExpired = 1,
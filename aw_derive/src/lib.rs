@@ -0,0 +1,171 @@
+//! Derive macros for working with AW packets.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident};
+
+/// Map the fields of a struct to packet variables.
+///
+/// Each field needs `#[aw(var = VarID)]`, naming a `VarID` variant. Fields
+/// marked `optional` must be an `Option` and are left out when missing;
+/// other fields are required. The field types must implement
+/// `aw_core::PacketVarValue`.
+///
+/// This generates `TryFrom<&AWPacket>` and an `add_to_packet` method. If the
+/// struct has `#[aw(packet = PacketType)]`, it also generates
+/// `From<Struct> for AWPacket`.
+///
+/// ```ignore
+/// #[derive(AWPacketData)]
+/// #[aw(packet = Telegram)]
+/// struct Telegram {
+///     #[aw(var = TelegramTo)]
+///     to: String,
+///     #[aw(var = TelegramMessage, optional)]
+///     message: Option<String>,
+/// }
+/// ```
+#[proc_macro_derive(AWPacketData, attributes(aw))]
+pub fn derive_packet_data(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct FieldMapping {
+    name: Ident,
+    var: Ident,
+    optional: bool,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+
+    let mut packet_type: Option<Ident> = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("aw")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("packet") {
+                packet_type = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `packet = PacketType`"))
+            }
+        })?;
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "AWPacketData requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "AWPacketData can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut mappings = Vec::<FieldMapping>::new();
+    for field in fields {
+        let field_name = field.ident.clone().expect("Named fields have names");
+        let mut var: Option<Ident> = None;
+        let mut optional = false;
+
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("aw")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("var") {
+                    var = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("optional") {
+                    optional = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `var = VarID` or `optional`"))
+                }
+            })?;
+        }
+
+        let var = var.ok_or_else(|| {
+            syn::Error::new_spanned(&field_name, "missing #[aw(var = VarID)] attribute")
+        })?;
+
+        mappings.push(FieldMapping {
+            name: field_name,
+            var,
+            optional,
+        });
+    }
+
+    let reads = mappings.iter().map(|m| {
+        let field = &m.name;
+        let var = &m.var;
+        if m.optional {
+            quote! {
+                #field: ::aw_core::PacketVarValue::get_from(packet, ::aw_core::VarID::#var)
+            }
+        } else {
+            let missing = format!("No {var}");
+            quote! {
+                #field: ::aw_core::PacketVarValue::get_from(packet, ::aw_core::VarID::#var)
+                    .ok_or_else(|| #missing.to_string())?
+            }
+        }
+    });
+
+    let writes = mappings.iter().map(|m| {
+        let field = &m.name;
+        let var = &m.var;
+        if m.optional {
+            quote! {
+                if let Some(value) = &self.#field {
+                    ::aw_core::PacketVarValue::add_to(value, packet, ::aw_core::VarID::#var);
+                }
+            }
+        } else {
+            quote! {
+                ::aw_core::PacketVarValue::add_to(&self.#field, packet, ::aw_core::VarID::#var);
+            }
+        }
+    });
+
+    let into_packet = packet_type.map(|packet_type| {
+        quote! {
+            impl ::std::convert::From<#name> for ::aw_core::AWPacket {
+                fn from(data: #name) -> Self {
+                    let mut packet = ::aw_core::AWPacket::new(::aw_core::PacketType::#packet_type);
+                    data.add_to_packet(&mut packet);
+                    packet
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl ::std::convert::TryFrom<&::aw_core::AWPacket> for #name {
+            type Error = String;
+
+            fn try_from(packet: &::aw_core::AWPacket) -> Result<Self, Self::Error> {
+                Ok(Self {
+                    #(#reads,)*
+                })
+            }
+        }
+
+        impl #name {
+            /// Add each field to a packet as a variable.
+            pub fn add_to_packet(&self, packet: &mut ::aw_core::AWPacket) {
+                #(#writes)*
+            }
+        }
+
+        #into_packet
+    })
+}
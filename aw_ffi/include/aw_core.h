@@ -0,0 +1,89 @@
+/*
+ * C interface to aw_core's packet encoding and connection framing.
+ *
+ * Packets and ciphers are opaque and must be released with their _free
+ * function. Unless noted, pointer arguments must be valid and non-null.
+ *
+ * Functions which copy into a caller's buffer return the number of bytes
+ * needed. Nothing is copied if the buffer is null or too small, so they can
+ * be called once with a null buffer to find the size. They return -1 if the
+ * value does not exist or the operation failed.
+ *
+ * Strings are NUL-terminated and use the wire character set (Latin-1).
+ */
+#ifndef AW_CORE_H
+#define AW_CORE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct AWPacket AWPacket;
+typedef struct AWCryptA4 AWCryptA4;
+
+/* Returned by aw_frame_check */
+#define AW_FRAME_INCOMPLETE 0
+#define AW_FRAME_INVALID (-1)
+
+/* Packets */
+
+/* Returns NULL if the opcode is not a known packet type. */
+AWPacket *aw_packet_new(int16_t opcode);
+/* Accepts NULL. */
+void aw_packet_free(AWPacket *packet);
+int16_t aw_packet_opcode(const AWPacket *packet);
+void aw_packet_set_headers(AWPacket *packet, uint16_t header_0, uint16_t header_1);
+
+/* Adding vars returns false if the var ID is not known. */
+bool aw_packet_add_byte(AWPacket *packet, uint16_t id, uint8_t value);
+bool aw_packet_add_int(AWPacket *packet, uint16_t id, int32_t value);
+bool aw_packet_add_float(AWPacket *packet, uint16_t id, float value);
+bool aw_packet_add_string(AWPacket *packet, uint16_t id, const char *value);
+bool aw_packet_add_data(AWPacket *packet, uint16_t id, const uint8_t *data, size_t len);
+
+/* Getting vars returns false if the packet has no such var of that type. */
+bool aw_packet_get_byte(const AWPacket *packet, uint16_t id, uint8_t *out);
+bool aw_packet_get_int(const AWPacket *packet, uint16_t id, int32_t *out);
+bool aw_packet_get_float(const AWPacket *packet, uint16_t id, float *out);
+/* The returned length includes the NUL terminator. */
+ptrdiff_t aw_packet_get_string(const AWPacket *packet, uint16_t id, char *out, size_t out_len);
+ptrdiff_t aw_packet_get_data(const AWPacket *packet, uint16_t id, uint8_t *out, size_t out_len);
+
+/* Serialize a packet, compressing it if it is large enough to benefit. */
+ptrdiff_t aw_packet_serialize(const AWPacket *packet, bool compress, uint8_t *out, size_t out_len);
+/* Returns NULL on failure. consumed may be NULL. */
+AWPacket *aw_packet_deserialize(const uint8_t *data, size_t len, size_t *consumed);
+
+/* Framing */
+
+/*
+ * Examine received bytes. Returns the length of the first packet once all of
+ * it has arrived, AW_FRAME_INCOMPLETE if more bytes are needed, or
+ * AW_FRAME_INVALID if the stream is corrupt. compressed may be NULL, otherwise
+ * it is set to whether the packet must be passed to aw_frame_decompress before
+ * aw_packet_deserialize.
+ */
+int32_t aw_frame_check(const uint8_t *data, size_t len, bool *compressed);
+/* Decompress a whole compressed packet into one or more serialized packets. */
+ptrdiff_t aw_frame_decompress(const uint8_t *data, size_t len, uint8_t *out, size_t out_len);
+
+/* Stream cipher */
+
+/* Create a cipher with a random key. */
+AWCryptA4 *aw_cipher_new(void);
+AWCryptA4 *aw_cipher_from_key(const uint8_t *key, size_t len);
+/* Accepts NULL. */
+void aw_cipher_free(AWCryptA4 *cipher);
+size_t aw_cipher_get_key(const AWCryptA4 *cipher, uint8_t *out, size_t out_len);
+void aw_cipher_encrypt(AWCryptA4 *cipher, uint8_t *data, size_t len);
+void aw_cipher_decrypt(AWCryptA4 *cipher, uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* AW_CORE_H */
@@ -0,0 +1,321 @@
+//! C bindings for aw_core's packet encoding and connection framing.
+//!
+//! The matching header is include/aw_core.h. Packets and ciphers are handed out
+//! as opaque pointers which must be released with their `_free` function.
+#![allow(clippy::missing_safety_doc)] // Safety requirements are documented in the header
+use std::ffi::{c_char, CStr};
+use std::ptr;
+use std::slice;
+
+use aw_core::encoding::wire_codepage;
+use aw_core::{AWCryptA4, AWPacket, DeserializeError, PacketType, VarID};
+use num_traits::FromPrimitive;
+
+/// Returned by aw_frame_check when more bytes are needed.
+pub const AW_FRAME_INCOMPLETE: i32 = 0;
+/// Returned by aw_frame_check when the data cannot be a packet.
+pub const AW_FRAME_INVALID: i32 = -1;
+
+/// Copy bytes into a caller's buffer, returning how many bytes were needed.
+unsafe fn copy_out(bytes: &[u8], out: *mut u8, out_len: usize) -> usize {
+    if !out.is_null() && bytes.len() <= out_len {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len());
+    }
+    bytes.len()
+}
+
+unsafe fn bytes_in<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+fn var_id(id: u16) -> Option<VarID> {
+    VarID::from_u16(id)
+}
+
+#[no_mangle]
+pub extern "C" fn aw_packet_new(opcode: i16) -> *mut AWPacket {
+    match PacketType::from_i16(opcode) {
+        Some(opcode) => Box::into_raw(Box::new(AWPacket::new(opcode))),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_free(packet: *mut AWPacket) {
+    if !packet.is_null() {
+        drop(Box::from_raw(packet));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_opcode(packet: *const AWPacket) -> i16 {
+    (*packet).get_opcode() as i16
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_set_headers(
+    packet: *mut AWPacket,
+    header_0: u16,
+    header_1: u16,
+) {
+    (*packet).set_header_0(header_0);
+    (*packet).set_header_1(header_1);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_add_byte(packet: *mut AWPacket, id: u16, value: u8) -> bool {
+    match var_id(id) {
+        Some(id) => {
+            (*packet).add_byte(id, value);
+            true
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_add_int(packet: *mut AWPacket, id: u16, value: i32) -> bool {
+    match var_id(id) {
+        Some(id) => {
+            (*packet).add_int(id, value);
+            true
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_add_float(packet: *mut AWPacket, id: u16, value: f32) -> bool {
+    match var_id(id) {
+        Some(id) => {
+            (*packet).add_float(id, value);
+            true
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_add_string(
+    packet: *mut AWPacket,
+    id: u16,
+    value: *const c_char,
+) -> bool {
+    match var_id(id) {
+        Some(id) if !value.is_null() => {
+            let bytes = CStr::from_ptr(value).to_bytes();
+            (*packet).add_string(id, wire_codepage().decode(bytes));
+            true
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_add_data(
+    packet: *mut AWPacket,
+    id: u16,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    match var_id(id) {
+        Some(id) => {
+            (*packet).add_data(id, bytes_in(data, len).to_vec());
+            true
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_get_byte(
+    packet: *const AWPacket,
+    id: u16,
+    out: *mut u8,
+) -> bool {
+    match var_id(id).and_then(|id| (*packet).get_byte(id)) {
+        Some(value) => {
+            *out = value;
+            true
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_get_int(
+    packet: *const AWPacket,
+    id: u16,
+    out: *mut i32,
+) -> bool {
+    match var_id(id).and_then(|id| (*packet).get_int(id)) {
+        Some(value) => {
+            *out = value;
+            true
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_get_float(
+    packet: *const AWPacket,
+    id: u16,
+    out: *mut f32,
+) -> bool {
+    match var_id(id).and_then(|id| (*packet).get_float(id)) {
+        Some(value) => {
+            *out = value;
+            true
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_get_string(
+    packet: *const AWPacket,
+    id: u16,
+    out: *mut c_char,
+    out_len: usize,
+) -> isize {
+    match var_id(id).and_then(|id| (*packet).get_string(id)) {
+        Some(value) => {
+            let mut bytes = wire_codepage().encode(&value);
+            bytes.push(0);
+            copy_out(&bytes, out as *mut u8, out_len) as isize
+        }
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_get_data(
+    packet: *const AWPacket,
+    id: u16,
+    out: *mut u8,
+    out_len: usize,
+) -> isize {
+    match var_id(id).and_then(|id| (*packet).get_data(id)) {
+        Some(value) => copy_out(&value, out, out_len) as isize,
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_serialize(
+    packet: *const AWPacket,
+    compress: bool,
+    out: *mut u8,
+    out_len: usize,
+) -> isize {
+    let serialized = match (*packet).serialize() {
+        Ok(x) => x,
+        Err(_) => return -1,
+    };
+
+    let bytes = if compress {
+        match AWPacket::compress_if_needed(&serialized) {
+            Ok(x) => x,
+            Err(_) => return -1,
+        }
+    } else {
+        serialized
+    };
+
+    copy_out(&bytes, out, out_len) as isize
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_packet_deserialize(
+    data: *const u8,
+    len: usize,
+    consumed: *mut usize,
+) -> *mut AWPacket {
+    match AWPacket::deserialize(bytes_in(data, len)) {
+        Ok((packet, used)) => {
+            if !consumed.is_null() {
+                *consumed = used;
+            }
+            Box::into_raw(Box::new(packet))
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_frame_check(data: *const u8, len: usize, compressed: *mut bool) -> i32 {
+    let data = bytes_in(data, len);
+    let (frame_len, is_compressed) = match AWPacket::deserialize_check(data) {
+        Ok(frame_len) => (frame_len, false),
+        Err(DeserializeError::Compressed(frame_len)) => (frame_len, true),
+        Err(DeserializeError::Length) => return AW_FRAME_INCOMPLETE,
+        Err(DeserializeError::InvalidHeader) => return AW_FRAME_INVALID,
+    };
+
+    if data.len() < frame_len {
+        return AW_FRAME_INCOMPLETE;
+    }
+
+    if !compressed.is_null() {
+        *compressed = is_compressed;
+    }
+    frame_len as i32
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_frame_decompress(
+    data: *const u8,
+    len: usize,
+    out: *mut u8,
+    out_len: usize,
+) -> isize {
+    match AWPacket::decompress(bytes_in(data, len)) {
+        Ok(bytes) => copy_out(&bytes, out, out_len) as isize,
+        Err(_) => -1,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn aw_cipher_new() -> *mut AWCryptA4 {
+    Box::into_raw(Box::new(AWCryptA4::new()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_cipher_from_key(key: *const u8, len: usize) -> *mut AWCryptA4 {
+    Box::into_raw(Box::new(AWCryptA4::from_key(bytes_in(key, len))))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_cipher_free(cipher: *mut AWCryptA4) {
+    if !cipher.is_null() {
+        drop(Box::from_raw(cipher));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_cipher_get_key(
+    cipher: *const AWCryptA4,
+    out: *mut u8,
+    out_len: usize,
+) -> usize {
+    copy_out(&(*cipher).get_key(), out, out_len)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_cipher_encrypt(cipher: *mut AWCryptA4, data: *mut u8, len: usize) {
+    if !data.is_null() {
+        (*cipher).encrypt_in_place(slice::from_raw_parts_mut(data, len));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aw_cipher_decrypt(cipher: *mut AWCryptA4, data: *mut u8, len: usize) {
+    if !data.is_null() {
+        (*cipher).decrypt_in_place(slice::from_raw_parts_mut(data, len));
+    }
+}
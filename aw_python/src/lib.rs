@@ -0,0 +1,230 @@
+//! Python bindings for aw_core's packets.
+//!
+//! Build with `maturin develop` from this directory, then:
+//!
+//! ```python
+//! import awtools
+//! packet = awtools.Packet(awtools.PacketType.Login)
+//! packet.add_string(awtools.VarID.LoginUsername, "Tourist")
+//! data = packet.serialize()
+//! ```
+use aw_core::encoding::wire_codepage;
+use aw_core::{AWPacket, AWPacketVar, PacketType, VarID};
+use num_traits::FromPrimitive;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+/// Highest number checked for when listing packet types and var IDs.
+const MAX_ENUM_VALUE: i32 = 1024;
+
+fn var_id(id: u16) -> PyResult<VarID> {
+    VarID::from_u16(id).ok_or_else(|| PyValueError::new_err(format!("Unknown var ID {id}")))
+}
+
+/// A packet which can be serialized to or parsed from the AW wire format.
+#[pyclass(name = "Packet")]
+#[derive(Clone)]
+struct PyPacket {
+    packet: AWPacket,
+}
+
+#[pymethods]
+impl PyPacket {
+    #[new]
+    fn new(opcode: i16) -> PyResult<Self> {
+        let opcode = PacketType::from_i16(opcode)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown packet type {opcode}")))?;
+        Ok(Self {
+            packet: AWPacket::new(opcode),
+        })
+    }
+
+    #[getter]
+    fn opcode(&self) -> i16 {
+        self.packet.get_opcode() as i16
+    }
+
+    #[getter]
+    fn header_0(&self) -> u16 {
+        self.packet.get_header_0()
+    }
+
+    #[setter]
+    fn set_header_0(&mut self, value: u16) {
+        self.packet.set_header_0(value);
+    }
+
+    #[getter]
+    fn header_1(&self) -> u16 {
+        self.packet.get_header_1()
+    }
+
+    #[setter]
+    fn set_header_1(&mut self, value: u16) {
+        self.packet.set_header_1(value);
+    }
+
+    fn add_byte(&mut self, id: u16, value: u8) -> PyResult<()> {
+        self.packet.add_byte(var_id(id)?, value);
+        Ok(())
+    }
+
+    fn add_int(&mut self, id: u16, value: i32) -> PyResult<()> {
+        self.packet.add_int(var_id(id)?, value);
+        Ok(())
+    }
+
+    fn add_uint(&mut self, id: u16, value: u32) -> PyResult<()> {
+        self.packet.add_uint(var_id(id)?, value);
+        Ok(())
+    }
+
+    fn add_float(&mut self, id: u16, value: f32) -> PyResult<()> {
+        self.packet.add_float(var_id(id)?, value);
+        Ok(())
+    }
+
+    fn add_string(&mut self, id: u16, value: String) -> PyResult<()> {
+        self.packet.add_string(var_id(id)?, value);
+        Ok(())
+    }
+
+    fn add_data(&mut self, id: u16, value: Vec<u8>) -> PyResult<()> {
+        self.packet.add_data(var_id(id)?, value);
+        Ok(())
+    }
+
+    fn get_byte(&self, id: u16) -> PyResult<Option<u8>> {
+        Ok(self.packet.get_byte(var_id(id)?))
+    }
+
+    fn get_int(&self, id: u16) -> PyResult<Option<i32>> {
+        Ok(self.packet.get_int(var_id(id)?))
+    }
+
+    fn get_uint(&self, id: u16) -> PyResult<Option<u32>> {
+        Ok(self.packet.get_uint(var_id(id)?))
+    }
+
+    fn get_float(&self, id: u16) -> PyResult<Option<f32>> {
+        Ok(self.packet.get_float(var_id(id)?))
+    }
+
+    fn get_string(&self, id: u16) -> PyResult<Option<String>> {
+        Ok(self.packet.get_string(var_id(id)?))
+    }
+
+    fn get_data<'py>(&self, py: Python<'py>, id: u16) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        Ok(self
+            .packet
+            .get_data(var_id(id)?)
+            .map(|data| PyBytes::new(py, &data)))
+    }
+
+    /// List the packet's vars as (var ID, value) tuples.
+    fn vars(&self, py: Python<'_>) -> PyResult<Vec<(u16, PyObject)>> {
+        self.packet
+            .get_vars()
+            .iter()
+            .map(|var| {
+                let value = match var {
+                    AWPacketVar::Byte(_, x) => x.into_pyobject(py)?.into_any(),
+                    AWPacketVar::Int(_, x) => x.into_pyobject(py)?.into_any(),
+                    AWPacketVar::Uint(_, x) => x.into_pyobject(py)?.into_any(),
+                    AWPacketVar::Float(_, x) => x.into_pyobject(py)?.into_any(),
+                    AWPacketVar::String(_, x) => x.into_pyobject(py)?.into_any(),
+                    AWPacketVar::Data(_, x) => PyBytes::new(py, x).into_any(),
+                };
+                Ok((var.get_var_id() as u16, value.unbind()))
+            })
+            .collect()
+    }
+
+    /// Serialize the packet, compressing it if asked and large enough to benefit.
+    #[pyo3(signature = (compress = false))]
+    fn serialize<'py>(&self, py: Python<'py>, compress: bool) -> PyResult<Bound<'py, PyBytes>> {
+        let mut bytes = self.packet.serialize().map_err(PyValueError::new_err)?;
+        if compress {
+            bytes = AWPacket::compress_if_needed(&bytes).map_err(PyValueError::new_err)?;
+        }
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Parse a packet from the start of `data`, returning it and the number of bytes used.
+    #[staticmethod]
+    fn deserialize(data: &[u8]) -> PyResult<(Self, usize)> {
+        let (packet, consumed) = AWPacket::deserialize(data).map_err(PyValueError::new_err)?;
+        Ok((Self { packet }, consumed))
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.packet == other.packet
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.packet)
+    }
+}
+
+/// Decompress a compressed packet into one or more serialized packets.
+#[pyfunction]
+fn decompress<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    let bytes = AWPacket::decompress(data).map_err(PyValueError::new_err)?;
+    Ok(PyBytes::new(py, &bytes))
+}
+
+/// Encode a string in the character set used on the wire.
+#[pyfunction]
+fn encode_string<'py>(py: Python<'py>, value: &str) -> Bound<'py, PyBytes> {
+    PyBytes::new(py, &wire_codepage().encode(value))
+}
+
+/// Build a Python IntEnum from every value a Rust enum recognizes.
+fn int_enum<'py, T: std::fmt::Debug>(
+    py: Python<'py>,
+    name: &str,
+    values: impl Iterator<Item = (i32, Option<T>)>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let members = PyDict::new(py);
+    for (value, item) in values {
+        if let Some(item) = item {
+            let item_name = format!("{item:?}");
+            if item_name != "Unknown" {
+                members.set_item(item_name, value)?;
+            }
+        }
+    }
+
+    py.import("enum")?
+        .getattr("IntEnum")?
+        .call1((name, members))
+}
+
+#[pymodule]
+fn awtools(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+
+    m.add_class::<PyPacket>()?;
+    m.add_function(wrap_pyfunction!(decompress, m)?)?;
+    m.add_function(wrap_pyfunction!(encode_string, m)?)?;
+
+    m.add(
+        "PacketType",
+        int_enum(
+            py,
+            "PacketType",
+            (-1..=MAX_ENUM_VALUE).map(|x| (x, PacketType::from_i32(x))),
+        )?,
+    )?;
+    m.add(
+        "VarID",
+        int_enum(
+            py,
+            "VarID",
+            (0..=MAX_ENUM_VALUE).map(|x| (x, VarID::from_i32(x))),
+        )?,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,250 @@
+//! State of the trace viewer and how it responds to keys.
+use std::collections::BTreeMap;
+
+use aw_core::{AWPacket, AWPacketVar};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::feed::{FeedMessage, TracedPacket};
+
+/// Oldest packets are forgotten beyond this many.
+const MAX_RECORDS: usize = 50_000;
+
+/// A packet along with what could be made of it.
+pub struct Record {
+    pub time: u64,
+    pub peer: String,
+    pub sent: bool,
+    pub data: Vec<u8>,
+    pub packet: Result<AWPacket, String>,
+}
+
+impl Record {
+    fn new(traced: TracedPacket) -> Self {
+        let data = decode_hex(&traced.data);
+        let packet = AWPacket::deserialize(&data).map(|(packet, _)| packet);
+
+        Self {
+            time: traced.time,
+            peer: traced.peer.unwrap_or_else(|| "unknown".to_string()),
+            sent: traced.direction == "sent",
+            data,
+            packet,
+        }
+    }
+
+    pub fn type_name(&self) -> String {
+        match &self.packet {
+            Ok(packet) => format!("{:?}", packet.get_opcode()),
+            Err(_) => "<malformed>".to_string(),
+        }
+    }
+
+    /// Whether the record should be shown for a filter typed by the user.
+    fn matches(&self, filter: &str) -> bool {
+        if filter.is_empty() {
+            return true;
+        }
+        let filter = filter.to_lowercase();
+        if self.peer.contains(&filter) || self.type_name().to_lowercase().contains(&filter) {
+            return true;
+        }
+        match &self.packet {
+            Ok(packet) => packet.get_vars().iter().any(|var| {
+                format!("{:?}", var.get_var_id())
+                    .to_lowercase()
+                    .contains(&filter)
+            }),
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Clients,
+    Packets,
+}
+
+pub struct App {
+    pub addr: String,
+    pub status: String,
+    pub records: Vec<Record>,
+    /// Records received while paused, shown once unpaused
+    pub held: Vec<Record>,
+    pub paused: bool,
+    pub filter: String,
+    pub editing_filter: bool,
+    pub focus: Focus,
+    /// 0 is every client, otherwise an index into `clients()` plus one
+    pub selected_client: usize,
+    pub selected_packet: usize,
+    /// Whether to show the selected packet's hex dump in place of the packet list
+    pub hex_view: bool,
+    pub quit: bool,
+}
+
+impl App {
+    pub fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            status: "Connecting".to_string(),
+            records: Vec::new(),
+            held: Vec::new(),
+            paused: false,
+            filter: String::new(),
+            editing_filter: false,
+            focus: Focus::Packets,
+            selected_client: 0,
+            selected_packet: 0,
+            hex_view: false,
+            quit: false,
+        }
+    }
+
+    pub fn receive(&mut self, message: FeedMessage) {
+        match message {
+            FeedMessage::Connected => self.status = "Connected".to_string(),
+            FeedMessage::Disconnected(reason) => self.status = format!("Disconnected: {reason}"),
+            FeedMessage::Packet(traced) => {
+                let record = Record::new(traced);
+                if self.paused {
+                    self.held.push(record);
+                } else {
+                    self.push(record);
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, record: Record) {
+        self.records.push(record);
+        if self.records.len() > MAX_RECORDS {
+            self.records.remove(0);
+            self.selected_packet = self.selected_packet.saturating_sub(1);
+        }
+    }
+
+    /// Every client seen so far, with how many packets each has.
+    pub fn clients(&self) -> Vec<(String, usize)> {
+        let mut clients: BTreeMap<&str, usize> = BTreeMap::new();
+        for record in &self.records {
+            *clients.entry(&record.peer).or_insert(0) += 1;
+        }
+        clients
+            .into_iter()
+            .map(|(peer, count)| (peer.to_string(), count))
+            .collect()
+    }
+
+    /// Records shown with the current client selection and filter.
+    pub fn visible(&self) -> Vec<&Record> {
+        let client = match self.selected_client {
+            0 => None,
+            n => self.clients().get(n - 1).map(|(peer, _)| peer.clone()),
+        };
+
+        self.records
+            .iter()
+            .filter(|record| client.as_ref().is_none_or(|peer| record.peer == *peer))
+            .filter(|record| record.matches(&self.filter))
+            .collect()
+    }
+
+    pub fn key(&mut self, key: KeyEvent) {
+        if self.editing_filter {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.editing_filter = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            self.selected_packet = 0;
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char(' ') => self.toggle_pause(),
+            KeyCode::Char('/') => self.editing_filter = true,
+            KeyCode::Char('x') => {
+                self.records.clear();
+                self.selected_packet = 0;
+                self.selected_client = 0;
+            }
+            KeyCode::Esc => {
+                if self.hex_view {
+                    self.hex_view = false;
+                } else {
+                    self.filter.clear();
+                }
+            }
+            KeyCode::Enter => self.hex_view = !self.hex_view,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Clients => Focus::Packets,
+                    Focus::Packets => Focus::Clients,
+                }
+            }
+            KeyCode::Up => self.move_selection(-1),
+            KeyCode::Down => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-20),
+            KeyCode::PageDown => self.move_selection(20),
+            KeyCode::Home => self.move_selection(isize::MIN / 2),
+            KeyCode::End => self.move_selection(isize::MAX / 2),
+            _ => {}
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if !self.paused {
+            for record in std::mem::take(&mut self.held) {
+                self.push(record);
+            }
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let (selected, len) = match self.focus {
+            Focus::Clients => {
+                let len = self.clients().len() + 1;
+                (&mut self.selected_client, len)
+            }
+            Focus::Packets => {
+                let len = self.visible().len();
+                (&mut self.selected_packet, len)
+            }
+        };
+        let max = len.saturating_sub(1) as isize;
+        *selected = (*selected as isize).saturating_add(delta).clamp(0, max) as usize;
+
+        if self.focus == Focus::Clients {
+            self.selected_packet = 0;
+        }
+    }
+}
+
+pub fn describe_var(var: &AWPacketVar) -> String {
+    let value = match var {
+        AWPacketVar::Byte(_, x) => format!("byte {x}"),
+        AWPacketVar::Int(_, x) => format!("int {x}"),
+        AWPacketVar::Uint(_, x) => format!("uint {x}"),
+        AWPacketVar::Float(_, x) => format!("float {x}"),
+        AWPacketVar::String(_, x) => format!("string {x:?}"),
+        AWPacketVar::Data(_, x) => format!("data ({} bytes) {}", x.len(), encode_hex(x)),
+    };
+    format!("{:?} = {value}", var.get_var_id())
+}
+
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex(text: &str) -> Vec<u8> {
+    (0..text.len() / 2)
+        .filter_map(|i| u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,88 @@
+//! Reading the packet stream from the universe's admin interface.
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    sync::mpsc::Sender,
+    thread,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+/// How long to wait before reconnecting after the stream ends.
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+/// One packet as sent by the universe.
+#[derive(Debug, Deserialize)]
+pub struct TracedPacket {
+    /// Milliseconds since the unix epoch
+    pub time: u64,
+    pub peer: Option<String>,
+    pub direction: String,
+    /// Serialized packet as hex
+    pub data: String,
+}
+
+pub enum FeedMessage {
+    Connected,
+    Disconnected(String),
+    Packet(TracedPacket),
+}
+
+/// Start reading packets in the background, reconnecting whenever the stream ends.
+pub fn start(addr: String, token: String, tx: Sender<FeedMessage>) {
+    thread::spawn(move || loop {
+        let reason = match read_stream(&addr, &token, &tx) {
+            Ok(()) => "stream ended".to_string(),
+            Err(err) => err,
+        };
+        if tx.send(FeedMessage::Disconnected(reason)).is_err() {
+            return;
+        }
+        thread::sleep(RECONNECT_DELAY);
+    });
+}
+
+fn read_stream(addr: &str, token: &str, tx: &Sender<FeedMessage>) -> Result<(), String> {
+    let mut stream = TcpStream::connect(addr).map_err(|err| err.to_string())?;
+    write!(
+        stream,
+        "GET /packets HTTP/1.1\r\nHost: {addr}\r\nAuthorization: Bearer {token}\r\nAccept: text/event-stream\r\n\r\n"
+    )
+    .map_err(|err| err.to_string())?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader
+        .read_line(&mut status)
+        .map_err(|err| err.to_string())?;
+    if !status.contains(" 200 ") {
+        return Err(format!("universe answered {}", status.trim_end()));
+    }
+
+    // Skip the rest of the response head
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(|err| err.to_string())?;
+        if line.trim_end().is_empty() {
+            break;
+        }
+    }
+    tx.send(FeedMessage::Connected).ok();
+
+    for line in reader.lines() {
+        let line = line.map_err(|err| err.to_string())?;
+        if let Some(json) = line.strip_prefix("data: ") {
+            match serde_json::from_str::<TracedPacket>(json) {
+                Ok(packet) => {
+                    if tx.send(FeedMessage::Packet(packet)).is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(err) => return Err(format!("bad packet record: {err}")),
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! Watch the packets passing through a universe, live.
+//!
+//! Connects to the universe's admin interface and shows every packet sent
+//! and received, grouped by client, with filtering, pausing and a hex view.
+mod app;
+mod feed;
+mod ui;
+
+use std::{io, sync::mpsc, time::Duration};
+
+use clap::Parser;
+use crossterm::{
+    event::{self, Event, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{backend::CrosstermBackend, Terminal};
+
+#[derive(Parser)]
+struct Args {
+    /// Address of the universe's admin interface
+    #[clap(long, default_value = "127.0.0.1:6680")]
+    addr: String,
+
+    /// Admin interface token
+    #[clap(long)]
+    token: String,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let (tx, rx) = mpsc::channel();
+    feed::start(args.addr.clone(), args.token, tx);
+
+    if let Err(err) = run(&args.addr, rx) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
+
+fn run(addr: &str, rx: mpsc::Receiver<feed::FeedMessage>) -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let mut app = app::App::new(addr);
+    let result = (|| -> io::Result<()> {
+        while !app.quit {
+            for message in rx.try_iter() {
+                app.receive(message);
+            }
+
+            terminal.draw(|frame| ui::draw(frame, &mut app))?;
+
+            if event::poll(Duration::from_millis(100))? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        app.key(key);
+                    }
+                }
+            }
+        }
+        Ok(())
+    })();
+
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    result
+}
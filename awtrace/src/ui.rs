@@ -0,0 +1,200 @@
+//! Drawing the trace viewer.
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::app::{describe_var, App, Focus, Record};
+
+/// Bytes shown on each line of a hex dump.
+const HEX_LINE_LEN: usize = 16;
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(5),
+            Constraint::Length(12),
+            Constraint::Length(1),
+        ])
+        .split(frame.area());
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(26), Constraint::Min(20)])
+        .split(rows[0]);
+
+    draw_clients(frame, app, columns[0]);
+
+    let visible = app.visible();
+    let selected = visible.get(app.selected_packet).copied();
+    if app.hex_view {
+        draw_hex(frame, selected, columns[1]);
+    } else {
+        draw_packets(frame, app, &visible, columns[1]);
+    }
+    draw_detail(frame, selected, rows[1]);
+    draw_status(frame, app, rows[2]);
+}
+
+fn block(title: &str, focused: bool) -> Block<'_> {
+    let style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(style)
+        .title(title)
+}
+
+fn draw_clients(frame: &mut Frame, app: &App, area: Rect) {
+    let total = app.records.len();
+    let mut items = vec![ListItem::new(format!("All ({total})"))];
+    items.extend(
+        app.clients()
+            .into_iter()
+            .map(|(peer, count)| ListItem::new(format!("{peer} ({count})"))),
+    );
+
+    let list = List::new(items)
+        .block(block("Clients", app.focus == Focus::Clients))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(app.selected_client));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_packets(frame: &mut Frame, app: &App, visible: &[&Record], area: Rect) {
+    let items: Vec<ListItem> = visible
+        .iter()
+        .map(|record| {
+            let (arrow, color) = if record.sent {
+                ("->", Color::Green)
+            } else {
+                ("<-", Color::Cyan)
+            };
+            let vars = record
+                .packet
+                .as_ref()
+                .map(|packet| packet.get_vars().len())
+                .unwrap_or(0);
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{} ", format_time(record.time))),
+                Span::styled(format!("{arrow} "), Style::default().fg(color)),
+                Span::raw(format!("{:<21} ", record.peer)),
+                Span::styled(
+                    format!("{:<28}", record.type_name()),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(format!("{vars} vars, {} bytes", record.data.len())),
+            ]))
+        })
+        .collect();
+
+    let title = format!("Packets ({})", visible.len());
+    let list = List::new(items)
+        .block(block(&title, app.focus == Focus::Packets))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(app.selected_packet));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_hex(frame: &mut Frame, record: Option<&Record>, area: Rect) {
+    let lines: Vec<Line> = match record {
+        Some(record) => record
+            .data
+            .chunks(HEX_LINE_LEN)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+                let ascii: String = chunk
+                    .iter()
+                    .map(|&b| {
+                        if b.is_ascii_graphic() || b == b' ' {
+                            b as char
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                Line::from(format!(
+                    "{:08x}  {:<48} {ascii}",
+                    i * HEX_LINE_LEN,
+                    hex.join(" ")
+                ))
+            })
+            .collect(),
+        None => vec![Line::from("No packet selected")],
+    };
+
+    frame.render_widget(
+        Paragraph::new(lines).block(block("Hex (Enter to return)", true)),
+        area,
+    );
+}
+
+fn draw_detail(frame: &mut Frame, record: Option<&Record>, area: Rect) {
+    let lines: Vec<Line> = match record.map(|record| &record.packet) {
+        Some(Ok(packet)) => {
+            let mut lines = vec![Line::from(format!(
+                "{:?} ({}), header_0 {}, header_1 {}",
+                packet.get_opcode(),
+                packet.get_opcode() as i32,
+                packet.get_header_0(),
+                packet.get_header_1()
+            ))];
+            lines.extend(
+                packet
+                    .get_vars()
+                    .iter()
+                    .map(|var| Line::from(format!("  {}", describe_var(var)))),
+            );
+            lines
+        }
+        Some(Err(err)) => vec![Line::from(format!("Could not decode packet: {err}"))],
+        None => vec![],
+    };
+
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(block("Detail", false))
+            .wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
+fn draw_status(frame: &mut Frame, app: &App, area: Rect) {
+    let mut spans = vec![Span::raw(format!("{} {} ", app.addr, app.status))];
+    if app.paused {
+        spans.push(Span::styled(
+            format!(" PAUSED ({} held) ", app.held.len()),
+            Style::default().fg(Color::Black).bg(Color::Yellow),
+        ));
+    }
+    if app.editing_filter || !app.filter.is_empty() {
+        let cursor = if app.editing_filter { "_" } else { "" };
+        spans.push(Span::raw(format!(" filter: {}{cursor} ", app.filter)));
+    }
+    spans.push(Span::styled(
+        " q quit  space pause  / filter  tab switch  enter hex  x clear",
+        Style::default().fg(Color::DarkGray),
+    ));
+
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+/// Time of day (UTC) with milliseconds.
+fn format_time(millis: u64) -> String {
+    let secs = millis / 1000;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        millis % 1000
+    )
+}
@@ -0,0 +1,564 @@
+//! Public web pages which citizens reach by following links emailed to them.
+//!
+//! - `GET /verify?token=<token>` asks a new citizen to confirm their email address,
+//!   and posting it enables their account.
+//! - `GET /reset` asks for a citizen name, and posting it emails that citizen a
+//!   link to choose a new password.
+//! - `GET /reset/confirm?token=<token>` asks for the new password, and posting it
+//!   sets the password.
+//! - `GET /telegrams` asks for a citizen name, and posting it emails that citizen a
+//!   link to have telegrams they receive while offline emailed to them.
+//! - `GET /telegrams/confirm?token=<token>` and `GET /telegrams/unsubscribe?token=<token>`
+//!   ask the citizen to confirm, and posting them turns telegram emails on or off.
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use aw_core::ReasonCode;
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{
+    admin::http::{self, respond, PendingRequest, Request},
+    config::AccountConfig,
+    database::{citizen::CitizenQuery, token::TokenKind, CitizenDB, Database, TelegramDB, TokenDB},
+    email::{Email, Mailer},
+    packet_handler::check_valid_password,
+};
+
+/// Length of the tokens put in links.
+const TOKEN_LEN: usize = 32;
+
+/// Seconds the unsubscribe link in a telegram email can be used for
+const UNSUBSCRIBE_VALID_SECS: u32 = 90 * 86400;
+
+pub struct AccountServer {
+    listener: TcpListener,
+    /// Connections still sending their requests
+    pending: Vec<PendingRequest>,
+    reset_throttle: ResetThrottle,
+    telegram_throttle: ResetThrottle,
+}
+
+/// When emailed links were last asked for, by address and by citizen.
+#[derive(Default)]
+struct ResetThrottle {
+    by_ip: HashMap<IpAddr, Instant>,
+    by_citizen: HashMap<u32, Instant>,
+}
+
+impl ResetThrottle {
+    /// Whether another reset may be asked for from an address, or for a citizen.
+    /// Asking counts even when it is refused, so retrying does not help.
+    fn allow_ip(&mut self, ip: IpAddr, interval: Duration) -> bool {
+        Self::allow(&mut self.by_ip, ip, interval)
+    }
+
+    fn allow_citizen(&mut self, citizen_id: u32, interval: Duration) -> bool {
+        Self::allow(&mut self.by_citizen, citizen_id, interval)
+    }
+
+    fn allow<K: Eq + Hash>(map: &mut HashMap<K, Instant>, key: K, interval: Duration) -> bool {
+        map.retain(|_, last| last.elapsed() < interval);
+        map.insert(key, Instant::now()).is_none()
+    }
+}
+
+impl AccountServer {
+    /// Start listening if the account pages are enabled.
+    pub fn new(config: &AccountConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let addr = SocketAddr::new(config.ip, config.port);
+        let listener = match TcpListener::bind(addr) {
+            Ok(x) => x,
+            Err(err) => {
+                log::error!("Could not start the account pages on {addr}: {err}");
+                return None;
+            }
+        };
+        listener.set_nonblocking(true).ok()?;
+        log::info!("Account pages listening on {addr}");
+
+        Some(Self {
+            listener,
+            pending: Vec::new(),
+            reset_throttle: ResetThrottle::default(),
+            telegram_throttle: ResetThrottle::default(),
+        })
+    }
+
+    /// Handle new requests.
+    pub fn service(
+        &mut self,
+        database: &Database,
+        mailer: Option<&Mailer>,
+        config: &AccountConfig,
+    ) {
+        http::accept(&self.listener, &mut self.pending);
+        for (stream, addr, request) in http::take_requests(&mut self.pending, "account") {
+            self.handle_request(stream, addr, request, database, mailer, config);
+        }
+    }
+
+    fn handle_request(
+        &mut self,
+        mut stream: TcpStream,
+        addr: SocketAddr,
+        request: Request,
+        database: &Database,
+        mailer: Option<&Mailer>,
+        config: &AccountConfig,
+    ) {
+        let body = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/verify") => confirm_form(
+                &request,
+                "Confirm your email address to enable your citizenship.",
+            ),
+            ("POST", "/verify") => {
+                let form = request.form();
+                let token = form.get("token").map(String::as_str).unwrap_or_default();
+                match verify(token, database) {
+                    Ok(citizen) => {
+                        log::info!(
+                            "{} verified the email of {} (#{})",
+                            addr.ip(),
+                            citizen.name,
+                            citizen.id
+                        );
+                        message("Your email address has been confirmed. You can now log in.")
+                    }
+                    Err(ReasonCode::NoSuchCitizen) => {
+                        message("This link is invalid or has expired.")
+                    }
+                    Err(_) => message(
+                        "Your email address could not be confirmed. Please try again later.",
+                    ),
+                }
+            }
+            ("GET", "/reset") => page(
+                "<form method=\"post\">\
+                <p>Enter your citizen name to be emailed a link for choosing a new password.</p>\
+                <p><input name=\"name\" maxlength=\"16\"> <button>Send</button></p>\
+                </form>",
+            ),
+            ("POST", "/reset") => {
+                let name = request.form().remove("name").unwrap_or_default();
+                self.request_link(
+                    TokenKind::PasswordReset,
+                    &name,
+                    addr,
+                    database,
+                    mailer,
+                    config,
+                );
+                // The same answer either way, so names and addresses cannot be discovered here
+                message("If that citizen has an email address, a link has been sent to it.")
+            }
+            ("GET", "/reset/confirm") => {
+                let token = request.query.get("token").map(String::as_str);
+                page(&format!(
+                    "<form method=\"post\">\
+                    <input type=\"hidden\" name=\"token\" value=\"{}\">\
+                    <p>New password: <input type=\"password\" name=\"password\" maxlength=\"12\"> \
+                    <button>Change</button></p>\
+                    </form>",
+                    escape(token.unwrap_or_default())
+                ))
+            }
+            ("POST", "/reset/confirm") => {
+                let form = request.form();
+                let token = form.get("token").map(String::as_str).unwrap_or_default();
+                let password = form.get("password").map(String::as_str).unwrap_or_default();
+                match reset_password(token, password, database) {
+                    Ok(citizen) => {
+                        log::info!(
+                            "{} reset the password of {} (#{})",
+                            addr.ip(),
+                            citizen.name,
+                            citizen.id
+                        );
+                        message("Your password has been changed. You can now log in with it.")
+                    }
+                    Err(ReasonCode::NoSuchCitizen) => {
+                        message("This link is invalid or has expired.")
+                    }
+                    Err(ReasonCode::PasswordTooShort) => {
+                        message("Passwords must be at least 4 characters long.")
+                    }
+                    Err(ReasonCode::PasswordTooLong) => {
+                        message("Passwords may be at most 12 characters long.")
+                    }
+                    Err(_) => {
+                        message("Your password could not be changed. Please try again later.")
+                    }
+                }
+            }
+            ("GET", "/telegrams") => page(
+                "<form method=\"post\">\
+                <p>Enter your citizen name to be emailed a link for having telegrams you \
+                receive while offline sent to your email address.</p>\
+                <p><input name=\"name\" maxlength=\"16\"> <button>Send</button></p>\
+                </form>",
+            ),
+            ("POST", "/telegrams") => {
+                let name = request.form().remove("name").unwrap_or_default();
+                self.request_link(
+                    TokenKind::TelegramEmail,
+                    &name,
+                    addr,
+                    database,
+                    mailer,
+                    config,
+                );
+                message("If that citizen has an email address, a link has been sent to it.")
+            }
+            ("GET", "/telegrams/confirm") => confirm_form(
+                &request,
+                "Confirm to have telegrams you receive while offline emailed to you.",
+            ),
+            ("POST", "/telegrams/confirm") => {
+                match set_telegram_email(&request, TokenKind::TelegramEmail, database) {
+                    Ok(()) => {
+                        message("Telegrams you receive while offline will be emailed to you.")
+                    }
+                    Err(ReasonCode::NoSuchCitizen) => {
+                        message("This link is invalid or has expired.")
+                    }
+                    Err(_) => {
+                        message("Telegram emails could not be turned on. Please try again later.")
+                    }
+                }
+            }
+            ("GET", "/telegrams/unsubscribe") => {
+                confirm_form(&request, "Confirm to stop being emailed your telegrams.")
+            }
+            ("POST", "/telegrams/unsubscribe") => {
+                match set_telegram_email(&request, TokenKind::Unsubscribe, database) {
+                    Ok(()) => message("You will no longer be emailed your telegrams."),
+                    Err(ReasonCode::NoSuchCitizen) => {
+                        message("This link is invalid or has expired.")
+                    }
+                    Err(_) => {
+                        message("Telegram emails could not be turned off. Please try again later.")
+                    }
+                }
+            }
+            _ => return respond(&mut stream, 404, "text/plain", b"Not found"),
+        };
+
+        respond(&mut stream, 200, "text/html", body.as_bytes());
+    }
+
+    /// Email a citizen a password reset or telegram email link, if they have an
+    /// address and have not been sent one too recently.
+    fn request_link(
+        &mut self,
+        kind: TokenKind,
+        name: &str,
+        addr: SocketAddr,
+        database: &Database,
+        mailer: Option<&Mailer>,
+        config: &AccountConfig,
+    ) {
+        let (what, throttle, send): (_, _, SendLink) = match kind {
+            TokenKind::TelegramEmail => (
+                "telegram email",
+                &mut self.telegram_throttle,
+                send_telegram_email_link,
+            ),
+            _ => (
+                "password reset",
+                &mut self.reset_throttle,
+                send_password_reset,
+            ),
+        };
+
+        let interval = Duration::from_secs(config.reset_interval_secs);
+        if !throttle.allow_ip(addr.ip(), interval) {
+            log::info!("Throttling {what} requests from {}", addr.ip());
+            return;
+        }
+
+        let (Some(mailer), Ok(citizen)) = (mailer, database.citizen_by_name(name)) else {
+            return;
+        };
+
+        if citizen.email.is_empty() {
+            return;
+        }
+
+        if !throttle.allow_citizen(citizen.id, interval) {
+            log::info!(
+                "Throttling {what} requests for {} (#{})",
+                citizen.name,
+                citizen.id
+            );
+            return;
+        }
+
+        log::info!(
+            "{} asked for a {what} link for {} (#{})",
+            addr.ip(),
+            citizen.name,
+            citizen.id
+        );
+        if let Err(err) = send(&citizen, database, mailer, config) {
+            log::warn!("Could not send a {what} email to {}: {err:?}", citizen.name);
+        }
+    }
+}
+
+type SendLink = fn(&CitizenQuery, &Database, &Mailer, &AccountConfig) -> Result<(), ReasonCode>;
+
+fn verify(token: &str, database: &Database) -> Result<CitizenQuery, ReasonCode> {
+    let citizen_id = database
+        .token_take(token, TokenKind::VerifyEmail, unix_time())?
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+
+    database.citizen_set_enabled(citizen_id, true)?;
+    database.citizen_by_number(citizen_id)
+}
+
+/// Turn telegram emails on or off for the citizen a posted token was made for.
+fn set_telegram_email(
+    request: &Request,
+    kind: TokenKind,
+    database: &Database,
+) -> Result<(), ReasonCode> {
+    let form = request.form();
+    let token = form.get("token").map(String::as_str).unwrap_or_default();
+    let citizen_id = database
+        .token_take(token, kind, unix_time())?
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+
+    let subscribe = kind == TokenKind::TelegramEmail;
+    log::info!(
+        "Citizen #{citizen_id} turned telegram emails {}",
+        if subscribe { "on" } else { "off" }
+    );
+    database.telegram_email_set(citizen_id, subscribe, !subscribe)
+}
+
+fn reset_password(
+    token: &str,
+    password: &str,
+    database: &Database,
+) -> Result<CitizenQuery, ReasonCode> {
+    // Checked first so a mistyped password does not use up the link
+    check_valid_password(password)?;
+
+    let citizen_id = database
+        .token_take(token, TokenKind::PasswordReset, unix_time())?
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+
+    database.citizen_set_password(citizen_id, password)?;
+    database.citizen_clear_failed_logins(citizen_id)?;
+    // Any other links which were sent can no longer be used
+    database.token_revoke(citizen_id, TokenKind::PasswordReset)?;
+    database.citizen_by_number(citizen_id)
+}
+
+/// Email a new citizen a link which enables their account.
+pub fn send_verification(
+    citizen: &CitizenQuery,
+    database: &Database,
+    mailer: &Mailer,
+    config: &AccountConfig,
+) -> Result<(), ReasonCode> {
+    let link = new_link(
+        citizen,
+        TokenKind::VerifyEmail,
+        "/verify",
+        config.link_valid_secs,
+        database,
+        config,
+    )?;
+
+    mailer.send(Email {
+        to: citizen.email.clone(),
+        subject: "Confirm your email address".to_string(),
+        body: format!(
+            "Welcome, {}!\n\nFollow this link to confirm your email address and enable your citizenship:\n\n{link}\n",
+            citizen.name,
+        ),
+    });
+
+    Ok(())
+}
+
+/// Make a link which lets a citizen choose a new password. Only the newest link works.
+pub fn password_reset_link(
+    citizen: &CitizenQuery,
+    database: &Database,
+    config: &AccountConfig,
+) -> Result<String, ReasonCode> {
+    database.token_revoke(citizen.id, TokenKind::PasswordReset)?;
+    new_link(
+        citizen,
+        TokenKind::PasswordReset,
+        "/reset/confirm",
+        config.link_valid_secs,
+        database,
+        config,
+    )
+}
+
+/// Email a citizen a link which lets them choose a new password.
+pub fn send_password_reset(
+    citizen: &CitizenQuery,
+    database: &Database,
+    mailer: &Mailer,
+    config: &AccountConfig,
+) -> Result<(), ReasonCode> {
+    let link = password_reset_link(citizen, database, config)?;
+    mailer.send(password_reset_email(citizen, &link));
+
+    Ok(())
+}
+
+/// Email a citizen a link which turns on emailing the telegrams they receive while offline.
+fn send_telegram_email_link(
+    citizen: &CitizenQuery,
+    database: &Database,
+    mailer: &Mailer,
+    config: &AccountConfig,
+) -> Result<(), ReasonCode> {
+    database.token_revoke(citizen.id, TokenKind::TelegramEmail)?;
+    let link = new_link(
+        citizen,
+        TokenKind::TelegramEmail,
+        "/telegrams/confirm",
+        config.link_valid_secs,
+        database,
+        config,
+    )?;
+
+    mailer.send(Email {
+        to: citizen.email.clone(),
+        subject: "Receive telegrams by email".to_string(),
+        body: format!(
+            "Hello, {}.\n\nFollow this link to have telegrams you receive while offline emailed to you:\n\n{link}\n\nIf this wasn't you, you can ignore this email.\n",
+            citizen.name,
+        ),
+    });
+
+    Ok(())
+}
+
+/// Make a link which stops telegrams being emailed to a citizen. Only the newest link works.
+pub fn telegram_unsubscribe_link(
+    citizen: &CitizenQuery,
+    database: &Database,
+    config: &AccountConfig,
+) -> Result<String, ReasonCode> {
+    database.token_revoke(citizen.id, TokenKind::Unsubscribe)?;
+    new_link(
+        citizen,
+        TokenKind::Unsubscribe,
+        "/telegrams/unsubscribe",
+        UNSUBSCRIBE_VALID_SECS,
+        database,
+        config,
+    )
+}
+
+pub fn password_reset_email(citizen: &CitizenQuery, link: &str) -> Email {
+    Email {
+        to: citizen.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hello, {}.\n\nSomeone asked to reset the password of your citizenship. Follow this link to choose a new one:\n\n{link}\n\nIf this wasn't you, you can ignore this email.\n",
+            citizen.name,
+        ),
+    }
+}
+
+/// Store a new token for a citizen and build the link which uses it.
+fn new_link(
+    citizen: &CitizenQuery,
+    kind: TokenKind,
+    path: &str,
+    valid_secs: u32,
+    database: &Database,
+    config: &AccountConfig,
+) -> Result<String, ReasonCode> {
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LEN)
+        .map(char::from)
+        .collect();
+    let expiration = unix_time().saturating_add(valid_secs);
+    database.token_add(&token, kind, citizen.id, expiration)?;
+
+    Ok(format!(
+        "{}{path}?token={token}",
+        config.public_url.trim_end_matches('/')
+    ))
+}
+
+/// A page asking the citizen to confirm using the token in the link they followed.
+fn confirm_form(request: &Request, text: &str) -> String {
+    let token = request.query.get("token").map(String::as_str);
+    page(&format!(
+        "<form method=\"post\">\
+        <input type=\"hidden\" name=\"token\" value=\"{}\">\
+        <p>{text} <button>Confirm</button></p>\
+        </form>",
+        escape(token.unwrap_or_default())
+    ))
+}
+
+fn message(text: &str) -> String {
+    page(&format!("<p>{text}</p>"))
+}
+
+fn page(content: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><title>Universe</title></head><body>{content}</body></html>"
+    )
+}
+
+/// Make text safe to put in an HTML attribute.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unix_time() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_reset_throttle() {
+        let mut throttle = ResetThrottle::default();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let other_ip: IpAddr = "192.0.2.2".parse().unwrap();
+        let interval = Duration::from_secs(300);
+
+        assert!(throttle.allow_ip(ip, interval));
+        assert!(!throttle.allow_ip(ip, interval));
+        assert!(throttle.allow_ip(other_ip, interval));
+
+        assert!(throttle.allow_citizen(2, interval));
+        assert!(!throttle.allow_citizen(2, interval));
+        assert!(throttle.allow_citizen(3, interval));
+
+        // No interval means no limit
+        assert!(throttle.allow_ip(ip, Duration::ZERO));
+        assert!(throttle.allow_ip(ip, Duration::ZERO));
+    }
+}
@@ -0,0 +1,55 @@
+//! Admin routes for scheduled announcements.
+use std::net::TcpStream;
+
+use serde_json::{json, Value};
+
+use super::http::{respond, respond_json, Request};
+use crate::{announcements::Announcements, config::AnnouncementConfig};
+
+pub fn handle(
+    stream: &mut TcpStream,
+    request: &Request,
+    announcement_id: Option<&str>,
+    announcements: &mut Announcements,
+) {
+    match (request.method.as_str(), announcement_id) {
+        ("GET", None) => {
+            let list: Vec<Value> = announcements
+                .scheduled()
+                .iter()
+                .map(|scheduled| {
+                    json!({
+                        "id": scheduled.id,
+                        "message": scheduled.announcement.message,
+                        "at": scheduled.announcement.at,
+                        "daily_at": scheduled.announcement.daily_at,
+                        "interval_secs": scheduled.announcement.interval_secs,
+                        "next": scheduled.next,
+                    })
+                })
+                .collect();
+            respond_json(stream, 200, &Value::Array(list));
+        }
+        ("POST", None) => {
+            let announcement: AnnouncementConfig = match serde_json::from_slice(&request.body) {
+                Ok(x) => x,
+                Err(err) => return respond_json(stream, 400, &json!({ "error": err.to_string() })),
+            };
+            match announcements.add(announcement) {
+                Ok(id) => {
+                    log::info!("Admin interface scheduled announcement {id}");
+                    respond_json(stream, 201, &json!({ "id": id }));
+                }
+                Err(err) => respond_json(stream, 400, &json!({ "error": err })),
+            }
+        }
+        ("DELETE", Some(id)) => match id.parse() {
+            Ok(id) if announcements.remove(id) => {
+                log::info!("Admin interface removed announcement {id}");
+                respond_json(stream, 200, &json!({ "id": id }));
+            }
+            _ => respond(stream, 404, "text/plain", b"No such announcement"),
+        },
+        _ => respond(stream, 405, "text/plain", b"Method not allowed"),
+    }
+}
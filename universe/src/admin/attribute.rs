@@ -0,0 +1,72 @@
+//! Admin routes for universe attributes.
+use std::net::TcpStream;
+
+use aw_core::ReasonCode;
+use num_traits::FromPrimitive;
+use serde_json::{json, Map, Value};
+
+use super::http::{respond, respond_json, Request};
+use crate::{
+    attributes, audit,
+    client::ClientManager,
+    database::{attrib::Attribute, AttribDB, Database},
+};
+
+pub fn handle(
+    stream: &mut TcpStream,
+    request: &Request,
+    attribute_id: Option<&str>,
+    database: &Database,
+    client_manager: &ClientManager,
+    actor: &str,
+) {
+    match (request.method.as_str(), attribute_id) {
+        ("GET", None) => match database.attrib_get() {
+            Ok(values) => {
+                let mut map = Map::new();
+                for (attribute, value) in values {
+                    map.insert(
+                        (attribute as u32).to_string(),
+                        json!({ "name": format!("{attribute:?}"), "value": value }),
+                    );
+                }
+                respond_json(stream, 200, &Value::Object(map));
+            }
+            Err(rc) => respond_json(stream, 500, &json!({ "error": format!("{rc:?}") })),
+        },
+        ("PUT", Some(id)) => {
+            let attribute = match id.parse().ok().and_then(Attribute::from_u32) {
+                Some(x) => x,
+                None => return respond(stream, 404, "text/plain", b"No such attribute"),
+            };
+            let value = String::from_utf8_lossy(&request.body).to_string();
+            let before = database
+                .attrib_get()
+                .ok()
+                .and_then(|mut values| values.remove(&attribute));
+
+            match attributes::set_attribute(attribute, &value, database) {
+                Ok(()) => {
+                    log::info!("Admin interface set {attribute:?} to {value:?}");
+                    audit::record(
+                        database,
+                        actor,
+                        "attribute_change",
+                        &format!("attribute:{attribute:?}"),
+                        before.into(),
+                        value.clone().into(),
+                    );
+                    for client in client_manager.clients() {
+                        attributes::send_attributes(client, database);
+                    }
+                    respond_json(stream, 200, &json!({ "value": value }));
+                }
+                Err(ReasonCode::InvalidAttribute) => {
+                    respond_json(stream, 400, &json!({ "error": "Invalid value" }))
+                }
+                Err(rc) => respond_json(stream, 500, &json!({ "error": format!("{rc:?}") })),
+            }
+        }
+        _ => respond(stream, 405, "text/plain", b"Method not allowed"),
+    }
+}
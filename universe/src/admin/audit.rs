@@ -0,0 +1,49 @@
+//! Admin route for the audit log of administrative actions.
+use std::net::TcpStream;
+
+use serde_json::{json, Value};
+
+use super::http::{respond_json, Request};
+use crate::database::{AuditDB, Database};
+
+/// Number of entries listed when no limit is asked for.
+const DEFAULT_LIMIT: u32 = 100;
+
+/// Most entries listed at once.
+const MAX_LIMIT: u32 = 1000;
+
+pub fn list(stream: &mut TcpStream, request: &Request, database: &Database) {
+    let filter = |name: &str| request.query.get(name).map(String::as_str).unwrap_or("");
+    let limit = request
+        .query
+        .get("limit")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(DEFAULT_LIMIT)
+        .min(MAX_LIMIT);
+
+    match database.audit_list(filter("actor"), filter("action"), filter("target"), limit) {
+        Ok(entries) => {
+            let list: Vec<Value> = entries
+                .into_iter()
+                .map(|entry| {
+                    json!({
+                        "id": entry.id,
+                        "time": entry.timestamp,
+                        "actor": entry.actor,
+                        "action": entry.action,
+                        "target": entry.target,
+                        "before": parse(&entry.before),
+                        "after": parse(&entry.after),
+                    })
+                })
+                .collect();
+            respond_json(stream, 200, &Value::Array(list));
+        }
+        Err(rc) => respond_json(stream, 500, &json!({ "error": format!("{rc:?}") })),
+    }
+}
+
+/// Values are stored as JSON text, and empty where the target did not exist.
+fn parse(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or(Value::Null)
+}
@@ -0,0 +1,321 @@
+//! Admin routes for citizen accounts.
+use std::{
+    net::TcpStream,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::http::{respond, respond_json, Request};
+use crate::{
+    accounts,
+    audit::{self, citizen_json, citizen_target},
+    client::ClientManager,
+    config::AccountConfig,
+    database::{citizen::CitizenQuery, password, CitizenDB, Database},
+    email::Mailer,
+    packet_handler,
+};
+
+/// Citizen fields which may be set through the admin interface. Anything left out
+/// is unchanged, or takes its default for a new citizen.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct CitizenFields {
+    name: Option<String>,
+    password: Option<String>,
+    email: Option<String>,
+    priv_pass: Option<String>,
+    comment: Option<String>,
+    url: Option<String>,
+    expiration: Option<u32>,
+    bot_limit: Option<u32>,
+    beta: Option<bool>,
+    enabled: Option<bool>,
+    trial: Option<bool>,
+    privacy: Option<u32>,
+    cav_enabled: Option<bool>,
+    cav_template: Option<u32>,
+    multi_login: Option<bool>,
+}
+
+impl CitizenFields {
+    fn apply(self, citizen: &mut CitizenQuery) -> Result<(), String> {
+        let flag = |value: Option<bool>, current: u32| value.map(u32::from).unwrap_or(current);
+        let hashed = |rc| format!("Could not hash the password: {rc:?}");
+
+        citizen.name = self.name.unwrap_or(citizen.name.clone());
+        if let Some(new_password) = self.password {
+            citizen.password = password::prepare_password(&new_password).map_err(hashed)?;
+        }
+        citizen.email = self.email.unwrap_or(citizen.email.clone());
+        if let Some(priv_pass) = self.priv_pass {
+            citizen.priv_pass = password::prepare_privilege_password(&priv_pass).map_err(hashed)?;
+        }
+        citizen.comment = self.comment.unwrap_or(citizen.comment.clone());
+        citizen.url = self.url.unwrap_or(citizen.url.clone());
+        citizen.expiration = self.expiration.unwrap_or(citizen.expiration);
+        citizen.bot_limit = self.bot_limit.unwrap_or(citizen.bot_limit);
+        citizen.beta = flag(self.beta, citizen.beta);
+        citizen.enabled = flag(self.enabled, citizen.enabled);
+        citizen.trial = flag(self.trial, citizen.trial);
+        citizen.privacy = self.privacy.unwrap_or(citizen.privacy);
+        citizen.cav_enabled = flag(self.cav_enabled, citizen.cav_enabled);
+        citizen.cav_template = self.cav_template.unwrap_or(citizen.cav_template);
+        citizen.multi_login = flag(self.multi_login, citizen.multi_login);
+
+        Ok(())
+    }
+}
+
+pub fn handle(
+    stream: &mut TcpStream,
+    request: &Request,
+    citizen_id: Option<&str>,
+    database: &Database,
+    client_manager: &ClientManager,
+    actor: &str,
+) {
+    let citizen_id: Option<u32> = match citizen_id.map(str::parse) {
+        Some(Ok(x)) => Some(x),
+        Some(Err(_)) => return respond(stream, 400, "text/plain", b"Invalid citizen number"),
+        None => None,
+    };
+
+    match (request.method.as_str(), citizen_id) {
+        ("GET", None) => match database.citizen_all() {
+            Ok(citizens) => {
+                let list: Vec<Value> = citizens.iter().map(citizen_json).collect();
+                respond_json(stream, 200, &Value::Array(list));
+            }
+            Err(rc) => respond_json(stream, 500, &json!({ "error": format!("{rc:?}") })),
+        },
+        ("GET", Some(id)) => match database.citizen_by_number(id) {
+            Ok(citizen) => respond_json(stream, 200, &citizen_json(&citizen)),
+            Err(_) => respond(stream, 404, "text/plain", b"No such citizen"),
+        },
+        ("POST", None) => {
+            let fields: CitizenFields = match serde_json::from_slice(&request.body) {
+                Ok(x) => x,
+                Err(err) => return respond_json(stream, 400, &json!({ "error": err.to_string() })),
+            };
+            match add_citizen(fields, database) {
+                Ok(citizen) => {
+                    log::info!(
+                        "Admin interface added citizen {} (#{})",
+                        citizen.name,
+                        citizen.id
+                    );
+                    audit::record(
+                        database,
+                        actor,
+                        "citizen_add",
+                        &citizen_target(citizen.id),
+                        Value::Null,
+                        citizen_json(&citizen),
+                    );
+                    respond_json(stream, 201, &citizen_json(&citizen));
+                }
+                Err(err) => respond_json(stream, 400, &json!({ "error": err })),
+            }
+        }
+        ("PUT", Some(id)) => {
+            let fields: CitizenFields = match serde_json::from_slice(&request.body) {
+                Ok(x) => x,
+                Err(err) => return respond_json(stream, 400, &json!({ "error": err.to_string() })),
+            };
+            let before = database.citizen_by_number(id);
+            match change_citizen(id, fields, database) {
+                Ok(citizen) => {
+                    log::info!("Admin interface changed citizen {} (#{id})", citizen.name);
+                    audit::record(
+                        database,
+                        actor,
+                        "citizen_change",
+                        &citizen_target(id),
+                        before.as_ref().map(citizen_json).unwrap_or_default(),
+                        citizen_json(&citizen),
+                    );
+                    respond_json(stream, 200, &citizen_json(&citizen));
+                }
+                Err(err) => respond_json(stream, 400, &json!({ "error": err })),
+            }
+        }
+        ("DELETE", Some(id)) => {
+            match packet_handler::remove_citizen(id, database, client_manager) {
+                Ok(citizen) => {
+                    log::info!("Admin interface deleted citizen {} (#{id})", citizen.name);
+                    audit::record(
+                        database,
+                        actor,
+                        "citizen_delete",
+                        &citizen_target(id),
+                        citizen_json(&citizen),
+                        Value::Null,
+                    );
+                    respond_json(stream, 200, &citizen_json(&citizen));
+                }
+                Err(rc) => respond_json(stream, 400, &json!({ "error": format!("{rc:?}") })),
+            }
+        }
+        _ => respond(stream, 405, "text/plain", b"Method not allowed"),
+    }
+}
+
+/// Let a citizen who was locked out by failed logins log in again.
+pub fn unlock(stream: &mut TcpStream, citizen_id: &str, database: &Database, actor: &str) {
+    let citizen = match citizen_id.parse().map(|id| database.citizen_by_number(id)) {
+        Ok(Ok(x)) => x,
+        Ok(Err(_)) => return respond(stream, 404, "text/plain", b"No such citizen"),
+        Err(_) => return respond(stream, 400, "text/plain", b"Invalid citizen number"),
+    };
+
+    match database.citizen_clear_failed_logins(citizen.id) {
+        Ok(()) => {
+            log::info!(
+                "Admin interface unlocked citizen {} (#{})",
+                citizen.name,
+                citizen.id
+            );
+            audit::record(
+                database,
+                actor,
+                "citizen_unlock",
+                &citizen_target(citizen.id),
+                Value::Null,
+                Value::Null,
+            );
+            respond_json(stream, 200, &citizen_json(&citizen));
+        }
+        Err(rc) => respond_json(stream, 500, &json!({ "error": format!("{rc:?}") })),
+    }
+}
+
+/// Make a link for a citizen to choose a new password, emailing it to them if possible.
+/// The link is returned too, so it can be passed on some other way.
+pub fn reset_password(
+    stream: &mut TcpStream,
+    citizen_id: &str,
+    database: &Database,
+    mailer: Option<&Mailer>,
+    config: &AccountConfig,
+    actor: &str,
+) {
+    let citizen = match citizen_id.parse().map(|id| database.citizen_by_number(id)) {
+        Ok(Ok(x)) => x,
+        Ok(Err(_)) => return respond(stream, 404, "text/plain", b"No such citizen"),
+        Err(_) => return respond(stream, 400, "text/plain", b"Invalid citizen number"),
+    };
+
+    let link = match accounts::password_reset_link(&citizen, database, config) {
+        Ok(x) => x,
+        Err(rc) => return respond_json(stream, 500, &json!({ "error": format!("{rc:?}") })),
+    };
+
+    let emailed = match mailer {
+        Some(mailer) if !citizen.email.is_empty() => {
+            mailer.send(accounts::password_reset_email(&citizen, &link));
+            true
+        }
+        _ => false,
+    };
+
+    log::info!(
+        "Admin interface made a password reset link for {} (#{})",
+        citizen.name,
+        citizen.id
+    );
+    audit::record(
+        database,
+        actor,
+        "citizen_reset_password",
+        &citizen_target(citizen.id),
+        Value::Null,
+        json!({ "emailed": emailed }),
+    );
+    respond_json(stream, 200, &json!({ "link": link, "emailed": emailed }));
+}
+
+fn add_citizen(fields: CitizenFields, database: &Database) -> Result<CitizenQuery, String> {
+    let name = fields.name.clone().unwrap_or_default();
+    if name.is_empty() || fields.password.as_deref().unwrap_or_default().is_empty() {
+        return Err("A name and password are required".to_string());
+    }
+    if database.citizen_by_name(&name).is_ok() {
+        return Err(format!("The name {name} is already used"));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs() as u32;
+
+    let mut citizen = CitizenQuery {
+        id: database
+            .citizen_next_number()
+            .map_err(|rc| format!("{rc:?}"))?,
+        changed: 0,
+        name,
+        password: String::new(),
+        email: String::new(),
+        priv_pass: String::new(),
+        comment: String::new(),
+        url: String::new(),
+        immigration: now,
+        expiration: 0,
+        last_login: 0,
+        last_address: 0,
+        total_time: 0,
+        bot_limit: 0,
+        beta: 0,
+        enabled: 1,
+        trial: 0,
+        privacy: 0,
+        cav_enabled: 0,
+        cav_template: 0,
+        multi_login: 0,
+    };
+    fields.apply(&mut citizen)?;
+
+    database
+        .citizen_add(&citizen)
+        .map_err(|rc| format!("{rc:?}"))?;
+    database
+        .citizen_by_number(citizen.id)
+        .map_err(|rc| format!("{rc:?}"))
+}
+
+fn change_citizen(
+    citizen_id: u32,
+    fields: CitizenFields,
+    database: &Database,
+) -> Result<CitizenQuery, String> {
+    let mut citizen = database
+        .citizen_by_number(citizen_id)
+        .map_err(|_| "No such citizen".to_string())?;
+
+    if let Some(name) = &fields.name {
+        if name.is_empty() {
+            return Err("The name cannot be empty".to_string());
+        }
+        match database.citizen_by_name(name) {
+            Ok(other) if other.id != citizen_id => {
+                return Err(format!("The name {name} is already used"))
+            }
+            _ => {}
+        }
+    }
+    if fields.password.as_deref() == Some("") {
+        return Err("The password cannot be empty".to_string());
+    }
+
+    fields.apply(&mut citizen)?;
+
+    database
+        .citizen_change(&citizen)
+        .map_err(|rc| format!("{rc:?}"))?;
+    database
+        .citizen_by_number(citizen_id)
+        .map_err(|rc| format!("{rc:?}"))
+}
@@ -0,0 +1,104 @@
+//! Admin routes for citizen credit balances and their ledger.
+use std::net::TcpStream;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::http::{respond, respond_json, Request};
+use crate::{
+    audit::{self, citizen_target},
+    config::CreditConfig,
+    database::{CreditDB, Database},
+};
+
+/// Number of ledger entries shown with a balance.
+const HISTORY_LIMIT: u32 = 100;
+
+#[derive(Deserialize)]
+struct Adjustment {
+    amount: i64,
+    #[serde(default)]
+    reason: String,
+}
+
+pub fn handle(
+    stream: &mut TcpStream,
+    request: &Request,
+    citizen_id: &str,
+    database: &Database,
+    config: &CreditConfig,
+    actor: &str,
+) {
+    if !config.enabled {
+        return respond(stream, 404, "text/plain", b"Credits are not enabled");
+    }
+
+    let citizen_id: u32 = match citizen_id.parse() {
+        Ok(x) => x,
+        Err(_) => return respond(stream, 400, "text/plain", b"Invalid citizen number"),
+    };
+
+    match request.method.as_str() {
+        "GET" => {
+            let result = database.credit_balance(citizen_id).and_then(|balance| {
+                Ok((balance, database.credit_history(citizen_id, HISTORY_LIMIT)?))
+            });
+            match result {
+                Ok((balance, history)) => {
+                    let ledger: Vec<Value> = history
+                        .into_iter()
+                        .map(|entry| {
+                            json!({
+                                "id": entry.id,
+                                "amount": entry.amount,
+                                "balance": entry.balance,
+                                "actor": entry.actor,
+                                "reason": entry.reason,
+                                "time": entry.timestamp,
+                            })
+                        })
+                        .collect();
+                    respond_json(
+                        stream,
+                        200,
+                        &json!({ "balance": balance, "ledger": ledger }),
+                    );
+                }
+                Err(rc) => respond_json(stream, 500, &json!({ "error": format!("{rc:?}") })),
+            }
+        }
+        "POST" => {
+            let adjustment: Adjustment = match serde_json::from_slice(&request.body) {
+                Ok(x) => x,
+                Err(err) => return respond_json(stream, 400, &json!({ "error": err.to_string() })),
+            };
+            let result = database.credit_adjust(
+                citizen_id,
+                adjustment.amount,
+                config.allow_negative,
+                actor,
+                &adjustment.reason,
+            );
+            match result {
+                Ok(balance) => {
+                    log::info!(
+                        "{actor} adjusted the credits of citizen {citizen_id} by {} to {balance} ({})",
+                        adjustment.amount,
+                        adjustment.reason
+                    );
+                    audit::record(
+                        database,
+                        actor,
+                        "credit_adjust",
+                        &citizen_target(citizen_id),
+                        json!({ "balance": balance - adjustment.amount }),
+                        json!({ "balance": balance, "reason": adjustment.reason }),
+                    );
+                    respond_json(stream, 200, &json!({ "balance": balance }));
+                }
+                Err(rc) => respond_json(stream, 400, &json!({ "error": format!("{rc:?}") })),
+            }
+        }
+        _ => respond(stream, 405, "text/plain", b"Method not allowed"),
+    }
+}
@@ -0,0 +1,265 @@
+//! Just enough HTTP for the admin interface and the account pages.
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+/// Largest request accepted, in bytes.
+const MAX_REQUEST_LEN: usize = 8192;
+
+/// How long a client has to send its whole request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Most connections which may be waiting to finish their requests at once.
+const MAX_PENDING_REQUESTS: usize = 64;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// Header names are lowercase.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// A connection whose request has not all arrived yet.
+pub struct PendingRequest {
+    stream: TcpStream,
+    addr: SocketAddr,
+    data: Vec<u8>,
+    accepted: Instant,
+}
+
+impl PendingRequest {
+    fn new(stream: TcpStream, addr: SocketAddr) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            addr,
+            data: Vec::new(),
+            accepted: Instant::now(),
+        })
+    }
+
+    /// Read whatever has arrived without waiting, giving the request once it is complete.
+    fn poll(&mut self) -> Result<Option<Request>, String> {
+        let mut buf = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err("Connection closed".to_string()),
+                Ok(n) => self.data.extend(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.to_string()),
+            }
+            // Room for the longest head and the longest body
+            if self.data.len() > MAX_REQUEST_LEN * 2 {
+                return Err("Request too long".to_string());
+            }
+        }
+
+        match Request::parse(&self.data)? {
+            Some(request) => {
+                // Responses are written in one go
+                self.stream
+                    .set_nonblocking(false)
+                    .and_then(|_| self.stream.set_write_timeout(Some(REQUEST_TIMEOUT)))
+                    .map_err(|err| err.to_string())?;
+                Ok(Some(request))
+            }
+            None if self.accepted.elapsed() >= REQUEST_TIMEOUT => {
+                Err("Request took too long".to_string())
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Accept new connections without waiting for their requests.
+pub fn accept(listener: &TcpListener, pending: &mut Vec<PendingRequest>) {
+    while let Ok((stream, addr)) = listener.accept() {
+        if pending.len() >= MAX_PENDING_REQUESTS {
+            log::warn!(
+                "Dropping a connection from {} as too many are waiting",
+                addr.ip()
+            );
+            continue;
+        }
+        match PendingRequest::new(stream, addr) {
+            Ok(x) => pending.push(x),
+            Err(err) => log::debug!("Could not accept a connection from {}: {err}", addr.ip()),
+        }
+    }
+}
+
+/// Take the requests which have arrived in full. Connections which sent a bad request,
+/// or did not send all of it in time, are answered and dropped.
+pub fn take_requests(
+    pending: &mut Vec<PendingRequest>,
+    what: &str,
+) -> Vec<(TcpStream, SocketAddr, Request)> {
+    let mut ready = Vec::new();
+    for mut connection in std::mem::take(pending) {
+        match connection.poll() {
+            Ok(Some(request)) => ready.push((connection.stream, connection.addr, request)),
+            Ok(None) => pending.push(connection),
+            Err(err) => {
+                log::debug!("Bad {what} request from {}: {err}", connection.addr.ip());
+                respond(&mut connection.stream, 400, "text/plain", b"Bad request");
+            }
+        }
+    }
+    ready
+}
+
+impl Request {
+    /// Parse a request from the data received so far, or `None` if it is not all there yet.
+    pub fn parse(data: &[u8]) -> Result<Option<Self>, String> {
+        let head_len = match data.windows(4).position(|x| x == b"\r\n\r\n") {
+            Some(pos) => pos + 4,
+            None if data.len() > MAX_REQUEST_LEN => return Err("Request too long".to_string()),
+            None => return Ok(None),
+        };
+
+        let head = String::from_utf8_lossy(&data[..head_len]).to_string();
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next().unwrap_or_default().split(' ');
+        let method = request_line.next().unwrap_or_default().to_string();
+        let target = request_line.next().unwrap_or_default();
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), parse_query(query)),
+            None => (target.to_string(), HashMap::new()),
+        };
+
+        let headers: HashMap<String, String> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .collect();
+
+        let content_len: usize = headers
+            .get("content-length")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0);
+        if content_len > MAX_REQUEST_LEN {
+            return Err("Request body too long".to_string());
+        }
+
+        let body = match data.get(head_len..head_len + content_len) {
+            Some(body) => body.to_vec(),
+            None => return Ok(None),
+        };
+
+        Ok(Some(Self {
+            method,
+            path,
+            query,
+            headers,
+            body,
+        }))
+    }
+
+    /// Fields of a form posted as `application/x-www-form-urlencoded`.
+    pub fn form(&self) -> HashMap<String, String> {
+        parse_query(&String::from_utf8_lossy(&self.body))
+    }
+
+    /// Get the bearer token from the Authorization header or the token query parameter.
+    pub fn token(&self) -> Option<&str> {
+        self.headers
+            .get("authorization")
+            .and_then(|x| x.strip_prefix("Bearer "))
+            .or_else(|| self.query.get("token").map(String::as_str))
+    }
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+        .map(|(key, value)| (percent_decode(key), percent_decode(value)))
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut result = Vec::<u8>::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match u8::from_str_radix(&s[i + 1..i + 3], 16) {
+                Ok(x) => {
+                    result.push(x);
+                    i += 3;
+                    continue;
+                }
+                Err(_) => result.push(b'%'),
+            },
+            b'+' => result.push(b' '),
+            x => result.push(x),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+/// Write a JSON response.
+pub fn respond_json(stream: &mut TcpStream, status: u16, value: &serde_json::Value) {
+    respond(
+        stream,
+        status,
+        "application/json",
+        value.to_string().as_bytes(),
+    );
+}
+
+/// Write a complete response and close the connection.
+pub fn respond(stream: &mut TcpStream, status: u16, content_type: &str, body: &[u8]) {
+    let head = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status_text(status),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).ok();
+    stream.write_all(body).ok();
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        405 => "Method Not Allowed",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_parse() {
+        let data = b"POST /reset?x=1 HTTP/1.1\r\nContent-Length: 9\r\n\r\nname=Test";
+        let request = Request::parse(data).unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/reset");
+        assert_eq!(request.query.get("x").map(String::as_str), Some("1"));
+        assert_eq!(request.form().get("name").map(String::as_str), Some("Test"));
+
+        // Nothing is handled until the whole body is there
+        for end in 0..data.len() {
+            assert!(Request::parse(&data[..end]).unwrap().is_none(), "{end}");
+        }
+    }
+
+    #[test]
+    pub fn test_parse_too_long() {
+        assert!(Request::parse(&vec![b'a'; MAX_REQUEST_LEN + 1]).is_err());
+        assert!(Request::parse(b"POST / HTTP/1.1\r\nContent-Length: 100000\r\n\r\n").is_err());
+    }
+}
@@ -0,0 +1,381 @@
+//! HTTP interface for operators.
+//!
+//! Every request must carry the configured token, either as
+//! `Authorization: Bearer <token>` or as a `token` query parameter.
+//!
+//! - `GET /` serves a web dashboard built on the routes below, if it is enabled.
+//!   Open it as `/?token=<token>`.
+//! - `GET /events` streams universe events as server-sent events.
+//! - `GET /packets` streams every packet sent and received as server-sent events.
+//! - `GET /citizens` lists citizens, and `GET /citizens/<id>` shows one.
+//! - `POST /citizens` adds a citizen from JSON fields, `PUT /citizens/<id>` changes
+//!   the fields given, and `DELETE /citizens/<id>` deletes one.
+//! - `POST /citizens/<id>/reset` makes a password reset link for a citizen, and
+//!   emails it to them if they have an address.
+//! - `GET /citizens/<id>/profile` lists a citizen's profile fields.
+//! - `PUT /citizens/<id>/profile/<name>` sets a profile field to the request body.
+//! - `DELETE /citizens/<id>/profile/<name>` removes a profile field.
+//! - `GET /citizens/<id>/credits` shows a citizen's balance and recent ledger.
+//! - `POST /citizens/<id>/credits` adjusts a balance by `{"amount": n, "reason": "..."}`.
+//! - `GET /metrics` shows counters in the Prometheus text format.
+//! - `GET /worlds` lists attached world servers with their builds.
+//! - `GET /attributes` lists universe attributes, and `PUT /attributes/<id>` sets one
+//!   to the request body.
+//! - `GET /clients` lists connected clients, and `DELETE /clients/<session>`
+//!   disconnects a user.
+//! - `GET /audit` lists administrative actions, newest first. It takes `actor`,
+//!   `action` and `target` query parameters to filter by, and `limit`.
+//! - `GET /stats/urls` lists the URLs sent to users, such as the news page, with how
+//!   often each was sent and followed, most followed first.
+//! - `GET /announcements` lists scheduled announcements, `POST /announcements`
+//!   schedules one from JSON fields, and `DELETE /announcements/<id>` removes one.
+mod announcement;
+mod attribute;
+mod audit;
+mod citizen;
+mod credit;
+pub(crate) mod http;
+mod profile;
+mod session;
+mod stats;
+mod world;
+
+use std::{
+    io::{ErrorKind, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{channel, Receiver},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use aw_core::{set_packet_tap, Direction, PacketTrace};
+use serde_json::json;
+
+use crate::{
+    announcements::Announcements,
+    client::ClientManager,
+    config::{AdminConfig, Config},
+    database::Database,
+    email::Mailer,
+    events::{EventQueue, TimedEvent, UniverseEvent},
+    metrics::Metrics,
+};
+use http::{respond, PendingRequest, Request};
+
+/// The web dashboard, which uses the routes of the admin interface from the browser.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// How often to write to idle event streams, to notice when they close.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Most packets to hold for packet stream subscribers between services.
+const MAX_PACKETS_PER_SERVICE: usize = 10_000;
+
+/// The kinds of live stream which can be watched.
+#[derive(Debug, Clone, Copy)]
+enum Feed {
+    Events,
+    Packets,
+}
+
+/// The parts of the universe which admin requests look at and change.
+pub struct AdminContext<'a> {
+    pub queue: &'a EventQueue,
+    pub database: &'a Database,
+    pub client_manager: &'a ClientManager,
+    pub config: &'a Config,
+    pub metrics: &'a Metrics,
+    pub announcements: &'a mut Announcements,
+    pub mailer: Option<&'a Mailer>,
+}
+
+pub struct AdminServer {
+    listener: TcpListener,
+    /// Connections still sending their requests
+    pending: Vec<PendingRequest>,
+    token: String,
+    dashboard: bool,
+    event_subscribers: Vec<TcpStream>,
+    packet_subscribers: Vec<TcpStream>,
+    /// Packets from the protocol threads, while anyone is watching them
+    packets: Option<Receiver<PacketTrace>>,
+    last_keepalive: Instant,
+}
+
+impl AdminServer {
+    /// Start listening if the admin interface is enabled and has a token.
+    pub fn new(config: &AdminConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        if config.token.is_empty() {
+            log::warn!(
+                "The admin interface is enabled but has no token, so it will not be started"
+            );
+            return None;
+        }
+
+        let addr = SocketAddr::new(config.ip, config.port);
+        let listener = match TcpListener::bind(addr) {
+            Ok(x) => x,
+            Err(err) => {
+                log::error!("Could not start the admin interface on {addr}: {err}");
+                return None;
+            }
+        };
+        listener.set_nonblocking(true).ok()?;
+        log::info!("Admin interface listening on {addr}");
+
+        Some(Self {
+            listener,
+            pending: Vec::new(),
+            token: config.token.clone(),
+            dashboard: config.dashboard,
+            event_subscribers: Vec::new(),
+            packet_subscribers: Vec::new(),
+            packets: None,
+            last_keepalive: Instant::now(),
+        })
+    }
+
+    /// Handle new requests and send events to anyone watching.
+    pub fn service(&mut self, events: &[TimedEvent], ctx: &mut AdminContext) {
+        http::accept(&self.listener, &mut self.pending);
+        for (stream, addr, request) in http::take_requests(&mut self.pending, "admin") {
+            self.handle_request(stream, addr, request, ctx);
+        }
+
+        self.broadcast(events);
+    }
+
+    /// Format packets which have been traced since the last service as stream messages.
+    fn take_packet_messages(&mut self) -> String {
+        let mut message = String::new();
+        let packets = match &self.packets {
+            Some(x) => x,
+            None => return message,
+        };
+
+        for trace in packets.try_iter().take(MAX_PACKETS_PER_SERVICE) {
+            let time = trace
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let direction = match trace.direction {
+                Direction::Sent => "sent",
+                Direction::Received => "received",
+            };
+            let hex: String = trace.data.iter().map(|b| format!("{b:02x}")).collect();
+            let value = json!({
+                "time": time,
+                "peer": trace.peer.map(|x| x.to_string()),
+                "direction": direction,
+                "data": hex,
+            });
+            message.push_str(&format!("data: {value}\n\n"));
+        }
+
+        message
+    }
+
+    fn handle_request(
+        &mut self,
+        mut stream: TcpStream,
+        addr: SocketAddr,
+        request: Request,
+        ctx: &mut AdminContext,
+    ) {
+        if request.token() != Some(self.token.as_str()) {
+            log::warn!(
+                "Admin request from {} for {} had the wrong token",
+                addr.ip(),
+                request.path
+            );
+            ctx.queue.publish(UniverseEvent::AdminAuthFailure {
+                ip: addr.ip(),
+                path: request.path.clone(),
+            });
+            respond(&mut stream, 401, "text/plain", b"Unauthorized");
+            return;
+        }
+
+        // Actions are audited as the address they came from, since everyone shares the token
+        let actor = format!("admin:{}", addr.ip());
+        let segments: Vec<&str> = request.path.split('/').filter(|x| !x.is_empty()).collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", []) if self.dashboard => {
+                respond(&mut stream, 200, "text/html", DASHBOARD.as_bytes())
+            }
+            ("GET", ["events"]) => self.subscribe(stream, addr, Feed::Events),
+            ("GET", ["packets"]) => self.subscribe(stream, addr, Feed::Packets),
+            ("GET", ["metrics"]) => respond(
+                &mut stream,
+                200,
+                "text/plain; version=0.0.4",
+                ctx.metrics
+                    .render(ctx.client_manager, ctx.database)
+                    .as_bytes(),
+            ),
+            ("GET", ["worlds"]) => {
+                world::list(&mut stream, ctx.client_manager, &ctx.config.universe)
+            }
+            ("GET", ["audit"]) => audit::list(&mut stream, &request, ctx.database),
+            ("GET", ["stats", "urls"]) => stats::urls(&mut stream, ctx.database),
+            (_, ["citizens"]) => citizen::handle(
+                &mut stream,
+                &request,
+                None,
+                ctx.database,
+                ctx.client_manager,
+                &actor,
+            ),
+            (_, ["citizens", citizen_id]) => citizen::handle(
+                &mut stream,
+                &request,
+                Some(citizen_id),
+                ctx.database,
+                ctx.client_manager,
+                &actor,
+            ),
+            (_, ["attributes"]) => attribute::handle(
+                &mut stream,
+                &request,
+                None,
+                ctx.database,
+                ctx.client_manager,
+                &actor,
+            ),
+            (_, ["attributes", attribute_id]) => attribute::handle(
+                &mut stream,
+                &request,
+                Some(attribute_id),
+                ctx.database,
+                ctx.client_manager,
+                &actor,
+            ),
+            (_, ["clients"]) => session::handle(
+                &mut stream,
+                &request,
+                None,
+                ctx.client_manager,
+                ctx.database,
+                &actor,
+            ),
+            (_, ["clients", session_id]) => session::handle(
+                &mut stream,
+                &request,
+                Some(session_id),
+                ctx.client_manager,
+                ctx.database,
+                &actor,
+            ),
+            (_, ["announcements"]) => {
+                announcement::handle(&mut stream, &request, None, ctx.announcements)
+            }
+            (_, ["announcements", id]) => {
+                announcement::handle(&mut stream, &request, Some(id), ctx.announcements)
+            }
+            ("POST", ["citizens", citizen_id, "unlock"]) => {
+                citizen::unlock(&mut stream, citizen_id, ctx.database, &actor)
+            }
+            ("POST", ["citizens", citizen_id, "reset"]) => citizen::reset_password(
+                &mut stream,
+                citizen_id,
+                ctx.database,
+                ctx.mailer,
+                &ctx.config.accounts,
+                &actor,
+            ),
+            (_, ["citizens", citizen_id, "profile", ..]) => profile::handle(
+                &mut stream,
+                &request,
+                citizen_id,
+                ctx.database,
+                &ctx.config.profile,
+                &actor,
+            ),
+            (_, ["citizens", citizen_id, "credits"]) => credit::handle(
+                &mut stream,
+                &request,
+                citizen_id,
+                ctx.database,
+                &ctx.config.credits,
+                &actor,
+            ),
+            _ => respond(&mut stream, 404, "text/plain", b"Not found"),
+        }
+    }
+
+    fn subscribe(&mut self, mut stream: TcpStream, addr: SocketAddr, feed: Feed) {
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+        if stream.write_all(head.as_bytes()).is_err() {
+            return;
+        }
+
+        // Subscribers which cannot keep up are dropped rather than holding up the universe
+        stream.set_nonblocking(true).ok();
+        match feed {
+            Feed::Events => {
+                log::info!("{} is watching universe events", addr.ip());
+                self.event_subscribers.push(stream);
+            }
+            Feed::Packets => {
+                log::info!("{} is watching packets", addr.ip());
+                if self.packets.is_none() {
+                    let (tx, rx) = channel();
+                    set_packet_tap(Some(tx));
+                    self.packets = Some(rx);
+                }
+                self.packet_subscribers.push(stream);
+            }
+        }
+    }
+
+    fn broadcast(&mut self, events: &[TimedEvent]) {
+        let mut event_message = String::new();
+        for event in events {
+            if let Ok(json) = serde_json::to_string(event) {
+                event_message.push_str(&format!("data: {json}\n\n"));
+            }
+        }
+        let packet_message = self.take_packet_messages();
+
+        let keepalive = self.last_keepalive.elapsed() >= KEEPALIVE_INTERVAL;
+        if keepalive {
+            self.last_keepalive = Instant::now();
+        }
+
+        send_to_subscribers(&mut self.event_subscribers, event_message, keepalive);
+        send_to_subscribers(&mut self.packet_subscribers, packet_message, keepalive);
+
+        // Stop tracing once nobody is watching, since it is not free
+        if self.packet_subscribers.is_empty() && self.packets.is_some() {
+            set_packet_tap(None);
+            self.packets = None;
+        }
+    }
+}
+
+fn send_to_subscribers(subscribers: &mut Vec<TcpStream>, mut message: String, keepalive: bool) {
+    if subscribers.is_empty() {
+        return;
+    }
+
+    if message.is_empty() {
+        if !keepalive {
+            return;
+        }
+        message.push_str(": keepalive\n\n");
+    }
+
+    subscribers.retain_mut(|stream| match stream.write_all(message.as_bytes()) {
+        Ok(()) => true,
+        Err(err) => {
+            if err.kind() == ErrorKind::WouldBlock {
+                log::info!("Dropping a stream which is not keeping up");
+            }
+            false
+        }
+    });
+}
@@ -0,0 +1,85 @@
+//! Admin routes for citizen profile fields.
+use std::net::TcpStream;
+
+use serde_json::{json, Map, Value};
+
+use super::http::{respond, respond_json, Request};
+use crate::{
+    audit::{self, citizen_target, profile_field_json},
+    config::ProfileConfig,
+    database::{Database, ProfileDB},
+};
+
+pub fn handle(
+    stream: &mut TcpStream,
+    request: &Request,
+    citizen_id: &str,
+    database: &Database,
+    config: &ProfileConfig,
+    actor: &str,
+) {
+    let citizen_id: u32 = match citizen_id.parse() {
+        Ok(x) => x,
+        Err(_) => return respond(stream, 400, "text/plain", b"Invalid citizen number"),
+    };
+
+    // Everything after /citizens/<id>/profile/ is the field name
+    let field = request
+        .path
+        .trim_matches('/')
+        .splitn(4, '/')
+        .nth(3)
+        .map(str::to_string);
+
+    match (request.method.as_str(), field) {
+        ("GET", None) => match database.profile_get_all(citizen_id) {
+            Ok(fields) => {
+                let map: Map<String, Value> = fields
+                    .into_iter()
+                    .map(|field| (field.name, Value::String(field.value)))
+                    .collect();
+                respond_json(stream, 200, &Value::Object(map));
+            }
+            Err(rc) => respond_json(stream, 500, &json!({ "error": format!("{rc:?}") })),
+        },
+        ("PUT", Some(name)) => {
+            let value = String::from_utf8_lossy(&request.body).to_string();
+            let before = profile_field_json(database, citizen_id, &name);
+            let result = config
+                .check_field(&name, &value)
+                .and_then(|_| database.profile_set(citizen_id, &name, &value));
+            match result {
+                Ok(()) => {
+                    audit::record(
+                        database,
+                        actor,
+                        "profile_update",
+                        &citizen_target(citizen_id),
+                        before,
+                        json!({ &name: &value }),
+                    );
+                    respond_json(stream, 200, &json!({ name: value }));
+                }
+                Err(rc) => respond_json(stream, 400, &json!({ "error": format!("{rc:?}") })),
+            }
+        }
+        ("DELETE", Some(name)) => {
+            let before = profile_field_json(database, citizen_id, &name);
+            match database.profile_delete(citizen_id, &name) {
+                Ok(()) => {
+                    audit::record(
+                        database,
+                        actor,
+                        "profile_delete",
+                        &citizen_target(citizen_id),
+                        before,
+                        Value::Null,
+                    );
+                    respond(stream, 200, "text/plain", b"");
+                }
+                Err(rc) => respond_json(stream, 500, &json!({ "error": format!("{rc:?}") })),
+            }
+        }
+        _ => respond(stream, 405, "text/plain", b"Method not allowed"),
+    }
+}
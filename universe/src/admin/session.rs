@@ -0,0 +1,118 @@
+//! Admin routes for connected clients.
+use std::net::TcpStream;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::http::{respond, respond_json, Request};
+use crate::{
+    audit,
+    client::{ClientManager, Entity},
+    database::Database,
+};
+
+pub fn handle(
+    stream: &mut TcpStream,
+    request: &Request,
+    session_id: Option<&str>,
+    client_manager: &ClientManager,
+    database: &Database,
+    actor: &str,
+) {
+    match (request.method.as_str(), session_id) {
+        ("GET", None) => list(stream, client_manager),
+        ("DELETE", Some(session_id)) => {
+            let session_id: u16 = match session_id.parse() {
+                Ok(x) => x,
+                Err(_) => return respond(stream, 400, "text/plain", b"Invalid session number"),
+            };
+            let reason = match request.body.is_empty() {
+                true => KickRequest::default(),
+                false => match serde_json::from_slice(&request.body) {
+                    Ok(x) => x,
+                    Err(err) => {
+                        return respond_json(stream, 400, &json!({ "error": err.to_string() }))
+                    }
+                },
+            };
+            kick(
+                stream,
+                session_id,
+                &reason.reason,
+                client_manager,
+                database,
+                actor,
+            );
+        }
+        _ => respond(stream, 405, "text/plain", b"Method not allowed"),
+    }
+}
+
+fn list(stream: &mut TcpStream, client_manager: &ClientManager) {
+    let clients: Vec<Value> = client_manager
+        .clients()
+        .iter()
+        .map(|client| {
+            let info = client.info();
+            let mut value = json!({
+                "ip": client.addr.ip(),
+                "type": info.client_type.map(|x| format!("{x:?}")),
+                "rtt_ms": client.rtt().map(|rtt| rtt.as_millis() as u64),
+            });
+            match &info.entity {
+                Some(Entity::Player(player)) => {
+                    value["session"] = json!(player.session_id);
+                    value["name"] = json!(player.username);
+                    value["citizen"] = json!(player.citizen_id);
+                    value["privilege"] = json!(player.privilege_id);
+                    value["build"] = json!(player.build);
+                    value["world"] = json!(player.world);
+                    value["online_secs"] = json!(player.session_start.elapsed().as_secs());
+                }
+                Some(Entity::WorldServer(server)) => {
+                    value["build"] = json!(server.build);
+                }
+                None => {}
+            }
+            value
+        })
+        .collect();
+
+    respond_json(stream, 200, &Value::Array(clients));
+}
+
+/// Optional body of a request to disconnect a session.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct KickRequest {
+    /// Shown to the user before they are disconnected
+    reason: String,
+}
+
+fn kick(
+    stream: &mut TcpStream,
+    session_id: u16,
+    reason: &str,
+    client_manager: &ClientManager,
+    database: &Database,
+    actor: &str,
+) {
+    match client_manager.kick_session(session_id, reason) {
+        Ok(client) => {
+            log::info!(
+                "Admin interface disconnected session {session_id} ({})",
+                client.addr.ip()
+            );
+            audit::record(
+                database,
+                actor,
+                "kick",
+                &client.actor(),
+                Value::Null,
+                json!({ "session": session_id, "reason": reason }),
+            );
+            respond_json(stream, 200, &json!({ "session": session_id }));
+        }
+        Err(_) => respond(stream, 404, "text/plain", b"No such session"),
+    }
+}
@@ -0,0 +1,22 @@
+//! Admin route for statistics on URLs sent to users.
+use std::net::TcpStream;
+
+use serde_json::{json, Value};
+
+use super::http::respond_json;
+use crate::database::{Database, StatsDB};
+
+pub fn urls(stream: &mut TcpStream, database: &Database) {
+    let list: Vec<Value> = database
+        .stats_url_get_all()
+        .into_iter()
+        .map(|stats| {
+            json!({
+                "url": stats.url,
+                "sent": stats.sent,
+                "clicks": stats.clicks,
+            })
+        })
+        .collect();
+    respond_json(stream, 200, &Value::Array(list));
+}
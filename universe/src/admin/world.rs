@@ -0,0 +1,37 @@
+//! Admin routes for attached world servers.
+use std::net::TcpStream;
+
+use serde_json::{json, Value};
+
+use super::http::respond_json;
+use crate::{
+    client::{ClientManager, Entity},
+    config::UniverseConfig,
+};
+
+pub fn list(stream: &mut TcpStream, client_manager: &ClientManager, config: &UniverseConfig) {
+    let servers: Vec<Value> = client_manager
+        .clients()
+        .iter()
+        .filter_map(|client| match &client.info().entity {
+            Some(Entity::WorldServer(server)) => {
+                let worlds: Vec<Value> = server
+                    .worlds
+                    .iter()
+                    .map(|world| json!({ "name": world.name, "users": world.user_count }))
+                    .collect();
+                Some(json!({
+                    "ip": client.addr.ip(),
+                    "port": server.server_port,
+                    "build": server.build,
+                    "version": server.version,
+                    "outdated": config.world_server_outdated(server.build),
+                    "worlds": worlds,
+                }))
+            }
+            _ => None,
+        })
+        .collect();
+
+    respond_json(stream, 200, &Value::Array(servers));
+}
@@ -0,0 +1,133 @@
+//! Messages sent to every user's chat window on a schedule, such as reminders
+//! of events. Announcements come from the configuration file, and more can be
+//! added through the admin interface until the universe restarts.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{client::ClientManager, config::AnnouncementConfig};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub struct Scheduled {
+    pub id: u32,
+    pub announcement: AnnouncementConfig,
+    /// Unix time the message is next sent
+    pub next: u64,
+}
+
+#[derive(Default)]
+pub struct Announcements {
+    scheduled: Vec<Scheduled>,
+    next_id: u32,
+}
+
+impl Announcements {
+    pub fn new(config: &[AnnouncementConfig]) -> Self {
+        let mut announcements = Self::default();
+        for announcement in config {
+            if let Err(err) = announcements.add(announcement.clone()) {
+                log::warn!("Ignoring announcement {:?}: {err}", announcement.message);
+            }
+        }
+        announcements
+    }
+
+    /// Schedule an announcement, returning the ID it can be removed by.
+    pub fn add(&mut self, announcement: AnnouncementConfig) -> Result<u32, String> {
+        if announcement.message.is_empty() {
+            return Err("the message is empty".to_string());
+        }
+
+        let next = first_send_time(&announcement, unix_now())?;
+        self.next_id += 1;
+        self.scheduled.push(Scheduled {
+            id: self.next_id,
+            announcement,
+            next,
+        });
+        Ok(self.next_id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        let count = self.scheduled.len();
+        self.scheduled.retain(|x| x.id != id);
+        self.scheduled.len() != count
+    }
+
+    pub fn scheduled(&self) -> &[Scheduled] {
+        &self.scheduled
+    }
+
+    /// Send the announcements which are due, and work out when each is next sent.
+    pub fn send_due(&mut self, client_manager: &ClientManager) {
+        let now = unix_now();
+
+        for scheduled in &mut self.scheduled {
+            if scheduled.next > now {
+                continue;
+            }
+
+            let announcement = &scheduled.announcement;
+            log::info!("Sending announcement {:?}", announcement.message);
+            client_manager
+                .send_console_message_to(None, &announcement.message)
+                .ok();
+
+            scheduled.next = if announcement.interval_secs != 0 {
+                scheduled.next + announcement.interval_secs
+            } else if !announcement.daily_at.is_empty() {
+                scheduled.next + SECONDS_PER_DAY
+            } else {
+                // Sent once, so done with
+                0
+            };
+        }
+
+        self.scheduled.retain(|x| x.next != 0);
+    }
+}
+
+fn first_send_time(announcement: &AnnouncementConfig, now: u64) -> Result<u64, String> {
+    let kinds = [
+        announcement.at != 0,
+        !announcement.daily_at.is_empty(),
+        announcement.interval_secs != 0,
+    ];
+    if kinds.iter().filter(|x| **x).count() != 1 {
+        return Err("exactly one of at, daily_at and interval_secs must be set".to_string());
+    }
+
+    if announcement.at != 0 {
+        if announcement.at <= now {
+            return Err("at is in the past".to_string());
+        }
+        Ok(announcement.at)
+    } else if !announcement.daily_at.is_empty() {
+        let offset = parse_time_of_day(&announcement.daily_at)
+            .ok_or_else(|| format!("{:?} is not a time like 18:30", announcement.daily_at))?;
+        let send_time = now - now % SECONDS_PER_DAY + offset;
+        Ok(match send_time > now {
+            true => send_time,
+            false => send_time + SECONDS_PER_DAY,
+        })
+    } else {
+        Ok(now + announcement.interval_secs)
+    }
+}
+
+/// Seconds into the day of a time written as "HH:MM".
+fn parse_time_of_day(text: &str) -> Option<u64> {
+    let (hours, minutes) = text.split_once(':')?;
+    let hours: u64 = hours.trim().parse().ok()?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    if hours >= 24 || minutes >= 60 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs()
+}
@@ -2,8 +2,50 @@ use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::database::attrib::{AttribDB, Attribute};
+use crate::database::group::GroupRights;
 use crate::database::Database;
 use crate::{AWPacket, Client, PacketType, VarID};
+use aw_core::ReasonCode;
+
+/// Every universe attribute, with the packet variable it is sent and changed with.
+/// `Unknown26` is left out, since ID 26 is `IdentifyUserIP` everywhere else.
+const ATTRIBUTE_VARS: &[(Attribute, VarID)] = &[
+    (Attribute::AllowTourists, VarID::AttributeAllowTourists),
+    (Attribute::UnknownBilling1, VarID::AttributeUnknownBilling1),
+    (Attribute::BetaBrowser, VarID::AttributeBetaBrowser),
+    (Attribute::MinimumBrowser, VarID::AttributeMinimumBrowser),
+    (Attribute::LatestBrowser, VarID::AttributeLatestBrowser),
+    (Attribute::UniverseBuild, VarID::AttributeUniverseBuild),
+    (Attribute::CitizenChanges, VarID::AttributeCitizenChanges),
+    (Attribute::UnknownBilling7, VarID::AttributeUnknownBilling7),
+    (Attribute::RegistrationMethod, VarID::AttributeBillingMethod),
+    (Attribute::BillingUnknown9, VarID::AttributeBillingUnknown9),
+    (Attribute::SearchTabURL, VarID::AttributeSearchTabURL),
+    (Attribute::Timestamp, VarID::AttributeTimestamp),
+    (Attribute::WelcomeMessage, VarID::AttributeWelcomeMessage),
+    (Attribute::BetaWorld, VarID::AttributeBetaWorld),
+    (Attribute::MinimumWorld, VarID::AttributeMinimumWorld),
+    (Attribute::LatestWorld, VarID::AttributeLatestWorld),
+    (
+        Attribute::DefaultStartWorld,
+        VarID::AttributeDefaultStartWorld,
+    ),
+    (Attribute::Userlist, VarID::AttributeUserlist),
+    (Attribute::NotepadTabURL, VarID::AttributeNotepadTabURL),
+    (Attribute::MailTemplate, VarID::AttributeMailTemplate),
+    (Attribute::MailFile, VarID::AttributeMailFile),
+    (Attribute::MailCommand, VarID::AttributeMailCommand),
+    (Attribute::PAVObjectPath, VarID::AttributePAVObjectPath),
+    (
+        Attribute::UnknownUniverseSetting,
+        VarID::AttributeUnknownUniverseSetting,
+    ),
+    (Attribute::ObjectPath, VarID::AttributeObjectPath),
+    (Attribute::TexturePath, VarID::AttributeTexturePath),
+    (Attribute::MediaProxyURL, VarID::AttributeMediaProxyURL),
+    (Attribute::ObjectRefresh, VarID::AttributeObjectRefresh),
+    (Attribute::PerCitizenPorts, VarID::AttributePerCitizenPorts),
+];
 
 pub fn send_attributes(client: &Client, database: &Database) {
     let mut packet = AWPacket::new(PacketType::Attributes);
@@ -11,131 +53,27 @@ pub fn send_attributes(client: &Client, database: &Database) {
     packet.set_header_1(0);
 
     let attribs = get_attributes(database);
+    let admin = client.has_permission(GroupRights::ATTRIBUTE_ADMIN);
 
-    packet.add_string(
-        VarID::AttributeAllowTourists,
-        attribs
-            .get(&Attribute::AllowTourists)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeBetaBrowser,
-        attribs
-            .get(&Attribute::BetaBrowser)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeUniverseBuild,
-        attribs
-            .get(&Attribute::UniverseBuild)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeCitizenChanges,
-        attribs
-            .get(&Attribute::CitizenChanges)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeSearchTabURL,
-        attribs
-            .get(&Attribute::SearchTabURL)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeTimestamp,
-        attribs
-            .get(&Attribute::Timestamp)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeWelcomeMessage,
-        attribs
-            .get(&Attribute::WelcomeMessage)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeBetaWorld,
-        attribs
-            .get(&Attribute::BetaWorld)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeMinimumWorld,
-        attribs
-            .get(&Attribute::MinimumWorld)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeLatestWorld,
-        attribs
-            .get(&Attribute::LatestWorld)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeDefaultStartWorld,
-        attribs
-            .get(&Attribute::DefaultStartWorld)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeUserlist,
-        attribs
-            .get(&Attribute::Userlist)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeNotepadTabURL,
-        attribs
-            .get(&Attribute::NotepadTabURL)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeMinimumBrowser,
-        attribs
-            .get(&Attribute::MinimumBrowser)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeLatestBrowser,
-        attribs
-            .get(&Attribute::LatestBrowser)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(
-        VarID::AttributeUnknownBilling7,
-        attribs
-            .get(&Attribute::UnknownBilling7)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
-    packet.add_string(VarID::AttributeBillingMethod, "".to_string());
-    packet.add_string(
-        VarID::AttributeBillingUnknown9,
-        attribs
-            .get(&Attribute::BillingUnknown9)
-            .unwrap_or(&String::new())
-            .to_string(),
-    );
+    for (attribute, var_id) in ATTRIBUTE_VARS {
+        if is_private_attribute(*attribute) && !admin {
+            continue;
+        }
+        packet.add_string(*var_id, attribs.get(attribute).cloned().unwrap_or_default());
+    }
 
     client.connection.send(packet);
 }
 
+/// Attributes which describe how the server is run, rather than the universe,
+/// and are only shown to admins.
+fn is_private_attribute(attribute: Attribute) -> bool {
+    matches!(
+        attribute,
+        Attribute::MailTemplate | Attribute::MailFile | Attribute::MailCommand
+    )
+}
+
 pub fn get_attributes(database: &Database) -> HashMap<Attribute, String> {
     let mut result = match database.attrib_get() {
         Ok(attribs) => attribs,
@@ -158,38 +96,97 @@ pub fn get_attributes(database: &Database) -> HashMap<Attribute, String> {
     result
 }
 
-pub fn set_attribute(var_id: VarID, value: &str, database: &Database) -> Result<(), ()> {
-    let id = match var_id {
-        VarID::AttributeAllowTourists => Attribute::AllowTourists,
-        VarID::AttributeUnknownBilling1 => Attribute::UnknownBilling1,
-        VarID::AttributeBetaBrowser => Attribute::BetaBrowser,
-        VarID::AttributeMinimumBrowser => Attribute::MinimumBrowser,
-        VarID::AttributeLatestBrowser => Attribute::LatestBrowser,
-        VarID::AttributeUniverseBuild => Attribute::UniverseBuild,
-        VarID::AttributeCitizenChanges => Attribute::CitizenChanges,
-        VarID::AttributeUnknownBilling7 => Attribute::UnknownBilling7,
-        VarID::AttributeBillingMethod => Attribute::BillingMethod,
-        VarID::AttributeBillingUnknown9 => Attribute::BillingUnknown9,
-        VarID::AttributeSearchTabURL => Attribute::SearchTabURL,
-        VarID::AttributeTimestamp => Attribute::Timestamp,
-        VarID::AttributeWelcomeMessage => Attribute::WelcomeMessage,
-        VarID::AttributeBetaWorld => Attribute::BetaWorld,
-        VarID::AttributeMinimumWorld => Attribute::MinimumWorld,
-        VarID::AttributeLatestWorld => Attribute::LatestWorld,
-        VarID::AttributeDefaultStartWorld => Attribute::DefaultStartWorld,
-        VarID::AttributeUserlist => Attribute::Userlist,
-        VarID::AttributeNotepadTabURL => Attribute::NotepadTabURL,
-        VarID::AttributeMailTemplate => Attribute::MailTemplate,
-        VarID::AttributeMailFile => Attribute::MailFile,
-        VarID::AttributeMailCommand => Attribute::MailCommand,
-        VarID::AttributePAVObjectPath => Attribute::PAVObjectPath,
-        VarID::AttributeUnknownUniverseSetting => Attribute::UnknownUniverseSetting,
-        _ => {
-            return Err(());
+/// The attribute sent in a variable.
+pub fn attribute_for_var(var_id: VarID) -> Option<Attribute> {
+    ATTRIBUTE_VARS
+        .iter()
+        .find(|(_, attribute_var)| *attribute_var == var_id)
+        .map(|(attribute, _)| *attribute)
+}
+
+/// Look an attribute up by its name, ignoring case.
+pub fn attribute_by_name(name: &str) -> Option<Attribute> {
+    ATTRIBUTE_VARS
+        .iter()
+        .map(|(attribute, _)| *attribute)
+        .find(|attribute| format!("{attribute:?}").eq_ignore_ascii_case(name))
+}
+
+/// Store a new value for an attribute, if it is a sensible one.
+pub fn set_attribute(
+    attribute: Attribute,
+    value: &str,
+    database: &Database,
+) -> Result<(), ReasonCode> {
+    if !is_valid_attribute_value(attribute, value) {
+        log::info!("Rejected invalid value {value:?} for attribute {attribute:?}");
+        return Err(ReasonCode::InvalidAttribute);
+    }
+
+    database.attrib_set(attribute, value)
+}
+
+/// Check that an attribute value is sensible before it gets stored.
+fn is_valid_attribute_value(attribute: Attribute, value: &str) -> bool {
+    match attribute {
+        // Content paths must be web URLs so browsers and worlds can download from them
+        Attribute::PAVObjectPath
+        | Attribute::ObjectPath
+        | Attribute::TexturePath
+        | Attribute::MediaProxyURL => {
+            value.is_empty()
+                || ((value.starts_with("http://") || value.starts_with("https://"))
+                    && !value.contains(char::is_whitespace))
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    pub fn test_attribute_table() {
+        // Every attribute but Unknown26 has a variable to be sent and changed with
+        for id in 0..=u8::MAX as u32 {
+            if let Some(attribute) = Attribute::from_u32(id) {
+                if attribute == Attribute::Unknown26 {
+                    continue;
+                }
+                let (_, var_id) = ATTRIBUTE_VARS
+                    .iter()
+                    .find(|(x, _)| *x == attribute)
+                    .unwrap_or_else(|| panic!("{attribute:?} is missing"));
+                assert_eq!(*var_id as u32, id, "{attribute:?}");
+            }
         }
-    };
 
-    database.attrib_set(id, value).map_err(|_| ())?;
+        assert_eq!(
+            attribute_by_name("objectrefresh"),
+            Some(Attribute::ObjectRefresh)
+        );
+        assert_eq!(attribute_for_var(VarID::IdentifyUserIP), None);
+        assert_eq!(attribute_by_name("unknown26"), None);
+        assert_eq!(attribute_for_var(VarID::ConsoleMessage), None);
+    }
 
-    Ok(())
+    #[test]
+    pub fn test_attribute_validation() {
+        let database = Database::unreachable();
+        assert_eq!(
+            set_attribute(Attribute::ObjectPath, "file:///etc", &database),
+            Err(ReasonCode::InvalidAttribute)
+        );
+        assert!(is_valid_attribute_value(
+            Attribute::ObjectPath,
+            "https://example.com/objects"
+        ));
+        assert!(is_valid_attribute_value(Attribute::ObjectPath, ""));
+        assert!(is_valid_attribute_value(
+            Attribute::WelcomeMessage,
+            "Hello world"
+        ));
+    }
 }
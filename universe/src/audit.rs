@@ -0,0 +1,87 @@
+//! Recording administrative actions, so universes with several admins can see
+//! who changed what. The admin interface lists them at `/audit`.
+use serde_json::{json, Value};
+
+use crate::database::{citizen::CitizenQuery, license::LicenseQuery, AuditDB, Database, ProfileDB};
+
+/// Record an action, with the target as it was before and after as JSON (`Value::Null`
+/// where it did not exist). Failures are logged rather than stopping the action.
+pub fn record(
+    database: &Database,
+    actor: &str,
+    action: &str,
+    target: &str,
+    before: Value,
+    after: Value,
+) {
+    let text = |value: Value| match value {
+        Value::Null => String::new(),
+        value => value.to_string(),
+    };
+
+    if let Err(rc) = database.audit_add(actor, action, target, &text(before), &text(after)) {
+        log::warn!("Could not record {action} of {target} by {actor} in the audit log: {rc:?}");
+    }
+}
+
+/// Everything about a citizen except their passwords.
+pub fn citizen_json(citizen: &CitizenQuery) -> Value {
+    json!({
+        "id": citizen.id,
+        "name": citizen.name,
+        "email": citizen.email,
+        "comment": citizen.comment,
+        "url": citizen.url,
+        "immigration": citizen.immigration,
+        "expiration": citizen.expiration,
+        "last_login": citizen.last_login,
+        "last_address": citizen.last_address,
+        "total_time": citizen.total_time,
+        "bot_limit": citizen.bot_limit,
+        "beta": citizen.beta != 0,
+        "enabled": citizen.enabled != 0,
+        "trial": citizen.trial != 0,
+        "privacy": citizen.privacy,
+        "cav_enabled": citizen.cav_enabled != 0,
+        "cav_template": citizen.cav_template,
+        "multi_login": citizen.multi_login != 0,
+    })
+}
+
+/// What an audit entry about a citizen is about.
+pub fn citizen_target(citizen_id: u32) -> String {
+    format!("citizen:{citizen_id}")
+}
+
+/// A citizen's profile field as it is now, or `Value::Null` if it is not set.
+pub fn profile_field_json(database: &Database, citizen_id: u32, name: &str) -> Value {
+    database
+        .profile_get_all(citizen_id)
+        .ok()
+        .and_then(|fields| fields.into_iter().find(|field| field.name == name))
+        .map(|field| json!({ name: field.value }))
+        .unwrap_or(Value::Null)
+}
+
+/// Everything about a world license except its password.
+pub fn license_json(lic: &LicenseQuery) -> Value {
+    json!({
+        "id": lic.id,
+        "name": lic.name,
+        "email": lic.email,
+        "comment": lic.comment,
+        "creation": lic.creation,
+        "expiration": lic.expiration,
+        "users": lic.users,
+        "world_size": lic.world_size,
+        "hidden": lic.hidden != 0,
+        "tourists": lic.tourists != 0,
+        "voip": lic.voip != 0,
+        "plugins": lic.plugins != 0,
+    })
+}
+
+/// What an audit entry about a world license is about.
+pub fn license_target(name: &str) -> String {
+    format!("license:{name}")
+}
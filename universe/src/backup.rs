@@ -0,0 +1,155 @@
+//! Export of the universe database to a portable JSON file, and restoring from one.
+//!
+//! Citizens, licenses, contacts and attributes are included. Restoring adds rows
+//! which are missing and overwrites rows which already exist, so a backup can be
+//! restored over a partially filled database.
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path};
+
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::database::{
+    attrib::Attribute, citizen::CitizenQuery, license::LicenseQuery, AttribDB, CitizenDB,
+    ContactDB, Database, LicenseDB,
+};
+
+/// Version of the backup format, raised whenever it changes incompatibly.
+const BACKUP_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Backup {
+    version: u32,
+    citizens: Vec<CitizenQuery>,
+    licenses: Vec<LicenseQuery>,
+    contacts: Vec<ContactRecord>,
+    /// Attribute values by attribute ID
+    attributes: BTreeMap<u32, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ContactRecord {
+    citizen: u32,
+    contact: u32,
+    options: u32,
+}
+
+/// Write the contents of the universe database to a file.
+pub fn export(database: &Database, path: &Path) -> Result<(), String> {
+    let citizens = database
+        .citizen_all()
+        .map_err(|x| format!("Could not read citizens: {x:?}"))?;
+
+    let contacts = citizens
+        .iter()
+        .flat_map(|citizen| database.contact_get_all(citizen.id))
+        .map(|contact| ContactRecord {
+            citizen: contact.citizen,
+            contact: contact.contact,
+            options: contact.options.bits(),
+        })
+        .collect();
+
+    let mut licenses = Vec::new();
+    let mut last_name = String::new();
+    while let Ok(license) = database.license_next(&last_name) {
+        last_name = license.name.clone();
+        licenses.push(license);
+    }
+
+    let attributes = database
+        .attrib_get()
+        .map_err(|x| format!("Could not read attributes: {x:?}"))?
+        .into_iter()
+        .map(|(attribute, value)| (attribute as u32, value))
+        .collect();
+
+    let backup = Backup {
+        version: BACKUP_VERSION,
+        citizens,
+        licenses,
+        contacts,
+        attributes,
+    };
+
+    let file = File::create(path).map_err(|err| format!("Could not create backup: {err}"))?;
+    serde_json::to_writer_pretty(file, &backup)
+        .map_err(|err| format!("Could not write backup: {err}"))?;
+
+    log::info!(
+        "Exported {} citizens, {} licenses, {} contacts and {} attributes",
+        backup.citizens.len(),
+        backup.licenses.len(),
+        backup.contacts.len(),
+        backup.attributes.len()
+    );
+
+    Ok(())
+}
+
+/// Load the contents of a backup file into the universe database.
+pub fn import(database: &Database, path: &Path) -> Result<(), String> {
+    let file = File::open(path).map_err(|err| format!("Could not open backup: {err}"))?;
+    let backup: Backup = serde_json::from_reader(BufReader::new(file))
+        .map_err(|err| format!("Could not read backup: {err}"))?;
+
+    if backup.version != BACKUP_VERSION {
+        return Err(format!(
+            "Backup is version {}, but only version {BACKUP_VERSION} is supported",
+            backup.version
+        ));
+    }
+
+    for citizen in &backup.citizens {
+        let result = match database.citizen_by_number(citizen.id) {
+            Ok(_) => database.citizen_change(citizen),
+            Err(_) => database.citizen_add(citizen),
+        };
+        if let Err(x) = result {
+            log::warn!("Could not restore citizen {}: {x:?}", citizen.id);
+        }
+    }
+
+    for license in &backup.licenses {
+        let result = match database.license_by_name(&license.name) {
+            Ok(existing) => database.license_change(&LicenseQuery {
+                id: existing.id,
+                ..license.clone()
+            }),
+            Err(_) => database.license_add(license),
+        };
+        if let Err(x) = result {
+            log::warn!("Could not restore license {}: {x:?}", license.name);
+        }
+    }
+
+    for contact in &backup.contacts {
+        if let Err(x) = database.contact_set(contact.citizen, contact.contact, contact.options) {
+            log::warn!(
+                "Could not restore contact {} of {}: {x:?}",
+                contact.contact,
+                contact.citizen
+            );
+        }
+    }
+
+    for (id, value) in &backup.attributes {
+        match Attribute::from_u32(*id) {
+            Some(attribute) => {
+                if let Err(x) = database.attrib_set(attribute, value) {
+                    log::warn!("Could not restore attribute {id}: {x:?}");
+                }
+            }
+            None => log::warn!("Skipping unknown attribute {id}"),
+        }
+    }
+
+    log::info!(
+        "Restored {} citizens, {} licenses, {} contacts and {} attributes",
+        backup.citizens.len(),
+        backup.licenses.len(),
+        backup.contacts.len(),
+        backup.attributes.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! Publishing universe events to a message broker, so other services can
+//! follow what is happening without polling the admin interface.
+//!
+//! Each event is published as JSON to `<prefix>.<event type>` on NATS or
+//! `<prefix>/<event type>` on MQTT. Events are handed to a background thread,
+//! and are dropped rather than queued while the broker is unreachable.
+mod mqtt;
+mod nats;
+
+use std::{
+    io,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{BusProtocol, EventBusConfig},
+    events::TimedEvent,
+};
+
+/// How long to wait before trying to reach the broker again.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// How often to check the connection while no events are being published.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A connection to a message broker.
+trait Publisher {
+    fn publish(&mut self, kind: &str, payload: &[u8]) -> io::Result<()>;
+
+    /// Answer anything the broker has sent and keep the connection alive.
+    fn poll(&mut self) -> io::Result<()>;
+}
+
+struct Message {
+    kind: String,
+    payload: Vec<u8>,
+}
+
+pub struct EventBus {
+    sender: Sender<Message>,
+}
+
+impl EventBus {
+    /// Start the publishing thread. Returns None if the event bus is disabled.
+    pub fn new(config: &EventBusConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let config = config.clone();
+        let (sender, receiver) = mpsc::channel::<Message>();
+        thread::spawn(move || run(&config, receiver));
+
+        Some(Self { sender })
+    }
+
+    pub fn publish(&self, events: &[TimedEvent]) {
+        for event in events {
+            let value = match serde_json::to_value(event) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let kind = value["type"].as_str().unwrap_or("unknown").to_string();
+            let payload = value.to_string().into_bytes();
+
+            if self.sender.send(Message { kind, payload }).is_err() {
+                log::error!("Event bus thread has stopped");
+                return;
+            }
+        }
+    }
+}
+
+fn connect(config: &EventBusConfig) -> io::Result<Box<dyn Publisher>> {
+    Ok(match config.protocol {
+        BusProtocol::Nats => Box::new(nats::NatsPublisher::connect(config)?),
+        BusProtocol::Mqtt => Box::new(mqtt::MqttPublisher::connect(config)?),
+    })
+}
+
+fn run(config: &EventBusConfig, receiver: Receiver<Message>) {
+    let mut publisher: Option<Box<dyn Publisher>> = None;
+    let mut last_attempt: Option<Instant> = None;
+
+    loop {
+        let message = match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(x) => Some(x),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        if publisher.is_none() && last_attempt.is_none_or(|x| x.elapsed() >= RECONNECT_DELAY) {
+            last_attempt = Some(Instant::now());
+            match connect(config) {
+                Ok(x) => {
+                    log::info!(
+                        "Connected to event bus at {}:{}",
+                        config.host,
+                        config.port()
+                    );
+                    publisher = Some(x);
+                }
+                Err(err) => log::warn!(
+                    "Could not connect to event bus at {}:{}: {err}",
+                    config.host,
+                    config.port()
+                ),
+            }
+        }
+
+        if let Some(connection) = &mut publisher {
+            let result = match &message {
+                Some(message) => connection.publish(&message.kind, &message.payload),
+                None => connection.poll(),
+            };
+            if let Err(err) = result {
+                log::warn!("Lost connection to event bus: {err}");
+                publisher = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! A minimal MQTT 3.1.1 client which only publishes, at QoS 0.
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use super::Publisher;
+use crate::config::EventBusConfig;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Seconds the broker should wait without hearing from us before giving up.
+const KEEP_ALIVE_SECS: u16 = 60;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PINGREQ: u8 = 0xC0;
+
+pub struct MqttPublisher {
+    stream: TcpStream,
+    prefix: String,
+    last_sent: Instant,
+}
+
+impl MqttPublisher {
+    pub fn connect(config: &EventBusConfig) -> io::Result<Self> {
+        let mut stream = TcpStream::connect((config.host.as_str(), config.port()))?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+
+        // Clean session, with credentials if configured
+        let mut flags = 0x02;
+        let mut body = Vec::new();
+        write_string(&mut body, "MQTT");
+        body.push(4); // Protocol level 3.1.1
+        let flags_index = body.len();
+        body.push(0);
+        body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+        write_string(&mut body, &config.client_id);
+        if !config.username.is_empty() {
+            flags |= 0x80;
+            write_string(&mut body, &config.username);
+            if !config.password.is_empty() {
+                flags |= 0x40;
+                write_string(&mut body, &config.password);
+            }
+        }
+        body[flags_index] = flags;
+        stream.write_all(&packet(CONNECT, &body))?;
+
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack)?;
+        if connack[0] != CONNACK {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "broker did not acknowledge the connection",
+            ));
+        }
+        if connack[3] != 0 {
+            return Err(io::Error::new(
+                ErrorKind::ConnectionRefused,
+                format!("broker refused the connection with code {}", connack[3]),
+            ));
+        }
+        stream.set_nonblocking(true)?;
+
+        Ok(Self {
+            stream,
+            prefix: config.topic_prefix.clone(),
+            last_sent: Instant::now(),
+        })
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(data);
+        self.stream.set_nonblocking(true)?;
+        self.last_sent = Instant::now();
+        result
+    }
+}
+
+impl Publisher for MqttPublisher {
+    fn publish(&mut self, kind: &str, payload: &[u8]) -> io::Result<()> {
+        self.poll()?;
+
+        let mut body = Vec::new();
+        write_string(&mut body, &format!("{}/{kind}", self.prefix));
+        body.extend_from_slice(payload);
+        self.send(&packet(PUBLISH, &body))
+    }
+
+    fn poll(&mut self) -> io::Result<()> {
+        // Nothing the broker sends us needs an answer, so it is discarded
+        let mut buf = [0u8; 256];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(ErrorKind::ConnectionAborted.into()),
+                Ok(_) => continue,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        if self.last_sent.elapsed() >= Duration::from_secs(KEEP_ALIVE_SECS as u64 / 2) {
+            self.send(&[PINGREQ, 0])?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Build a packet from its type and body, with the body's length in between.
+fn packet(packet_type: u8, body: &[u8]) -> Vec<u8> {
+    let mut data = vec![packet_type];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        data.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    data.extend_from_slice(body);
+    data
+}
+
+fn write_string(buf: &mut Vec<u8>, text: &str) {
+    buf.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    buf.extend_from_slice(text.as_bytes());
+}
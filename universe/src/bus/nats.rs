@@ -0,0 +1,93 @@
+//! A minimal NATS client which only publishes.
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use serde_json::json;
+
+use super::Publisher;
+use crate::config::EventBusConfig;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct NatsPublisher {
+    stream: TcpStream,
+    prefix: String,
+}
+
+impl NatsPublisher {
+    pub fn connect(config: &EventBusConfig) -> io::Result<Self> {
+        let mut stream = TcpStream::connect((config.host.as_str(), config.port()))?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+
+        // The server introduces itself first
+        let mut info = String::new();
+        BufReader::new(&mut stream).read_line(&mut info)?;
+        if !info.starts_with("INFO ") {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unexpected greeting {:?}", info.trim_end()),
+            ));
+        }
+
+        let mut options = json!({
+            "verbose": false,
+            "pedantic": false,
+            "name": config.client_id,
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        if !config.username.is_empty() {
+            options["user"] = json!(config.username);
+            options["pass"] = json!(config.password);
+        }
+        stream.write_all(format!("CONNECT {options}\r\n").as_bytes())?;
+        stream.set_nonblocking(true)?;
+
+        Ok(Self {
+            stream,
+            prefix: config.topic_prefix.clone(),
+        })
+    }
+}
+
+impl Publisher for NatsPublisher {
+    fn publish(&mut self, kind: &str, payload: &[u8]) -> io::Result<()> {
+        self.poll()?;
+
+        let subject = format!("{}.{kind}", self.prefix);
+        let mut message = format!("PUB {subject} {}\r\n", payload.len()).into_bytes();
+        message.extend_from_slice(payload);
+        message.extend_from_slice(b"\r\n");
+
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(&message);
+        self.stream.set_nonblocking(true)?;
+        result
+    }
+
+    fn poll(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(ErrorKind::ConnectionAborted.into()),
+                Ok(n) => {
+                    let text = String::from_utf8_lossy(&buf[..n]);
+                    for line in text.lines() {
+                        if line == "PING" {
+                            self.stream.set_nonblocking(false)?;
+                            self.stream.write_all(b"PONG\r\n")?;
+                            self.stream.set_nonblocking(true)?;
+                        } else if let Some(err) = line.strip_prefix("-ERR ") {
+                            log::warn!("Event bus error: {err}");
+                        }
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
@@ -1,21 +1,35 @@
 use std::{
     cell::{Ref, RefCell, RefMut},
     net::{IpAddr, SocketAddr},
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
+    config::{Config, DuplicateLogin, TouristConfig, TrialConfig},
     database::{
         citizen::{CitizenDB, CitizenQuery},
-        Database,
+        group::GroupRights,
+        password, Database, GroupDB,
     },
-    packet_handler::{self, update_contacts_of_user},
-    player::{PlayerInfo, PlayerState},
+    events::{EventQueue, UniverseEvent},
+    packet_handler::{self, update_contacts_of_user, LoginCredentials, TokenBucket},
+    player::{PlayerInfo, PlayerState, UserListView},
+    protocol::ProtocolVersion,
     world::{World, WorldServerInfo},
     AWConnection, AWCryptRSA,
 };
 use aw_core::{AWPacket, PacketType, ReasonCode};
 use num_derive::FromPrimitive;
+use rand::Rng;
+
+/// Number of random bytes in a login challenge.
+const LOGIN_CHALLENGE_LEN: usize = 16;
+
+/// Sent to a session which an admin disconnects, followed by any reason they gave
+const KICKED: &str = "You have been disconnected by an administrator.";
+
+/// Sent to a session which is replaced by the same citizen logging in again
+const LOGGED_IN_ELSEWHERE: &str = "You have been disconnected because you logged in elsewhere.";
 
 /// Game-related client state
 #[derive(Default)]
@@ -38,18 +52,27 @@ impl Entity {
         build: i32,
         username: &str,
         ip: IpAddr,
+        group_rights: GroupRights,
     ) -> Self {
         Self::Player(PlayerInfo {
             build,
             session_id,
             citizen_id: Some(citizen_id),
-            privilege_id: privilege_id,
+            // Acting as oneself is the same as not acquiring a privilege
+            privilege_id: privilege_id.filter(|id| *id != 0 && *id != citizen_id),
             username: username.to_string(),
             nonce: None,
             world: None,
+            world_address: None,
             ip,
             state: PlayerState::Online,
             afk: false,
+            dnd: false,
+            join_requests: Vec::new(),
+            group_rights,
+            session_start: Instant::now(),
+            session_warned: false,
+            trial: false,
         })
     }
 
@@ -62,9 +85,38 @@ impl Entity {
             username: username.to_string(),
             nonce: None,
             world: None,
+            world_address: None,
+            ip,
+            state: PlayerState::Online,
+            afk: false,
+            dnd: false,
+            join_requests: Vec::new(),
+            group_rights: GroupRights::empty(),
+            session_start: Instant::now(),
+            session_warned: false,
+            trial: false,
+        })
+    }
+
+    pub fn new_bot(owner_id: u32, session_id: u16, build: i32, name: &str, ip: IpAddr) -> Self {
+        Self::Player(PlayerInfo {
+            build,
+            session_id,
+            citizen_id: None,
+            privilege_id: Some(owner_id),
+            username: format!("[{name}]"),
+            nonce: None,
+            world: None,
+            world_address: None,
             ip,
             state: PlayerState::Online,
             afk: false,
+            dnd: false,
+            join_requests: Vec::new(),
+            group_rights: GroupRights::empty(),
+            session_start: Instant::now(),
+            session_warned: false,
+            trial: false,
         })
     }
 
@@ -84,6 +136,18 @@ pub struct Client {
     user_info: RefCell<UserInfo>,
     pub addr: SocketAddr,
     pub last_heartbeat: u64,
+    heartbeat_sent: RefCell<Option<Instant>>,
+    /// Heartbeats sent since the client last sent anything
+    missed_heartbeats: RefCell<u32>,
+    rtt: RefCell<Option<Duration>>,
+    /// Tokens left for sending packets, created with the first packet when rate limiting
+    pub packet_budget: RefCell<Option<TokenBucket>>,
+    /// Random value the client must send back when logging in, if challenges are enabled
+    pub login_challenge: Vec<u8>,
+    /// Protocol the client speaks, known once it has logged in
+    protocol: RefCell<ProtocolVersion>,
+    /// User list entries the client has been sent
+    pub user_list: RefCell<UserListView>,
 }
 
 impl Client {
@@ -100,6 +164,15 @@ impl Client {
             user_info: RefCell::new(Default::default()),
             addr,
             last_heartbeat: now,
+            heartbeat_sent: RefCell::new(None),
+            missed_heartbeats: RefCell::new(0),
+            rtt: RefCell::new(None),
+            packet_budget: RefCell::new(None),
+            login_challenge: rand::thread_rng()
+                .gen::<[u8; LOGIN_CHALLENGE_LEN]>()
+                .to_vec(),
+            protocol: RefCell::new(ProtocolVersion::default()),
+            user_list: RefCell::new(UserListView::default()),
         }
     }
 
@@ -107,10 +180,51 @@ impl Client {
         *self.dead.borrow_mut() = true;
     }
 
+    /// Tell the client why it is being disconnected, then disconnect it.
+    /// The message is sent before the connection closes.
+    pub fn disconnect_with_message(&self, message: &str) {
+        packet_handler::send_console_message(self, message);
+        self.kill();
+    }
+
     pub fn is_dead(&self) -> bool {
         *self.dead.borrow()
     }
 
+    /// Remember when a heartbeat was sent so the response time can be measured.
+    pub fn record_heartbeat_sent(&self) {
+        *self.heartbeat_sent.borrow_mut() = Some(Instant::now());
+    }
+
+    /// Update the round trip time estimate if a heartbeat is awaiting a response.
+    /// Any packet from the client counts as a response.
+    pub fn record_heartbeat_response(&self) {
+        *self.missed_heartbeats.borrow_mut() = 0;
+        if let Some(sent) = self.heartbeat_sent.borrow_mut().take() {
+            let sample = sent.elapsed();
+            let mut rtt = self.rtt.borrow_mut();
+
+            // Smooth the estimate the same way TCP does so one slow reply doesn't dominate
+            *rtt = Some(match *rtt {
+                Some(previous) => (previous * 7 + sample) / 8,
+                None => sample,
+            });
+        }
+    }
+
+    /// Estimated round trip time to the client, if it has been measured yet.
+    pub fn rtt(&self) -> Option<Duration> {
+        *self.rtt.borrow()
+    }
+
+    pub fn protocol(&self) -> ProtocolVersion {
+        *self.protocol.borrow()
+    }
+
+    pub fn set_protocol(&self, protocol: ProtocolVersion) {
+        *self.protocol.borrow_mut() = protocol;
+    }
+
     pub fn info_mut(&self) -> RefMut<UserInfo> {
         self.user_info.borrow_mut()
     }
@@ -119,15 +233,81 @@ impl Client {
         self.user_info.borrow()
     }
 
+    /// Describe who the client is, for the credit ledger and the audit log.
+    pub fn actor(&self) -> String {
+        match &self.info().entity {
+            Some(Entity::Player(info)) => match info.citizen_id {
+                Some(id) => format!("citizen:{id}"),
+                None => format!("player:{}", info.username),
+            },
+            Some(Entity::WorldServer(_)) => format!("world:{}", self.addr.ip()),
+            None => format!("client:{}", self.addr.ip()),
+        }
+    }
+
+    /// Whether the client is logged in as a bot on behalf of a citizen.
+    pub fn is_bot_of(&self, owner_id: u32) -> bool {
+        let info = self.info();
+        match &info.entity {
+            Some(Entity::Player(player)) => {
+                info.client_type == Some(ClientType::Bot) && player.privilege_id == Some(owner_id)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the client may do what the given rights allow, either through
+    /// its groups or by being an admin.
+    pub fn has_permission(&self, rights: GroupRights) -> bool {
+        if self.has_admin_permissions() {
+            return true;
+        }
+
+        match &self.info().entity {
+            Some(Entity::Player(info)) => info.group_rights.contains(rights),
+            _ => false,
+        }
+    }
+
+    /// Whether the client may look up, change or delete a citizen as a citizen
+    /// admin. Only admins may do so to another admin, so the role cannot be used
+    /// to take over their accounts.
+    pub fn may_manage_citizen(&self, citizen_id: u32, database: &Database) -> bool {
+        if self.has_admin_permissions() {
+            return true;
+        }
+        if !self.has_permission(GroupRights::CITIZEN_ADMIN) {
+            return false;
+        }
+
+        // A citizen whose groups cannot be checked might be an admin
+        let rights = database
+            .groups_of_citizen(citizen_id)
+            .map(|groups| {
+                groups
+                    .iter()
+                    .fold(GroupRights::empty(), |rights, group| rights | group.rights)
+            })
+            .unwrap_or(GroupRights::ADMIN);
+        !is_admin_citizen(citizen_id, rights)
+    }
+
     pub fn has_admin_permissions(&self) -> bool {
         if let Some(Entity::Player(info)) = &self.info().entity {
-            info.citizen_id == Some(1) || info.privilege_id == Some(1)
+            info.citizen_id == Some(1)
+                || info.effective_privilege() == 1
+                || info.group_rights.contains(GroupRights::ADMIN)
         } else {
             false
         }
     }
 }
 
+/// Whether a citizen is the Administrator or has admin rights through their groups.
+pub fn is_admin_citizen(citizen_id: u32, rights: GroupRights) -> bool {
+    citizen_id == 1 || rights.contains(GroupRights::ADMIN)
+}
+
 #[derive(FromPrimitive, Clone, Copy, Debug, PartialEq)]
 pub enum ClientType {
     World = 1,
@@ -165,6 +345,69 @@ impl ClientManager {
         None
     }
 
+    /// Send a line of text to one session's chat window, or to every user's if no
+    /// session is given. Returns how many users it was sent to.
+    pub fn send_console_message_to(
+        &self,
+        session_id: Option<u16>,
+        message: &str,
+    ) -> Result<usize, ReasonCode> {
+        if let Some(session_id) = session_id {
+            let client = self
+                .get_client_by_session_id(session_id)
+                .ok_or(ReasonCode::NoSuchSession)?;
+            packet_handler::send_console_message(client, message);
+            return Ok(1);
+        }
+
+        let mut count = 0;
+        for client in &self.clients {
+            if client.info().entity.as_ref().is_some_and(Entity::is_player) {
+                packet_handler::send_console_message(client, message);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Disconnect a user's session on an admin's behalf, telling them why.
+    pub fn kick_session(&self, session_id: u16, reason: &str) -> Result<&Client, ReasonCode> {
+        let client = self
+            .get_client_by_session_id(session_id)
+            .ok_or(ReasonCode::NoSuchSession)?;
+
+        let message = if reason.is_empty() {
+            KICKED.to_string()
+        } else {
+            format!("{KICKED} Reason: {reason}")
+        };
+        log::info!(
+            "Kicking session {session_id} ({}): {reason}",
+            client.addr.ip()
+        );
+        client.disconnect_with_message(&message);
+
+        Ok(client)
+    }
+
+    /// Disconnect every other session logged in as a citizen, telling them why.
+    pub fn replace_sessions_of(&self, citizen_id: u32, new_client: &Client) {
+        for client in self.clients() {
+            let same_citizen = match &client.info().entity {
+                Some(Entity::Player(info)) => info.citizen_id == Some(citizen_id),
+                _ => false,
+            };
+            if !same_citizen || std::ptr::eq(client, new_client) {
+                continue;
+            }
+            log::info!(
+                "Replacing session of citizen {citizen_id} from {}",
+                client.addr.ip()
+            );
+            client.disconnect_with_message(LOGGED_IN_ELSEWHERE);
+        }
+    }
+
     pub fn get_client_by_citizen_id(&self, citizen_id: u32) -> Option<&Client> {
         for client in self.clients() {
             if let Some(Entity::Player(info)) = &client.info().entity {
@@ -176,6 +419,18 @@ impl ClientManager {
         None
     }
 
+    /// Find the world server which is hosting a world.
+    pub fn get_world_server_by_world(&self, world_name: &str) -> Option<&Client> {
+        for client in self.clients() {
+            if let Some(Entity::WorldServer(server)) = &client.info().entity {
+                if server.get_world(world_name).is_some() {
+                    return Some(client);
+                }
+            }
+        }
+        None
+    }
+
     pub fn add_client(&mut self, client: Client) {
         self.clients.push(client);
     }
@@ -184,33 +439,87 @@ impl ClientManager {
         &self.clients
     }
 
-    pub fn remove_dead_clients(&mut self, database: &Database) {
+    pub fn remove_dead_clients(&mut self, database: &Database, events: &EventQueue) {
         for client in self.clients().iter().filter(|x| x.is_dead()) {
             log::info!("Disconnected {}", client.addr.ip());
+            let name = match &client.info().entity {
+                Some(Entity::Player(player)) => Some(player.username.clone()),
+                _ => None,
+            };
+            events.publish(UniverseEvent::Disconnect {
+                ip: client.addr.ip(),
+                name,
+            });
+
             if let Some(Entity::WorldServer(server_info)) = &mut client.info_mut().entity {
                 packet_handler::world_server_hide_all(server_info);
             }
-            if let Some(Entity::WorldServer(server_info)) = &client.info().entity {
-                World::send_updates_to_all(&server_info.worlds, self);
+            // Copied out so the server's own entry is not borrowed while players leave it
+            let server = match &client.info().entity {
+                Some(Entity::WorldServer(server_info)) => Some((
+                    SocketAddr::new(client.addr.ip(), server_info.server_port),
+                    server_info.worlds.clone(),
+                )),
+                _ => None,
+            };
+            if let Some((server_addr, worlds)) = server {
+                // Worlds which are still running on other servers stay listed
+                let updates = worlds
+                    .iter()
+                    .map(|world| {
+                        self.get_world_listing(&world.name)
+                            .unwrap_or_else(|| world.clone())
+                    })
+                    .collect::<Vec<World>>();
+                World::send_updates_to_all(&updates, self);
+
+                self.leave_worlds_of_server(server_addr, database);
             }
 
             if let Some(Entity::Player(player)) = &mut client.info_mut().entity {
                 player.state = PlayerState::Offline;
             }
             if let Some(Entity::Player(player)) = &client.info().entity {
-                PlayerInfo::send_update_to_all(player, self);
+                PlayerInfo::send_update_to_all(player, self, database);
 
                 if let Some(citizen_id) = player.citizen_id {
                     // Update the user's friends to tell them this user is now offline
                     update_contacts_of_user(citizen_id, database, self);
+
+                    let session_secs = player.session_start.elapsed().as_secs() as u32;
+                    if let Err(x) = database.citizen_add_total_time(citizen_id, session_secs) {
+                        log::warn!("Could not record time online of citizen {citizen_id}: {x:?}");
+                    }
                 }
             }
         }
         self.clients = self.clients.drain(..).filter(|x| !x.is_dead()).collect();
     }
 
-    pub fn check_tourist(&self, username: &str) -> Result<(), ReasonCode> {
-        check_valid_name(username, true)?;
+    /// Take players out of the worlds a departing world server was hosting,
+    /// so user and contact lists stop showing them there.
+    fn leave_worlds_of_server(&self, server_addr: SocketAddr, database: &Database) {
+        let mut moved = Vec::new();
+        for client in self.clients.iter().filter(|client| !client.is_dead()) {
+            if let Some(Entity::Player(player)) = &mut client.info_mut().entity {
+                if player.world_address == Some(server_addr) {
+                    player.world = None;
+                    player.world_address = None;
+                    moved.push(player.clone());
+                }
+            }
+        }
+
+        for player in moved {
+            PlayerInfo::send_update_to_all(&player, self, database);
+            if let Some(citizen_id) = player.citizen_id {
+                update_contacts_of_user(citizen_id, database, self);
+            }
+        }
+    }
+
+    pub fn check_tourist(&self, username: &str, rules: &TouristConfig) -> Result<(), ReasonCode> {
+        check_tourist_name(username, rules)?;
 
         for other_client in self.clients() {
             if let Some(Entity::Player(info)) = &other_client.info().entity {
@@ -227,18 +536,22 @@ impl ClientManager {
         &self,
         db: &Database,
         client: &Client,
-        username: &Option<String>,
-        password: &Option<String>,
-        priv_id: Option<u32>,
-        priv_pass: &Option<String>,
+        credentials: &LoginCredentials,
+        config: &Config,
     ) -> Result<CitizenQuery, ReasonCode> {
         // Name and password must be present
-        let password = password.as_ref().ok_or(ReasonCode::InvalidPassword)?;
+        let password = credentials
+            .password
+            .as_ref()
+            .ok_or(ReasonCode::InvalidPassword)?;
         if password.is_empty() {
             return Err(ReasonCode::InvalidPassword);
         }
 
-        let username = username.as_ref().ok_or(ReasonCode::NoSuchCitizen)?;
+        let username = credentials
+            .username
+            .as_ref()
+            .ok_or(ReasonCode::NoSuchCitizen)?;
         if username.is_empty() {
             return Err(ReasonCode::NoSuchCitizen);
         }
@@ -248,43 +561,46 @@ impl ClientManager {
             return Err(ReasonCode::NoSuchCitizen);
         }
 
-        // Checks if acquiring a privilege
-        if let Some(priv_id) = priv_id.filter(|x| *x != 0) {
-            // Get acting citizen
-            let priv_citizen = db
-                .citizen_by_number(priv_id)
-                .map_err(|_| ReasonCode::NoSuchActingCitizen)?;
-
-            // Is it enabled?
-            if priv_citizen.enabled == 0 && priv_citizen.id != 1 {
-                return Err(ReasonCode::NoSuchActingCitizen);
-            }
-
-            // Is the priv pass present and correct?
-            let priv_pass = priv_pass
-                .as_ref()
-                .ok_or(ReasonCode::ActingPasswordInvalid)?;
-            if *priv_pass != priv_citizen.priv_pass {
-                return Err(ReasonCode::ActingPasswordInvalid);
-            }
-        }
-
         // Get login citizen
         let login_citizen = db
             .citizen_by_name(username)
             .or(Err(ReasonCode::NoSuchCitizen))?;
 
         // Is login password correct?
-        if login_citizen.password != *password {
+        if !password::verify_password(&login_citizen.password, password) {
             return Err(ReasonCode::InvalidPassword);
         }
 
+        // Passwords stored before hashing was introduced are hashed now that we have them
+        if !password::is_hashed(&login_citizen.password) {
+            if let Err(x) = db.citizen_set_password(login_citizen.id, password) {
+                log::warn!(
+                    "Could not hash password of citizen {}: {x:?}",
+                    login_citizen.id
+                );
+            }
+        }
+
+        // Checks if acquiring another citizen's privileges
+        let priv_id = credentials
+            .privilege_id
+            .filter(|x| *x != 0 && *x != login_citizen.id);
+        if let Some(priv_id) = priv_id {
+            Self::check_privilege(db, priv_id, &credentials.privilege_password, &config.trial)?;
+        }
+
         // Is it enabled?
         if login_citizen.enabled == 0 {
             return Err(ReasonCode::CitizenDisabled);
         }
 
-        // Is this citizen already logged in?
+        // Is this citizen already logged in? Some accounts may hold several sessions.
+        // Otherwise the older session may be replaced once the login succeeds.
+        if login_citizen.multi_login != 0
+            || config.universe.duplicate_login == DuplicateLogin::Replace
+        {
+            return Ok(login_citizen);
+        }
         for other_client in self.clients() {
             if let Some(Entity::Player(info)) = &other_client.info().entity {
                 if info.citizen_id == Some(login_citizen.id) {
@@ -299,7 +615,143 @@ impl ClientManager {
         Ok(login_citizen)
     }
 
-    pub fn send_heartbeats(&mut self) {
+    /// Check the privilege password of the citizen a user wants to act as.
+    fn check_privilege(
+        db: &Database,
+        priv_id: u32,
+        priv_pass: &Option<String>,
+        trial: &TrialConfig,
+    ) -> Result<(), ReasonCode> {
+        // Get acting citizen
+        let priv_citizen = db
+            .citizen_by_number(priv_id)
+            .map_err(|_| ReasonCode::NoSuchActingCitizen)?;
+
+        // Is it enabled?
+        if priv_citizen.enabled == 0 && priv_citizen.id != 1 {
+            return Err(ReasonCode::NoSuchActingCitizen);
+        }
+
+        // Is the priv pass present and correct? A citizen without a privilege
+        // password cannot have their privileges acquired at all.
+        if !Self::check_privilege_password(db, &priv_citizen, priv_pass) {
+            return Err(ReasonCode::ActingPasswordInvalid);
+        }
+
+        if !trial.allows_privilege_password(priv_citizen.trial != 0) {
+            return Err(ReasonCode::ActingPasswordInvalid);
+        }
+
+        Ok(())
+    }
+
+    /// Check a privilege password given for a citizen, hashing it if it was still
+    /// stored as plain text.
+    fn check_privilege_password(
+        db: &Database,
+        citizen: &CitizenQuery,
+        priv_pass: &Option<String>,
+    ) -> bool {
+        let Some(priv_pass) = priv_pass else {
+            return false;
+        };
+        if citizen.priv_pass.is_empty() || !password::verify_password(&citizen.priv_pass, priv_pass)
+        {
+            return false;
+        }
+
+        if !password::is_hashed(&citizen.priv_pass) {
+            if let Err(x) = db.citizen_set_privilege_password(citizen.id, priv_pass) {
+                log::warn!(
+                    "Could not hash privilege password of citizen {}: {x:?}",
+                    citizen.id
+                );
+            }
+        }
+        true
+    }
+
+    /// Check a bot's credentials, returning its owner if it may log in.
+    pub fn check_bot(
+        &self,
+        db: &Database,
+        client: &Client,
+        name: &Option<String>,
+        owner_id: Option<u32>,
+        priv_pass: &Option<String>,
+        trial: &TrialConfig,
+    ) -> Result<CitizenQuery, ReasonCode> {
+        let name = name.as_ref().ok_or(ReasonCode::NoSuchCitizen)?;
+        if name.is_empty() || name.starts_with('[') || name.starts_with('"') {
+            return Err(ReasonCode::NoSuchCitizen);
+        }
+
+        // Bots act on behalf of an owner citizen
+        let owner_id = owner_id
+            .filter(|x| *x != 0)
+            .ok_or(ReasonCode::NoSuchActingCitizen)?;
+        let owner = db
+            .citizen_by_number(owner_id)
+            .map_err(|_| ReasonCode::NoSuchActingCitizen)?;
+
+        if owner.enabled == 0 && owner.id != 1 {
+            return Err(ReasonCode::NoSuchActingCitizen);
+        }
+
+        // Owners authorize bots with their privilege password
+        if !Self::check_privilege_password(db, &owner, priv_pass) {
+            return Err(ReasonCode::ActingPasswordInvalid);
+        }
+        if !trial.allows_privilege_password(owner.trial != 0) {
+            return Err(ReasonCode::ActingPasswordInvalid);
+        }
+
+        // A bot logging in again on the same connection doesn't count against the
+        // limit, unless it was a bot for someone else before
+        let mut bots = self.bot_count(owner.id);
+        if client.is_bot_of(owner.id) {
+            bots = bots.saturating_sub(1);
+        }
+        let bot_limit = trial.bot_limit(owner.trial != 0, owner.bot_limit);
+        if bots >= bot_limit as usize {
+            log::info!(
+                "Refusing bot {name:?} of citizen {}, who already has {bots} of {bot_limit} bots",
+                owner.id
+            );
+            return Err(ReasonCode::BotLimitExceeded);
+        }
+
+        Ok(owner)
+    }
+
+    /// Number of users and bots currently logged in.
+    pub fn player_count(&self) -> usize {
+        self.clients()
+            .iter()
+            .filter(|client| client.info().entity.as_ref().is_some_and(Entity::is_player))
+            .count()
+    }
+
+    /// Number of connections open from an address.
+    pub fn connections_from(&self, ip: IpAddr) -> usize {
+        self.clients()
+            .iter()
+            .filter(|client| client.addr.ip() == ip)
+            .count()
+    }
+
+    /// Number of bots currently logged in on behalf of a citizen, across every
+    /// connection. Bots which have been disconnected but not yet removed are not counted.
+    pub fn bot_count(&self, owner_id: u32) -> usize {
+        self.clients()
+            .iter()
+            .filter(|client| !client.is_dead() && client.is_bot_of(owner_id))
+            .count()
+    }
+
+    /// Send heartbeats to clients which are due one, disconnecting those which
+    /// have not answered the last `missed_limit` (unless it is 0).
+    pub fn send_heartbeats(&mut self, missed_limit: u32) {
         for client in &mut self.clients {
             let now = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -310,25 +762,146 @@ impl ClientManager {
             let next_heartbeat = client.last_heartbeat + 30;
 
             if next_heartbeat <= now {
-                log::info!("Sending heartbeat to {}", client.addr.ip());
+                // Any packet since the last heartbeat would have cleared it
+                if client.heartbeat_sent.borrow().is_some() {
+                    *client.missed_heartbeats.borrow_mut() += 1;
+                }
+
+                let missed = *client.missed_heartbeats.borrow();
+                if missed_limit != 0 && missed >= missed_limit {
+                    log::info!(
+                        "Disconnecting {} after {missed} unanswered heartbeats",
+                        client.addr.ip()
+                    );
+                    client.kill();
+                    continue;
+                }
+
+                log::debug!("Sending heartbeat to {}", client.addr.ip());
                 let packet = AWPacket::new(PacketType::Heartbeat);
                 client.connection.send(packet);
+                client.record_heartbeat_sent();
                 client.last_heartbeat = now;
             }
         }
     }
 
+    /// Warn tourists and trial citizens whose session is nearly over, and disconnect
+    /// those whose time is up.
+    pub fn expire_sessions(&self, config: &Config) {
+        for client in &self.clients {
+            let mut info = client.info_mut();
+            let client_type = info.client_type;
+            let player = match &mut info.entity {
+                Some(Entity::Player(player)) => player,
+                _ => continue,
+            };
+
+            let (max_session_secs, warning_secs, expired_message, session, remedy) =
+                match client_type {
+                    Some(ClientType::Tourist) => (
+                        config.tourists.max_session_secs,
+                        config.tourists.session_warning_secs,
+                        &config.tourists.session_expired_message,
+                        "tourist session",
+                        "Register as a citizen",
+                    ),
+                    Some(ClientType::Citizen) if player.trial => (
+                        config.trial.max_session_secs,
+                        config.trial.session_warning_secs,
+                        &config.trial.session_expired_message,
+                        "trial session",
+                        "Upgrade your citizenship",
+                    ),
+                    _ => continue,
+                };
+
+            let max_session = Duration::from_secs(max_session_secs);
+            if max_session.is_zero() {
+                continue;
+            }
+            let warning = Duration::from_secs(warning_secs);
+
+            let elapsed = player.session_start.elapsed();
+            if elapsed >= max_session {
+                log::info!(
+                    "Disconnecting {} ({}) at the end of their session",
+                    player.username,
+                    client.addr.ip()
+                );
+                packet_handler::send_console_message(client, expired_message);
+                client.kill();
+            } else if !player.session_warned && elapsed + warning >= max_session {
+                player.session_warned = true;
+                let minutes = (max_session - elapsed).as_secs().div_ceil(60);
+                packet_handler::send_console_message(
+                    client,
+                    &format!(
+                        "Your {session} ends in {minutes} minute(s). {remedy} to stay longer."
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Find a world by name. If it is running on several servers, the least
+    /// loaded one is returned.
     pub fn get_world_by_name(&self, name: &str) -> Option<World> {
-        for client in self.clients() {
+        self.get_world_replicas(name)
+            .into_iter()
+            .min_by_key(|world| (world.load(), world.user_count))
+    }
+
+    /// Every running copy of a world on servers which are still connected.
+    pub fn get_world_replicas(&self, name: &str) -> Vec<World> {
+        let mut replicas = Vec::<World>::new();
+        for client in self.clients().iter().filter(|x| !x.is_dead()) {
             if let Some(Entity::WorldServer(server)) = &client.info().entity {
-                for world in &server.worlds {
-                    if world.name.eq_ignore_ascii_case(name) {
-                        return Some(world.clone());
+                if let Some(world) = server.get_world(name) {
+                    replicas.push(world.clone());
+                }
+            }
+        }
+        replicas
+    }
+
+    /// A world as it should appear in world lists, with its replicas combined.
+    pub fn get_world_listing(&self, name: &str) -> Option<World> {
+        World::merge_replicas(&self.get_world_replicas(name))
+    }
+
+    /// Count the players the universe has identified as being in one copy of a
+    /// world, on the server running it.
+    pub fn count_players_in_replica(&self, world: &World) -> u32 {
+        let address = SocketAddr::new(world.ip, world.port);
+        let mut count = 0;
+        for client in self.clients() {
+            if let Some(Entity::Player(player)) = &client.info().entity {
+                let in_world = player
+                    .world
+                    .as_ref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(&world.name));
+                if in_world && player.world_address == Some(address) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Count the players the universe has identified as being in a world.
+    pub fn count_players_in_world(&self, world_name: &str) -> u32 {
+        let mut count = 0;
+        for client in self.clients() {
+            if let Some(Entity::Player(player)) = &client.info().entity {
+                if let Some(player_world) = &player.world {
+                    if player_world.eq_ignore_ascii_case(world_name) {
+                        count += 1;
                     }
                 }
             }
         }
-        None
+        count
     }
 
     pub fn get_world_infos(&self) -> Vec<World> {
@@ -336,7 +909,14 @@ impl ClientManager {
         let mut world_list = Vec::<World>::new();
         for client in self.clients() {
             if let Some(Entity::WorldServer(world_server)) = &client.info().entity {
-                world_list.extend(world_server.worlds.clone());
+                for world in &world_server.worlds {
+                    let listed = world_list
+                        .iter()
+                        .any(|x| x.name.eq_ignore_ascii_case(&world.name));
+                    if !listed {
+                        world_list.extend(self.get_world_listing(&world.name));
+                    }
+                }
             }
         }
         world_list
@@ -354,22 +934,21 @@ impl ClientManager {
     }
 }
 
-fn check_valid_name(name: &str, is_tourist: bool) -> Result<(), ReasonCode> {
-    let mut name = name.to_string();
+fn check_tourist_name(name: &str, rules: &TouristConfig) -> Result<(), ReasonCode> {
+    // Tourist names must start and end with quotes
+    let name = name
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+        .ok_or(ReasonCode::NoSuchCitizen)?;
 
-    if is_tourist {
-        // Tourist names must start and end with quotes
-        if !name.starts_with('"') || !name.ends_with('"') {
-            return Err(ReasonCode::NoSuchCitizen);
-        }
+    let length = name.chars().count();
 
-        // Strip quotes to continue check
-        name.remove(0);
-        name.remove(name.len() - 1);
+    if length < rules.minimum_length {
+        return Err(ReasonCode::NameTooShort);
     }
 
-    if name.len() < 2 {
-        return Err(ReasonCode::NameTooShort);
+    if rules.maximum_length != 0 && length > rules.maximum_length {
+        return Err(ReasonCode::NameTooLong);
     }
 
     if name.ends_with(' ') {
@@ -380,9 +959,284 @@ fn check_valid_name(name: &str, is_tourist: bool) -> Result<(), ReasonCode> {
         return Err(ReasonCode::NameContainsInvalidBlank);
     }
 
-    if !name.chars().all(char::is_alphanumeric) {
+    let valid_char = |c: char| {
+        let alphanumeric = match rules.ascii_only {
+            true => c.is_ascii_alphanumeric(),
+            false => c.is_alphanumeric(),
+        };
+        alphanumeric || rules.extra_characters.contains(c)
+    };
+
+    if !name.chars().all(valid_char) {
         return Err(ReasonCode::NameContainsNonalphanumericChar);
     }
 
+    if !name.starts_with(&rules.required_prefix) || !name.ends_with(&rules.required_suffix) {
+        return Err(ReasonCode::NoSuchCitizen);
+    }
+
+    if (!rules.forbidden_prefix.is_empty() && name.starts_with(&rules.forbidden_prefix))
+        || (!rules.forbidden_suffix.is_empty() && name.ends_with(&rules.forbidden_suffix))
+    {
+        return Err(ReasonCode::NoSuchCitizen);
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+impl Client {
+    /// A client connected over loopback, for tests. Nothing reads what is sent to it.
+    pub fn loopback() -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let addr = stream.local_addr().unwrap();
+        Self::new(AWConnection::new(aw_core::AWProtocol::new(stream)), addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{WorldRating, WorldStatus};
+
+    fn world_server(port: u16, world_name: &str) -> Client {
+        let client = Client::loopback();
+        let world = World {
+            name: world_name.to_string(),
+            status: WorldStatus::Permitted,
+            rating: WorldRating::G,
+            ip: client.addr.ip(),
+            port,
+            max_users: 0,
+            world_size: 0,
+            user_count: 0,
+        };
+        client.info_mut().client_type = Some(ClientType::World);
+        client.info_mut().entity = Some(Entity::WorldServer(WorldServerInfo {
+            build: 0,
+            version: None,
+            server_port: port,
+            worlds: vec![world],
+        }));
+        client
+    }
+
+    fn player_in(server: &Client, port: u16, citizen_id: Option<u32>) -> Client {
+        let client = Client::loopback();
+        let mut entity = match citizen_id {
+            Some(id) => Entity::new_citizen(
+                id,
+                None,
+                id as u16,
+                0,
+                "Citizen",
+                client.addr.ip(),
+                GroupRights::empty(),
+            ),
+            None => Entity::new_tourist(100, 0, "\"Tourist\"", client.addr.ip()),
+        };
+        if let Entity::Player(player) = &mut entity {
+            player.world = Some("Test".to_string());
+            player.world_address = Some(SocketAddr::new(server.addr.ip(), port));
+        }
+        client.info_mut().entity = Some(entity);
+        client
+    }
+
+    fn citizen_with_rights(citizen_id: u32, rights: GroupRights) -> Client {
+        let client = Client::loopback();
+        client.info_mut().client_type = Some(ClientType::Citizen);
+        client.info_mut().entity = Some(Entity::new_citizen(
+            citizen_id,
+            None,
+            1,
+            0,
+            "Citizen",
+            client.addr.ip(),
+            rights,
+        ));
+        client
+    }
+
+    #[test]
+    pub fn test_role_permissions() {
+        let citizen_admin = citizen_with_rights(5, GroupRights::CITIZEN_ADMIN);
+        assert!(citizen_admin.has_permission(GroupRights::CITIZEN_ADMIN));
+        assert!(!citizen_admin.has_permission(GroupRights::WORLD_ADMIN));
+        assert!(!citizen_admin.has_admin_permissions());
+
+        let admin = citizen_with_rights(6, GroupRights::ADMIN);
+        assert!(admin.has_permission(GroupRights::CITIZEN_ADMIN | GroupRights::BROADCAST));
+        assert!(admin.has_admin_permissions());
+
+        let administrator = citizen_with_rights(1, GroupRights::empty());
+        assert!(administrator.has_permission(GroupRights::EJECT_ADMIN));
+
+        let citizen = citizen_with_rights(7, GroupRights::empty());
+        assert!(!citizen.has_permission(GroupRights::CITIZEN_ADMIN));
+
+        let tourist = Client::loopback();
+        tourist.info_mut().entity =
+            Some(Entity::new_tourist(2, 0, "\"Tourist\"", tourist.addr.ip()));
+        assert!(!tourist.has_permission(GroupRights::BROADCAST));
+    }
+
+    #[test]
+    pub fn test_manage_citizens() {
+        assert!(is_admin_citizen(1, GroupRights::empty()));
+        assert!(is_admin_citizen(
+            5,
+            GroupRights::ADMIN | GroupRights::BROADCAST
+        ));
+        assert!(!is_admin_citizen(5, GroupRights::CITIZEN_ADMIN));
+
+        // Citizens whose groups cannot be checked are only managed by admins
+        let database = Database::unreachable();
+        let citizen_admin = citizen_with_rights(5, GroupRights::CITIZEN_ADMIN);
+        assert!(!citizen_admin.may_manage_citizen(1, &database));
+        assert!(!citizen_admin.may_manage_citizen(8, &database));
+
+        let admin = citizen_with_rights(6, GroupRights::ADMIN);
+        assert!(admin.may_manage_citizen(1, &database));
+        assert!(admin.may_manage_citizen(8, &database));
+
+        let citizen = citizen_with_rights(7, GroupRights::empty());
+        assert!(!citizen.may_manage_citizen(8, &database));
+    }
+
+    #[test]
+    pub fn test_world_server_disconnect() {
+        let database = Database::unreachable();
+        let events = EventQueue::default();
+        let mut client_manager = ClientManager::default();
+
+        let server = world_server(6670, "Test");
+        let citizen = player_in(&server, 6670, Some(5));
+        let tourist = player_in(&server, 6670, None);
+        server.kill();
+        client_manager.add_client(server);
+        client_manager.add_client(citizen);
+        client_manager.add_client(tourist);
+
+        client_manager.remove_dead_clients(&database, &events);
+
+        assert_eq!(client_manager.clients().len(), 2);
+        for client in client_manager.clients() {
+            match &client.info().entity {
+                Some(Entity::Player(player)) => {
+                    assert_eq!(player.world, None);
+                    assert_eq!(player.world_address, None);
+                }
+                _ => panic!("Only the players should be left"),
+            }
+        }
+        assert!(client_manager.get_world_by_name("Test").is_none());
+    }
+
+    #[test]
+    pub fn test_replica_full() {
+        let mut client_manager = ClientManager::default();
+        let first = world_server(6670, "Test");
+        let second = world_server(6671, "Test");
+        for server in [&first, &second] {
+            if let Some(Entity::WorldServer(info)) = &mut server.info_mut().entity {
+                info.worlds[0].max_users = 2;
+            }
+        }
+        let players: Vec<Client> = (1..=2)
+            .map(|id| player_in(&first, 6670, Some(id)))
+            .collect();
+        client_manager.add_client(first);
+        client_manager.add_client(second);
+        for player in players {
+            client_manager.add_client(player);
+        }
+
+        let replicas = client_manager.get_world_replicas("Test");
+        let (full, empty) = match replicas[0].port {
+            6670 => (&replicas[0], &replicas[1]),
+            _ => (&replicas[1], &replicas[0]),
+        };
+        assert!(full.is_full(client_manager.count_players_in_replica(full)));
+        assert!(!empty.is_full(client_manager.count_players_in_replica(empty)));
+
+        // Across both servers there is still room
+        let listing = client_manager.get_world_listing("Test").unwrap();
+        assert!(!listing.is_full(client_manager.count_players_in_world("Test")));
+    }
+
+    #[test]
+    pub fn test_other_servers_keep_their_players() {
+        let database = Database::unreachable();
+        let events = EventQueue::default();
+        let mut client_manager = ClientManager::default();
+
+        let leaving = world_server(6670, "Test");
+        let staying = world_server(6671, "Test");
+        let player = player_in(&staying, 6671, Some(5));
+        leaving.kill();
+        client_manager.add_client(leaving);
+        client_manager.add_client(staying);
+        client_manager.add_client(player);
+
+        client_manager.remove_dead_clients(&database, &events);
+
+        let player = client_manager.get_client_by_citizen_id(5).unwrap();
+        match &player.info().entity {
+            Some(Entity::Player(player)) => assert_eq!(player.world.as_deref(), Some("Test")),
+            _ => panic!("Expected a player"),
+        }
+        assert!(client_manager.get_world_by_name("Test").is_some());
+    }
+
+    #[test]
+    pub fn test_session_expiry() {
+        let mut config = Config::default();
+        config.tourists.max_session_secs = 600;
+        config.trial.max_session_secs = 600;
+        config.trial.session_warning_secs = 300;
+
+        let started = |client: &Client, secs: u64| {
+            if let Some(Entity::Player(player)) = &mut client.info_mut().entity {
+                player.session_start = Instant::now() - Duration::from_secs(secs);
+            }
+        };
+
+        let tourist = Client::loopback();
+        tourist.info_mut().client_type = Some(ClientType::Tourist);
+        tourist.info_mut().entity =
+            Some(Entity::new_tourist(1, 0, "\"Tourist\"", tourist.addr.ip()));
+        started(&tourist, 601);
+
+        let trial = citizen_with_rights(2, GroupRights::empty());
+        if let Some(Entity::Player(player)) = &mut trial.info_mut().entity {
+            player.trial = true;
+        }
+        started(&trial, 400);
+
+        let citizen = citizen_with_rights(3, GroupRights::empty());
+        started(&citizen, 100_000);
+
+        let mut client_manager = ClientManager::default();
+        client_manager.add_client(tourist);
+        client_manager.add_client(trial);
+        client_manager.add_client(citizen);
+        client_manager.expire_sessions(&config);
+
+        let clients = client_manager.clients();
+        // The tourist's time is up, and the trial citizen is warned theirs nearly is
+        assert!(clients[0].is_dead());
+        assert!(!clients[1].is_dead());
+        match &clients[1].info().entity {
+            Some(Entity::Player(player)) => assert!(player.session_warned),
+            _ => panic!("Expected a player"),
+        }
+        // Citizens who are not on a trial stay as long as they like
+        assert!(!clients[2].is_dead());
+
+        started(&clients[1], 600);
+        client_manager.expire_sessions(&config);
+        assert!(client_manager.clients()[1].is_dead());
+    }
+}
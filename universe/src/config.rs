@@ -1,13 +1,76 @@
-use std::net::Ipv4Addr;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4},
+    time::Duration,
+};
 
+use aw_core::{encoding::Codepage, QueueLimits, ReasonCode};
 use serde::{Deserialize, Serialize};
 const UNIVERSE_CONFIG_PATH: &str = "universe.toml";
+/// Start of the names of environment variables which override settings
+const ENV_PREFIX: &str = "AW_";
 
 /// Struct representing all configurations in the config file.
 #[derive(Deserialize, Serialize, Debug, Default)]
 pub struct Config {
     pub universe: UniverseConfig,
     pub mysql: MysqlConfig,
+    #[serde(default)]
+    pub news: NewsConfig,
+    #[serde(default)]
+    pub tourists: TouristConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub scripting: ScriptingConfig,
+    #[serde(default)]
+    pub email: EmailConfig,
+    #[serde(default)]
+    pub cav: CavConfig,
+    #[serde(default)]
+    pub profile: ProfileConfig,
+    #[serde(default)]
+    pub headers: HeaderConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub credits: CreditConfig,
+    #[serde(default)]
+    pub digest: DigestConfig,
+    #[serde(default)]
+    pub event_bus: EventBusConfig,
+    #[serde(default)]
+    pub presence: PresenceConfig,
+    #[serde(default)]
+    pub xfer: XferConfig,
+    #[serde(default)]
+    pub console: ConsoleConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub login_throttle: LoginThrottleConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub browsers: BrowserConfig,
+    #[serde(default)]
+    pub accounts: AccountConfig,
+    #[serde(default)]
+    pub expiration: ExpirationConfig,
+    #[serde(default)]
+    pub trial: TrialConfig,
+    /// Explanatory text sent alongside a reason code, keyed by the reason's
+    /// name (e.g. "CitizenDisabled") or number (e.g. "77").
+    #[serde(default)]
+    pub reason_messages: HashMap<String, String>,
+    /// Messages sent to every user's chat window on a schedule
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub announcements: Vec<AnnouncementConfig>,
+    /// URLs which are sent events as they happen
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
 }
 
 /// Configuration section for the universe
@@ -17,31 +80,870 @@ pub struct UniverseConfig {
     pub port: u16,
     pub user_list: bool,
     pub allow_citizen_changes: bool,
+    /// Addresses which may connect as world servers. None may connect if empty.
+    #[serde(default = "default_world_server_allowlist")]
+    pub world_server_allowlist: Vec<IpAddr>,
+    /// Character set of strings sent by clients: "latin1" or "windows1252"
+    #[serde(default)]
+    pub codepage: Codepage,
+    /// Pass join requests on to citizens in do not disturb mode instead of declining them
+    #[serde(default)]
+    pub allow_joins_when_dnd: bool,
+    /// Let several world servers run the same world, with users sent to the least loaded one
+    #[serde(default)]
+    pub allow_world_replicas: bool,
+    /// Send each connection a random challenge which must be returned with its
+    /// login, so captured logins cannot be replayed. Browsers of this build or
+    /// newer must return it; older ones may. 0 disables challenges.
+    #[serde(default)]
+    pub login_challenge_minimum_build: i32,
+    /// World servers older than this build are reported to admins. 0 disables the check.
+    #[serde(default)]
+    pub minimum_world_server_build: i32,
+    /// Disconnect world servers older than `minimum_world_server_build` instead of only warning
+    #[serde(default)]
+    pub refuse_outdated_world_servers: bool,
+    /// Let new users create their own citizen accounts from the browser. This is
+    /// the starting value of the registration method attribute, which attribute
+    /// admins can change while the universe runs.
+    #[serde(default)]
+    pub allow_immigration: bool,
+    /// Most citizens which may immigrate from one address within
+    /// `immigration_window_secs` (0 for no limit)
+    #[serde(default = "default_immigrations_per_ip")]
+    pub immigrations_per_ip: u32,
+    #[serde(default = "default_immigration_window_secs")]
+    pub immigration_window_secs: u64,
+    /// Address clients are told to reach the universe at, if it differs from `ip`
+    /// (e.g. when listening on 0.0.0.0 behind NAT)
+    #[serde(default)]
+    pub advertised_ip: Option<Ipv4Addr>,
+    /// Port clients are told to reach the universe at (0 for the same as `port`)
+    #[serde(default)]
+    pub advertised_port: u16,
+    /// What happens when a citizen logs in while already logged in elsewhere:
+    /// "reject" the new login, or "replace" the older session
+    #[serde(default)]
+    pub duplicate_login: DuplicateLogin,
+    /// Most users and bots which may be logged in at once (0 for no limit)
+    #[serde(default)]
+    pub max_clients: usize,
+    /// Most connections which may be open from one address (0 for no limit).
+    /// Addresses in `world_server_allowlist` are not limited.
+    #[serde(default)]
+    pub max_connections_per_ip: usize,
+    /// Also accept clients on this IPv6 address (e.g. `::`), on the same port as `ip`
+    #[serde(default)]
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+/// How to handle a citizen logging in while they already have a session.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateLogin {
+    /// Refuse the new login
+    #[default]
+    Reject,
+    /// Disconnect the existing session and let the new one in
+    Replace,
+}
+
+impl UniverseConfig {
+    /// Whether a world server connecting from the given address should be accepted.
+    pub fn world_server_allowed(&self, ip: IpAddr) -> bool {
+        self.world_server_allowlist.contains(&ip)
+    }
+
+    /// The address the universe presents to clients.
+    pub fn advertised_address(&self) -> SocketAddrV4 {
+        let port = match self.advertised_port {
+            0 => self.port,
+            port => port,
+        };
+        SocketAddrV4::new(self.advertised_ip.unwrap_or(self.ip), port)
+    }
+
+    /// The address clients should use for a world server. World servers running on
+    /// the universe's own machine are reached through its advertised address.
+    pub fn world_server_address(&self, ip: IpAddr) -> IpAddr {
+        match self.advertised_ip {
+            Some(advertised) if ip.is_loopback() => IpAddr::V4(advertised),
+            _ => ip,
+        }
+    }
+
+    /// Whether a world server of the given build is older than the configured minimum.
+    pub fn world_server_outdated(&self, build: i32) -> bool {
+        self.minimum_world_server_build != 0 && build < self.minimum_world_server_build
+    }
 }
 
 /// Configuation section for the mysql connection
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct MysqlConfig {
     pub hostname: String,
     pub port: u16,
     pub username: String,
     pub password: String,
     pub database: String,
+    /// Connections the pool keeps open even when idle (0 for the default)
+    #[serde(default)]
+    pub pool_min_connections: usize,
+    /// Most connections the pool will open at once (0 for the default)
+    #[serde(default)]
+    pub pool_max_connections: usize,
+    /// Seconds to wait when opening a connection to the server (0 for no limit)
+    #[serde(default)]
+    pub connect_timeout_secs: u64,
+}
+
+/// Configuration section for the news page opened by browsers on login
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct NewsConfig {
+    /// URL to open on login. Nothing is sent if this is empty.
+    pub url: String,
+    /// Oldest browser build which should be sent the URL (0 for no minimum)
+    pub minimum_build: i32,
+    /// Newest browser build which should be sent the URL (0 for no maximum)
+    pub maximum_build: i32,
+    /// Only send the URL to citizens who have not logged in since it last changed
+    pub only_when_changed: bool,
+    /// Unix timestamp of the last time the news content changed
+    pub last_changed: u32,
+}
+
+/// Configuration section for socket options applied to accepted connections.
+/// A value of 0 leaves the operating system default in place.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Seconds of idle time before TCP keepalive probes are sent
+    pub keepalive_secs: u64,
+    /// Seconds to wait on a read before the connection is considered dead
+    pub read_timeout_secs: u64,
+    /// Seconds to wait on a write before the connection is considered dead
+    pub write_timeout_secs: u64,
+    /// Size of the socket receive buffer in bytes
+    pub recv_buffer_size: usize,
+    /// Size of the socket send buffer in bytes
+    pub send_buffer_size: usize,
+    /// Disable Nagle's algorithm
+    pub nodelay: bool,
+    /// Bytes to fill each packet group to when sending lists, from 4096 to 32767 (0 for the largest)
+    pub packet_group_size: usize,
+    /// Seconds between logging packet group statistics (0 to disable)
+    pub stats_log_secs: u64,
+    /// Queued outgoing messages above which list updates are dropped
+    pub send_queue_high_water: usize,
+    /// Queued outgoing messages at which everything is dropped
+    pub send_queue_max: usize,
+    /// Seconds a client's send queue may stay backed up before it is disconnected
+    pub send_stall_secs: u64,
+    /// Maximum bytes per second sent to each client (0 for no limit)
+    pub client_send_rate: u64,
+    /// Malformed packet score at which a client is disconnected (0 for the default).
+    /// Each malformed packet scores 10 and each good packet takes 1 off.
+    pub malformed_limit: u32,
+    /// Expect a PROXY protocol (version 1 or 2) header at the start of every connection
+    /// and take the client's address from it. Connections without one are refused.
+    pub proxy_protocol: bool,
+    /// Addresses allowed to send PROXY headers. Required when `proxy_protocol` is on.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Heartbeats in a row a client may leave unanswered before it is disconnected
+    /// (0 to never disconnect clients for it)
+    pub missed_heartbeat_limit: u32,
+}
+
+impl NetworkConfig {
+    /// Send queue limits, using the defaults for any which are 0.
+    pub fn queue_limits(&self) -> QueueLimits {
+        let default = QueueLimits::default();
+        QueueLimits {
+            high_water: match self.send_queue_high_water {
+                0 => default.high_water,
+                x => x,
+            },
+            max: match self.send_queue_max {
+                0 => default.max,
+                x => x,
+            },
+            stall_timeout: match self.send_stall_secs {
+                0 => default.stall_timeout,
+                x => Duration::from_secs(x),
+            },
+        }
+    }
+}
+
+/// Configuration section for outgoing email
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct EmailConfig {
+    pub enabled: bool,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    /// Upgrade the connection to TLS with STARTTLS
+    pub starttls: bool,
+    /// Leave empty to send without authenticating
+    pub username: String,
+    pub password: String,
+    /// Sender address, e.g. "Universe <universe@example.com>"
+    pub from: String,
+    /// Seconds between emails of telegrams received while offline (0 to disable)
+    pub telegram_interval_secs: u64,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            smtp_host: "localhost".to_string(),
+            smtp_port: 25,
+            starttls: false,
+            username: String::new(),
+            password: String::new(),
+            from: "universe@localhost".to_string(),
+            telegram_interval_secs: 900,
+        }
+    }
+}
+
+/// Configuration section for the public web pages which citizens follow links
+/// from their email to
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct AccountConfig {
+    pub enabled: bool,
+    pub ip: IpAddr,
+    pub port: u16,
+    /// Address the pages are reached at from outside, used to build links in emails
+    pub public_url: String,
+    /// Keep citizens who immigrate disabled until they follow a link emailed to them
+    pub verify_email: bool,
+    /// Seconds an emailed link can be used for
+    pub link_valid_secs: u32,
+    /// Seconds before another password reset may be asked for from the same
+    /// address or for the same citizen (0 for no limit)
+    pub reset_interval_secs: u64,
+}
+
+impl Default for AccountConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 6681,
+            public_url: "http://localhost:6681".to_string(),
+            verify_email: false,
+            link_valid_secs: 86400,
+            reset_interval_secs: 300,
+        }
+    }
+}
+
+/// Configuration section for citizens whose citizenship has expired
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct ExpirationConfig {
+    /// Seconds after expiring that a citizen may still log in as usual
+    pub grace_period_secs: u32,
+    /// Let expired citizens in as tourists instead of refusing them
+    pub expired_as_tourist: bool,
+}
+
+impl ExpirationConfig {
+    /// Seconds a citizen with the given expiration has left to log in, or None if it
+    /// has not expired. Zero means it has expired and the grace period is over.
+    pub fn grace_remaining(&self, expiration: u32, now: u32) -> Option<u32> {
+        if expiration == 0 || now <= expiration {
+            return None;
+        }
+
+        Some(
+            expiration
+                .saturating_add(self.grace_period_secs)
+                .saturating_sub(now),
+        )
+    }
+}
+
+/// Configuration section for what citizens on a trial may not do
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct TrialConfig {
+    /// Let trial citizens set a privilege password, which bots and acting users log in with
+    pub privilege_password: bool,
+    /// Most bots a trial citizen may run, lowering their own bot limit if it is higher
+    pub max_bots: Option<u32>,
+    /// Longest a trial citizen may stay connected, in seconds (0 for no limit)
+    pub max_session_secs: u64,
+    /// How long before the end of a session to warn the citizen, in seconds
+    pub session_warning_secs: u64,
+    /// Sent to a trial citizen when their session ends
+    pub session_expired_message: String,
+}
+
+impl Default for TrialConfig {
+    fn default() -> Self {
+        Self {
+            privilege_password: true,
+            max_bots: None,
+            max_session_secs: 0,
+            session_warning_secs: 300,
+            session_expired_message:
+                "Your trial session is over. Upgrade your citizenship to stay longer.".to_string(),
+        }
+    }
+}
+
+impl TrialConfig {
+    /// Whether a citizen's privilege password may be used to log in bots or act as them.
+    pub fn allows_privilege_password(&self, trial: bool) -> bool {
+        !trial || self.privilege_password
+    }
+
+    /// Most bots a citizen with the given limit of their own may have logged in at once.
+    pub fn bot_limit(&self, trial: bool, bot_limit: u32) -> u32 {
+        match self.max_bots {
+            Some(max_bots) if trial => bot_limit.min(max_bots),
+            _ => bot_limit,
+        }
+    }
+}
+
+/// Configuration section for custom avatars
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct CavConfig {
+    /// Largest accepted avatar definition in bytes
+    pub max_definition_size: usize,
+}
+
+impl Default for CavConfig {
+    fn default() -> Self {
+        Self {
+            max_definition_size: 4000,
+        }
+    }
+}
+
+/// Configuration section for extra citizen profile fields
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct ProfileConfig {
+    /// Fields anyone may see. Other fields are only shown to the citizen and admins.
+    pub public_fields: Vec<String>,
+    /// Fields citizens may set on their own profile. Admins may set any field.
+    pub editable_fields: Vec<String>,
+    /// Longest allowed field name
+    pub max_name_length: usize,
+    /// Longest allowed field value
+    pub max_value_length: usize,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        let fields = vec!["pronouns".to_string(), "homepage".to_string()];
+        Self {
+            public_fields: fields.clone(),
+            editable_fields: fields,
+            max_name_length: 64,
+            max_value_length: 255,
+        }
+    }
+}
+
+impl ProfileConfig {
+    /// Check that a field name and value are acceptable to store.
+    pub fn check_field(&self, name: &str, value: &str) -> Result<(), ReasonCode> {
+        if name.is_empty() || name.len() > self.max_name_length {
+            return Err(ReasonCode::InvalidRequest);
+        }
+        if value.len() > self.max_value_length {
+            return Err(ReasonCode::TooManyBytes);
+        }
+        Ok(())
+    }
+}
+
+/// Configuration section for citizen credit balances
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct CreditConfig {
+    pub enabled: bool,
+    /// Let adjustments take a balance below zero
+    pub allow_negative: bool,
+}
+
+/// Configuration section for the daily activity digest sent to admins
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct DigestConfig {
+    pub enabled: bool,
+    /// Hour of the day (UTC) to send the digest
+    pub hour: u8,
+    /// Also email the digest to admins with an email address
+    pub email: bool,
+}
+
+/// Message broker protocols events can be published over
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BusProtocol {
+    #[default]
+    Nats,
+    Mqtt,
+}
+
+/// Configuration section for publishing events to a message broker
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct EventBusConfig {
+    pub enabled: bool,
+    /// "nats" or "mqtt"
+    pub protocol: BusProtocol,
+    pub host: String,
+    /// 0 for the protocol's usual port
+    pub port: u16,
+    /// Start of every subject or topic events are published to
+    pub topic_prefix: String,
+    pub client_id: String,
+    /// Leave empty to connect without authenticating
+    pub username: String,
+    pub password: String,
+}
+
+impl Default for EventBusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            protocol: BusProtocol::default(),
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            topic_prefix: "awuniverse".to_string(),
+            client_id: "awuniverse".to_string(),
+            username: String::new(),
+            password: String::new(),
+        }
+    }
+}
+
+impl EventBusConfig {
+    pub fn port(&self) -> u16 {
+        match (self.port, self.protocol) {
+            (0, BusProtocol::Nats) => 4222,
+            (0, BusProtocol::Mqtt) => 1883,
+            (port, _) => port,
+        }
+    }
+}
+
+/// Configuration section for sharing who is online through Redis
+/// (requires the redis-presence feature)
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct PresenceConfig {
+    pub enabled: bool,
+    pub redis_url: String,
+    /// Start of every key the universe stores
+    pub key_prefix: String,
+    /// Name of this universe process among those sharing the store.
+    /// Defaults to the universe's address and port.
+    pub instance_id: String,
+    /// Seconds between refreshes of the shared view
+    pub refresh_secs: u64,
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            redis_url: "redis://127.0.0.1/".to_string(),
+            key_prefix: "awuniverse".to_string(),
+            instance_id: String::new(),
+            refresh_secs: 30,
+        }
+    }
+}
+
+/// Configuration section for chunked transfers of large payloads
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct XferConfig {
+    /// Largest payload in bytes that may be uploaded
+    pub max_size: usize,
+    /// Largest chunk in bytes sent or accepted in one packet
+    pub chunk_size: usize,
+    /// Seconds an unfinished upload is kept for resuming
+    pub timeout_secs: u64,
+    /// Most unfinished uploads each citizen may have at once
+    pub max_uploads: usize,
+}
+
+impl Default for XferConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 65536,
+            chunk_size: 4096,
+            timeout_secs: 600,
+            max_uploads: 4,
+        }
+    }
+}
+
+/// Configuration section for limiting how quickly each connection may send packets.
+/// Every packet costs one token from a bucket which refills over time.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Tokens added to each connection's bucket per second
+    pub packets_per_sec: f64,
+    /// Most tokens a bucket can hold, which allows short bursts
+    pub burst: f64,
+    /// Tokens taken for packets which are expensive to handle, such as logins and lookups
+    pub expensive_cost: f64,
+    /// Dropped packets after which the connection is closed (0 to never disconnect)
+    pub disconnect_after: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            packets_per_sec: 20.0,
+            burst: 100.0,
+            expensive_cost: 5.0,
+            disconnect_after: 500,
+        }
+    }
+}
+
+/// Configuration section for slowing down and locking out repeated failed logins
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct LoginThrottleConfig {
+    pub enabled: bool,
+    /// Failed logins from an address or for a name before logins have to wait
+    pub free_attempts: u32,
+    /// Seconds to wait after the first failure past the free attempts, doubling with each one after
+    pub base_delay_secs: u64,
+    /// Longest wait in seconds between attempts
+    pub max_delay_secs: u64,
+    /// Seconds after the last failure that an address or name is forgiven
+    pub forget_after_secs: u64,
+    /// Wrong passwords for a citizen in a row before their account is locked until
+    /// an admin unlocks it (0 to never lock accounts)
+    pub lock_after: u32,
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            free_attempts: 3,
+            base_delay_secs: 2,
+            max_delay_secs: 300,
+            forget_after_secs: 3600,
+            lock_after: 0,
+        }
+    }
+}
+
+/// Configuration section for stopping the universe with Ctrl-C or SIGTERM
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// Sent to every user's chat window before they are disconnected (empty to send nothing)
+    pub message: String,
+    /// Longest time in seconds to wait for queued packets to reach clients
+    pub drain_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            message: "The universe is shutting down.".to_string(),
+            drain_secs: 5,
+        }
+    }
+}
+
+/// Configuration section for which browser builds may log in
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct BrowserConfig {
+    /// Browsers older than this build are told to upgrade. 0 disables the check.
+    pub minimum_build: i32,
+    /// If not empty, only these builds may log in
+    pub allowed_builds: Vec<i32>,
+    /// Where browsers which are refused can get a newer version (empty to send nothing)
+    pub upgrade_url: String,
+}
+
+impl BrowserConfig {
+    /// Whether a browser of the given build may log in.
+    pub fn allows_build(&self, build: i32) -> bool {
+        if self.minimum_build != 0 && build < self.minimum_build {
+            return false;
+        }
+
+        self.allowed_builds.is_empty() || self.allowed_builds.contains(&build)
+    }
+}
+
+/// A message sent to everyone on a schedule. Exactly one of `at`, `daily_at`
+/// and `interval_secs` says when.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnnouncementConfig {
+    pub message: String,
+    /// Unix time to send the message once
+    pub at: u64,
+    /// Time of day (UTC) to send the message every day, as "HH:MM"
+    pub daily_at: String,
+    /// Seconds between repeats of the message
+    pub interval_secs: u64,
+}
+
+/// What a webhook is sent for each event.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// The event as JSON, the same as it is published to the event bus
+    #[default]
+    Json,
+    /// A Discord message describing the event
+    Discord,
+    /// A Slack message describing the event
+    Slack,
+}
+
+/// A URL events are POSTed to.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Types of event to send, such as "login" or "world_start". Every event is sent if empty.
+    pub events: Vec<String>,
+    pub format: WebhookFormat,
+}
+
+impl WebhookConfig {
+    /// Whether this webhook should be sent events of a type.
+    pub fn wants(&self, kind: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|x| x == kind)
+    }
+}
+
+/// How log records are written.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, with the client's session and citizen number
+    Json,
+}
+
+/// Configuration section for log output. The level is set with `--log-level`.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    /// File to write the log to. The log goes to standard error if this is empty.
+    pub file: String,
+    /// Size in megabytes at which the log file is rotated (0 to never rotate)
+    pub max_file_size_mb: u64,
+    /// Number of rotated log files to keep
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            file: String::new(),
+            max_file_size_mb: 100,
+            max_files: 5,
+        }
+    }
+}
+
+/// Configuration section for operator commands
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct ConsoleConfig {
+    /// Read commands from standard input
+    pub stdin: bool,
+    /// Local port to accept telnet connections for commands on (0 to disable)
+    pub telnet_port: u16,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            stdin: true,
+            telnet_port: 0,
+        }
+    }
+}
+
+/// Configuration section for the HTTP admin interface
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub enabled: bool,
+    pub ip: IpAddr,
+    pub port: u16,
+    /// Secret which every request must include. The interface will not start without one.
+    pub token: String,
+    /// Serve a web page for managing the universe at `/`
+    pub dashboard: bool,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 6680,
+            token: String::new(),
+            dashboard: false,
+        }
+    }
+}
+
+/// Configuration section for investigating the unknown packet header fields
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct HeaderConfig {
+    /// Count the header values seen for each packet type and log them with the stats
+    pub observe: bool,
+    /// Header values to send instead of the usual ones, keyed by packet type number
+    pub overrides: HashMap<String, HeaderOverrideConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy)]
+#[serde(default)]
+pub struct HeaderOverrideConfig {
+    pub header_0: Option<u16>,
+    pub header_1: Option<u16>,
+}
+
+/// Configuration section for event hooks
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct ScriptingConfig {
+    /// Path to a Lua script defining hooks (requires the scripting feature)
+    pub script: Option<String>,
+}
+
+/// Configuration section for rules about tourist names
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct TouristConfig {
+    /// Minimum length of a name, not including quotes
+    pub minimum_length: usize,
+    /// Maximum length of a name, not including quotes (0 for no maximum)
+    pub maximum_length: usize,
+    /// Only allow ASCII letters and numbers
+    pub ascii_only: bool,
+    /// Characters allowed in names in addition to letters and numbers
+    pub extra_characters: String,
+    /// Text every name must start with
+    pub required_prefix: String,
+    /// Text every name must end with
+    pub required_suffix: String,
+    /// Text no name may start with
+    pub forbidden_prefix: String,
+    /// Text no name may end with
+    pub forbidden_suffix: String,
+    /// Longest a tourist may stay connected, in seconds (0 for no limit)
+    pub max_session_secs: u64,
+    /// How long before the end of a session to warn the tourist, in seconds
+    pub session_warning_secs: u64,
+    /// Sent to a tourist when their session ends
+    pub session_expired_message: String,
+}
+
+impl Default for TouristConfig {
+    fn default() -> Self {
+        Self {
+            minimum_length: 2,
+            maximum_length: 0,
+            ascii_only: false,
+            extra_characters: String::new(),
+            required_prefix: String::new(),
+            required_suffix: String::new(),
+            forbidden_prefix: String::new(),
+            forbidden_suffix: String::new(),
+            max_session_secs: 0,
+            session_warning_secs: 300,
+            session_expired_message:
+                "Your tourist session is over. Register as a citizen to stay longer.".to_string(),
+        }
+    }
+}
+
+impl NewsConfig {
+    /// Whether the news URL should be opened by a browser of the given build.
+    pub fn applies_to_build(&self, build: i32) -> bool {
+        if self.url.is_empty() {
+            return false;
+        }
+
+        if self.minimum_build != 0 && build < self.minimum_build {
+            return false;
+        }
+
+        if self.maximum_build != 0 && build > self.maximum_build {
+            return false;
+        }
+
+        true
+    }
 }
 
 impl Config {
-    /// Read and (if necessary) generate configuation file.
+    /// Read and (if necessary) generate configuation file, then apply any
+    /// overrides from environment variables.
     pub fn get() -> Result<Self, String> {
         let config: Self = match std::fs::read_to_string(UNIVERSE_CONFIG_PATH) {
             Ok(contents) => toml::from_str(&contents).map_err(|e| e.to_string())?,
             Err(_) => Config::default(),
         };
 
+        // Saved before overriding, so secrets given in the environment stay out of the file
         config.save();
 
+        let mut value = toml::Value::try_from(&config).map_err(|e| e.to_string())?;
+        let overrides = apply_env_overrides(&mut value, std::env::vars());
+        let config: Self = value
+            .try_into()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+
+        // Logging is not set up until the config has been read
+        for name in unknown_overrides(&config, &overrides)? {
+            eprintln!("Ignoring {name}, which is not a setting");
+        }
+
         Ok(config)
     }
 
+    /// Get the operator-provided message for a reason code, if any. Names are
+    /// case insensitive, since those set from the environment are lowercased.
+    pub fn reason_message(&self, rc: ReasonCode) -> Option<&str> {
+        let name = format!("{rc:?}");
+        self.reason_messages
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&name))
+            .map(|(_, message)| message)
+            .or_else(|| self.reason_messages.get(&(rc as i32).to_string()))
+            .map(String::as_str)
+    }
+
     /// Write configuation to disk.
     pub fn save(&self) {
         let contents = toml::to_string(&self).unwrap_or_default();
@@ -49,6 +951,96 @@ impl Config {
     }
 }
 
+/// Override settings with variables named `AW_<SECTION>__<SETTING>`, such as
+/// `AW_UNIVERSE__PORT` or `AW_MYSQL__PASSWORD`. Deeper tables take more `__`
+/// separated parts. Names are case insensitive and values are read as TOML,
+/// except for settings which are already strings. Returns the names of the
+/// variables used, with the path of the setting each one names.
+fn apply_env_overrides(
+    config: &mut toml::Value,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Vec<(String, Vec<String>)> {
+    let mut overrides = Vec::new();
+    for (name, raw) in vars {
+        let path = match name.strip_prefix(ENV_PREFIX) {
+            Some(path) if path.contains("__") => path.to_lowercase(),
+            _ => continue,
+        };
+        let keys: Vec<&str> = path.split("__").collect();
+        set_env_override(config, &keys, raw);
+        overrides.push((name, keys.into_iter().map(String::from).collect()));
+    }
+    overrides
+}
+
+/// The variables among `overrides` which name no setting. Settings which are not
+/// known are dropped when the config is read, so they are missing once it is
+/// written out again.
+fn unknown_overrides<'a>(
+    config: &Config,
+    overrides: &'a [(String, Vec<String>)],
+) -> Result<Vec<&'a str>, String> {
+    let value = toml::Value::try_from(config).map_err(|e| e.to_string())?;
+    Ok(overrides
+        .iter()
+        .filter(|(_, keys)| {
+            keys.iter()
+                .try_fold(&value, |value, key| value.get(key.as_str()))
+                .is_none()
+        })
+        .map(|(name, _)| name.as_str())
+        .collect())
+}
+
+fn set_env_override(value: &mut toml::Value, keys: &[&str], raw: String) {
+    let table = match value.as_table_mut() {
+        Some(x) => x,
+        // A setting which isn't a table can't have settings inside it
+        None => return,
+    };
+
+    match keys {
+        [] => {}
+        [last] => {
+            let value = match table.get(*last) {
+                Some(toml::Value::String(_)) => toml::Value::String(raw),
+                _ => parse_env_value(&raw),
+            };
+            table.insert(last.to_string(), value);
+        }
+        [key, rest @ ..] => {
+            let inner = table
+                .entry(key.to_string())
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+            set_env_override(inner, rest, raw);
+        }
+    }
+}
+
+/// Read an environment variable's value as TOML, or as a plain string if it isn't valid TOML.
+fn parse_env_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::value::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+fn default_immigrations_per_ip() -> u32 {
+    3
+}
+
+fn default_immigration_window_secs() -> u64 {
+    24 * 60 * 60
+}
+
+/// Only world servers on the universe's own machine are accepted unless configured.
+fn default_world_server_allowlist() -> Vec<IpAddr> {
+    vec![
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(Ipv6Addr::LOCALHOST),
+    ]
+}
+
 impl Default for UniverseConfig {
     fn default() -> Self {
         Self {
@@ -56,6 +1048,22 @@ impl Default for UniverseConfig {
             port: 6670,
             user_list: true,
             allow_citizen_changes: true,
+            world_server_allowlist: default_world_server_allowlist(),
+            codepage: Codepage::default(),
+            allow_joins_when_dnd: false,
+            allow_world_replicas: false,
+            login_challenge_minimum_build: 0,
+            minimum_world_server_build: 0,
+            refuse_outdated_world_servers: false,
+            allow_immigration: false,
+            immigrations_per_ip: default_immigrations_per_ip(),
+            immigration_window_secs: default_immigration_window_secs(),
+            advertised_ip: None,
+            advertised_port: 0,
+            duplicate_login: DuplicateLogin::default(),
+            max_clients: 0,
+            max_connections_per_ip: 0,
+            ipv6: None,
         }
     }
 }
@@ -68,6 +1076,105 @@ impl Default for MysqlConfig {
             username: "root".to_string(),
             password: "password".to_string(),
             database: "aworld_universe".to_string(),
+            pool_min_connections: 0,
+            pool_max_connections: 0,
+            connect_timeout_secs: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn overridden(env: &[(&str, &str)]) -> (Config, Vec<(String, Vec<String>)>) {
+        let mut value = toml::Value::try_from(Config::default()).unwrap();
+        let overrides = apply_env_overrides(&mut value, vars(env));
+        (value.try_into().unwrap(), overrides)
+    }
+
+    #[test]
+    pub fn test_env_overrides() {
+        let (config, overrides) = overridden(&[
+            ("AW_UNIVERSE__PORT", "7000"),
+            ("AW_MySQL__Password", "1234"),
+            ("AW_TRIAL__MAX_BOTS", "2"),
+            ("AW_REASON_MESSAGES__CITIZENDISABLED", "Ask a caretaker"),
+            ("AW_NOT_A_SECTION", "1"),
+            ("PATH", "/usr/bin"),
+        ]);
+
+        assert_eq!(config.universe.port, 7000);
+        // Settings which are strings stay strings even when they look like numbers
+        assert_eq!(config.mysql.password, "1234");
+        // Settings left out of the file can be set too
+        assert_eq!(config.trial.max_bots, Some(2));
+        assert_eq!(
+            config.reason_message(ReasonCode::CitizenDisabled),
+            Some("Ask a caretaker")
+        );
+        assert_eq!(overrides.len(), 4);
+        assert!(unknown_overrides(&config, &overrides).unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_unknown_env_overrides() {
+        let (config, overrides) = overridden(&[
+            ("AW_UNIVERSE__PORT", "7000"),
+            ("AW_UNIVERSE__PROT", "7000"),
+            ("AW_UNIVERSE__PORT__INNER", "1"),
+            ("AW_NOWHERE__PORT", "1"),
+        ]);
+
+        assert_eq!(config.universe.port, 7000);
+        assert_eq!(
+            unknown_overrides(&config, &overrides).unwrap(),
+            vec![
+                "AW_UNIVERSE__PROT",
+                "AW_UNIVERSE__PORT__INNER",
+                "AW_NOWHERE__PORT"
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_expiration_grace() {
+        let mut config = ExpirationConfig::default();
+
+        // Citizens without an expiration never expire
+        assert_eq!(config.grace_remaining(0, 1000), None);
+        assert_eq!(config.grace_remaining(500, 500), None);
+        // Without a grace period, expired citizens have no time left
+        assert_eq!(config.grace_remaining(500, 501), Some(0));
+
+        config.grace_period_secs = 100;
+        assert_eq!(config.grace_remaining(500, 550), Some(50));
+        assert_eq!(config.grace_remaining(500, 600), Some(0));
+        assert_eq!(config.grace_remaining(500, 5000), Some(0));
+        assert_eq!(config.grace_remaining(u32::MAX - 10, u32::MAX), Some(0));
+    }
+
+    #[test]
+    pub fn test_trial_limits() {
+        let mut config = TrialConfig::default();
+        assert!(config.allows_privilege_password(true));
+        assert_eq!(config.bot_limit(true, 5), 5);
+
+        config.privilege_password = false;
+        config.max_bots = Some(1);
+        assert!(!config.allows_privilege_password(true));
+        assert_eq!(config.bot_limit(true, 5), 1);
+        assert_eq!(config.bot_limit(true, 0), 0);
+
+        // Only citizens on a trial are limited
+        assert!(config.allows_privilege_password(false));
+        assert_eq!(config.bot_limit(false, 5), 5);
+    }
+}
@@ -0,0 +1,271 @@
+//! Operator commands typed on the universe's standard input, or on a telnet
+//! connection to a local port.
+//!
+//! Lines are read on their own threads and handed to the universe loop, which runs
+//! the commands between servicing clients and writes the output back to where the
+//! line came from.
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use num_traits::FromPrimitive;
+use serde_json::{json, Value};
+
+use crate::{
+    attributes, audit,
+    client::{ClientManager, Entity},
+    config::ConsoleConfig,
+    database::{attrib::Attribute, AttribDB, CitizenDB, Database},
+};
+
+/// Who actions taken from the console are recorded as in the audit log.
+const ACTOR: &str = "console";
+
+const HELP: &str = "\
+Commands:
+  help                           Show this list
+  list                           List connected users and world servers
+  kick <session> [reason]        Disconnect a user, telling them why
+  broadcast <message>            Send a message to every user's chat window
+  set-attribute <name|id> <value> Change a universe attribute
+  unlock <citizen>               Let a citizen locked out by failed logins log in
+";
+
+/// A line typed by an operator, and where to send the output of running it.
+struct ConsoleLine {
+    line: String,
+    output: Box<dyn Write + Send>,
+}
+
+pub struct Console {
+    lines: Receiver<ConsoleLine>,
+}
+
+impl Console {
+    /// Start reading commands from the sources enabled in the config.
+    pub fn new(config: &ConsoleConfig) -> Option<Self> {
+        if !config.stdin && config.telnet_port == 0 {
+            return None;
+        }
+
+        let (sender, lines) = channel();
+
+        if config.stdin {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                read_lines(std::io::stdin().lock(), sender, || {
+                    Some(Box::new(std::io::stdout()))
+                });
+            });
+        }
+
+        if config.telnet_port != 0 {
+            // Commands are not authenticated, so they are only taken from this machine
+            let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, config.telnet_port));
+            match TcpListener::bind(addr) {
+                Ok(listener) => {
+                    log::info!("Console listening on {addr}");
+                    thread::spawn(move || accept_telnet(listener, sender));
+                }
+                Err(err) => log::error!("Could not start the console on {addr}: {err}"),
+            }
+        }
+
+        Some(Self { lines })
+    }
+
+    /// Run any commands which have been typed since the last service.
+    pub fn service(&self, database: &Database, client_manager: &ClientManager) {
+        for mut line in self.lines.try_iter() {
+            let output = run_command(&line.line, database, client_manager);
+            line.output.write_all(output.as_bytes()).ok();
+            line.output.flush().ok();
+        }
+    }
+}
+
+fn accept_telnet(listener: TcpListener, sender: Sender<ConsoleLine>) {
+    for stream in listener.incoming().flatten() {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            stream.set_write_timeout(Some(Duration::from_secs(5))).ok();
+            let output = match stream.try_clone() {
+                Ok(x) => x,
+                Err(_) => return,
+            };
+            read_lines(BufReader::new(stream), sender, move || {
+                output
+                    .try_clone()
+                    .ok()
+                    .map(|x| Box::new(x) as Box<dyn Write + Send>)
+            });
+        });
+    }
+}
+
+/// Send each line from `reader` to the universe, with a writer made by `output`
+/// for its results.
+fn read_lines<R, F>(reader: R, sender: Sender<ConsoleLine>, output: F)
+where
+    R: BufRead,
+    F: Fn() -> Option<Box<dyn Write + Send>>,
+{
+    for line in reader.lines() {
+        let line = match line {
+            Ok(x) => x.trim().to_string(),
+            Err(_) => break,
+        };
+        if line.is_empty() {
+            continue;
+        }
+
+        let output = match output() {
+            Some(x) => x,
+            None => break,
+        };
+        let line = ConsoleLine { line, output };
+        if sender.send(line).is_err() {
+            break;
+        }
+    }
+}
+
+fn run_command(line: &str, database: &Database, client_manager: &ClientManager) -> String {
+    let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+    let args = args.trim();
+
+    log::info!("Console command: {line}");
+
+    match command {
+        "help" => HELP.to_string(),
+        "list" => list(client_manager),
+        "kick" => {
+            let (session_id, reason) = args.split_once(' ').unwrap_or((args, ""));
+            match session_id.parse::<u16>() {
+                Ok(session_id) => match client_manager.kick_session(session_id, reason.trim()) {
+                    Ok(client) => {
+                        audit::record(
+                            database,
+                            ACTOR,
+                            "kick",
+                            &client.actor(),
+                            Value::Null,
+                            json!({ "session": session_id, "reason": reason.trim() }),
+                        );
+                        format!("Disconnected session {session_id}\n")
+                    }
+                    Err(_) => format!("No session {session_id}\n"),
+                },
+                Err(_) => "Usage: kick <session> [reason]\n".to_string(),
+            }
+        }
+        "broadcast" if !args.is_empty() => {
+            let count = client_manager
+                .send_console_message_to(None, args)
+                .unwrap_or(0);
+            format!("Sent to {count} users\n")
+        }
+        "broadcast" => "Usage: broadcast <message>\n".to_string(),
+        "set-attribute" => set_attribute(args, database, client_manager),
+        "unlock" if !args.is_empty() => match database.citizen_by_name(args) {
+            Ok(citizen) => match database.citizen_clear_failed_logins(citizen.id) {
+                Ok(()) => {
+                    audit::record(
+                        database,
+                        ACTOR,
+                        "citizen_unlock",
+                        &audit::citizen_target(citizen.id),
+                        Value::Null,
+                        Value::Null,
+                    );
+                    format!("Unlocked {} (#{})\n", citizen.name, citizen.id)
+                }
+                Err(x) => format!("Could not unlock {}: {x:?}\n", citizen.name),
+            },
+            Err(_) => format!("No citizen {args:?}\n"),
+        },
+        "unlock" => "Usage: unlock <citizen>\n".to_string(),
+        _ => format!("Unknown command {command:?}, try \"help\"\n"),
+    }
+}
+
+fn list(client_manager: &ClientManager) -> String {
+    let mut output = String::new();
+
+    for client in client_manager.clients() {
+        let rtt = match client.rtt() {
+            Some(rtt) => format!("{}ms", rtt.as_millis()),
+            None => "-".to_string(),
+        };
+        match &client.info().entity {
+            Some(Entity::Player(player)) => output.push_str(&format!(
+                "{:>5}  {:<20} {:<15} {:>7} {}\n",
+                player.session_id,
+                player.username,
+                client.addr.ip().to_string(),
+                rtt,
+                player.world.as_deref().unwrap_or("-")
+            )),
+            Some(Entity::WorldServer(server)) => {
+                let worlds: Vec<&str> = server.worlds.iter().map(|w| w.name.as_str()).collect();
+                output.push_str(&format!(
+                    "world  {:<20} {:<15} {:>7} {}\n",
+                    format!("build {}", server.build),
+                    client.addr.ip().to_string(),
+                    rtt,
+                    worlds.join(", ")
+                ));
+            }
+            None => {}
+        }
+    }
+
+    if output.is_empty() {
+        output.push_str("Nobody is connected\n");
+    }
+    output
+}
+
+fn set_attribute(args: &str, database: &Database, client_manager: &ClientManager) -> String {
+    let (name, value) = match args.split_once(' ') {
+        Some((name, value)) => (name, value.trim()),
+        None if !args.is_empty() => (args, ""),
+        None => return "Usage: set-attribute <name|id> <value>\n".to_string(),
+    };
+
+    let attribute = match name.parse::<u32>() {
+        Ok(id) => Attribute::from_u32(id),
+        Err(_) => attributes::attribute_by_name(name),
+    };
+    let attribute = match attribute {
+        Some(x) => x,
+        None => return format!("No attribute {name:?}\n"),
+    };
+
+    let before = database
+        .attrib_get()
+        .ok()
+        .and_then(|mut values| values.remove(&attribute));
+
+    match attributes::set_attribute(attribute, value, database) {
+        Ok(()) => {
+            audit::record(
+                database,
+                ACTOR,
+                "attribute_change",
+                &format!("attribute:{attribute:?}"),
+                before.into(),
+                value.into(),
+            );
+            for client in client_manager.clients() {
+                attributes::send_attributes(client, database);
+            }
+            format!("Set {attribute:?} to {value:?}\n")
+        }
+        Err(x) => format!("Could not set {attribute:?}: {x:?}\n"),
+    }
+}
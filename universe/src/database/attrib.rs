@@ -4,7 +4,6 @@ use crate::config::UniverseConfig;
 
 use super::{fetch_int, fetch_string, Database};
 use aw_core::ReasonCode;
-use mysql::prelude::*;
 use mysql::*;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
@@ -21,7 +20,9 @@ pub enum Attribute {
     UniverseBuild = 5,
     CitizenChanges = 6,
     UnknownBilling7 = 7,
-    BillingMethod = 8,
+    /// Whether new citizens may immigrate from the browser ("Y" or "N"), sent
+    /// as `AttributeBillingMethod`
+    RegistrationMethod = 8,
     BillingUnknown9 = 9,
     SearchTabURL = 10,
     Timestamp = 11,
@@ -37,6 +38,14 @@ pub enum Attribute {
     MailCommand = 21,
     PAVObjectPath = 22,
     UnknownUniverseSetting = 23,
+    ObjectPath = 24,
+    TexturePath = 25,
+    /// Would be sent with ID 26, which other packets use for `IdentifyUserIP`, so it
+    /// is neither sent nor changeable
+    Unknown26 = 26,
+    MediaProxyURL = 27,
+    ObjectRefresh = 28,
+    PerCitizenPorts = 29,
 }
 
 pub trait AttribDB {
@@ -47,10 +56,7 @@ pub trait AttribDB {
 
 impl AttribDB for Database {
     fn init_attrib(&self, universe_config: &UniverseConfig) {
-        let mut conn = self
-            .pool
-            .get_conn()
-            .expect("Could not get mysql connection.");
+        let mut conn = self.conn().expect("Could not get mysql connection.");
 
         conn.query_drop(
             r"CREATE TABLE IF NOT EXISTS awu_attrib ( 
@@ -74,6 +80,12 @@ impl AttribDB for Database {
             bool_attrib(universe_config.allow_citizen_changes),
         )
         .expect("Failed to set citizenchanges attribute.");
+
+        self.attrib_set(
+            Attribute::RegistrationMethod,
+            bool_attrib(universe_config.allow_immigration),
+        )
+        .expect("Failed to set registrationmethod attribute.");
     }
 
     fn attrib_set(&self, attribute_id: Attribute, value: &str) -> Result<(), ReasonCode> {
@@ -0,0 +1,155 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::Database;
+use crate::database;
+use aw_core::ReasonCode;
+use mysql::*;
+
+type Result<T, E> = std::result::Result<T, E>;
+
+/// One administrative action, such as changing a citizen or ejecting an address.
+#[derive(Debug, Clone)]
+pub struct AuditEntryQuery {
+    pub id: u32,
+    pub timestamp: u32,
+    /// Who took the action, such as "citizen:1" or "admin:127.0.0.1"
+    pub actor: String,
+    /// What was done, such as "citizen_change"
+    pub action: String,
+    /// What it was done to, such as "citizen:5" or "attribute:WelcomeMessage"
+    pub target: String,
+    /// JSON of the target before the action, or empty if it did not exist
+    pub before: String,
+    /// JSON of the target after the action, or empty if it no longer exists
+    pub after: String,
+}
+
+pub trait AuditDB {
+    fn init_audit(&self);
+    fn audit_add(
+        &self,
+        actor: &str,
+        action: &str,
+        target: &str,
+        before: &str,
+        after: &str,
+    ) -> Result<(), ReasonCode>;
+    /// The latest entries, newest first. Empty filters match everything.
+    fn audit_list(
+        &self,
+        actor: &str,
+        action: &str,
+        target: &str,
+        limit: u32,
+    ) -> Result<Vec<AuditEntryQuery>, ReasonCode>;
+}
+
+impl AuditDB for Database {
+    fn init_audit(&self) {
+        let mut conn = self.conn().expect("Could not get mysql connection.");
+
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_audit ( 
+                ID int(11) unsigned NOT NULL auto_increment, 
+                Timestamp int(11) NOT NULL default '0', 
+                Actor varchar(64) NOT NULL default '', 
+                Action varchar(32) NOT NULL default '', 
+                Target varchar(255) NOT NULL default '', 
+                OldValue text NOT NULL, 
+                NewValue text NOT NULL, 
+                PRIMARY KEY  (ID), 
+                KEY Index1 (Target) 
+            ) 
+            ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+    }
+
+    fn audit_add(
+        &self,
+        actor: &str,
+        action: &str,
+        target: &str,
+        before: &str,
+        after: &str,
+    ) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Current time is before the unix epoch.")
+            .as_secs();
+
+        conn.exec_drop(
+            r"INSERT INTO awu_audit (Timestamp, Actor, Action, Target, OldValue, NewValue) 
+            VALUES(:timestamp, :actor, :action, :target, :before, :after)",
+            params! {
+                "timestamp" => now,
+                "actor" => actor,
+                "action" => action,
+                "target" => target,
+                "before" => before,
+                "after" => after,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn audit_list(
+        &self,
+        actor: &str,
+        action: &str,
+        target: &str,
+        limit: u32,
+    ) -> Result<Vec<AuditEntryQuery>, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT * FROM awu_audit 
+                WHERE (:actor = '' OR Actor=:actor) 
+                AND (:action = '' OR Action=:action) 
+                AND (:target = '' OR Target=:target) 
+                ORDER BY ID DESC LIMIT :limit",
+                params! {
+                    "actor" => actor,
+                    "action" => action,
+                    "target" => target,
+                    "limit" => limit,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        rows.iter().map(fetch_audit_entry).collect()
+    }
+}
+
+fn fetch_audit_entry(row: &Row) -> Result<AuditEntryQuery, ReasonCode> {
+    let id: u32 = database::fetch_int(row, "ID")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let timestamp: u32 = database::fetch_int(row, "Timestamp")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let actor = database::fetch_string(row, "Actor").ok_or(ReasonCode::DatabaseError)?;
+    let action = database::fetch_string(row, "Action").ok_or(ReasonCode::DatabaseError)?;
+    let target = database::fetch_string(row, "Target").ok_or(ReasonCode::DatabaseError)?;
+    let before = database::fetch_string(row, "OldValue").ok_or(ReasonCode::DatabaseError)?;
+    let after = database::fetch_string(row, "NewValue").ok_or(ReasonCode::DatabaseError)?;
+
+    Ok(AuditEntryQuery {
+        id,
+        timestamp,
+        actor,
+        action,
+        target,
+        before,
+        after,
+    })
+}
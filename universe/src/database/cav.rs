@@ -1,16 +1,45 @@
 use super::Database;
-use mysql::prelude::*;
+use crate::database;
+use aw_core::ReasonCode;
+use mysql::*;
+
+type Result<T, E> = std::result::Result<T, E>;
+
+/// A citizen's custom avatar definition for one template.
+#[derive(Debug, Clone)]
+pub struct CavQuery {
+    pub citizen: u32,
+    pub template: u32,
+    pub changed: u32,
+    pub definition: Vec<u8>,
+}
+
+/// A kind of custom avatar citizens may base theirs on.
+#[derive(Debug, Clone)]
+pub struct CavTemplateQuery {
+    pub id: u32,
+    pub kind: u32,
+    pub rating: u32,
+    pub name: String,
+    pub model: String,
+}
 
 pub trait CavDB {
     fn init_cav(&self);
+    fn cav_get(&self, citizen_id: u32, template: u32) -> Result<CavQuery, ReasonCode>;
+    fn cav_set(&self, cav: &CavQuery) -> Result<(), ReasonCode>;
+    fn cav_delete(&self, citizen_id: u32, template: u32) -> Result<(), ReasonCode>;
+    fn cav_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode>;
+    fn cav_template_by_number(&self, template_id: u32) -> Result<CavTemplateQuery, ReasonCode>;
+    fn cav_template_next(&self, template_id: u32) -> Result<CavTemplateQuery, ReasonCode>;
+    fn cav_template_add(&self, template: &CavTemplateQuery) -> Result<u32, ReasonCode>;
+    fn cav_template_change(&self, template: &CavTemplateQuery) -> Result<(), ReasonCode>;
+    fn cav_template_delete(&self, template_id: u32) -> Result<(), ReasonCode>;
 }
 
 impl CavDB for Database {
     fn init_cav(&self) {
-        let mut conn = self
-            .pool
-            .get_conn()
-            .expect("Could not get mysql connection.");
+        let mut conn = self.conn().expect("Could not get mysql connection.");
 
         conn.query_drop(
             r"CREATE TABLE IF NOT EXISTS awu_cav ( 
@@ -41,5 +70,240 @@ impl CavDB for Database {
             ENGINE=MyISAM DEFAULT CHARSET=latin1;",
         )
         .unwrap();
+
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_cav_definition ( 
+                Citizen int(11) unsigned NOT NULL default '0', 
+                Template int(11) unsigned NOT NULL default '0', 
+                Changed int(11) unsigned NOT NULL default '0', 
+                Definition mediumblob NOT NULL, 
+                PRIMARY KEY  (Citizen,Template) 
+            ) 
+            ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+    }
+
+    fn cav_get(&self, citizen_id: u32, template: u32) -> Result<CavQuery, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT * FROM awu_cav_definition WHERE Citizen=:citizen AND Template=:template",
+                params! {
+                    "citizen" => citizen_id,
+                    "template" => template,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        match rows.first() {
+            Some(row) => fetch_cav(row),
+            None => Err(ReasonCode::NoSuchCav),
+        }
+    }
+
+    fn cav_set(&self, cav: &CavQuery) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"REPLACE INTO awu_cav_definition (Citizen, Template, Changed, Definition) 
+            VALUES(:citizen, :template, :changed, :definition)",
+            params! {
+                "citizen" => cav.citizen,
+                "template" => cav.template,
+                "changed" => cav.changed,
+                "definition" => &cav.definition,
+            },
+        )
+        .map_err(|_| ReasonCode::UnableToUpdateCav)?;
+
+        Ok(())
+    }
+
+    fn cav_delete(&self, citizen_id: u32, template: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_cav_definition WHERE Citizen=:citizen AND Template=:template",
+            params! {
+                "citizen" => citizen_id,
+                "template" => template,
+            },
+        )
+        .map_err(|_| ReasonCode::UnableToDeleteCav)?;
+
+        if conn.affected_rows() == 0 {
+            return Err(ReasonCode::NoSuchCav);
+        }
+
+        Ok(())
     }
+
+    fn cav_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_cav WHERE Citizen=:citizen",
+            params! {
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::UnableToDeleteCav)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_cav_definition WHERE Citizen=:citizen",
+            params! {
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::UnableToDeleteCav)?;
+
+        Ok(())
+    }
+
+    fn cav_template_by_number(&self, template_id: u32) -> Result<CavTemplateQuery, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT * FROM awu_cav_template WHERE ID=:id",
+                params! {
+                    "id" => template_id,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        match rows.first() {
+            Some(row) => fetch_cav_template(row),
+            None => Err(ReasonCode::NoSuchCav),
+        }
+    }
+
+    fn cav_template_next(&self, template_id: u32) -> Result<CavTemplateQuery, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT * FROM awu_cav_template WHERE ID>:id ORDER BY ID LIMIT 1",
+                params! {
+                    "id" => template_id,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        match rows.first() {
+            Some(row) => fetch_cav_template(row),
+            None => Err(ReasonCode::NoSuchCav),
+        }
+    }
+
+    fn cav_template_add(&self, template: &CavTemplateQuery) -> Result<u32, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"INSERT INTO awu_cav_template (Type, Rating, Name, Model) 
+            VALUES(:kind, :rating, :name, :model)",
+            params! {
+                "kind" => template.kind,
+                "rating" => template.rating,
+                "name" => &template.name,
+                "model" => &template.model,
+            },
+        )
+        .map_err(|_| ReasonCode::UnableToUpdateCav)?;
+
+        u32::try_from(conn.last_insert_id()).map_err(|_| ReasonCode::DatabaseError)
+    }
+
+    fn cav_template_change(&self, template: &CavTemplateQuery) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_cav_template SET Changed=NOT Changed, Type=:kind, Rating=:rating, 
+            Name=:name, Model=:model WHERE ID=:id",
+            params! {
+                "id" => template.id,
+                "kind" => template.kind,
+                "rating" => template.rating,
+                "name" => &template.name,
+                "model" => &template.model,
+            },
+        )
+        .map_err(|_| ReasonCode::UnableToUpdateCav)?;
+
+        Ok(())
+    }
+
+    fn cav_template_delete(&self, template_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_cav_template WHERE ID=:id",
+            params! {
+                "id" => template_id,
+            },
+        )
+        .map_err(|_| ReasonCode::UnableToDeleteCav)?;
+
+        if conn.affected_rows() == 0 {
+            return Err(ReasonCode::NoSuchCav);
+        }
+
+        Ok(())
+    }
+}
+
+fn fetch_cav(row: &Row) -> Result<CavQuery, ReasonCode> {
+    let citizen: u32 = database::fetch_int(row, "Citizen")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let template: u32 = database::fetch_int(row, "Template")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let changed: u32 = database::fetch_int(row, "Changed")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let definition = database::fetch_data(row, "Definition").ok_or(ReasonCode::DatabaseError)?;
+
+    Ok(CavQuery {
+        citizen,
+        template,
+        changed,
+        definition,
+    })
+}
+
+fn fetch_cav_template(row: &Row) -> Result<CavTemplateQuery, ReasonCode> {
+    let id: u32 = database::fetch_int(row, "ID")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let kind: u32 = database::fetch_int(row, "Type")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let rating: u32 = database::fetch_int(row, "Rating")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let name = database::fetch_string(row, "Name").unwrap_or_default();
+    let model = database::fetch_string(row, "Model").ok_or(ReasonCode::DatabaseError)?;
+
+    Ok(CavTemplateQuery {
+        id,
+        kind,
+        rating,
+        name,
+        model,
+    })
 }
@@ -1,14 +1,19 @@
 use crate::database;
 
-use super::Database;
+use super::{password, Database};
 use aw_core::ReasonCode;
+use mysql::params;
 use mysql::*;
-use mysql::{params, prelude::*};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 type Result<T, E> = std::result::Result<T, E>;
 
-#[derive(Debug)]
+/// Length of the generated password for the first Administrator account.
+const ADMIN_PASSWORD_LEN: usize = 12;
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CitizenQuery {
     pub id: u32,
     pub changed: u32,
@@ -30,14 +35,43 @@ pub struct CitizenQuery {
     pub enabled: u32,
     pub privacy: u32,
     pub trial: u32,
+    /// Whether the citizen may be logged in more than once at a time
+    pub multi_login: u32,
 }
 
 pub trait CitizenDB {
     fn init_citizen(&self);
     fn citizen_by_name(&self, name: &str) -> Result<CitizenQuery, ReasonCode>;
     fn citizen_by_number(&self, citizen_id: u32) -> Result<CitizenQuery, ReasonCode>;
+    fn citizen_all(&self) -> Result<Vec<CitizenQuery>, ReasonCode>;
+    /// Passwords are taken as stored values; new ones from users must be hashed with
+    /// `password::prepare_password` first.
     fn citizen_add(&self, citizen: &CitizenQuery) -> Result<(), ReasonCode>;
     fn citizen_change(&self, citizen: &CitizenQuery) -> Result<(), ReasonCode>;
+    fn citizen_delete(&self, citizen_id: u32) -> Result<(), ReasonCode>;
+    fn citizen_count_immigrated_since(&self, since: u32) -> Result<u32, ReasonCode>;
+    /// Numbers are never reused, so this is one past the highest number ever given out.
+    fn citizen_next_number(&self) -> Result<u32, ReasonCode>;
+    /// Whether the number belonged to a citizen who has since been deleted.
+    fn citizen_number_retired(&self, citizen_id: u32) -> Result<bool, ReasonCode>;
+    fn citizen_record_login(
+        &self,
+        citizen_id: u32,
+        time: u32,
+        address: u32,
+    ) -> Result<(), ReasonCode>;
+    fn citizen_add_total_time(&self, citizen_id: u32, seconds: u32) -> Result<(), ReasonCode>;
+    fn citizen_set_password(&self, citizen_id: u32, password: &str) -> Result<(), ReasonCode>;
+    fn citizen_set_enabled(&self, citizen_id: u32, enabled: bool) -> Result<(), ReasonCode>;
+    fn citizen_failed_logins(&self, citizen_id: u32) -> Result<u32, ReasonCode>;
+    fn citizen_record_failed_login(&self, citizen_id: u32) -> Result<u32, ReasonCode>;
+    fn citizen_clear_failed_logins(&self, citizen_id: u32) -> Result<(), ReasonCode>;
+    fn citizen_set_privilege_password(
+        &self,
+        citizen_id: u32,
+        password: &str,
+    ) -> Result<(), ReasonCode>;
+    fn citizen_discard(&self, citizen_id: u32) -> Result<(), ReasonCode>;
 }
 
 impl CitizenDB for Database {
@@ -66,6 +100,7 @@ impl CitizenDB for Database {
             Enabled tinyint(1) NOT NULL default '1', 
             Privacy int(11) NOT NULL default '0', 
             Trial tinyint(1) NOT NULL default '0', 
+            MultiLogin tinyint(1) NOT NULL default '0', 
             PRIMARY KEY  (ID), 
             UNIQUE KEY Index1 (Name), 
             KEY Index2 (Email) 
@@ -74,6 +109,27 @@ impl CitizenDB for Database {
         )
         .unwrap();
 
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_citizen_deleted ( 
+            ID int(11) unsigned NOT NULL default '0', 
+            Deleted int(11) NOT NULL default '0', 
+            PRIMARY KEY  (ID) 
+        ) 
+        ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+
+        self.add_column_if_missing(
+            "awu_citizen",
+            "MultiLogin",
+            "tinyint(1) NOT NULL default '0' AFTER Trial",
+        );
+        self.add_column_if_missing(
+            "awu_citizen",
+            "FailedLogins",
+            "int(11) NOT NULL default '0' AFTER MultiLogin",
+        );
+
         // Create default Administrator account if one doesn't exist yet
         if self.citizen_by_number(1).is_err() {
             let now = SystemTime::now()
@@ -85,7 +141,11 @@ impl CitizenDB for Database {
                 id: 1,
                 changed: 0,
                 name: "Administrator".to_string(),
-                password: "welcome".to_string(),
+                password: rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(ADMIN_PASSWORD_LEN)
+                    .map(char::from)
+                    .collect(),
                 //email: "support@activeworlds.com".to_string(),
                 email: Default::default(),
                 priv_pass: Default::default(),
@@ -103,10 +163,16 @@ impl CitizenDB for Database {
                 enabled: 1,
                 privacy: 0,
                 trial: 0,
+                multi_login: 0,
             };
 
             match self.citizen_add(&admin) {
-                Ok(_) => println!("Citizen #1 created as {} / {}", admin.name, admin.password),
+                Ok(_) => {
+                    // This is the only time the password is shown, so it goes
+                    // to the console regardless of the log level.
+                    println!("Citizen #1 created as {} / {}", admin.name, admin.password);
+                    println!("Log in and change this password, it will not be shown again.");
+                }
                 Err(_) => eprintln!("Failed to create citizen #1"),
             };
         }
@@ -158,6 +224,16 @@ impl CitizenDB for Database {
         }
     }
 
+    fn citizen_all(&self) -> Result<Vec<CitizenQuery>, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(r"SELECT * FROM awu_citizen ORDER BY ID", Params::Empty)
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        rows.iter().map(fetch_citizen).collect()
+    }
+
     fn citizen_add(&self, citizen: &CitizenQuery) -> Result<(), ReasonCode> {
         let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
 
@@ -165,16 +241,16 @@ impl CitizenDB for Database {
             r"INSERT INTO awu_citizen(
                 ID, Immigration, Expiration, LastLogin, LastAddress, TotalTime, 
                 BotLimit, Beta, Enabled, Trial, Privacy, CAVEnabled, CAVTemplate, 
-                Name, Password, Email, PrivPass, Comment, URL) 
+                Name, Password, Email, PrivPass, Comment, URL, MultiLogin) 
             VALUES(:id, :immigration, :expiration, :last_login, :last_address, :total_time, 
                 :bot_limit, :beta, :enabled, :trial, :privacy, :cav_enabled, :cav_template, 
-                :name, :password, :email, :priv_pass, :comment, :url)",
+                :name, :password, :email, :priv_pass, :comment, :url, :multi_login)",
             params! {
                 "id" => citizen.id,
                 "immigration" => citizen.immigration,
                 "expiration" => citizen.expiration,
                 "last_login" => citizen.last_login,
-                "last_address" => citizen.last_address,
+                "last_address" => citizen.last_address as i32,
                 "total_time" => citizen.total_time,
                 "bot_limit" => citizen.bot_limit,
                 "beta" => citizen.beta,
@@ -184,11 +260,12 @@ impl CitizenDB for Database {
                 "cav_enabled" => citizen.cav_enabled,
                 "cav_template" => citizen.cav_template,
                 "name" => &citizen.name,
-                "password" => &citizen.password,
+                "password" => password::migrate_password(&citizen.password)?,
                 "email" => &citizen.email,
-                "priv_pass" => &citizen.priv_pass,
+                "priv_pass" => password::migrate_privilege_password(&citizen.priv_pass)?,
                 "comment" => &citizen.comment,
-                "url" => &citizen.url
+                "url" => &citizen.url,
+                "multi_login" => citizen.multi_login
             },
         )
         .map_err(|_| ReasonCode::DatabaseError)?;
@@ -206,14 +283,14 @@ impl CitizenDB for Database {
                 Beta=:beta, Enabled=:enabled, Trial=:trial, Privacy=:privacy, 
                 CAVEnabled=:cav_enabled, CAVTemplate=:cav_template, Name=:name, 
                 Password=:password, Email=:email, PrivPass=:priv_pass, 
-                Comment=:comment, URL=:url
+                Comment=:comment, URL=:url, MultiLogin=:multi_login
                 WHERE ID=:id;",
             params! {
                 "id" => citizen.id,
                 "immigration" => citizen.immigration,
                 "expiration" => citizen.expiration,
                 "last_login" => citizen.last_login,
-                "last_address" => citizen.last_address,
+                "last_address" => citizen.last_address as i32,
                 "total_time" => citizen.total_time,
                 "bot_limit" => citizen.bot_limit,
                 "beta" => citizen.beta,
@@ -223,11 +300,220 @@ impl CitizenDB for Database {
                 "cav_enabled" => citizen.cav_enabled,
                 "cav_template" => citizen.cav_template,
                 "name" => &citizen.name,
-                "password" => &citizen.password,
+                "password" => password::migrate_password(&citizen.password)?,
                 "email" => &citizen.email,
-                "priv_pass" => &citizen.priv_pass,
+                "priv_pass" => password::migrate_privilege_password(&citizen.priv_pass)?,
                 "comment" => &citizen.comment,
-                "url" => &citizen.url
+                "url" => &citizen.url,
+                "multi_login" => citizen.multi_login
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn citizen_delete(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        // Remembered first, so the number is never handed out again
+        conn.exec_drop(
+            r"INSERT IGNORE INTO awu_citizen_deleted (ID, Deleted) VALUES (:id, UNIX_TIMESTAMP());",
+            params! {
+                "id" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_citizen WHERE ID=:id;",
+            params! {
+                "id" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn citizen_count_immigrated_since(&self, since: u32) -> Result<u32, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let count: Option<u32> = conn
+            .exec_first(
+                r"SELECT COUNT(*) FROM awu_citizen WHERE Immigration>=:since",
+                params! {
+                    "since" => since,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(count.unwrap_or(0))
+    }
+
+    fn citizen_next_number(&self) -> Result<u32, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let highest: Option<Option<u32>> = conn
+            .query_first(
+                r"SELECT GREATEST(
+                    COALESCE((SELECT MAX(ID) FROM awu_citizen), 0),
+                    COALESCE((SELECT MAX(ID) FROM awu_citizen_deleted), 0)
+                )",
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(highest.flatten().unwrap_or(0) + 1)
+    }
+
+    fn citizen_number_retired(&self, citizen_id: u32) -> Result<bool, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let found: Option<u32> = conn
+            .exec_first(
+                r"SELECT ID FROM awu_citizen_deleted WHERE ID=:id",
+                params! {
+                    "id" => citizen_id,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(found.is_some())
+    }
+
+    fn citizen_record_login(
+        &self,
+        citizen_id: u32,
+        time: u32,
+        address: u32,
+    ) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        // The address column is signed, so it holds the bits of the address as-is
+        conn.exec_drop(
+            r"UPDATE awu_citizen SET LastLogin=:last_login, LastAddress=:last_address 
+                WHERE ID=:id;",
+            params! {
+                "id" => citizen_id,
+                "last_login" => time,
+                "last_address" => address as i32,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn citizen_set_password(&self, citizen_id: u32, password: &str) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_citizen SET Password=:password WHERE ID=:id;",
+            params! {
+                "id" => citizen_id,
+                "password" => password::prepare_password(password)?,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn citizen_set_enabled(&self, citizen_id: u32, enabled: bool) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_citizen SET Enabled=:enabled WHERE ID=:id;",
+            params! {
+                "id" => citizen_id,
+                "enabled" => enabled as u32,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn citizen_failed_logins(&self, citizen_id: u32) -> Result<u32, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let failed: Option<u32> = conn
+            .exec_first(
+                r"SELECT FailedLogins FROM awu_citizen WHERE ID=:id;",
+                params! { "id" => citizen_id },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        failed.ok_or(ReasonCode::NoSuchCitizen)
+    }
+
+    fn citizen_record_failed_login(&self, citizen_id: u32) -> Result<u32, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_citizen SET FailedLogins=FailedLogins+1 WHERE ID=:id;",
+            params! { "id" => citizen_id },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+        drop(conn);
+
+        self.citizen_failed_logins(citizen_id)
+    }
+
+    fn citizen_clear_failed_logins(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_citizen SET FailedLogins=0 WHERE ID=:id;",
+            params! { "id" => citizen_id },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn citizen_add_total_time(&self, citizen_id: u32, seconds: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_citizen SET TotalTime=TotalTime+:seconds WHERE ID=:id;",
+            params! {
+                "id" => citizen_id,
+                "seconds" => seconds,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn citizen_set_privilege_password(
+        &self,
+        citizen_id: u32,
+        password: &str,
+    ) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_citizen SET PrivPass=:priv_pass WHERE ID=:id;",
+            params! {
+                "id" => citizen_id,
+                "priv_pass" => password::prepare_privilege_password(password)?,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn citizen_discard(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        // Unlike deleting, the number may be handed out again
+        conn.exec_drop(
+            r"DELETE FROM awu_citizen WHERE ID=:id;",
+            params! {
+                "id" => citizen_id,
             },
         )
         .map_err(|_| ReasonCode::DatabaseError)?;
@@ -277,10 +563,8 @@ fn fetch_citizen(row: &Row) -> Result<CitizenQuery, ReasonCode> {
         .try_into()
         .map_err(|_| ReasonCode::DatabaseError)?;
 
-    let last_address: u32 = database::fetch_int(row, "LastAddress")
-        .ok_or(ReasonCode::DatabaseError)?
-        .try_into()
-        .map_err(|_| ReasonCode::DatabaseError)?;
+    let last_address =
+        database::fetch_int(row, "LastAddress").ok_or(ReasonCode::DatabaseError)? as u32;
 
     let total_time: u32 = database::fetch_int(row, "TotalTime")
         .ok_or(ReasonCode::DatabaseError)?
@@ -322,6 +606,11 @@ fn fetch_citizen(row: &Row) -> Result<CitizenQuery, ReasonCode> {
         .try_into()
         .map_err(|_| ReasonCode::DatabaseError)?;
 
+    let multi_login: u32 = database::fetch_int(row, "MultiLogin")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
     Ok(CitizenQuery {
         id,
         changed,
@@ -343,5 +632,6 @@ fn fetch_citizen(row: &Row) -> Result<CitizenQuery, ReasonCode> {
         enabled,
         privacy,
         trial,
+        multi_login,
     })
 }
@@ -0,0 +1,193 @@
+//! Connections which run the same statements on MySQL, or on SQLite in development mode.
+use std::{error::Error, fmt};
+
+use mysql::{params, prelude::*, Params, PooledConn, TxOpts};
+
+#[cfg(feature = "dev")]
+use super::sqlite;
+
+pub enum Conn {
+    Mysql(PooledConn),
+    #[cfg(feature = "dev")]
+    Sqlite(rusqlite::Connection),
+}
+
+pub enum Transaction<'a> {
+    Mysql(mysql::Transaction<'a>),
+    #[cfg(feature = "dev")]
+    Sqlite(rusqlite::Transaction<'a>),
+}
+
+#[derive(Debug)]
+pub enum QueryError {
+    Mysql(mysql::Error),
+    #[cfg(feature = "dev")]
+    Sqlite(rusqlite::Error),
+}
+
+pub type QueryResult<T> = Result<T, QueryError>;
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mysql(err) => err.fmt(f),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for QueryError {}
+
+impl From<mysql::Error> for QueryError {
+    fn from(err: mysql::Error) -> Self {
+        Self::Mysql(err)
+    }
+}
+
+#[cfg(feature = "dev")]
+impl From<rusqlite::Error> for QueryError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Sqlite(err)
+    }
+}
+
+impl Conn {
+    pub fn exec<T: FromRow, S: AsRef<str>, P: Into<Params>>(
+        &mut self,
+        stmt: S,
+        params: P,
+    ) -> QueryResult<Vec<T>> {
+        match self {
+            Self::Mysql(conn) => Ok(conn.exec(stmt.as_ref(), params)?),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(conn) => sqlite::exec(conn, stmt.as_ref(), params.into()),
+        }
+    }
+
+    pub fn exec_first<T: FromRow, S: AsRef<str>, P: Into<Params>>(
+        &mut self,
+        stmt: S,
+        params: P,
+    ) -> QueryResult<Option<T>> {
+        match self {
+            Self::Mysql(conn) => Ok(conn.exec_first(stmt.as_ref(), params)?),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(conn) => Ok(sqlite::exec(conn, stmt.as_ref(), params.into())?
+                .into_iter()
+                .next()),
+        }
+    }
+
+    pub fn exec_drop<S: AsRef<str>, P: Into<Params>>(
+        &mut self,
+        stmt: S,
+        params: P,
+    ) -> QueryResult<()> {
+        match self {
+            Self::Mysql(conn) => Ok(conn.exec_drop(stmt.as_ref(), params)?),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(conn) => sqlite::exec_drop(conn, stmt.as_ref(), params.into()),
+        }
+    }
+
+    pub fn query<T: FromRow, Q: AsRef<str>>(&mut self, query: Q) -> QueryResult<Vec<T>> {
+        self.exec(query, Params::Empty)
+    }
+
+    pub fn query_first<T: FromRow, Q: AsRef<str>>(&mut self, query: Q) -> QueryResult<Option<T>> {
+        self.exec_first(query, Params::Empty)
+    }
+
+    pub fn query_drop<Q: AsRef<str>>(&mut self, query: Q) -> QueryResult<()> {
+        match self {
+            Self::Mysql(conn) => Ok(conn.query_drop(query.as_ref())?),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(conn) => sqlite::exec_drop(conn, query.as_ref(), Params::Empty),
+        }
+    }
+
+    /// The ID given to the row inserted by the last statement.
+    pub fn last_insert_id(&self) -> u64 {
+        match self {
+            Self::Mysql(conn) => conn.last_insert_id(),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(conn) => conn.last_insert_rowid() as u64,
+        }
+    }
+
+    /// How many rows the last statement changed.
+    pub fn affected_rows(&self) -> u64 {
+        match self {
+            Self::Mysql(conn) => conn.affected_rows(),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(conn) => conn.changes(),
+        }
+    }
+
+    pub fn start_transaction(&mut self, opts: TxOpts) -> QueryResult<Transaction<'_>> {
+        match self {
+            Self::Mysql(conn) => Ok(Transaction::Mysql(conn.start_transaction(opts)?)),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(conn) => Ok(Transaction::Sqlite(
+                conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?,
+            )),
+        }
+    }
+
+    /// Whether a table already has a column.
+    pub fn has_column(&mut self, table: &str, column: &str) -> QueryResult<bool> {
+        match self {
+            Self::Mysql(conn) => {
+                let existing: Option<String> = conn.exec_first(
+                    r"SELECT COLUMN_NAME FROM information_schema.COLUMNS
+                    WHERE TABLE_SCHEMA=DATABASE() AND TABLE_NAME=:table AND COLUMN_NAME=:column",
+                    params! {
+                        "table" => table,
+                        "column" => column,
+                    },
+                )?;
+                Ok(existing.is_some())
+            }
+            #[cfg(feature = "dev")]
+            Self::Sqlite(conn) => sqlite::has_column(conn, table, column),
+        }
+    }
+}
+
+impl Transaction<'_> {
+    pub fn exec_first<T: FromRow, S: AsRef<str>, P: Into<Params>>(
+        &mut self,
+        stmt: S,
+        params: P,
+    ) -> QueryResult<Option<T>> {
+        match self {
+            Self::Mysql(tx) => Ok(tx.exec_first(stmt.as_ref(), params)?),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(tx) => Ok(sqlite::exec(tx, stmt.as_ref(), params.into())?
+                .into_iter()
+                .next()),
+        }
+    }
+
+    pub fn exec_drop<S: AsRef<str>, P: Into<Params>>(
+        &mut self,
+        stmt: S,
+        params: P,
+    ) -> QueryResult<()> {
+        match self {
+            Self::Mysql(tx) => Ok(tx.exec_drop(stmt.as_ref(), params)?),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(tx) => sqlite::exec_drop(tx, stmt.as_ref(), params.into()),
+        }
+    }
+
+    /// Dropping a transaction without committing it rolls it back.
+    pub fn commit(self) -> QueryResult<()> {
+        match self {
+            Self::Mysql(tx) => Ok(tx.commit()?),
+            #[cfg(feature = "dev")]
+            Self::Sqlite(tx) => Ok(tx.commit()?),
+        }
+    }
+}
@@ -2,7 +2,6 @@ use super::Database;
 use crate::database;
 use aw_core::ReasonCode;
 use bitflags::bitflags;
-use mysql::prelude::*;
 use mysql::*;
 
 type Result<T, E> = std::result::Result<T, E>;
@@ -137,22 +136,24 @@ pub trait ContactDB {
     fn contact_set(&self, citizen_id: u32, contact_id: u32, options: u32)
         -> Result<(), ReasonCode>;
     fn contact_get(&self, citizen_id: u32, contact_id: u32) -> Result<ContactQuery, ReasonCode>;
+    fn contact_delete(&self, citizen_id: u32, contact_id: u32) -> Result<(), ReasonCode>;
     fn contact_get_all(&self, citizen_id: u32) -> Vec<ContactQuery>;
     fn contact_blocked(&self, citizen_id: u32, contact_id: u32) -> bool;
     fn contact_confirm_add(&self, citizen_id: u32, contact_id: u32) -> bool;
     fn contact_default(&self, citizen_id: u32) -> ContactQuery;
     fn contact_file_transfers_allowed(&self, citizen_id: u32, contact_id: u32) -> bool;
     fn contact_telegrams_allowed(&self, citizen_id: u32, contact_id: u32) -> bool;
+    fn contact_joins_allowed(&self, citizen_id: u32, contact_id: u32) -> bool;
     fn contact_friend_requests_allowed(&self, citizen_id: u32, contact_id: u32) -> bool;
     fn contact_status_allowed(&self, citizen_id: u32, contact_id: u32) -> bool;
+    fn contact_location_allowed(&self, citizen_id: u32, contact_id: u32) -> bool;
+    fn contact_invites_allowed(&self, citizen_id: u32, contact_id: u32) -> bool;
+    fn contact_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode>;
 }
 
 impl ContactDB for Database {
     fn init_contact(&self) {
-        let mut conn = self
-            .pool
-            .get_conn()
-            .expect("Could not get mysql connection.");
+        let mut conn = self.conn().expect("Could not get mysql connection.");
 
         conn.query_drop(
             r"CREATE TABLE IF NOT EXISTS awu_contact ( 
@@ -235,6 +236,21 @@ impl ContactDB for Database {
         }
     }
 
+    fn contact_delete(&self, citizen_id: u32, contact_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_contact WHERE Citizen=:citizen_id AND Contact=:contact_id;",
+            params! {
+                "citizen_id" => citizen_id,
+                "contact_id" => contact_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
     fn contact_get_all(&self, citizen_id: u32) -> Vec<ContactQuery> {
         let mut result = Vec::<ContactQuery>::new();
         let mut conn = match self.conn() {
@@ -336,6 +352,22 @@ impl ContactDB for Database {
         true
     }
 
+    fn contact_joins_allowed(&self, citizen_id: u32, contact_id: u32) -> bool {
+        let contact = self
+            .contact_get(citizen_id, contact_id)
+            .unwrap_or_else(|_| self.contact_default(citizen_id));
+
+        if contact.options.contains(ContactOptions::ALL_BLOCKED) {
+            return false;
+        }
+
+        if contact.options.contains(ContactOptions::JOIN_BLOCKED) {
+            return false;
+        }
+
+        true
+    }
+
     fn contact_friend_requests_allowed(&self, citizen_id: u32, contact_id: u32) -> bool {
         let contact = match self.contact_get(citizen_id, contact_id) {
             Ok(x) => x,
@@ -372,6 +404,44 @@ impl ContactDB for Database {
 
         true
     }
+
+    fn contact_location_allowed(&self, citizen_id: u32, contact_id: u32) -> bool {
+        let contact = match self.contact_get(citizen_id, contact_id) {
+            Ok(x) => x,
+            _ => return true,
+        };
+
+        if contact.options.contains(ContactOptions::ALL_BLOCKED) {
+            return false;
+        }
+
+        if contact.options.contains(ContactOptions::LOCATION_BLOCKED) {
+            return false;
+        }
+
+        true
+    }
+
+    fn contact_invites_allowed(&self, citizen_id: u32, contact_id: u32) -> bool {
+        self.contact_get(citizen_id, contact_id)
+            .unwrap_or_else(|_| self.contact_default(citizen_id))
+            .options
+            .is_invite_allowed()
+    }
+
+    fn contact_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_contact WHERE Citizen=:citizen OR Contact=:citizen",
+            params! {
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
 }
 
 fn fetch_contact(row: &Row) -> Result<ContactQuery, ReasonCode> {
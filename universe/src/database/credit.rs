@@ -0,0 +1,216 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::Database;
+use crate::database;
+use aw_core::ReasonCode;
+use mysql::*;
+
+type Result<T, E> = std::result::Result<T, E>;
+
+/// One adjustment to a citizen's credit balance.
+#[derive(Debug, Clone)]
+pub struct CreditEntryQuery {
+    pub id: u32,
+    pub amount: i64,
+    /// The citizen's balance after this adjustment
+    pub balance: i64,
+    /// Who made the adjustment, such as "world:Alpha" or "citizen:1"
+    pub actor: String,
+    pub reason: String,
+    pub timestamp: u32,
+}
+
+pub trait CreditDB {
+    fn init_credit(&self);
+    fn credit_balance(&self, citizen_id: u32) -> Result<i64, ReasonCode>;
+    fn credit_adjust(
+        &self,
+        citizen_id: u32,
+        amount: i64,
+        allow_negative: bool,
+        actor: &str,
+        reason: &str,
+    ) -> Result<i64, ReasonCode>;
+    fn credit_history(
+        &self,
+        citizen_id: u32,
+        limit: u32,
+    ) -> Result<Vec<CreditEntryQuery>, ReasonCode>;
+    fn credit_delete(&self, citizen_id: u32) -> Result<(), ReasonCode>;
+}
+
+impl CreditDB for Database {
+    fn init_credit(&self) {
+        let mut conn = self.conn().expect("Could not get mysql connection.");
+
+        // These tables use InnoDB rather than MyISAM so that a balance and its
+        // ledger entry are always written together.
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_credit ( 
+                Citizen int(11) unsigned NOT NULL default '0', 
+                Balance bigint(20) NOT NULL default '0', 
+                PRIMARY KEY  (Citizen) 
+            ) 
+            ENGINE=InnoDB DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_credit_ledger ( 
+                ID int(11) unsigned NOT NULL auto_increment, 
+                Citizen int(11) unsigned NOT NULL default '0', 
+                Amount bigint(20) NOT NULL default '0', 
+                Balance bigint(20) NOT NULL default '0', 
+                Actor varchar(64) NOT NULL default '', 
+                Reason varchar(255) NOT NULL default '', 
+                Timestamp int(11) NOT NULL default '0', 
+                PRIMARY KEY  (ID), 
+                KEY Index1 (Citizen) 
+            ) 
+            ENGINE=InnoDB DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+    }
+
+    fn credit_balance(&self, citizen_id: u32) -> Result<i64, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let balance: Option<i64> = conn
+            .exec_first(
+                r"SELECT Balance FROM awu_credit WHERE Citizen=:citizen",
+                params! {
+                    "citizen" => citizen_id,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(balance.unwrap_or(0))
+    }
+
+    fn credit_adjust(
+        &self,
+        citizen_id: u32,
+        amount: i64,
+        allow_negative: bool,
+        actor: &str,
+        reason: &str,
+    ) -> Result<i64, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+        let mut tx = conn
+            .start_transaction(TxOpts::default())
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        let balance: Option<i64> = tx
+            .exec_first(
+                r"SELECT Balance FROM awu_credit WHERE Citizen=:citizen FOR UPDATE",
+                params! {
+                    "citizen" => citizen_id,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        let new_balance = balance
+            .unwrap_or(0)
+            .checked_add(amount)
+            .ok_or(ReasonCode::InvalidArgument)?;
+
+        // Dropping the transaction without committing rolls it back
+        if new_balance < 0 && !allow_negative {
+            return Err(ReasonCode::InvalidRequest);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Current time is before the unix epoch.")
+            .as_secs();
+
+        tx.exec_drop(
+            r"REPLACE INTO awu_credit (Citizen, Balance) VALUES(:citizen, :balance)",
+            params! {
+                "citizen" => citizen_id,
+                "balance" => new_balance,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        tx.exec_drop(
+            r"INSERT INTO awu_credit_ledger (Citizen, Amount, Balance, Actor, Reason, Timestamp) 
+            VALUES(:citizen, :amount, :balance, :actor, :reason, :timestamp)",
+            params! {
+                "citizen" => citizen_id,
+                "amount" => amount,
+                "balance" => new_balance,
+                "actor" => actor,
+                "reason" => reason,
+                "timestamp" => now,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        tx.commit().map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(new_balance)
+    }
+
+    fn credit_history(
+        &self,
+        citizen_id: u32,
+        limit: u32,
+    ) -> Result<Vec<CreditEntryQuery>, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT * FROM awu_credit_ledger WHERE Citizen=:citizen 
+                ORDER BY ID DESC LIMIT :limit",
+                params! {
+                    "citizen" => citizen_id,
+                    "limit" => limit,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        rows.iter().map(fetch_credit_entry).collect()
+    }
+
+    fn credit_delete(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        // The ledger is kept as a record of what happened to the credits
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_credit WHERE Citizen=:citizen",
+            params! {
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+}
+
+fn fetch_credit_entry(row: &Row) -> Result<CreditEntryQuery, ReasonCode> {
+    let id: u32 = database::fetch_int(row, "ID")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let amount = database::fetch_int(row, "Amount").ok_or(ReasonCode::DatabaseError)?;
+    let balance = database::fetch_int(row, "Balance").ok_or(ReasonCode::DatabaseError)?;
+    let actor = database::fetch_string(row, "Actor").ok_or(ReasonCode::DatabaseError)?;
+    let reason = database::fetch_string(row, "Reason").ok_or(ReasonCode::DatabaseError)?;
+
+    let timestamp: u32 = database::fetch_int(row, "Timestamp")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    Ok(CreditEntryQuery {
+        id,
+        amount,
+        balance,
+        actor,
+        reason,
+        timestamp,
+    })
+}
@@ -1,16 +1,52 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
 use super::Database;
-use mysql::prelude::*;
+use crate::{database, packet_handler::ipv4_representation};
+use aw_core::ReasonCode;
+use mysql::*;
+
+type Result<T, E> = std::result::Result<T, E>;
+
+/// A ban of an address, or a range of addresses, from the universe.
+#[derive(Debug, Clone)]
+pub struct EjectQuery {
+    pub id: u32,
+    pub address: IpAddr,
+    /// Last address of the range, if the ejection covers more than one
+    pub address_end: Option<IpAddr>,
+    /// Unix time the ejection ends, or 0 if it never does
+    pub expiration: u32,
+    pub creation: u32,
+    pub comment: String,
+}
+
+impl EjectQuery {
+    pub fn covers(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        if ip.is_ipv4() != self.address.is_ipv4() {
+            return false;
+        }
+
+        match self.address_end {
+            Some(end) => self.address <= ip && ip <= end,
+            None => self.address == ip,
+        }
+    }
+}
 
 pub trait EjectDB {
     fn init_eject(&self);
+    fn eject_add(&self, eject: &EjectQuery) -> Result<u32, ReasonCode>;
+    fn eject_delete(&self, address: Ipv4Addr) -> Result<(), ReasonCode>;
+    fn eject_lookup(&self, address: Ipv4Addr) -> Result<EjectQuery, ReasonCode>;
+    fn eject_next(&self, address: Ipv4Addr) -> Result<EjectQuery, ReasonCode>;
+    fn eject_prev(&self, address: Ipv4Addr) -> Result<EjectQuery, ReasonCode>;
+    fn eject_active_for(&self, address: IpAddr, now: u32) -> Option<EjectQuery>;
 }
 
 impl EjectDB for Database {
     fn init_eject(&self) {
-        let mut conn = self
-            .pool
-            .get_conn()
-            .expect("Could not get mysql connection.");
+        let mut conn = self.conn().expect("Could not get mysql connection.");
 
         conn.query_drop(
             r"CREATE TABLE IF NOT EXISTS awu_eject ( 
@@ -25,5 +61,238 @@ impl EjectDB for Database {
             ENGINE=MyISAM DEFAULT CHARSET=latin1;",
         )
         .unwrap();
+
+        self.add_column_if_missing(
+            "awu_eject",
+            "AddressEnd",
+            "int(11) unsigned NOT NULL default '0' AFTER Address",
+        );
+        // IPv6 ejections keep their stand-in address in Address, for the protocol
+        self.add_column_if_missing(
+            "awu_eject",
+            "Address6",
+            "varbinary(16) NOT NULL default '' AFTER AddressEnd",
+        );
+        self.add_column_if_missing(
+            "awu_eject",
+            "AddressEnd6",
+            "varbinary(16) NOT NULL default '' AFTER Address6",
+        );
+    }
+
+    fn eject_add(&self, eject: &EjectQuery) -> Result<u32, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"INSERT INTO awu_eject (Expiration, Creation, Address, AddressEnd, Address6, AddressEnd6, Comment) 
+            VALUES(:expiration, :creation, :address, :address_end, :address6, :address_end6, :comment)",
+            params! {
+                "expiration" => eject.expiration,
+                "creation" => eject.creation,
+                "address" => u32::from(ipv4_representation(eject.address)),
+                "address_end" => match eject.address_end {
+                    Some(IpAddr::V4(end)) => u32::from(end),
+                    _ => 0,
+                },
+                "address6" => ipv6_octets(Some(eject.address)),
+                "address_end6" => ipv6_octets(eject.address_end),
+                "comment" => &eject.comment,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        u32::try_from(conn.last_insert_id()).map_err(|_| ReasonCode::DatabaseError)
+    }
+
+    fn eject_delete(&self, address: Ipv4Addr) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_eject WHERE Address=:address",
+            params! {
+                "address" => u32::from(address),
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        if conn.affected_rows() == 0 {
+            return Err(ReasonCode::NoSuchEjection);
+        }
+
+        Ok(())
+    }
+
+    fn eject_lookup(&self, address: Ipv4Addr) -> Result<EjectQuery, ReasonCode> {
+        self.eject_query(
+            r"SELECT * FROM awu_eject WHERE Address=:address LIMIT 1",
+            address,
+        )
+    }
+
+    fn eject_next(&self, address: Ipv4Addr) -> Result<EjectQuery, ReasonCode> {
+        self.eject_query(
+            r"SELECT * FROM awu_eject WHERE Address>:address ORDER BY Address LIMIT 1",
+            address,
+        )
+    }
+
+    fn eject_prev(&self, address: Ipv4Addr) -> Result<EjectQuery, ReasonCode> {
+        self.eject_query(
+            r"SELECT * FROM awu_eject WHERE Address<:address ORDER BY Address DESC LIMIT 1",
+            address,
+        )
+    }
+
+    fn eject_active_for(&self, address: IpAddr, now: u32) -> Option<EjectQuery> {
+        let mut conn = self.conn().ok()?;
+
+        let rows: Vec<Row> = match address.to_canonical() {
+            IpAddr::V4(v4) => conn.exec(
+                r"SELECT * FROM awu_eject WHERE Address<=:address 
+                AND (Address=:address OR AddressEnd>=:address) 
+                AND Address6='' 
+                AND (Expiration=0 OR Expiration>:now)",
+                params! {
+                    "address" => u32::from(v4),
+                    "now" => now,
+                },
+            ),
+            // There are few of these, so they are matched here
+            IpAddr::V6(_) => conn.exec(
+                r"SELECT * FROM awu_eject WHERE Address6<>'' 
+                AND (Expiration=0 OR Expiration>:now)",
+                params! {
+                    "now" => now,
+                },
+            ),
+        }
+        .ok()?;
+
+        rows.iter()
+            .filter_map(|row| fetch_eject(row).ok())
+            .find(|eject| eject.covers(address))
+    }
+}
+
+impl Database {
+    fn eject_query(&self, query: &str, address: Ipv4Addr) -> Result<EjectQuery, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                query,
+                params! {
+                    "address" => u32::from(address),
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        match rows.first() {
+            Some(row) => fetch_eject(row),
+            None => Err(ReasonCode::NoSuchEjection),
+        }
+    }
+}
+
+fn fetch_eject(row: &Row) -> Result<EjectQuery, ReasonCode> {
+    let id: u32 = database::fetch_int(row, "ID")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let address: u32 = database::fetch_int(row, "Address")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let address_end: u32 = database::fetch_int(row, "AddressEnd")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let expiration: u32 = database::fetch_int(row, "Expiration")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let creation: u32 = database::fetch_int(row, "Creation")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let comment = database::fetch_string(row, "Comment").ok_or(ReasonCode::DatabaseError)?;
+
+    // Missing from tables which have not been upgraded yet
+    let address6 = database::fetch_data(row, "Address6").and_then(ipv6_from_octets);
+    let address_end6 = database::fetch_data(row, "AddressEnd6").and_then(ipv6_from_octets);
+
+    let (address, address_end) = match address6 {
+        Some(address6) => (IpAddr::V6(address6), address_end6.map(IpAddr::V6)),
+        None => (
+            IpAddr::V4(Ipv4Addr::from(address)),
+            match address_end {
+                0 => None,
+                end => Some(IpAddr::V4(Ipv4Addr::from(end))),
+            },
+        ),
+    };
+
+    Ok(EjectQuery {
+        id,
+        address,
+        address_end,
+        expiration,
+        creation,
+        comment,
+    })
+}
+
+/// How an IPv6 address is stored, or nothing for an IPv4 one.
+fn ipv6_octets(address: Option<IpAddr>) -> Vec<u8> {
+    match address {
+        Some(IpAddr::V6(v6)) => v6.octets().to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+fn ipv6_from_octets(octets: Vec<u8>) -> Option<Ipv6Addr> {
+    <[u8; 16]>::try_from(octets).ok().map(Ipv6Addr::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eject(address: &str, address_end: Option<&str>) -> EjectQuery {
+        EjectQuery {
+            id: 1,
+            address: address.parse().unwrap(),
+            address_end: address_end.map(|end| end.parse().unwrap()),
+            expiration: 0,
+            creation: 0,
+            comment: String::new(),
+        }
+    }
+
+    #[test]
+    pub fn test_covers_ipv4() {
+        let single = eject("192.0.2.1", None);
+        assert!(single.covers("192.0.2.1".parse().unwrap()));
+        assert!(single.covers("::ffff:192.0.2.1".parse().unwrap()));
+        assert!(!single.covers("192.0.2.2".parse().unwrap()));
+
+        let range = eject("192.0.2.0", Some("192.0.2.255"));
+        assert!(range.covers("192.0.2.128".parse().unwrap()));
+        assert!(!range.covers("192.0.3.0".parse().unwrap()));
+        assert!(!range.covers("2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    pub fn test_covers_ipv6() {
+        let network = eject("2001:db8::", Some("2001:db8::ffff:ffff:ffff:ffff"));
+        assert!(network.covers("2001:db8::1".parse().unwrap()));
+        assert!(network.covers("2001:db8::abcd:1234".parse().unwrap()));
+        assert!(!network.covers("2001:db8:0:1::1".parse().unwrap()));
+        assert!(!network.covers("192.0.2.1".parse().unwrap()));
     }
 }
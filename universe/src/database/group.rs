@@ -0,0 +1,298 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::Database;
+use crate::database;
+use aw_core::ReasonCode;
+use bitflags::bitflags;
+use mysql::*;
+
+type Result<T, E> = std::result::Result<T, E>;
+
+bitflags! {
+    /// Rights given to every member of a group.
+    #[derive(Default)]
+    pub struct GroupRights : u32 {
+        /// Members have the same permissions as the Administrator, including all below
+        const ADMIN = 0b0000_0000_0000_0001;
+        /// Members may look up, add, change and delete citizens
+        const CITIZEN_ADMIN = 0b0000_0000_0000_0010;
+        /// Members may look up, add, change and delete world licenses
+        const WORLD_ADMIN = 0b0000_0000_0000_0100;
+        /// Members may eject and kick users
+        const EJECT_ADMIN = 0b0000_0000_0000_1000;
+        /// Members may change the universe attributes and see the private ones
+        const ATTRIBUTE_ADMIN = 0b0000_0000_0001_0000;
+        /// Members may send console messages to other users
+        const BROADCAST = 0b0000_0000_0010_0000;
+        /// Members may see and change anyone's credit balance
+        const CREDIT_ADMIN = 0b0000_0000_0100_0000;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupQuery {
+    pub id: u32,
+    pub name: String,
+    pub owner: u32,
+    pub rights: GroupRights,
+}
+
+pub trait GroupDB {
+    fn init_group(&self);
+    fn group_by_name(&self, name: &str) -> Result<GroupQuery, ReasonCode>;
+    fn group_add(&self, name: &str, owner: u32) -> Result<u32, ReasonCode>;
+    fn group_delete(&self, group_id: u32) -> Result<(), ReasonCode>;
+    fn group_set_rights(&self, group_id: u32, rights: GroupRights) -> Result<(), ReasonCode>;
+    fn group_members(&self, group_id: u32) -> Result<Vec<u32>, ReasonCode>;
+    fn group_add_member(&self, group_id: u32, citizen_id: u32) -> Result<(), ReasonCode>;
+    fn group_remove_member(&self, group_id: u32, citizen_id: u32) -> Result<(), ReasonCode>;
+    fn groups_of_citizen(&self, citizen_id: u32) -> Result<Vec<GroupQuery>, ReasonCode>;
+    fn group_rights_of_citizen(&self, citizen_id: u32) -> GroupRights;
+    fn group_citizens_with_rights(&self, rights: GroupRights) -> Result<Vec<u32>, ReasonCode>;
+    fn group_remove_citizen(&self, citizen_id: u32) -> Result<(), ReasonCode>;
+}
+
+impl GroupDB for Database {
+    fn init_group(&self) {
+        let mut conn = self.conn().expect("Could not get mysql connection.");
+
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_group ( 
+                ID int(11) unsigned NOT NULL auto_increment, 
+                Name varchar(50) NOT NULL default '', 
+                Owner int(11) unsigned NOT NULL default '0', 
+                Rights int(11) unsigned NOT NULL default '0', 
+                Created int(11) NOT NULL default '0', 
+                PRIMARY KEY  (ID), 
+                UNIQUE KEY Index1 (Name) 
+            ) 
+            ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_group_member ( 
+                GroupID int(11) unsigned NOT NULL default '0', 
+                Citizen int(11) unsigned NOT NULL default '0', 
+                PRIMARY KEY  (GroupID, Citizen), 
+                KEY Index1 (Citizen) 
+            ) 
+            ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+    }
+
+    fn group_by_name(&self, name: &str) -> Result<GroupQuery, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT * FROM awu_group WHERE Name=:name",
+                params! {
+                    "name" => name,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        match rows.first() {
+            Some(row) => fetch_group(row),
+            None => Err(ReasonCode::InvalidArgument),
+        }
+    }
+
+    fn group_add(&self, name: &str, owner: u32) -> Result<u32, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Current time is before the unix epoch.")
+            .as_secs();
+
+        conn.exec_drop(
+            r"INSERT INTO awu_group (Name, Owner, Created) VALUES(:name, :owner, :created)",
+            params! {
+                "name" => name,
+                "owner" => owner,
+                "created" => now,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        u32::try_from(conn.last_insert_id()).map_err(|_| ReasonCode::DatabaseError)
+    }
+
+    fn group_delete(&self, group_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_group_member WHERE GroupID=:id",
+            params! {
+                "id" => group_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_group WHERE ID=:id",
+            params! {
+                "id" => group_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn group_set_rights(&self, group_id: u32, rights: GroupRights) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_group SET Rights=:rights WHERE ID=:id",
+            params! {
+                "rights" => rights.bits(),
+                "id" => group_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn group_members(&self, group_id: u32) -> Result<Vec<u32>, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT Citizen FROM awu_group_member WHERE GroupID=:id ORDER BY Citizen",
+                params! {
+                    "id" => group_id,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        rows.iter()
+            .map(|row| {
+                database::fetch_int(row, "Citizen")
+                    .and_then(|x| u32::try_from(x).ok())
+                    .ok_or(ReasonCode::DatabaseError)
+            })
+            .collect()
+    }
+
+    fn group_add_member(&self, group_id: u32, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"INSERT IGNORE INTO awu_group_member (GroupID, Citizen) VALUES(:id, :citizen)",
+            params! {
+                "id" => group_id,
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn group_remove_member(&self, group_id: u32, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_group_member WHERE GroupID=:id AND Citizen=:citizen",
+            params! {
+                "id" => group_id,
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn groups_of_citizen(&self, citizen_id: u32) -> Result<Vec<GroupQuery>, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT g.* FROM awu_group g 
+                JOIN awu_group_member m ON m.GroupID=g.ID 
+                WHERE m.Citizen=:citizen ORDER BY g.Name",
+                params! {
+                    "citizen" => citizen_id,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        rows.iter().map(fetch_group).collect()
+    }
+
+    fn group_rights_of_citizen(&self, citizen_id: u32) -> GroupRights {
+        self.groups_of_citizen(citizen_id)
+            .unwrap_or_default()
+            .iter()
+            .fold(GroupRights::empty(), |rights, group| rights | group.rights)
+    }
+
+    fn group_citizens_with_rights(&self, rights: GroupRights) -> Result<Vec<u32>, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT DISTINCT m.Citizen FROM awu_group_member m 
+                JOIN awu_group g ON m.GroupID=g.ID 
+                WHERE g.Rights & :rights = :rights ORDER BY m.Citizen",
+                params! {
+                    "rights" => rights.bits(),
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        rows.iter()
+            .map(|row| {
+                database::fetch_int(row, "Citizen")
+                    .and_then(|x| u32::try_from(x).ok())
+                    .ok_or(ReasonCode::DatabaseError)
+            })
+            .collect()
+    }
+
+    fn group_remove_citizen(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_group_member WHERE Citizen=:citizen",
+            params! {
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+}
+
+fn fetch_group(row: &Row) -> Result<GroupQuery, ReasonCode> {
+    let id: u32 = database::fetch_int(row, "ID")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let name = database::fetch_string(row, "Name").ok_or(ReasonCode::DatabaseError)?;
+
+    let owner: u32 = database::fetch_int(row, "Owner")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let rights: u32 = database::fetch_int(row, "Rights")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    Ok(GroupQuery {
+        id,
+        name,
+        owner,
+        rights: GroupRights::from_bits_truncate(rights),
+    })
+}
@@ -4,12 +4,12 @@ use crate::database;
 
 use super::Database;
 use aw_core::ReasonCode;
-use mysql::prelude::*;
 use mysql::*;
+use serde::{Deserialize, Serialize};
 
 type Result<T, E> = std::result::Result<T, E>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LicenseQuery {
     pub id: u32,
     pub name: String,
@@ -36,14 +36,12 @@ pub trait LicenseDB {
     fn license_next(&self, name: &str) -> Result<LicenseQuery, ReasonCode>;
     fn license_prev(&self, name: &str) -> Result<LicenseQuery, ReasonCode>;
     fn license_change(&self, lic: &LicenseQuery) -> Result<(), ReasonCode>;
+    fn license_delete(&self, name: &str) -> Result<(), ReasonCode>;
 }
 
 impl LicenseDB for Database {
     fn init_license(&self) {
-        let mut conn = self
-            .pool
-            .get_conn()
-            .expect("Could not get mysql connection.");
+        let mut conn = self.conn().expect("Could not get mysql connection.");
 
         // "Range" has been changed to "WorldSize" because range is now a keyword.
         conn.query_drop(
@@ -108,10 +106,11 @@ impl LicenseDB for Database {
                 VALUES(:creation, :expiration, :last_start, :last_address, :hidden, :tourists,
                     :users, :world_size, :voip, :plugins, :name, :password, :email, :comment);",
             params! {
-                "creation" => now,
+                // Licenses brought over from elsewhere keep their history
+                "creation" => if lic.creation != 0 { lic.creation as u64 } else { now },
                 "expiration" => lic.expiration,
-                "last_start" => 0,
-                "last_address" => 0,
+                "last_start" => lic.last_start,
+                "last_address" => lic.last_address as i32,
                 "hidden" => lic.hidden,
                 "tourists" => lic.tourists,
                 "users" => lic.users,
@@ -189,7 +188,7 @@ impl LicenseDB for Database {
                 "creation" => lic.creation,
                 "expiration" => lic.expiration,
                 "last_start" => lic.last_start,
-                "last_address" => lic.last_address,
+                "last_address" => lic.last_address as i32,
                 "hidden" => lic.hidden,
                 "tourists" => lic.tourists,
                 "users" => lic.users,
@@ -206,6 +205,20 @@ impl LicenseDB for Database {
 
         Ok(())
     }
+
+    fn license_delete(&self, name: &str) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_license WHERE Name=:name;",
+            params! {
+                "name" => name,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
 }
 
 fn fetch_license(row: &Row) -> Result<LicenseQuery, ReasonCode> {
@@ -239,10 +252,8 @@ fn fetch_license(row: &Row) -> Result<LicenseQuery, ReasonCode> {
         .try_into()
         .map_err(|_| ReasonCode::DatabaseError)?;
 
-    let last_address: u32 = database::fetch_int(row, "LastAddress")
-        .ok_or(ReasonCode::DatabaseError)?
-        .try_into()
-        .map_err(|_| ReasonCode::DatabaseError)?;
+    let last_address =
+        database::fetch_int(row, "LastAddress").ok_or(ReasonCode::DatabaseError)? as u32;
 
     let users: u32 = database::fetch_int(row, "Users")
         .ok_or(ReasonCode::DatabaseError)?
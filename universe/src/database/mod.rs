@@ -3,25 +3,74 @@ use mysql::*;
 use crate::config::{MysqlConfig, UniverseConfig};
 
 pub use self::attrib::AttribDB;
+pub use self::audit::AuditDB;
 pub use self::cav::CavDB;
 pub use self::citizen::CitizenDB;
 pub use self::contact::ContactDB;
+pub use self::credit::CreditDB;
 pub use self::eject::EjectDB;
+pub use self::group::GroupDB;
 pub use self::license::LicenseDB;
+pub use self::profile::ProfileDB;
+pub use self::stats::StatsDB;
 pub use self::telegram::TelegramDB;
+pub use self::token::TokenDB;
 pub mod attrib;
+pub mod audit;
 pub mod cav;
 pub mod citizen;
+pub mod conn;
 pub mod contact;
+pub mod credit;
 pub mod eject;
+pub mod group;
 pub mod license;
+pub mod password;
+pub mod profile;
+#[cfg(feature = "dev")]
+pub mod sqlite;
+pub mod stats;
 pub mod telegram;
+pub mod timing;
+pub mod token;
+
+/// Pool size limits from the config, keeping the defaults for anything left at 0.
+fn pool_opts(config: &MysqlConfig) -> Result<PoolOpts, String> {
+    let defaults = PoolConstraints::default();
+    let min = match config.pool_min_connections {
+        0 => defaults.min(),
+        min => min,
+    };
+    let max = match config.pool_max_connections {
+        0 => defaults.max().max(min),
+        max => max,
+    };
+
+    let constraints = PoolConstraints::new(min, max).ok_or(format!(
+        "Database pool minimum ({min}) is larger than its maximum ({max})"
+    ))?;
+
+    Ok(PoolOpts::default().with_constraints(constraints))
+}
 
 type Result<T, E> = core::result::Result<T, E>;
+use conn::Conn;
 use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use timing::{QueryTiming, TimedConn};
+
+enum Backend {
+    Mysql(Pool),
+    /// Used in development mode
+    #[cfg(feature = "dev")]
+    Sqlite(sqlite::TempDatabase),
+}
+
 pub struct Database {
-    pool: Pool,
+    backend: Backend,
     config: MysqlConfig,
+    query_timing: Arc<QueryTiming>,
 }
 
 impl Database {
@@ -32,18 +81,73 @@ impl Database {
         let port = &config.port;
         let database_name = &config.database;
         let uri = format!("mysql://{username}:{password}@{hostname}:{port}/{database_name}");
-        let pool = Pool::new(uri.as_str())
+        let opts = Opts::from_url(&uri)
+            .map_err(|err| format!("Invalid database connection settings: {err}"))?;
+
+        // Broken connections are dropped by the pool and replaced on the next request,
+        // so losing the server only fails the requests made while it is away.
+        let opts = OptsBuilder::from_opts(opts)
+            .pool_opts(pool_opts(&config)?)
+            .tcp_connect_timeout(
+                Some(Duration::from_secs(config.connect_timeout_secs))
+                    .filter(|timeout| !timeout.is_zero()),
+            );
+
+        let pool = Pool::new(opts)
             .map_err(|err| format!("Could not create database connection pool: {err}"))?;
 
-        let db = Self { pool, config };
+        let db = Self {
+            backend: Backend::Mysql(pool),
+            config,
+            query_timing: Default::default(),
+        };
+
+        db.init_tables(universe_config);
+
+        Ok(db)
+    }
+
+    /// A database in a temporary SQLite file, removed again when it is dropped.
+    #[cfg(feature = "dev")]
+    pub fn temporary(universe_config: &UniverseConfig) -> Result<Self, String> {
+        let temp = sqlite::TempDatabase::create()?;
+        log::info!("Using temporary database {}", temp.path().display());
+
+        let db = Self {
+            backend: Backend::Sqlite(temp),
+            config: Default::default(),
+            query_timing: Default::default(),
+        };
 
         db.init_tables(universe_config);
 
         Ok(db)
     }
 
-    pub fn conn(&self) -> Result<PooledConn, Box<dyn Error>> {
-        Ok(self.pool.get_conn()?)
+    pub fn conn(&self) -> Result<TimedConn, Box<dyn Error>> {
+        let conn = match &self.backend {
+            Backend::Mysql(pool) => Conn::Mysql(pool.get_conn()?),
+            #[cfg(feature = "dev")]
+            Backend::Sqlite(temp) => Conn::Sqlite(temp.connect()?),
+        };
+        Ok(TimedConn::new(conn, self.query_timing.clone()))
+    }
+
+    pub fn query_timing(&self) -> &QueryTiming {
+        &self.query_timing
+    }
+
+    /// Add a column to a table created by an older version of the universe.
+    fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) {
+        let mut conn = self.conn().expect("Could not get mysql connection.");
+
+        if !conn.has_column(table, column).unwrap() {
+            log::info!("Adding column {column} to {table}");
+            conn.query_drop(format!(
+                "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+            ))
+            .unwrap();
+        }
     }
 
     fn init_tables(&self, universe_config: &UniverseConfig) {
@@ -54,6 +158,37 @@ impl Database {
         self.init_telegram();
         self.init_cav();
         self.init_eject();
+        self.init_stats();
+        self.init_profile();
+        self.init_group();
+        self.init_credit();
+        self.init_token();
+        self.init_audit();
+    }
+}
+
+#[cfg(test)]
+impl Database {
+    /// A database whose server can never be reached, for testing code which
+    /// carries on when its queries fail.
+    pub fn unreachable() -> Self {
+        let config = MysqlConfig {
+            port: 1,
+            ..Default::default()
+        };
+        let constraints = PoolConstraints::new(0, 1).expect("Invalid pool constraints");
+        let opts = OptsBuilder::new()
+            .ip_or_hostname(Some(config.hostname.clone()))
+            .tcp_port(config.port)
+            .pool_opts(PoolOpts::default().with_constraints(constraints));
+
+        Self {
+            backend: Backend::Mysql(
+                Pool::new(opts).expect("Could not create database connection pool"),
+            ),
+            config,
+            query_timing: Default::default(),
+        }
     }
 }
 
@@ -92,3 +227,21 @@ pub fn fetch_string(row: &Row, name: &str) -> Option<String> {
     }
     None
 }
+
+pub fn fetch_data(row: &Row, name: &str) -> Option<Vec<u8>> {
+    for column in row.columns_ref() {
+        let column_value = &row[column.name_str().as_ref()];
+        let column_name = column.name_str().to_string();
+        if column_name == name {
+            match column_value {
+                Value::Bytes(x) => {
+                    return Some(x.clone());
+                }
+                _ => {
+                    return None;
+                }
+            }
+        }
+    }
+    None
+}
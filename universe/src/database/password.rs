@@ -0,0 +1,97 @@
+//! Hashing of citizen passwords and privilege passwords.
+//!
+//! Passwords are stored as Argon2 PHC strings. Rows written before hashing was
+//! introduced still hold the plain password, and are rehashed the next time the
+//! password is used.
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use aw_core::ReasonCode;
+
+/// Hash a password for storage.
+pub fn hash_password(password: &str) -> Result<String, ReasonCode> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| ReasonCode::DatabaseError)
+}
+
+/// Whether a stored password is already hashed rather than legacy plain text.
+pub fn is_hashed(stored: &str) -> bool {
+    stored.starts_with("$argon2") && PasswordHash::new(stored).is_ok()
+}
+
+/// Hash a password given by a user for storage. It is hashed even if it looks
+/// like a hash already, so nobody can choose the stored value directly.
+pub fn prepare_password(password: &str) -> Result<String, ReasonCode> {
+    hash_password(password)
+}
+
+/// Hash a privilege password given by a user for storage. An empty one means the
+/// citizen has none, so it is kept empty.
+pub fn prepare_privilege_password(password: &str) -> Result<String, ReasonCode> {
+    if password.is_empty() {
+        Ok(String::new())
+    } else {
+        prepare_password(password)
+    }
+}
+
+/// Hash a password which was already stored, such as one from an existing row or
+/// a backup, if it is still legacy plain text. Hashed ones are kept as they are.
+pub fn migrate_password(stored: &str) -> Result<String, ReasonCode> {
+    if is_hashed(stored) {
+        Ok(stored.to_string())
+    } else {
+        hash_password(stored)
+    }
+}
+
+/// Like `migrate_password`, keeping an empty privilege password empty.
+pub fn migrate_privilege_password(stored: &str) -> Result<String, ReasonCode> {
+    if stored.is_empty() {
+        Ok(String::new())
+    } else {
+        migrate_password(stored)
+    }
+}
+
+/// Check a password given by a user against the stored one.
+pub fn verify_password(stored: &str, given: &str) -> bool {
+    if !is_hashed(stored) {
+        return stored == given;
+    }
+
+    match PasswordHash::new(stored) {
+        Ok(hash) => Argon2::default()
+            .verify_password(given.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_prepare_password() {
+        // Something that looks like a hash is still just a password when given by a user
+        let hash = hash_password("secret").unwrap();
+        let stored = prepare_password(&hash).unwrap();
+        assert_ne!(stored, hash);
+        assert!(verify_password(&stored, &hash));
+        assert!(!verify_password(&stored, "secret"));
+
+        // Stored values are kept if hashed, and hashed if legacy plain text
+        assert_eq!(migrate_password(&hash).unwrap(), hash);
+        assert!(verify_password(
+            &migrate_password("secret").unwrap(),
+            "secret"
+        ));
+        assert_eq!(migrate_privilege_password("").unwrap(), "");
+    }
+}
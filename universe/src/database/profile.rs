@@ -0,0 +1,106 @@
+use super::Database;
+use crate::database;
+use aw_core::ReasonCode;
+use mysql::*;
+
+type Result<T, E> = std::result::Result<T, E>;
+
+/// An extra piece of information on a citizen's profile, such as their pronouns.
+#[derive(Debug, Clone)]
+pub struct ProfileFieldQuery {
+    pub name: String,
+    pub value: String,
+}
+
+pub trait ProfileDB {
+    fn init_profile(&self);
+    fn profile_get_all(&self, citizen_id: u32) -> Result<Vec<ProfileFieldQuery>, ReasonCode>;
+    fn profile_set(&self, citizen_id: u32, name: &str, value: &str) -> Result<(), ReasonCode>;
+    fn profile_delete(&self, citizen_id: u32, name: &str) -> Result<(), ReasonCode>;
+    fn profile_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode>;
+}
+
+impl ProfileDB for Database {
+    fn init_profile(&self) {
+        let mut conn = self.conn().expect("Could not get mysql connection.");
+
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_citizen_profile ( 
+                Citizen int(11) unsigned NOT NULL default '0', 
+                Name varchar(64) NOT NULL default '', 
+                Value varchar(255) NOT NULL default '', 
+                PRIMARY KEY  (Citizen, Name) 
+            ) 
+            ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+    }
+
+    fn profile_get_all(&self, citizen_id: u32) -> Result<Vec<ProfileFieldQuery>, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        let rows: Vec<Row> = conn
+            .exec(
+                r"SELECT * FROM awu_citizen_profile WHERE Citizen=:citizen ORDER BY Name",
+                params! {
+                    "citizen" => citizen_id,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        rows.iter().map(fetch_profile_field).collect()
+    }
+
+    fn profile_set(&self, citizen_id: u32, name: &str, value: &str) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"REPLACE INTO awu_citizen_profile (Citizen, Name, Value) 
+            VALUES(:citizen, :name, :value)",
+            params! {
+                "citizen" => citizen_id,
+                "name" => name,
+                "value" => value,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn profile_delete(&self, citizen_id: u32, name: &str) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_citizen_profile WHERE Citizen=:citizen AND Name=:name",
+            params! {
+                "citizen" => citizen_id,
+                "name" => name,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn profile_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_citizen_profile WHERE Citizen=:citizen",
+            params! {
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+}
+
+fn fetch_profile_field(row: &Row) -> Result<ProfileFieldQuery, ReasonCode> {
+    let name = database::fetch_string(row, "Name").ok_or(ReasonCode::DatabaseError)?;
+    let value = database::fetch_string(row, "Value").ok_or(ReasonCode::DatabaseError)?;
+
+    Ok(ProfileFieldQuery { name, value })
+}
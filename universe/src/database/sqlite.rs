@@ -0,0 +1,350 @@
+//! The temporary SQLite database used in development mode.
+//!
+//! The tables are written for MySQL, so their statements are translated where SQLite
+//! differs, and results are turned into MySQL rows for the code reading them.
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use mysql::{consts::ColumnType, params, prelude::FromRow, Params, Value};
+use mysql_common::{packets::Column, row::new_row};
+use rusqlite::{
+    types::{Value as SqliteValue, ValueRef},
+    Connection, Statement,
+};
+
+use super::conn::{QueryError, QueryResult};
+
+/// How long to wait for another connection to finish writing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// MySQL statements and the SQLite ones they are run as. All statements write these
+/// in upper case.
+const REPLACEMENTS: &[(&str, &str)] = &[
+    ("INSERT IGNORE", "INSERT OR IGNORE"),
+    ("ON DUPLICATE KEY UPDATE", "ON CONFLICT DO UPDATE SET"),
+    ("UNIX_TIMESTAMP()", "CAST(strftime('%s', 'now') AS INTEGER)"),
+    ("GREATEST(", "MAX("),
+    (" FOR UPDATE", ""),
+];
+
+/// A database file which is removed again when the universe stops.
+pub struct TempDatabase {
+    path: PathBuf,
+}
+
+impl TempDatabase {
+    pub fn create() -> Result<Self, String> {
+        let path =
+            std::env::temp_dir().join(format!("awuniverse-dev-{}.sqlite", std::process::id()));
+
+        // Left behind by an earlier process with the same ID
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .map_err(|err| format!("Could not remove old {}: {err}", path.display()))?;
+        }
+
+        Ok(Self { path })
+    }
+
+    pub fn connect(&self) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(conn)
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            log::warn!("Could not remove {}: {err}", self.path.display());
+        }
+    }
+}
+
+pub fn exec<T: FromRow>(conn: &Connection, stmt: &str, params: Params) -> QueryResult<Vec<T>> {
+    let mut statement = prepare(conn, stmt, params)?;
+    let columns: Arc<[Column]> = statement
+        .column_names()
+        .into_iter()
+        .map(|name| Column::new(ColumnType::MYSQL_TYPE_VAR_STRING).with_name(name.as_bytes()))
+        .collect();
+
+    let mut result = Vec::new();
+    let mut rows = statement.raw_query();
+    while let Some(row) = rows.next()? {
+        let values = (0..columns.len())
+            .map(|index| row.get_ref(index).map(from_sqlite))
+            .collect::<rusqlite::Result<Vec<Value>>>()?;
+        let row = mysql::from_row_opt(new_row(values, columns.clone()))
+            .map_err(|err| QueryError::Mysql(mysql::Error::FromRowError(err.0)))?;
+        result.push(row);
+    }
+
+    Ok(result)
+}
+
+pub fn exec_drop(conn: &Connection, stmt: &str, params: Params) -> QueryResult<()> {
+    prepare(conn, stmt, params)?.raw_execute()?;
+    Ok(())
+}
+
+pub fn has_column(conn: &Connection, table: &str, column: &str) -> QueryResult<bool> {
+    let found: Option<i64> = exec(
+        conn,
+        r"SELECT COUNT(*) FROM pragma_table_info(:table) WHERE name=:column COLLATE NOCASE",
+        params! {
+            "table" => table,
+            "column" => column,
+        },
+    )?
+    .into_iter()
+    .next();
+
+    Ok(found.unwrap_or(0) > 0)
+}
+
+fn prepare<'a>(conn: &'a Connection, stmt: &str, params: Params) -> QueryResult<Statement<'a>> {
+    let mut statement = conn.prepare(&translate(stmt))?;
+
+    match params {
+        Params::Empty => {}
+        Params::Named(named) => {
+            for (name, value) in named {
+                let name: &[u8] = name.as_ref();
+                let name = format!(":{}", String::from_utf8_lossy(name));
+                if let Some(index) = statement.parameter_index(&name)? {
+                    statement.raw_bind_parameter(index, to_sqlite(value))?;
+                }
+            }
+        }
+        Params::Positional(values) => {
+            for (index, value) in values.into_iter().enumerate() {
+                statement.raw_bind_parameter(index + 1, to_sqlite(value))?;
+            }
+        }
+    }
+
+    Ok(statement)
+}
+
+/// Rewrite a MySQL statement for SQLite.
+pub fn translate(stmt: &str) -> String {
+    let trimmed = stmt.trim_start();
+    let starts_with = |prefix: &str| {
+        trimmed
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    };
+
+    if starts_with("CREATE TABLE") {
+        return translate_create(trimmed);
+    }
+    if starts_with("ALTER TABLE") {
+        if let Some((head, column)) = trimmed.split_once("ADD COLUMN ") {
+            return format!("{head}ADD COLUMN {}", translate_column(column));
+        }
+    }
+
+    REPLACEMENTS
+        .iter()
+        .fold(stmt.to_string(), |sql, (mysql, sqlite)| {
+            sql.replace(mysql, sqlite)
+        })
+}
+
+/// Rewrite a table's columns and keys, leaving out its MySQL table options.
+fn translate_create(stmt: &str) -> String {
+    let (open, close) = match (stmt.find('('), stmt.rfind(')')) {
+        (Some(open), Some(close)) if open < close => (open, close),
+        _ => return stmt.to_string(),
+    };
+
+    let definitions = split_definitions(&stmt[open + 1..close]);
+    let auto_increment = definitions
+        .iter()
+        .any(|definition| definition.to_lowercase().contains("auto_increment"));
+
+    let definitions: Vec<String> = definitions
+        .iter()
+        .map(|definition| definition.trim())
+        .filter_map(|definition| {
+            let upper = definition.to_uppercase();
+            if upper.starts_with("PRIMARY KEY") {
+                // The auto increment column is made the primary key itself
+                (!auto_increment).then(|| definition.to_string())
+            } else if upper.starts_with("UNIQUE KEY") {
+                definition
+                    .find('(')
+                    .map(|columns| format!("UNIQUE {}", &definition[columns..]))
+            } else if upper.starts_with("KEY ") {
+                // Plain indexes are not worth having for a development database
+                None
+            } else {
+                Some(translate_column(definition))
+            }
+        })
+        .collect();
+
+    format!("{}({})", &stmt[..open], definitions.join(", "))
+}
+
+/// Rewrite a column definition. Text is compared without case, as it is in MySQL.
+fn translate_column(definition: &str) -> String {
+    let mut words = definition.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let kind = words.next().unwrap_or_default();
+    let mut rest: Vec<&str> = words
+        .filter(|word| !word.eq_ignore_ascii_case("unsigned"))
+        .collect();
+
+    if rest
+        .iter()
+        .any(|word| word.eq_ignore_ascii_case("auto_increment"))
+    {
+        return format!("{name} INTEGER PRIMARY KEY AUTOINCREMENT");
+    }
+
+    // Where an added column goes only matters to MySQL
+    if let Some(after) = rest
+        .iter()
+        .position(|word| word.eq_ignore_ascii_case("AFTER"))
+    {
+        rest.truncate(after);
+    }
+
+    let base = kind.split('(').next().unwrap_or_default().to_lowercase();
+    let kind = match base.as_str() {
+        "tinyint" | "smallint" | "mediumint" | "int" | "integer" | "bigint" => "INTEGER",
+        "float" | "double" | "real" => "REAL",
+        "char" | "varchar" | "tinytext" | "text" | "mediumtext" | "longtext" => {
+            "TEXT COLLATE NOCASE"
+        }
+        "binary" | "varbinary" | "tinyblob" | "blob" | "mediumblob" | "longblob" => "BLOB",
+        _ => kind,
+    };
+
+    format!("{name} {kind} {}", rest.join(" "))
+        .trim_end()
+        .to_string()
+}
+
+/// Split the inside of a CREATE TABLE on the commas between definitions.
+fn split_definitions(body: &str) -> Vec<&str> {
+    let mut definitions = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (index, c) in body.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                definitions.push(&body[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    definitions.push(&body[start..]);
+
+    definitions
+        .into_iter()
+        .filter(|definition| !definition.trim().is_empty())
+        .collect()
+}
+
+fn to_sqlite(value: Value) -> SqliteValue {
+    match value {
+        Value::NULL => SqliteValue::Null,
+        Value::Bytes(bytes) => match String::from_utf8(bytes) {
+            Ok(text) => SqliteValue::Text(text),
+            Err(err) => SqliteValue::Blob(err.into_bytes()),
+        },
+        Value::Int(x) => SqliteValue::Integer(x),
+        Value::UInt(x) => SqliteValue::Integer(x as i64),
+        Value::Float(x) => SqliteValue::Real(x.into()),
+        Value::Double(x) => SqliteValue::Real(x),
+        // Dates and times are not stored in any table
+        other => SqliteValue::Text(other.as_sql(true).trim_matches('\'').to_string()),
+    }
+}
+
+fn from_sqlite(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::NULL,
+        ValueRef::Integer(x) => Value::Int(x),
+        ValueRef::Real(x) => Value::Double(x),
+        ValueRef::Text(bytes) | ValueRef::Blob(bytes) => Value::Bytes(bytes.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_translate() {
+        assert_eq!(
+            translate(
+                r"CREATE TABLE IF NOT EXISTS awu_example (
+                    ID int(11) unsigned NOT NULL auto_increment,
+                    Name varchar(255) NOT NULL default '',
+                    Data varbinary(16) NOT NULL default '',
+                    PRIMARY KEY  (ID), UNIQUE KEY Index1 (Name),
+                    KEY Index2 (Data)
+                ) ENGINE=MyISAM DEFAULT CHARSET=latin1;"
+            ),
+            "CREATE TABLE IF NOT EXISTS awu_example (ID INTEGER PRIMARY KEY AUTOINCREMENT, \
+            Name TEXT COLLATE NOCASE NOT NULL default '', \
+            Data BLOB NOT NULL default '', UNIQUE (Name))"
+        );
+        assert_eq!(
+            translate("ALTER TABLE awu_eject ADD COLUMN AddressEnd int(11) unsigned NOT NULL default '0' AFTER Address"),
+            "ALTER TABLE awu_eject ADD COLUMN AddressEnd INTEGER NOT NULL default '0'"
+        );
+        assert_eq!(
+            translate("INSERT IGNORE INTO awu_example (ID) VALUES (UNIX_TIMESTAMP())"),
+            "INSERT OR IGNORE INTO awu_example (ID) VALUES (CAST(strftime('%s', 'now') AS INTEGER))"
+        );
+    }
+
+    #[test]
+    pub fn test_rows() {
+        let conn = Connection::open_in_memory().unwrap();
+        exec_drop(
+            &conn,
+            r"CREATE TABLE IF NOT EXISTS awu_example (
+                ID int(11) unsigned NOT NULL auto_increment,
+                Name varchar(255) NOT NULL default '',
+                Sent int(11) NOT NULL default '0',
+                PRIMARY KEY (ID), UNIQUE KEY Index1 (Name)
+            ) ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+            Params::Empty,
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            exec_drop(
+                &conn,
+                r"INSERT INTO awu_example (Name, Sent) VALUES(:name, 1)
+                ON DUPLICATE KEY UPDATE Sent=Sent+1",
+                params! { "name" => "Alice" },
+            )
+            .unwrap();
+        }
+
+        // Names match without case, like MySQL's
+        let rows: Vec<(u32, String, u32)> = exec(
+            &conn,
+            r"SELECT * FROM awu_example WHERE Name=:name",
+            params! { "name" => "alice" },
+        )
+        .unwrap();
+        assert_eq!(rows, vec![(1, "Alice".to_string(), 2)]);
+        assert!(has_column(&conn, "awu_example", "sent").unwrap());
+        assert!(!has_column(&conn, "awu_example", "Missing").unwrap());
+    }
+}
@@ -0,0 +1,105 @@
+use super::Database;
+use crate::database;
+use aw_core::ReasonCode;
+use mysql::*;
+
+type Result<T, E> = std::result::Result<T, E>;
+
+/// How often a URL has been sent to users and how often they followed it.
+/// No record is kept of who clicked.
+#[derive(Debug, Clone)]
+pub struct UrlStatsQuery {
+    pub url: String,
+    pub sent: u32,
+    pub clicks: u32,
+}
+
+pub trait StatsDB {
+    fn init_stats(&self);
+    fn stats_url_sent(&self, url: &str) -> Result<(), ReasonCode>;
+    /// Count a click on a URL which has been sent. Clicks on other URLs are not stored.
+    fn stats_url_clicked(&self, url: &str) -> Result<(), ReasonCode>;
+    fn stats_url_get_all(&self) -> Vec<UrlStatsQuery>;
+}
+
+impl StatsDB for Database {
+    fn init_stats(&self) {
+        let mut conn = self.conn().expect("Could not get mysql connection.");
+
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_url_stats ( 
+                URL varchar(255) NOT NULL default '', 
+                Sent int(11) unsigned NOT NULL default '0', 
+                Clicks int(11) unsigned NOT NULL default '0', 
+                PRIMARY KEY  (URL) 
+            ) 
+            ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+    }
+
+    fn stats_url_sent(&self, url: &str) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"INSERT INTO awu_url_stats (URL, Sent) VALUES(:url, 1) 
+            ON DUPLICATE KEY UPDATE Sent=Sent+1",
+            params! {
+                "url" => url,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn stats_url_clicked(&self, url: &str) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_url_stats SET Clicks=Clicks+1 WHERE URL=:url",
+            params! {
+                "url" => url,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn stats_url_get_all(&self) -> Vec<UrlStatsQuery> {
+        let mut stats = Vec::<UrlStatsQuery>::new();
+        let mut conn = match self.conn() {
+            Ok(x) => x,
+            Err(_) => return stats,
+        };
+
+        let rows: Vec<Row> = conn
+            .query(r"SELECT * FROM awu_url_stats ORDER BY Clicks DESC")
+            .unwrap_or_default();
+
+        for row in &rows {
+            if let Ok(url_stats) = fetch_url_stats(row) {
+                stats.push(url_stats);
+            }
+        }
+
+        stats
+    }
+}
+
+fn fetch_url_stats(row: &Row) -> Result<UrlStatsQuery, ReasonCode> {
+    let url: String = database::fetch_string(row, "URL").ok_or(ReasonCode::DatabaseError)?;
+
+    let sent: u32 = database::fetch_int(row, "Sent")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let clicks: u32 = database::fetch_int(row, "Clicks")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    Ok(UrlStatsQuery { url, sent, clicks })
+}
@@ -1,7 +1,6 @@
 use super::Database;
 use crate::database;
 use aw_core::ReasonCode;
-use mysql::prelude::*;
 use mysql::*;
 
 type Result<T, E> = std::result::Result<T, E>;
@@ -16,6 +15,14 @@ pub struct TelegramQuery {
     pub delivered: u32,
 }
 
+/// A citizen who has asked to be emailed telegrams received while offline.
+#[derive(Debug, Clone)]
+pub struct TelegramEmailQuery {
+    pub citizen: u32,
+    /// ID of the newest telegram which has already been emailed
+    pub last_forwarded: u32,
+}
+
 pub trait TelegramDB {
     fn init_telegram(&self);
     fn telegram_add(
@@ -28,14 +35,24 @@ pub trait TelegramDB {
     fn telegram_get_undelivered(&self, citizen_id: u32) -> Vec<TelegramQuery>;
     fn telegram_get_all(&self, citizen_id: u32) -> Vec<TelegramQuery>;
     fn telegram_mark_delivered(&self, telegram_id: u32) -> Result<(), ReasonCode>;
+    fn telegram_email_set(
+        &self,
+        citizen_id: u32,
+        enabled: bool,
+        unsubscribed: bool,
+    ) -> Result<(), ReasonCode>;
+    fn telegram_email_subscribers(&self) -> Vec<TelegramEmailQuery>;
+    fn telegram_email_set_forwarded(
+        &self,
+        citizen_id: u32,
+        telegram_id: u32,
+    ) -> Result<(), ReasonCode>;
+    fn telegram_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode>;
 }
 
 impl TelegramDB for Database {
     fn init_telegram(&self) {
-        let mut conn = self
-            .pool
-            .get_conn()
-            .expect("Could not get mysql connection.");
+        let mut conn = self.conn().expect("Could not get mysql connection.");
 
         conn.query_drop(
             r"CREATE TABLE IF NOT EXISTS awu_telegram ( 
@@ -51,6 +68,18 @@ impl TelegramDB for Database {
             ENGINE=MyISAM DEFAULT CHARSET=latin1;",
         )
         .unwrap();
+
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_telegram_email ( 
+                Citizen int(11) unsigned NOT NULL default '0', 
+                Enabled tinyint(1) NOT NULL default '0', 
+                Unsubscribed tinyint(1) NOT NULL default '0', 
+                LastForwarded int(11) unsigned NOT NULL default '0', 
+                PRIMARY KEY  (Citizen) 
+            ) 
+            ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
     }
 
     fn telegram_add(
@@ -143,6 +172,91 @@ impl TelegramDB for Database {
 
         Ok(())
     }
+
+    fn telegram_email_set(
+        &self,
+        citizen_id: u32,
+        enabled: bool,
+        unsubscribed: bool,
+    ) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"INSERT INTO awu_telegram_email (Citizen, Enabled, Unsubscribed) 
+            VALUES(:id, :enabled, :unsubscribed) 
+            ON DUPLICATE KEY UPDATE Enabled=:enabled, Unsubscribed=:unsubscribed",
+            params! {
+                "id" => citizen_id,
+                "enabled" => enabled as u32,
+                "unsubscribed" => unsubscribed as u32,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn telegram_email_subscribers(&self) -> Vec<TelegramEmailQuery> {
+        let mut subscribers = Vec::<TelegramEmailQuery>::new();
+        let mut conn = match self.conn() {
+            Ok(x) => x,
+            Err(_) => return subscribers,
+        };
+
+        let rows: Vec<Row> = conn
+            .query(r"SELECT * FROM awu_telegram_email WHERE Enabled=1 AND Unsubscribed=0")
+            .unwrap_or_default();
+
+        for row in &rows {
+            if let Ok(subscriber) = fetch_telegram_email(row) {
+                subscribers.push(subscriber);
+            }
+        }
+
+        subscribers
+    }
+
+    fn telegram_email_set_forwarded(
+        &self,
+        citizen_id: u32,
+        telegram_id: u32,
+    ) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"UPDATE awu_telegram_email SET LastForwarded=:telegram_id 
+            WHERE Citizen=:id",
+            params! {
+                "id" => citizen_id,
+                "telegram_id" => telegram_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn telegram_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_telegram WHERE Citizen=:citizen",
+            params! {
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_telegram_email WHERE Citizen=:citizen",
+            params! {
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
 }
 
 fn fetch_telegram(row: &Row) -> Result<TelegramQuery, ReasonCode> {
@@ -183,3 +297,20 @@ fn fetch_telegram(row: &Row) -> Result<TelegramQuery, ReasonCode> {
         delivered,
     })
 }
+
+fn fetch_telegram_email(row: &Row) -> Result<TelegramEmailQuery, ReasonCode> {
+    let citizen: u32 = database::fetch_int(row, "Citizen")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    let last_forwarded: u32 = database::fetch_int(row, "LastForwarded")
+        .ok_or(ReasonCode::DatabaseError)?
+        .try_into()
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+    Ok(TelegramEmailQuery {
+        citizen,
+        last_forwarded,
+    })
+}
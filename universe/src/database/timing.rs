@@ -0,0 +1,87 @@
+//! Timing of database queries, for metrics.
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use super::conn::Conn;
+
+/// Upper bounds in seconds of the query time histogram buckets.
+const BUCKETS: [f64; 7] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+#[derive(Default)]
+pub struct QueryTiming {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    buckets: [AtomicU64; BUCKETS.len()],
+}
+
+impl QueryTiming {
+    fn record(&self, secs: f64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros
+            .fetch_add((secs * 1_000_000.0) as u64, Ordering::Relaxed);
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            if secs <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum_secs(&self) -> f64 {
+        self.total_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+    }
+
+    /// Cumulative counts of queries no slower than each bucket's bound.
+    pub fn buckets(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(bound, bucket)| (*bound, bucket.load(Ordering::Relaxed)))
+    }
+}
+
+/// A connection which records how long it was held when dropped.
+pub struct TimedConn {
+    conn: Conn,
+    start: Instant,
+    timing: Arc<QueryTiming>,
+}
+
+impl TimedConn {
+    pub fn new(conn: Conn, timing: Arc<QueryTiming>) -> Self {
+        Self {
+            conn,
+            start: Instant::now(),
+            timing,
+        }
+    }
+}
+
+impl Deref for TimedConn {
+    type Target = Conn;
+
+    fn deref(&self) -> &Conn {
+        &self.conn
+    }
+}
+
+impl DerefMut for TimedConn {
+    fn deref_mut(&mut self) -> &mut Conn {
+        &mut self.conn
+    }
+}
+
+impl Drop for TimedConn {
+    fn drop(&mut self) {
+        self.timing.record(self.start.elapsed().as_secs_f64());
+    }
+}
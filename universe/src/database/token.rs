@@ -0,0 +1,168 @@
+use super::Database;
+use aw_core::ReasonCode;
+use mysql::*;
+use sha2::{Digest, Sha256};
+
+type Result<T, E> = std::result::Result<T, E>;
+
+/// What a token emailed to a citizen lets them do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Confirm the email address of a new citizen
+    VerifyEmail = 1,
+    /// Choose a new password
+    PasswordReset = 2,
+    /// Start emailing telegrams received while offline
+    TelegramEmail = 3,
+    /// Stop emailing telegrams
+    Unsubscribe = 4,
+}
+
+pub trait TokenDB {
+    fn init_token(&self);
+    fn token_add(
+        &self,
+        token: &str,
+        kind: TokenKind,
+        citizen_id: u32,
+        expiration: u32,
+    ) -> Result<(), ReasonCode>;
+    /// Use up a token, returning the citizen it was made for if it exists and has
+    /// not expired.
+    fn token_take(&self, token: &str, kind: TokenKind, now: u32)
+        -> Result<Option<u32>, ReasonCode>;
+    /// Delete every token of one kind made for a citizen.
+    fn token_revoke(&self, citizen_id: u32, kind: TokenKind) -> Result<(), ReasonCode>;
+    fn token_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode>;
+}
+
+/// Tokens are stored hashed, so the links cannot be rebuilt from the database.
+/// They are long and random, so a plain hash is enough.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+impl TokenDB for Database {
+    fn init_token(&self) {
+        let mut conn = self.conn().expect("Could not get mysql connection.");
+
+        conn.query_drop(
+            r"CREATE TABLE IF NOT EXISTS awu_token ( 
+                Token varchar(64) NOT NULL default '', 
+                Kind tinyint(3) unsigned NOT NULL default '0', 
+                Citizen int(11) unsigned NOT NULL default '0', 
+                Expiration int(11) NOT NULL default '0', 
+                PRIMARY KEY  (Token) 
+            ) 
+            ENGINE=MyISAM DEFAULT CHARSET=latin1;",
+        )
+        .unwrap();
+    }
+
+    fn token_add(
+        &self,
+        token: &str,
+        kind: TokenKind,
+        citizen_id: u32,
+        expiration: u32,
+    ) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"INSERT INTO awu_token (Token, Kind, Citizen, Expiration) 
+                VALUES(:token, :kind, :citizen, :expiration);",
+            params! {
+                "token" => hash_token(token),
+                "kind" => kind as u8,
+                "citizen" => citizen_id,
+                "expiration" => expiration,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn token_take(
+        &self,
+        token: &str,
+        kind: TokenKind,
+        now: u32,
+    ) -> Result<Option<u32>, ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+        let token = hash_token(token);
+
+        let found: Option<(u32, u32)> = conn
+            .exec_first(
+                r"SELECT Citizen, Expiration FROM awu_token WHERE Token=:token AND Kind=:kind;",
+                params! {
+                    "token" => &token,
+                    "kind" => kind as u8,
+                },
+            )
+            .map_err(|_| ReasonCode::DatabaseError)?;
+
+        // Expired tokens are cleared out along with the one being used
+        conn.exec_drop(
+            r"DELETE FROM awu_token WHERE Token=:token OR Expiration<:now;",
+            params! {
+                "token" => token,
+                "now" => now,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(found
+            .filter(|(_, expiration)| *expiration >= now)
+            .map(|(citizen_id, _)| citizen_id))
+    }
+
+    fn token_revoke(&self, citizen_id: u32, kind: TokenKind) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_token WHERE Citizen=:citizen AND Kind=:kind",
+            params! {
+                "citizen" => citizen_id,
+                "kind" => kind as u8,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+
+    fn token_delete_all(&self, citizen_id: u32) -> Result<(), ReasonCode> {
+        let mut conn = self.conn().map_err(|_| ReasonCode::DatabaseError)?;
+
+        conn.exec_drop(
+            r"DELETE FROM awu_token WHERE Citizen=:citizen",
+            params! {
+                "citizen" => citizen_id,
+            },
+        )
+        .map_err(|_| ReasonCode::DatabaseError)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_hash_token() {
+        let hash = hash_token("abc");
+        assert_eq!(
+            hash,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Fits the Token column
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash_token("abd"), hash);
+    }
+}
@@ -0,0 +1,132 @@
+//! Development mode, which runs against a temporary SQLite database filled with
+//! sample data, so no MySQL server is needed. The database is removed again when
+//! the universe stops.
+// The sample data is only used when built with the dev feature
+#![cfg_attr(not(feature = "dev"), allow(dead_code))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    config::UniverseConfig,
+    database::{
+        attrib::Attribute, citizen::CitizenQuery, license::LicenseQuery, AttribDB, CitizenDB,
+        Database, LicenseDB,
+    },
+};
+
+/// Password given to every sample citizen and world.
+const SAMPLE_PASSWORD: &str = "password";
+
+const SAMPLE_CITIZENS: &[&str] = &["Alice", "Bob", "Carol"];
+
+const SAMPLE_WORLDS: &[&str] = &["devworld", "testworld"];
+
+/// Create the development database and fill it with sample data.
+pub fn open_database(config: &UniverseConfig) -> Result<Database, String> {
+    #[cfg(feature = "dev")]
+    {
+        let database = Database::temporary(config)?;
+        seed(&database);
+        Ok(database)
+    }
+
+    #[cfg(not(feature = "dev"))]
+    {
+        let _ = config;
+        Err("Development mode needs the universe built with the dev feature".to_string())
+    }
+}
+
+/// Add sample citizens, worlds and attributes which do not exist yet.
+fn seed(database: &Database) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs() as u32;
+
+    for name in SAMPLE_CITIZENS {
+        if database.citizen_by_name(name).is_ok() {
+            continue;
+        }
+
+        let id = match database.citizen_next_number() {
+            Ok(id) => id,
+            Err(_) => {
+                log::warn!("Could not number sample citizen {name}");
+                continue;
+            }
+        };
+        let citizen = CitizenQuery {
+            id,
+            changed: 0,
+            name: name.to_string(),
+            password: SAMPLE_PASSWORD.to_string(),
+            email: format!("{}@example.com", name.to_lowercase()),
+            priv_pass: String::new(),
+            comment: "Sample citizen".to_string(),
+            url: String::new(),
+            immigration: now,
+            expiration: 0,
+            last_login: 0,
+            last_address: 0,
+            total_time: 0,
+            bot_limit: 3,
+            beta: 0,
+            cav_enabled: 0,
+            cav_template: 0,
+            enabled: 1,
+            privacy: 0,
+            trial: 0,
+            multi_login: 0,
+        };
+
+        match database.citizen_add(&citizen) {
+            Ok(()) => log::info!("Added sample citizen {name} / {SAMPLE_PASSWORD}"),
+            Err(_) => log::warn!("Could not add sample citizen {name}"),
+        }
+    }
+
+    for name in SAMPLE_WORLDS {
+        if database.license_by_name(name).is_ok() {
+            continue;
+        }
+
+        let license = LicenseQuery {
+            id: 0,
+            name: name.to_string(),
+            password: SAMPLE_PASSWORD.to_string(),
+            email: String::new(),
+            comment: "Sample world".to_string(),
+            creation: now,
+            expiration: 0,
+            last_start: 0,
+            last_address: 0,
+            users: 50,
+            world_size: 100,
+            hidden: 0,
+            changed: 0,
+            tourists: 1,
+            voip: 0,
+            plugins: 1,
+        };
+
+        match database.license_add(&license) {
+            Ok(()) => log::info!("Added sample world {name} / {SAMPLE_PASSWORD}"),
+            Err(_) => log::warn!("Could not add sample world {name}"),
+        }
+    }
+
+    let attributes = database.attrib_get().unwrap_or_default();
+    let samples = [
+        (
+            Attribute::WelcomeMessage,
+            "Welcome to the development universe.",
+        ),
+        (Attribute::DefaultStartWorld, SAMPLE_WORLDS[0]),
+    ];
+    for (attribute, value) in samples {
+        let current = attributes.get(&attribute).cloned().unwrap_or_default();
+        if current.is_empty() && database.attrib_set(attribute, value).is_err() {
+            log::warn!("Could not set sample attribute {attribute:?}");
+        }
+    }
+}
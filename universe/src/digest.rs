@@ -0,0 +1,173 @@
+//! A daily summary of universe activity sent to admins by telegram and email.
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    client::ClientManager,
+    config::DigestConfig,
+    database::{group::GroupRights, CitizenDB, Database, GroupDB, TelegramDB},
+    email::{Email, Mailer},
+    events::{TimedEvent, UniverseEvent},
+    packet_handler::send_telegram_update_available,
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Number of errors logged since the universe started.
+static ERRORS_LOGGED: AtomicU64 = AtomicU64::new(0);
+
+/// Wraps the real logger to count errors for the digest.
+pub struct ErrorCountingLogger<L: log::Log>(pub L);
+
+impl<L: log::Log> log::Log for ErrorCountingLogger<L> {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() == log::Level::Error {
+            ERRORS_LOGGED.fetch_add(1, Ordering::Relaxed);
+        }
+        self.0.log(record);
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+/// Activity counted since the last digest was sent.
+pub struct DailyDigest {
+    period_start: u64,
+    next_send: u64,
+    logins: u32,
+    failed_logins: u32,
+    peak_users: u32,
+    errors_at_start: u64,
+}
+
+impl DailyDigest {
+    pub fn new(config: &DigestConfig) -> Self {
+        let now = unix_now();
+        Self {
+            period_start: now,
+            next_send: next_send_time(now, config.hour),
+            logins: 0,
+            failed_logins: 0,
+            peak_users: 0,
+            errors_at_start: ERRORS_LOGGED.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count logins from the universe's event stream.
+    pub fn observe_events(&mut self, events: &[TimedEvent]) {
+        for event in events {
+            if let UniverseEvent::Login { reason_code, .. } = &event.event {
+                if *reason_code == 0 {
+                    self.logins += 1;
+                } else {
+                    self.failed_logins += 1;
+                }
+            }
+        }
+    }
+
+    pub fn observe_users(&mut self, users: u32) {
+        self.peak_users = self.peak_users.max(users);
+    }
+
+    /// Send the digest if it is due, then start counting again.
+    pub fn send_if_due(
+        &mut self,
+        config: &DigestConfig,
+        database: &Database,
+        client_manager: &ClientManager,
+        mailer: Option<&Mailer>,
+    ) {
+        let now = unix_now();
+        if !config.enabled || now < self.next_send {
+            return;
+        }
+
+        let report = self.report(database);
+        log::info!("Sending daily digest to admins");
+        for citizen_id in admin_citizens(database) {
+            if let Err(err) = database.telegram_add(citizen_id, 1, now as u32, &report) {
+                log::warn!("Could not send the daily digest to citizen {citizen_id}: {err:?}");
+                continue;
+            }
+            if let Some(client) = client_manager.get_client_by_citizen_id(citizen_id) {
+                send_telegram_update_available(client, database);
+            }
+
+            if let (true, Some(mailer)) = (config.email, mailer) {
+                match database.citizen_by_number(citizen_id) {
+                    Ok(citizen) if !citizen.email.is_empty() => mailer.send(Email {
+                        to: citizen.email,
+                        subject: "Daily universe digest".to_string(),
+                        body: report.clone(),
+                    }),
+                    _ => {}
+                }
+            }
+        }
+
+        let errors = ERRORS_LOGGED.load(Ordering::Relaxed);
+        *self = Self {
+            period_start: now,
+            next_send: next_send_time(now, config.hour),
+            logins: 0,
+            failed_logins: 0,
+            peak_users: 0,
+            errors_at_start: errors,
+        };
+    }
+
+    fn report(&self, database: &Database) -> String {
+        let registrations = database
+            .citizen_count_immigrated_since(self.period_start as u32)
+            .map(|x| x.to_string())
+            .unwrap_or_else(|_| "unknown".to_string());
+        let errors = ERRORS_LOGGED
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.errors_at_start);
+        let hours = unix_now().saturating_sub(self.period_start) / 3600;
+
+        format!(
+            "Universe digest for the last {hours} hours: {} logins, {} failed logins, \
+            {registrations} new citizens, {} peak users, {errors} errors logged.",
+            self.logins, self.failed_logins, self.peak_users
+        )
+    }
+}
+
+/// The Administrator and members of groups with admin rights.
+fn admin_citizens(database: &Database) -> Vec<u32> {
+    let mut admins = database
+        .group_citizens_with_rights(GroupRights::ADMIN)
+        .unwrap_or_default();
+    if !admins.contains(&1) {
+        admins.insert(0, 1);
+    }
+    admins
+}
+
+/// The next time after `now` which falls on the given UTC hour.
+fn next_send_time(now: u64, hour: u8) -> u64 {
+    let day_start = now - now % SECONDS_PER_DAY;
+    let send_time = day_start + u64::from(hour % 24) * 3600;
+    if send_time > now {
+        send_time
+    } else {
+        send_time + SECONDS_PER_DAY
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs()
+}
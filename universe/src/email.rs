@@ -0,0 +1,195 @@
+//! Outgoing email over SMTP.
+//!
+//! Messages are handed to a background thread so a slow mail server never
+//! stalls the main loop.
+use std::{
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lettre::{
+    message::header::ContentType, transport::smtp::authentication::Credentials, Message,
+    SmtpTransport, Transport,
+};
+
+use crate::{
+    accounts,
+    client::ClientManager,
+    config::{AccountConfig, EmailConfig},
+    database::{CitizenDB, Database, TelegramDB},
+};
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub struct Mailer {
+    sender: Sender<Email>,
+}
+
+impl Mailer {
+    /// Start the mail thread. Returns None if email is disabled or misconfigured.
+    pub fn new(config: &EmailConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let from = match config.from.parse() {
+            Ok(from) => from,
+            Err(err) => {
+                log::error!("Invalid email sender address {:?}: {err}", config.from);
+                return None;
+            }
+        };
+
+        let builder = if config.starttls {
+            match SmtpTransport::starttls_relay(&config.smtp_host) {
+                Ok(builder) => builder,
+                Err(err) => {
+                    log::error!("Could not set up SMTP relay {}: {err}", config.smtp_host);
+                    return None;
+                }
+            }
+        } else {
+            SmtpTransport::builder_dangerous(&config.smtp_host)
+        };
+
+        let mut builder = builder.port(config.smtp_port);
+        if !config.username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                config.username.clone(),
+                config.password.clone(),
+            ));
+        }
+        let transport = builder.build();
+
+        let (sender, receiver) = mpsc::channel::<Email>();
+        thread::spawn(move || {
+            for email in receiver {
+                if let Err(err) = send(&transport, &from, &email) {
+                    log::warn!("Could not send email to {}: {err}", email.to);
+                }
+            }
+        });
+
+        Some(Self { sender })
+    }
+
+    /// Queue an email to be sent.
+    pub fn send(&self, email: Email) {
+        if self.sender.send(email).is_err() {
+            log::error!("Mail thread has stopped");
+        }
+    }
+}
+
+fn send(
+    transport: &SmtpTransport,
+    from: &lettre::message::Mailbox,
+    email: &Email,
+) -> Result<(), String> {
+    let message = Message::builder()
+        .from(from.clone())
+        .to(email.to.parse().map_err(|err| format!("{err}"))?)
+        .subject(&email.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body.clone())
+        .map_err(|err| format!("{err}"))?;
+
+    transport.send(&message).map_err(|err| format!("{err}"))?;
+
+    Ok(())
+}
+
+/// Email citizens who have opted in any telegrams they received while offline
+/// which have not already been emailed. Each citizen gets at most one email per call.
+pub fn forward_offline_telegrams(
+    mailer: &Mailer,
+    database: &Database,
+    client_manager: &ClientManager,
+    accounts: &AccountConfig,
+) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs() as u32;
+
+    for subscriber in database.telegram_email_subscribers() {
+        // Citizens who are online get their telegrams in the browser
+        if client_manager
+            .get_client_by_citizen_id(subscriber.citizen)
+            .is_some()
+        {
+            continue;
+        }
+
+        let telegrams: Vec<_> = database
+            .telegram_get_undelivered(subscriber.citizen)
+            .into_iter()
+            .filter(|telegram| telegram.id > subscriber.last_forwarded)
+            .collect();
+
+        let newest = match telegrams.iter().map(|telegram| telegram.id).max() {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let citizen = match database.citizen_by_number(subscriber.citizen) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        if citizen.email.is_empty() {
+            continue;
+        }
+
+        let mut body = format!(
+            "Hello {}, you received {} telegram(s) while you were away.\n\n",
+            citizen.name,
+            telegrams.len()
+        );
+        for telegram in &telegrams {
+            let from_name = match database.citizen_by_number(telegram.from) {
+                Ok(cit) => cit.name,
+                Err(_) => "<unknown>".to_string(),
+            };
+            let minutes = now.saturating_sub(telegram.timestamp) / 60;
+            body.push_str(&format!(
+                "From {from_name} ({minutes} minutes ago):\n{}\n\n",
+                telegram.message
+            ));
+        }
+        let unsubscribe = if accounts.enabled {
+            accounts::telegram_unsubscribe_link(&citizen, database, accounts).ok()
+        } else {
+            None
+        };
+        match unsubscribe {
+            Some(link) => body.push_str(&format!(
+                "Log in to reply. To stop these emails, follow this link:\n\n{link}\n"
+            )),
+            None => body.push_str(
+                "Log in to reply. To stop these emails, ask a caretaker to unsubscribe you.\n",
+            ),
+        }
+
+        mailer.send(Email {
+            to: citizen.email,
+            subject: "New telegrams".to_string(),
+            body,
+        });
+
+        if database
+            .telegram_email_set_forwarded(subscriber.citizen, newest)
+            .is_err()
+        {
+            log::warn!(
+                "Could not record forwarded telegrams for citizen {}",
+                subscriber.citizen
+            );
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Events describing what is happening in the universe, for operators to watch.
+use std::{
+    cell::RefCell,
+    net::IpAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use aw_core::ReasonCode;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UniverseEvent {
+    Connect {
+        ip: IpAddr,
+    },
+    Login {
+        ip: IpAddr,
+        name: String,
+        citizen_id: Option<u32>,
+        reason: String,
+        reason_code: i32,
+    },
+    Disconnect {
+        ip: IpAddr,
+        name: Option<String>,
+    },
+    /// A new citizen created their own account
+    Register {
+        ip: IpAddr,
+        name: String,
+        citizen_id: u32,
+    },
+    AttributeChange {
+        ip: IpAddr,
+        attribute: String,
+        value: String,
+    },
+    Telegram {
+        from: String,
+        to: String,
+    },
+    WorldStart {
+        ip: IpAddr,
+        name: String,
+    },
+    WorldStop {
+        ip: IpAddr,
+        name: String,
+    },
+    CreditChange {
+        citizen_id: u32,
+        amount: i64,
+        balance: i64,
+        actor: String,
+        reason: String,
+    },
+    /// An admin interface request was refused for having the wrong token
+    AdminAuthFailure {
+        ip: IpAddr,
+        path: String,
+    },
+}
+
+impl UniverseEvent {
+    pub fn login(ip: IpAddr, name: &str, citizen_id: Option<u32>, rc: ReasonCode) -> Self {
+        Self::Login {
+            ip,
+            name: name.to_string(),
+            citizen_id,
+            reason: format!("{rc:?}"),
+            reason_code: rc as i32,
+        }
+    }
+}
+
+/// An event along with when it happened.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimedEvent {
+    pub time: u64,
+    #[serde(flatten)]
+    pub event: UniverseEvent,
+}
+
+/// Events which have happened since they were last collected.
+#[derive(Default)]
+pub struct EventQueue {
+    pending: RefCell<Vec<TimedEvent>>,
+}
+
+impl EventQueue {
+    pub fn publish(&self, event: UniverseEvent) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Current time is before the unix epoch.")
+            .as_secs();
+
+        self.pending.borrow_mut().push(TimedEvent { time, event });
+    }
+
+    /// Remove and return all pending events.
+    pub fn take(&self) -> Vec<TimedEvent> {
+        std::mem::take(&mut *self.pending.borrow_mut())
+    }
+}
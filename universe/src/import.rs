@@ -0,0 +1,208 @@
+//! Import of citizens, licenses, contacts and telegrams from the database of an
+//! original Activeworlds universe server.
+//!
+//! The original server keeps its data in MySQL tables with the same names as
+//! ours, so a dump of it is loaded into a MySQL database of its own and read from
+//! there. Columns which the original server does not have are left at their
+//! defaults, and rows which already exist in the universe database are skipped,
+//! so an import can safely be repeated.
+use mysql::{prelude::Queryable, Conn, Opts, Row};
+
+use crate::database::{
+    self, citizen::CitizenQuery, license::LicenseQuery, CitizenDB, ContactDB, Database, LicenseDB,
+    TelegramDB,
+};
+
+/// Number of rows brought over from one table.
+#[derive(Debug, Default)]
+struct ImportCount {
+    imported: usize,
+    skipped: usize,
+}
+
+/// Copy everything supported from the database at `source_url` into the universe database.
+pub fn import_aw_universe(database: &Database, source_url: &str) -> Result<(), String> {
+    let opts =
+        Opts::from_url(source_url).map_err(|err| format!("Invalid source database URL: {err}"))?;
+    let mut source =
+        Conn::new(opts).map_err(|err| format!("Could not connect to source database: {err}"))?;
+
+    let citizens = import_citizens(database, &mut source)?;
+    log::info!("Citizens: {citizens:?}");
+
+    let licenses = import_licenses(database, &mut source)?;
+    log::info!("Licenses: {licenses:?}");
+
+    let contacts = import_contacts(database, &mut source)?;
+    log::info!("Contacts: {contacts:?}");
+
+    let telegrams = import_telegrams(database, &mut source)?;
+    log::info!("Telegrams: {telegrams:?}");
+
+    Ok(())
+}
+
+fn read_table(source: &mut Conn, table: &str) -> Result<Vec<Row>, String> {
+    source
+        .exec(format!("SELECT * FROM {table}"), ())
+        .map_err(|err| format!("Could not read {table}: {err}"))
+}
+
+fn int(row: &Row, name: &str) -> u32 {
+    // Addresses are stored in signed columns, so only the bits are kept
+    database::fetch_int(row, name).unwrap_or(0) as u32
+}
+
+fn string(row: &Row, name: &str) -> String {
+    database::fetch_string(row, name).unwrap_or_default()
+}
+
+fn import_citizens(database: &Database, source: &mut Conn) -> Result<ImportCount, String> {
+    let mut count = ImportCount::default();
+
+    for row in read_table(source, "awu_citizen")? {
+        let citizen = CitizenQuery {
+            id: int(&row, "ID"),
+            changed: 0,
+            name: string(&row, "Name"),
+            // Plain passwords are hashed as they are stored
+            password: string(&row, "Password"),
+            email: string(&row, "Email"),
+            priv_pass: string(&row, "PrivPass"),
+            comment: string(&row, "Comment"),
+            url: string(&row, "URL"),
+            immigration: int(&row, "Immigration"),
+            expiration: int(&row, "Expiration"),
+            last_login: int(&row, "LastLogin"),
+            last_address: int(&row, "LastAddress"),
+            total_time: int(&row, "TotalTime"),
+            bot_limit: int(&row, "BotLimit"),
+            beta: int(&row, "Beta"),
+            enabled: int(&row, "Enabled"),
+            trial: int(&row, "Trial"),
+            privacy: int(&row, "Privacy"),
+            cav_enabled: int(&row, "CAVEnabled"),
+            cav_template: int(&row, "CAVTemplate"),
+            multi_login: 0,
+        };
+
+        if citizen.id == 0
+            || citizen.name.is_empty()
+            || database.citizen_by_number(citizen.id).is_ok()
+            || database.citizen_number_retired(citizen.id) != Ok(false)
+            || database.citizen_by_name(&citizen.name).is_ok()
+        {
+            count.skipped += 1;
+            continue;
+        }
+
+        match database.citizen_add(&citizen) {
+            Ok(()) => count.imported += 1,
+            Err(x) => {
+                log::warn!("Could not import citizen {}: {x:?}", citizen.id);
+                count.skipped += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+fn import_licenses(database: &Database, source: &mut Conn) -> Result<ImportCount, String> {
+    let mut count = ImportCount::default();
+
+    for row in read_table(source, "awu_license")? {
+        let license = LicenseQuery {
+            id: 0,
+            name: string(&row, "Name"),
+            password: string(&row, "Password"),
+            email: string(&row, "Email"),
+            comment: string(&row, "Comment"),
+            creation: int(&row, "Creation"),
+            expiration: int(&row, "Expiration"),
+            last_start: int(&row, "LastStart"),
+            last_address: int(&row, "LastAddress"),
+            users: int(&row, "Users"),
+            // The original server calls this column "Range"
+            world_size: match database::fetch_int(&row, "WorldSize") {
+                Some(size) => size as u32,
+                None => int(&row, "Range"),
+            },
+            hidden: int(&row, "Hidden"),
+            changed: 0,
+            tourists: int(&row, "Tourists"),
+            voip: int(&row, "Voip"),
+            plugins: int(&row, "Plugins"),
+        };
+
+        if license.name.is_empty() || database.license_by_name(&license.name).is_ok() {
+            count.skipped += 1;
+            continue;
+        }
+
+        match database.license_add(&license) {
+            Ok(()) => count.imported += 1,
+            Err(x) => {
+                log::warn!("Could not import license {}: {x:?}", license.name);
+                count.skipped += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+fn import_contacts(database: &Database, source: &mut Conn) -> Result<ImportCount, String> {
+    let mut count = ImportCount::default();
+
+    for row in read_table(source, "awu_contact")? {
+        let citizen = int(&row, "Citizen");
+        let contact = int(&row, "Contact");
+
+        if database.contact_get(citizen, contact).is_ok() {
+            count.skipped += 1;
+            continue;
+        }
+
+        match database.contact_set(citizen, contact, int(&row, "Options")) {
+            Ok(()) => count.imported += 1,
+            Err(x) => {
+                log::warn!("Could not import contact {contact} of {citizen}: {x:?}");
+                count.skipped += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+/// Only telegrams which were never delivered are imported, since the rest have
+/// already been read and there is no way to mark them as such when adding them.
+fn import_telegrams(database: &Database, source: &mut Conn) -> Result<ImportCount, String> {
+    let mut count = ImportCount::default();
+
+    for row in read_table(source, "awu_telegram")? {
+        let to = int(&row, "Citizen");
+        let timestamp = int(&row, "Timestamp");
+
+        let already_present = database
+            .telegram_get_undelivered(to)
+            .iter()
+            .any(|telegram| telegram.timestamp == timestamp);
+
+        if int(&row, "Delivered") != 0 || already_present {
+            count.skipped += 1;
+            continue;
+        }
+
+        match database.telegram_add(to, int(&row, "From"), timestamp, &string(&row, "Message")) {
+            Ok(()) => count.imported += 1,
+            Err(x) => {
+                log::warn!("Could not import telegram to {to}: {x:?}");
+                count.skipped += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,195 @@
+//! Log output in plain text or JSON, to standard error or to a rotated file.
+//!
+//! While a packet is being handled, the session and citizen number of the client
+//! which sent it are attached to JSON records, so one user's activity can be
+//! followed through the log.
+use std::{
+    cell::RefCell,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::IpAddr,
+    path::PathBuf,
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use serde_json::json;
+
+use crate::config::{LogFormat, LoggingConfig};
+
+thread_local! {
+    static CONTEXT: RefCell<Option<LogContext>> = const { RefCell::new(None) };
+}
+
+/// The client a record was logged on behalf of.
+#[derive(Debug, Clone, Copy)]
+pub struct LogContext {
+    pub ip: IpAddr,
+    pub session_id: Option<u16>,
+    pub citizen_id: Option<u32>,
+}
+
+/// Attach a client to everything logged until the context is cleared.
+pub fn set_context(context: Option<LogContext>) {
+    CONTEXT.with(|x| *x.borrow_mut() = context);
+}
+
+pub struct UniverseLogger {
+    level: log::LevelFilter,
+    format: LogFormat,
+    output: Mutex<Output>,
+}
+
+enum Output {
+    Stderr,
+    File(RotatingFile),
+}
+
+impl UniverseLogger {
+    pub fn new(level: log::LevelFilter, config: &LoggingConfig) -> io::Result<Self> {
+        let output = if config.file.is_empty() {
+            Output::Stderr
+        } else {
+            Output::File(RotatingFile::open(
+                PathBuf::from(&config.file),
+                config.max_file_size_mb * 1024 * 1024,
+                config.max_files,
+            )?)
+        };
+
+        Ok(Self {
+            level,
+            format: config.format,
+            output: Mutex::new(output),
+        })
+    }
+
+    pub fn level(&self) -> log::LevelFilter {
+        self.level
+    }
+
+    fn format(&self, record: &log::Record) -> String {
+        let time = humantime::format_rfc3339_millis(SystemTime::now());
+        match self.format {
+            LogFormat::Text => format!(
+                "[{time} {:<5} {}] {}\n",
+                record.level(),
+                record.target(),
+                record.args()
+            ),
+            LogFormat::Json => {
+                let mut value = json!({
+                    "time": time.to_string(),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                if let Some(context) = CONTEXT.with(|x| *x.borrow()) {
+                    value["ip"] = json!(context.ip);
+                    value["session"] = json!(context.session_id);
+                    value["citizen"] = json!(context.citizen_id);
+                }
+                format!("{value}\n")
+            }
+        }
+    }
+}
+
+impl log::Log for UniverseLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = self.format(record);
+        let mut output = match self.output.lock() {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        match &mut *output {
+            Output::Stderr => {
+                io::stderr().write_all(line.as_bytes()).ok();
+            }
+            Output::File(file) => {
+                if let Err(err) = file.write(line.as_bytes()) {
+                    eprintln!("Could not write to log file: {err}");
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut output) = self.output.lock() {
+            if let Output::File(file) = &mut *output {
+                file.file.flush().ok();
+            }
+        }
+    }
+}
+
+/// A log file which is moved aside to `<path>.1`, `<path>.2`, ... once it is too large.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// Size in bytes at which the file is rotated (0 to never rotate)
+    max_size: u64,
+    /// Number of rotated files kept
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.max_size != 0 && self.size + data.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        self.file.write_all(data)?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path).ok();
+        } else {
+            fs::remove_file(self.rotated_path(self.max_files)).ok();
+            for n in (1..self.max_files).rev() {
+                fs::rename(self.rotated_path(n), self.rotated_path(n + 1)).ok();
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Slowing down repeated failed logins, so passwords cannot be guessed quickly.
+//!
+//! Failures are counted for both the address a login came from and the name it
+//! tried. Once either has failed more than the free attempts allow, further logins
+//! are refused for a delay which doubles with each failure, up to a maximum.
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use aw_core::ReasonCode;
+
+use crate::config::LoginThrottleConfig;
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+impl Failures {
+    /// When the next login may be attempted, if it must wait.
+    fn retry_at(&self, config: &LoginThrottleConfig) -> Option<Instant> {
+        let over = self.count.checked_sub(config.free_attempts)?;
+        let delay = config
+            .base_delay_secs
+            .saturating_mul(1 << over.min(31))
+            .min(config.max_delay_secs);
+        Some(self.last + Duration::from_secs(delay))
+    }
+}
+
+/// Recent failed logins, by address and by lowercased name.
+#[derive(Default)]
+pub struct LoginThrottle {
+    by_ip: RefCell<HashMap<IpAddr, Failures>>,
+    by_name: RefCell<HashMap<String, Failures>>,
+}
+
+impl LoginThrottle {
+    /// Refuse a login if its address or name has to wait after failing too often.
+    pub fn check(
+        &self,
+        ip: IpAddr,
+        name: Option<&str>,
+        config: &LoginThrottleConfig,
+    ) -> Result<(), ReasonCode> {
+        if !config.enabled {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let waiting = |failures: Option<&Failures>| {
+            failures
+                .and_then(|f| f.retry_at(config))
+                .is_some_and(|retry_at| retry_at > now)
+        };
+
+        if waiting(self.by_ip.borrow().get(&ip)) {
+            log::info!("Throttling login from {ip} after repeated failures");
+            return Err(ReasonCode::NotYet);
+        }
+
+        if let Some(name) = name {
+            if waiting(self.by_name.borrow().get(&name.to_lowercase())) {
+                log::info!("Throttling login as {name:?} after repeated failures");
+                return Err(ReasonCode::NotYet);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn record_failure(&self, ip: IpAddr, name: Option<&str>) {
+        fn add<K: Eq + Hash>(map: &RefCell<HashMap<K, Failures>>, key: K) {
+            let mut map = map.borrow_mut();
+            let failures = map.entry(key).or_insert(Failures {
+                count: 0,
+                last: Instant::now(),
+            });
+            failures.count += 1;
+            failures.last = Instant::now();
+        }
+
+        add(&self.by_ip, ip);
+        if let Some(name) = name {
+            add(&self.by_name, name.to_lowercase());
+        }
+    }
+
+    /// Forget the failures of an address and name after a successful login.
+    pub fn record_success(&self, ip: IpAddr, name: Option<&str>) {
+        self.by_ip.borrow_mut().remove(&ip);
+        if let Some(name) = name {
+            self.by_name.borrow_mut().remove(&name.to_lowercase());
+        }
+    }
+
+    /// Forget failures which are old enough not to matter any more.
+    pub fn expire(&self, config: &LoginThrottleConfig) {
+        let forget_after = Duration::from_secs(config.forget_after_secs);
+        self.by_ip
+            .borrow_mut()
+            .retain(|_, f| f.last.elapsed() < forget_after);
+        self.by_name
+            .borrow_mut()
+            .retain(|_, f| f.last.elapsed() < forget_after);
+    }
+}
@@ -1,55 +1,202 @@
 use aw_core::*;
 
+mod accounts;
+pub mod admin;
 mod client;
 pub use client::{Client, ClientType};
 mod universe_server;
 pub use universe_server::UniverseServer;
+mod announcements;
 pub mod attributes;
+mod audit;
+mod backup;
+mod bus;
 pub mod universe_license;
 pub use attributes::send_attributes;
 pub mod config;
+mod console;
 mod database;
+mod dev;
+mod digest;
+pub mod email;
+pub mod events;
+mod import;
+mod logging;
+mod login_throttle;
+mod metrics;
 pub mod packet_handler;
 pub mod player;
+mod presence_store;
+mod protocol;
+mod proxy_protocol;
+pub mod scripting;
+#[cfg(all(windows, feature = "service"))]
+mod service;
+mod systemd;
+mod webhooks;
 pub mod world;
+mod xfer;
 
 use env_logger::Builder;
 pub use log::{debug, error, info, trace, warn};
 
 use clap::Parser;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 struct Args {
     #[clap(long, value_parser, default_value_t = log::LevelFilter::Info)]
     /// Verbosity of logging: <off | error | warn | info | debug | trace>
     log_level: log::LevelFilter,
+
+    #[clap(long)]
+    /// Run against a temporary SQLite database filled with sample data instead of
+    /// MySQL. Needs the dev feature
+    dev: bool,
+
+    #[clap(long, value_name = "MYSQL_URL")]
+    /// Import citizens, licenses, contacts and telegrams from an original
+    /// Activeworlds universe database, then exit
+    import_aw: Option<String>,
+
+    #[clap(long, value_name = "FILE")]
+    /// Write citizens, licenses, contacts and attributes to a JSON backup, then exit
+    export: Option<PathBuf>,
+
+    #[clap(long, value_name = "FILE", conflicts_with = "export")]
+    /// Restore citizens, licenses, contacts and attributes from a JSON backup, then exit
+    import: Option<PathBuf>,
+
+    #[cfg(all(windows, feature = "service"))]
+    #[clap(long)]
+    /// Register the universe as a Windows service, then exit. The service runs
+    /// from this executable's folder.
+    install_service: bool,
+
+    #[cfg(all(windows, feature = "service"))]
+    #[clap(long)]
+    /// Remove the Windows service, then exit
+    uninstall_service: bool,
+
+    #[cfg(all(windows, feature = "service"))]
+    #[clap(long, hide = true)]
+    /// Run under the Windows service manager
+    service: bool,
 }
 
-fn init_logging(level: log::LevelFilter) {
-    let mut builder = Builder::new();
-    builder.filter_level(level);
-    builder.init();
+fn init_logging(level: log::LevelFilter, config: &config::LoggingConfig) {
+    // Plain text on standard error is left to env_logger, as it always has been
+    if config.format == config::LogFormat::Text && config.file.is_empty() {
+        let mut builder = Builder::new();
+        builder.filter_level(level);
+        let logger = builder.build();
+        log::set_max_level(logger.filter());
+        log::set_boxed_logger(Box::new(digest::ErrorCountingLogger(logger)))
+            .expect("Logger was already initialized");
+        return;
+    }
+
+    match logging::UniverseLogger::new(level, config) {
+        Ok(logger) => {
+            log::set_max_level(logger.level());
+            log::set_boxed_logger(Box::new(digest::ErrorCountingLogger(logger)))
+                .expect("Logger was already initialized");
+        }
+        Err(err) => {
+            eprintln!("Could not open log file {}: {err}", config.file);
+            init_logging(level, &config::LoggingConfig::default());
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
-    init_logging(args.log_level);
+
+    #[cfg(all(windows, feature = "service"))]
+    if run_service_command(&args) {
+        return;
+    }
 
     match config::Config::get() {
         Ok(config) => {
-            start_universe(config);
+            init_logging(args.log_level, &config.logging);
+
+            if args.import_aw.is_some() || args.export.is_some() || args.import.is_some() {
+                if args.dev {
+                    eprintln!("The development database is removed when the universe stops, so it cannot be imported into or exported");
+                    return;
+                }
+                maintain_database(config, &args);
+                return;
+            }
+            start_universe(config, args.dev);
         }
         Err(err) => {
+            init_logging(args.log_level, &config::LoggingConfig::default());
             eprintln!("Could not get universe configuration: {err}");
         }
     }
 }
 
-fn start_universe(config: config::Config) {
-    match UniverseServer::new(config) {
-        Ok(mut universe) => {
-            universe.run();
+/// Handle the Windows service options, returning whether one was given.
+#[cfg(all(windows, feature = "service"))]
+fn run_service_command(args: &Args) -> bool {
+    let result = if args.install_service {
+        service::install()
+    } else if args.uninstall_service {
+        service::uninstall()
+    } else if args.service {
+        service::run(args.log_level)
+    } else {
+        return false;
+    };
+
+    if let Err(err) = result {
+        eprintln!("Windows service error: {err}");
+    }
+    true
+}
+
+/// Run the one-off database tasks requested on the command line instead of the universe.
+fn maintain_database(config: config::Config, args: &Args) {
+    let database = match database::Database::new(config.mysql, &config.universe) {
+        Ok(database) => database,
+        Err(err) => {
+            eprintln!("Could not open universe database: {err}");
+            return;
+        }
+    };
+
+    if let Some(source_url) = &args.import_aw {
+        match import::import_aw_universe(&database, source_url) {
+            Ok(()) => log::info!("Import finished"),
+            Err(err) => eprintln!("Could not import universe database: {err}"),
+        }
+    }
+
+    if let Some(path) = &args.import {
+        if let Err(err) = backup::import(&database, path) {
+            eprintln!("Could not restore backup: {err}");
+        }
+    }
+
+    if let Some(path) = &args.export {
+        if let Err(err) = backup::export(&database, path) {
+            eprintln!("Could not export backup: {err}");
         }
+    }
+}
+
+fn start_universe(config: config::Config, dev: bool) {
+    let universe = if dev {
+        dev::open_database(&config.universe)
+            .and_then(|database| UniverseServer::with_database(config, database))
+    } else {
+        UniverseServer::new(config)
+    };
+
+    match universe {
+        Ok(mut universe) => universe.run(),
         Err(err) => {
             eprintln!("Could not create universe: {err}");
         }
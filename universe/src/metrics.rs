@@ -0,0 +1,128 @@
+//! Counters for the Prometheus `/metrics` route of the admin interface.
+use std::{cell::RefCell, collections::BTreeMap, fmt::Write};
+
+use aw_core::PacketType;
+
+use crate::{
+    client::{ClientManager, ClientType, Entity},
+    database::Database,
+    events::{TimedEvent, UniverseEvent},
+};
+
+#[derive(Default)]
+pub struct Metrics {
+    /// Packets handled, by packet type
+    packets: RefCell<BTreeMap<String, u64>>,
+    /// Login attempts, by the reason code they were answered with
+    logins: RefCell<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    pub fn record_packet(&self, packet_type: PacketType) {
+        *self
+            .packets
+            .borrow_mut()
+            .entry(format!("{packet_type:?}"))
+            .or_default() += 1;
+    }
+
+    pub fn observe_events(&self, events: &[TimedEvent]) {
+        let mut logins = self.logins.borrow_mut();
+        for event in events {
+            if let UniverseEvent::Login { reason, .. } = &event.event {
+                *logins.entry(reason.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Everything in the Prometheus text format.
+    pub fn render(&self, client_manager: &ClientManager, database: &Database) -> String {
+        let mut out = String::new();
+
+        let mut clients = BTreeMap::<&str, u64>::new();
+        let mut rtt_sum = 0.0;
+        let mut rtt_count = 0;
+        let mut rtt_max: f64 = 0.0;
+        for client in client_manager.clients() {
+            let info = client.info();
+            let kind = match (&info.entity, info.client_type) {
+                (Some(Entity::WorldServer(_)), _) => "world",
+                (_, Some(ClientType::Citizen)) => "citizen",
+                (_, Some(ClientType::Tourist)) => "tourist",
+                (_, Some(ClientType::Bot)) => "bot",
+                _ => "unidentified",
+            };
+            *clients.entry(kind).or_default() += 1;
+
+            if let Some(rtt) = client.rtt() {
+                let secs = rtt.as_secs_f64();
+                rtt_sum += secs;
+                rtt_count += 1;
+                rtt_max = rtt_max.max(secs);
+            }
+        }
+
+        out.push_str("# HELP universe_clients Connected clients by type.\n");
+        out.push_str("# TYPE universe_clients gauge\n");
+        for (kind, count) in &clients {
+            writeln!(out, "universe_clients{{type=\"{kind}\"}} {count}").ok();
+        }
+
+        out.push_str("# HELP universe_logins_total Login attempts by the reason code answered.\n");
+        out.push_str("# TYPE universe_logins_total counter\n");
+        for (reason, count) in self.logins.borrow().iter() {
+            writeln!(out, "universe_logins_total{{reason=\"{reason}\"}} {count}").ok();
+        }
+
+        out.push_str("# HELP universe_packets_total Packets handled by packet type.\n");
+        out.push_str("# TYPE universe_packets_total counter\n");
+        for (packet_type, count) in self.packets.borrow().iter() {
+            writeln!(
+                out,
+                "universe_packets_total{{type=\"{packet_type}\"}} {count}"
+            )
+            .ok();
+        }
+
+        out.push_str("# HELP universe_heartbeat_rtt_seconds Latest heartbeat round trips of connected clients.\n");
+        out.push_str("# TYPE universe_heartbeat_rtt_seconds gauge\n");
+        let rtt_average = if rtt_count == 0 {
+            0.0
+        } else {
+            rtt_sum / rtt_count as f64
+        };
+        writeln!(
+            out,
+            "universe_heartbeat_rtt_seconds{{stat=\"average\"}} {rtt_average}"
+        )
+        .ok();
+        writeln!(
+            out,
+            "universe_heartbeat_rtt_seconds{{stat=\"max\"}} {rtt_max}"
+        )
+        .ok();
+
+        let timing = database.query_timing();
+        out.push_str(
+            "# HELP universe_db_query_seconds Time database connections were held for queries.\n",
+        );
+        out.push_str("# TYPE universe_db_query_seconds histogram\n");
+        for (bound, count) in timing.buckets() {
+            writeln!(
+                out,
+                "universe_db_query_seconds_bucket{{le=\"{bound}\"}} {count}"
+            )
+            .ok();
+        }
+        writeln!(
+            out,
+            "universe_db_query_seconds_bucket{{le=\"+Inf\"}} {}",
+            timing.count()
+        )
+        .ok();
+        writeln!(out, "universe_db_query_seconds_sum {}", timing.sum_secs()).ok();
+        writeln!(out, "universe_db_query_seconds_count {}", timing.count()).ok();
+
+        out
+    }
+}
@@ -1,11 +1,35 @@
-use crate::{attributes, client::Client, database::Database};
-use aw_core::{AWCryptRSA, AWPacket, AWPacketVar, PacketType, VarID};
+use crate::{
+    attributes,
+    client::Client,
+    config::{Config, UniverseConfig},
+    database::Database,
+};
+use aw_core::{AWCryptRSA, AWPacket, AWPacketVar, PacketType, ReasonCode, VarID};
+
+/// Send a line of text to a client's chat window.
+pub fn send_console_message(client: &Client, message: &str) {
+    let mut packet = AWPacket::new(PacketType::ConsoleMessage);
+    packet.add_byte(VarID::ConsoleRed, 0);
+    packet.add_byte(VarID::ConsoleGreen, 0);
+    packet.add_byte(VarID::ConsoleBlue, 0);
+    packet.add_byte(VarID::ConsoleBold, 1);
+    packet.add_byte(VarID::ConsoleItalics, 0);
+    packet.add_string(VarID::ConsoleMessage, message.to_string());
+    client.connection.send(packet);
+}
+
+/// Send the operator's explanation of a reason code, if one is configured.
+pub fn send_reason_message(client: &Client, config: &Config, rc: ReasonCode) {
+    if let Some(message) = config.reason_message(rc) {
+        send_console_message(client, message);
+    }
+}
 
 /// Handle a client requesting the server's public RSA key.
 /// We generate a new RSA key pair for each client since AW
 /// versions prior to 7.0 use very weak RSA encryption.
 /// We send the generated key pair to the client.
-pub fn public_key_request(client: &Client) {
+pub fn public_key_request(client: &Client, config: &UniverseConfig) {
     let key = client
         .rsa
         .encode_public_key()
@@ -13,6 +37,9 @@ pub fn public_key_request(client: &Client) {
 
     let mut packet = AWPacket::new(PacketType::PublicKeyResponse);
     packet.add_data(VarID::EncryptionKey, key);
+    if config.login_challenge_minimum_build != 0 {
+        packet.add_data(VarID::LoginChallenge, client.login_challenge.clone());
+    }
     client.connection.send(packet);
 }
 
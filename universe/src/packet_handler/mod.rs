@@ -1,7 +1,11 @@
 mod common;
 mod player;
+mod rate_limit;
+mod registry;
 mod world;
 
 pub use common::*;
 pub use player::*;
+pub use rate_limit::*;
+pub use registry::*;
 pub use world::*;
@@ -1,8 +1,10 @@
 use crate::{
-    attributes,
-    attributes::set_attribute,
+    attributes::{self, attribute_for_var, set_attribute},
+    audit,
     client::{Client, ClientManager},
-    database::Database,
+    database::{group::GroupRights, AttribDB, Database},
+    events::{EventQueue, UniverseEvent},
+    scripting::Scripts,
 };
 use aw_core::*;
 
@@ -11,20 +13,48 @@ pub fn attribute_change(
     packet: &AWPacket,
     database: &Database,
     client_manager: &ClientManager,
+    scripts: &Scripts,
+    events: &EventQueue,
 ) {
-    // Only admins should be able to change Universe attributes
-    if !client.has_admin_permissions() {
+    // Only attribute admins should be able to change Universe attributes
+    if !client.has_permission(GroupRights::ATTRIBUTE_ADMIN) {
         log::info!(
-            "Client {} tried to set attributes but is not an admin",
+            "Client {} tried to set attributes but is not an attribute admin",
             client.addr.ip()
         );
         return;
     }
 
+    let before = database.attrib_get().unwrap_or_default();
+
     for var in packet.get_vars().iter() {
         if let AWPacketVar::String(id, val) = var {
+            let val = match scripts.on_attribute_change(*id, val.clone()) {
+                Ok(val) => val,
+                Err(rc) => {
+                    log::info!("Script refused setting {id:?}: {rc:?}");
+                    continue;
+                }
+            };
             log::info!("Client {} setting {:?} to {:?}", client.addr.ip(), id, val);
-            set_attribute(*id, val, database).ok();
+            let Some(attribute) = attribute_for_var(*id) else {
+                continue;
+            };
+            if set_attribute(attribute, &val, database).is_ok() {
+                audit::record(
+                    database,
+                    &client.actor(),
+                    "attribute_change",
+                    &format!("attribute:{attribute:?}"),
+                    before.get(&attribute).cloned().into(),
+                    val.clone().into(),
+                );
+                events.publish(UniverseEvent::AttributeChange {
+                    ip: client.addr.ip(),
+                    attribute: format!("{id:?}"),
+                    value: val,
+                });
+            }
         }
     }
 
@@ -0,0 +1,88 @@
+use crate::{
+    client::{Client, ClientManager, Entity},
+    database::{ContactDB, Database},
+};
+use aw_core::*;
+
+/// Pass a botgram on to every session of the citizen it is addressed to,
+/// including bots they own. The sender is told whether it was delivered with
+/// BotgramResponse. Botgrams follow the same contact options as telegrams.
+pub fn botgram(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) {
+    let rc = match try_botgram(client, packet, database, client_manager) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    let mut response = AWPacket::new(PacketType::BotgramResponse);
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_botgram(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) -> Result<(), ReasonCode> {
+    let (from_id, from_name) = match &client.info().entity {
+        Some(Entity::Player(info)) => (info.effective_privilege(), info.username.clone()),
+        _ => return Err(ReasonCode::NotLoggedIn),
+    };
+    // Tourists have nobody for a reply to be addressed to
+    if from_id == 0 {
+        return Err(ReasonCode::NotLoggedIn);
+    }
+
+    let to_id = packet
+        .get_uint(VarID::BotgramCitizenNumber)
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+    let message = packet
+        .get_string(VarID::BotgramMessage)
+        .ok_or(ReasonCode::MessageLengthBad)?;
+    if message.is_empty() {
+        return Err(ReasonCode::MessageLengthBad);
+    }
+
+    // Someone hiding their online status is answered as if they were offline
+    if !database.contact_status_allowed(to_id, from_id) {
+        return Err(ReasonCode::NoSuchSession);
+    }
+
+    if !database.contact_telegrams_allowed(from_id, to_id)
+        || !database.contact_telegrams_allowed(to_id, from_id)
+    {
+        return Err(ReasonCode::TelegramBlocked);
+    }
+
+    let mut botgram = AWPacket::new(PacketType::Botgram);
+    botgram.add_uint(VarID::BotgramFromCitizenNumber, from_id);
+    botgram.add_string(VarID::BotgramFromUsername, from_name);
+    botgram.add_string(VarID::BotgramMessage, message);
+    botgram.add_int(
+        VarID::BotgramType,
+        packet.get_int(VarID::BotgramType).unwrap_or(0),
+    );
+
+    let mut delivered = false;
+    for target in client_manager.clients() {
+        let is_recipient = match &target.info().entity {
+            Some(Entity::Player(info)) => info.effective_privilege() == to_id,
+            _ => false,
+        };
+        if is_recipient && !std::ptr::eq(target, client) {
+            target.connection.send(botgram.clone());
+            delivered = true;
+        }
+    }
+
+    if !delivered {
+        return Err(ReasonCode::NoSuchSession);
+    }
+
+    Ok(())
+}
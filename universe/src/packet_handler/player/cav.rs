@@ -0,0 +1,352 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    client::{Client, ClientManager, Entity},
+    config::CavConfig,
+    database::{
+        cav::{CavQuery, CavTemplateQuery},
+        CavDB, CitizenDB, Database,
+    },
+};
+use aw_core::*;
+
+/// A citizen stores a new definition for their custom avatar.
+/// Admins may change the avatar of any citizen.
+pub fn cav_change(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+    config: &CavConfig,
+) {
+    let rc = match try_cav_change(client, packet, database, config) {
+        Ok(citizen_id) => {
+            notify_cav_changed(citizen_id, database, client_manager);
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    let mut response = AWPacket::new(PacketType::WorldCAVResult);
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_cav_change(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    config: &CavConfig,
+) -> Result<u32, ReasonCode> {
+    let definition = packet
+        .get_data(VarID::CAVDefinition)
+        .ok_or(ReasonCode::InvalidArgument)?;
+
+    if definition.len() > config.max_definition_size {
+        return Err(ReasonCode::InvalidArgument);
+    }
+
+    store_cav_definition(client, packet, definition, database)
+}
+
+/// Store a definition for the avatar a request is about, returning the citizen it belongs to.
+pub(super) fn store_cav_definition(
+    client: &Client,
+    packet: &AWPacket,
+    definition: Vec<u8>,
+    database: &Database,
+) -> Result<u32, ReasonCode> {
+    let citizen_id = owned_citizen_id(client, packet)?;
+
+    let citizen = database
+        .citizen_by_number(citizen_id)
+        .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+    if citizen.cav_enabled == 0 {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs() as u32;
+
+    database.cav_set(&CavQuery {
+        citizen: citizen_id,
+        template: packet
+            .get_uint(VarID::CAVTemplate)
+            .unwrap_or(citizen.cav_template),
+        changed: now,
+        definition,
+    })?;
+
+    Ok(citizen_id)
+}
+
+/// A world server or player asks for a citizen's custom avatar.
+pub fn world_cav(client: &Client, packet: &AWPacket, database: &Database) {
+    let mut response = AWPacket::new(PacketType::WorldCAVResult);
+
+    let rc = match try_world_cav(client, packet, database) {
+        Ok(cav) => {
+            response.add_uint(VarID::CAVCitizen, cav.citizen);
+            response.add_uint(VarID::CAVTemplate, cav.template);
+            response.add_data(VarID::CAVDefinition, cav.definition);
+
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    // Lets the requester match the result to the avatar it asked about
+    if let Some(session_id) = packet.get_int(VarID::CAVSession) {
+        response.add_int(VarID::CAVSession, session_id);
+    }
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_world_cav(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+) -> Result<CavQuery, ReasonCode> {
+    if client.info().entity.is_none() {
+        return Err(ReasonCode::NotLoggedIn);
+    }
+
+    let citizen_id = packet
+        .get_uint(VarID::CAVCitizen)
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+
+    let citizen = database
+        .citizen_by_number(citizen_id)
+        .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+    if citizen.cav_enabled == 0 {
+        return Err(ReasonCode::NoSuchCav);
+    }
+
+    let template = packet
+        .get_uint(VarID::CAVTemplate)
+        .unwrap_or(citizen.cav_template);
+
+    database.cav_get(citizen_id, template)
+}
+
+/// A citizen removes their custom avatar. Admins may remove that of any citizen.
+pub fn cav_delete(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) {
+    let rc = match try_cav_delete(client, packet, database) {
+        Ok(citizen_id) => {
+            notify_cav_changed(citizen_id, database, client_manager);
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    let mut response = AWPacket::new(PacketType::CavDelete);
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_cav_delete(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+) -> Result<u32, ReasonCode> {
+    let citizen_id = owned_citizen_id(client, packet)?;
+
+    let citizen = database
+        .citizen_by_number(citizen_id)
+        .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+    let template = packet
+        .get_uint(VarID::CAVTemplate)
+        .unwrap_or(citizen.cav_template);
+
+    database.cav_delete(citizen_id, template)?;
+
+    Ok(citizen_id)
+}
+
+/// Send a citizen's current avatar to the world server hosting them, if they
+/// are in a world, so that other users see the change without relogging.
+pub fn notify_cav_changed(citizen_id: u32, database: &Database, client_manager: &ClientManager) {
+    let (session_id, world_name) = match client_manager.get_client_by_citizen_id(citizen_id) {
+        Some(player_client) => match &player_client.info().entity {
+            Some(Entity::Player(player)) => match &player.world {
+                Some(world) => (player.session_id, world.clone()),
+                None => return,
+            },
+            _ => return,
+        },
+        None => return,
+    };
+
+    let server = match client_manager.get_world_server_by_world(&world_name) {
+        Some(x) => x,
+        None => return,
+    };
+
+    let mut packet = AWPacket::new(PacketType::WorldCAVResult);
+    packet.add_uint(VarID::CAVCitizen, citizen_id);
+    packet.add_int(VarID::CAVSession, session_id.into());
+
+    let cav = database
+        .citizen_by_number(citizen_id)
+        .map_err(|_| ReasonCode::NoSuchCitizen)
+        .and_then(|citizen| {
+            if citizen.cav_enabled == 0 {
+                return Err(ReasonCode::NoSuchCav);
+            }
+            database.cav_get(citizen_id, citizen.cav_template)
+        });
+
+    let rc = match cav {
+        Ok(cav) => {
+            packet.add_uint(VarID::CAVTemplate, cav.template);
+            packet.add_data(VarID::CAVDefinition, cav.definition);
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    packet.add_int(VarID::ReasonCode, rc as i32);
+    server.connection.send(packet);
+}
+
+/// A user looks up a custom avatar template by its number.
+pub fn cav_template_by_number(client: &Client, packet: &AWPacket, database: &Database) {
+    send_cav_template_lookup(client, packet, database, PacketType::CavTemplateByNumber);
+}
+
+/// A user looks up the template following the given number, for browsing.
+pub fn cav_template_next(client: &Client, packet: &AWPacket, database: &Database) {
+    send_cav_template_lookup(client, packet, database, PacketType::CavTemplateNext);
+}
+
+fn send_cav_template_lookup(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    packet_type: PacketType,
+) {
+    let mut response = AWPacket::new(packet_type);
+
+    let template = if client.info().entity.is_none() {
+        Err(ReasonCode::NotLoggedIn)
+    } else {
+        let template_id = packet.get_uint(VarID::CAVTemplate).unwrap_or(0);
+        match packet_type {
+            PacketType::CavTemplateNext => database.cav_template_next(template_id),
+            _ => database.cav_template_by_number(template_id),
+        }
+    };
+
+    let rc = match template {
+        Ok(template) => {
+            response.add_uint(VarID::CAVTemplate, template.id);
+            response.add_uint(VarID::CAVTemplateType, template.kind);
+            response.add_uint(VarID::CAVTemplateRating, template.rating);
+            response.add_string(VarID::CAVTemplateName, template.name);
+            response.add_string(VarID::CAVTemplateModel, template.model);
+
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+/// An admin adds a template, or changes an existing one. Template 0 adds a new one.
+pub fn cav_template_change(client: &Client, packet: &AWPacket, database: &Database) {
+    let mut response = AWPacket::new(PacketType::CavTemplateChange);
+
+    let rc = match try_cav_template_change(client, packet, database) {
+        Ok(template_id) => {
+            response.add_uint(VarID::CAVTemplate, template_id);
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_cav_template_change(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+) -> Result<u32, ReasonCode> {
+    if !client.has_admin_permissions() {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let model = packet
+        .get_string(VarID::CAVTemplateModel)
+        .filter(|model| !model.is_empty())
+        .ok_or(ReasonCode::InvalidArgument)?;
+
+    let mut template = CavTemplateQuery {
+        id: packet.get_uint(VarID::CAVTemplate).unwrap_or(0),
+        kind: packet.get_uint(VarID::CAVTemplateType).unwrap_or(0),
+        rating: packet.get_uint(VarID::CAVTemplateRating).unwrap_or(0),
+        name: packet
+            .get_string(VarID::CAVTemplateName)
+            .unwrap_or_default(),
+        model,
+    };
+
+    if template.id == 0 {
+        template.id = database.cav_template_add(&template)?;
+    } else {
+        database.cav_template_by_number(template.id)?;
+        database.cav_template_change(&template)?;
+    }
+
+    Ok(template.id)
+}
+
+/// An admin removes a template.
+pub fn cav_template_delete(client: &Client, packet: &AWPacket, database: &Database) {
+    let rc = if !client.has_admin_permissions() {
+        ReasonCode::Unauthorized
+    } else {
+        match packet.get_uint(VarID::CAVTemplate) {
+            Some(template_id) => match database.cav_template_delete(template_id) {
+                Ok(()) => ReasonCode::Success,
+                Err(x) => x,
+            },
+            None => ReasonCode::NoSuchCav,
+        }
+    };
+
+    let mut response = AWPacket::new(PacketType::CavTemplateDelete);
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+/// The citizen whose avatar a request is about, if the client may change it.
+fn owned_citizen_id(client: &Client, packet: &AWPacket) -> Result<u32, ReasonCode> {
+    let own_id = match &client.info().entity {
+        Some(Entity::Player(info)) => info.citizen_id.ok_or(ReasonCode::NotLoggedIn)?,
+        _ => return Err(ReasonCode::NotLoggedIn),
+    };
+
+    match packet.get_uint(VarID::CAVCitizen) {
+        Some(id) if id != own_id && !client.has_admin_permissions() => {
+            Err(ReasonCode::Unauthorized)
+        }
+        Some(id) => Ok(id),
+        None => Ok(own_id),
+    }
+}
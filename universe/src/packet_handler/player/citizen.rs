@@ -1,18 +1,26 @@
 use crate::{
-    client::{Client, ClientType, Entity},
+    audit::{self, citizen_json, citizen_target},
+    client::{Client, ClientManager, ClientType, Entity},
+    config::TrialConfig,
     database::citizen::CitizenQuery,
-    database::CitizenDB,
-    database::Database,
+    database::group::GroupRights,
+    database::{password, Database},
+    database::{CavDB, CitizenDB, ContactDB, CreditDB, GroupDB, ProfileDB, TelegramDB, TokenDB},
+    player::PlayerInfo,
+    scripting::Scripts,
 };
 use aw_core::*;
+use aw_derive::AWPacketData;
+
+use super::notify_cav_changed;
 
 pub fn citizen_next(client: &Client, packet: &AWPacket, database: &Database) {
     let mut rc = ReasonCode::Success;
     let mut response = AWPacket::new(PacketType::CitizenInfo);
 
-    if !client.has_admin_permissions() {
+    if !client.has_permission(GroupRights::CITIZEN_ADMIN) {
         log::info!(
-            "Client {} tried to use CitizenNext but is not an admin",
+            "Client {} tried to use CitizenNext but is not a citizen admin",
             client.addr.ip()
         );
         rc = ReasonCode::Unauthorized;
@@ -20,9 +28,12 @@ pub fn citizen_next(client: &Client, packet: &AWPacket, database: &Database) {
         // TODO: next should be able to skip IDs
         let citizen_id = packet.get_uint(VarID::CitizenNumber).unwrap_or(0);
         match database.citizen_by_number(citizen_id.saturating_add(1)) {
+            Ok(citizen) if !may_see(client, info, &citizen, database) => {
+                rc = ReasonCode::Unauthorized;
+            }
             Ok(citizen) => {
                 let same_citizen_id = Some(citizen.id) == info.citizen_id;
-                let is_admin = client.has_admin_permissions();
+                let is_admin = client.has_permission(GroupRights::CITIZEN_ADMIN);
                 let vars = citizen_info_vars(&citizen, same_citizen_id, is_admin);
                 for v in vars {
                     response.add_var(v);
@@ -43,9 +54,9 @@ pub fn citizen_prev(client: &Client, packet: &AWPacket, database: &Database) {
     let mut rc = ReasonCode::Success;
     let mut response = AWPacket::new(PacketType::CitizenInfo);
 
-    if !client.has_admin_permissions() {
+    if !client.has_permission(GroupRights::CITIZEN_ADMIN) {
         log::info!(
-            "Client {} tried to use CitizenPrev but is not an admin",
+            "Client {} tried to use CitizenPrev but is not a citizen admin",
             client.addr.ip()
         );
         rc = ReasonCode::Unauthorized;
@@ -53,9 +64,12 @@ pub fn citizen_prev(client: &Client, packet: &AWPacket, database: &Database) {
         // TODO: prev should be able to skip IDs
         let citizen_id = packet.get_uint(VarID::CitizenNumber).unwrap_or(0);
         match database.citizen_by_number(citizen_id.saturating_sub(1)) {
+            Ok(citizen) if !may_see(client, info, &citizen, database) => {
+                rc = ReasonCode::Unauthorized;
+            }
             Ok(citizen) => {
                 let same_citizen_id = Some(citizen.id) == info.citizen_id;
-                let is_admin = client.has_admin_permissions();
+                let is_admin = client.has_permission(GroupRights::CITIZEN_ADMIN);
                 let vars = citizen_info_vars(&citizen, same_citizen_id, is_admin);
                 for v in vars {
                     response.add_var(v);
@@ -76,18 +90,21 @@ pub fn citizen_lookup_by_name(client: &Client, packet: &AWPacket, database: &Dat
     let mut rc = ReasonCode::Success;
     let mut response = AWPacket::new(PacketType::CitizenInfo);
 
-    if !client.has_admin_permissions() {
+    if !client.has_permission(GroupRights::CITIZEN_ADMIN) {
         log::info!(
-            "Client {} tried to use CitizenLookupByName but is not an admin",
+            "Client {} tried to use CitizenLookupByName but is not a citizen admin",
             client.addr.ip()
         );
         rc = ReasonCode::Unauthorized;
     } else if let Some(Entity::Player(info)) = &client.info().entity {
         match packet.get_string(VarID::CitizenName) {
             Some(citizen_name) => match database.citizen_by_name(&citizen_name) {
+                Ok(citizen) if !may_see(client, info, &citizen, database) => {
+                    rc = ReasonCode::Unauthorized;
+                }
                 Ok(citizen) => {
                     let same_citizen_id = Some(citizen.id) == info.citizen_id;
-                    let is_admin = client.has_admin_permissions();
+                    let is_admin = client.has_permission(GroupRights::CITIZEN_ADMIN);
                     let vars = citizen_info_vars(&citizen, same_citizen_id, is_admin);
                     for v in vars {
                         response.add_var(v);
@@ -112,18 +129,21 @@ pub fn citizen_lookup_by_number(client: &Client, packet: &AWPacket, database: &D
     let mut rc = ReasonCode::Success;
     let mut response = AWPacket::new(PacketType::CitizenInfo);
 
-    if !client.has_admin_permissions() {
+    if !client.has_permission(GroupRights::CITIZEN_ADMIN) {
         log::info!(
-            "Client {} tried to use CitizenLookupByNumber but is not an admin",
+            "Client {} tried to use CitizenLookupByNumber but is not a citizen admin",
             client.addr.ip()
         );
         rc = ReasonCode::Unauthorized;
     } else if let Some(Entity::Player(info)) = &client.info().entity {
         match packet.get_uint(VarID::CitizenNumber) {
             Some(citizen_id) => match database.citizen_by_number(citizen_id) {
+                Ok(citizen) if !may_see(client, info, &citizen, database) => {
+                    rc = ReasonCode::Unauthorized;
+                }
                 Ok(citizen) => {
                     let same_citizen_id = Some(citizen.id) == info.citizen_id;
-                    let is_admin = client.has_admin_permissions();
+                    let is_admin = client.has_permission(GroupRights::CITIZEN_ADMIN);
                     let vars = citizen_info_vars(&citizen, same_citizen_id, is_admin);
                     for v in vars {
                         response.add_var(v);
@@ -144,7 +164,23 @@ pub fn citizen_lookup_by_number(client: &Client, packet: &AWPacket, database: &D
     client.connection.send(response);
 }
 
-pub fn citizen_change(client: &Client, packet: &AWPacket, database: &Database) {
+/// Whether a client may see the details of a citizen it looked up.
+fn may_see(
+    client: &Client,
+    info: &PlayerInfo,
+    citizen: &CitizenQuery,
+    database: &Database,
+) -> bool {
+    Some(citizen.id) == info.citizen_id || client.may_manage_citizen(citizen.id, database)
+}
+
+pub fn citizen_change(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+    trial: &TrialConfig,
+) {
     let changed_info = citizen_from_packet(packet);
     if changed_info.is_err() {
         log::trace!("Could not change citizen: {:?}", changed_info);
@@ -152,21 +188,48 @@ pub fn citizen_change(client: &Client, packet: &AWPacket, database: &Database) {
     }
     let changed_info = changed_info.unwrap();
     let mut rc = ReasonCode::Success;
+    let mut cav_changed = false;
 
     if let Some(Entity::Player(info)) = &client.info().entity {
-        // Client needs to be the user in question or an admin
-        if Some(changed_info.id) != info.citizen_id && !client.has_admin_permissions() {
+        // Client needs to be the user in question or a citizen admin who may manage them
+        if Some(changed_info.id) != info.citizen_id
+            && !client.may_manage_citizen(changed_info.id, database)
+        {
             rc = ReasonCode::Unauthorized;
         } else {
             match database.citizen_by_number(changed_info.id) {
                 Ok(original_info) => {
-                    if let Err(x) = modify_citizen(
+                    // Older browsers don't know about this flag, so it is left alone if not sent
+                    let multi_login = packet.get_byte(VarID::CitizenMultiLogin).map(u32::from);
+                    match modify_citizen(
                         &original_info,
                         &changed_info,
+                        multi_login,
                         database,
-                        client.has_admin_permissions(),
+                        client.has_permission(GroupRights::CITIZEN_ADMIN),
+                        trial,
                     ) {
-                        rc = x;
+                        Ok(()) => {
+                            // Citizens changing their own details are not administrative actions
+                            if Some(changed_info.id) != info.citizen_id {
+                                audit::record(
+                                    database,
+                                    &client.actor(),
+                                    "citizen_change",
+                                    &citizen_target(changed_info.id),
+                                    citizen_json(&original_info),
+                                    database
+                                        .citizen_by_number(changed_info.id)
+                                        .as_ref()
+                                        .map(citizen_json)
+                                        .unwrap_or_default(),
+                                );
+                            }
+                            cav_changed = original_info.cav_template != changed_info.cav_template
+                                || (client.has_permission(GroupRights::CITIZEN_ADMIN)
+                                    && original_info.cav_enabled != changed_info.cav_enabled);
+                        }
+                        Err(x) => rc = x,
                     }
                 }
                 Err(_) => {
@@ -176,6 +239,11 @@ pub fn citizen_change(client: &Client, packet: &AWPacket, database: &Database) {
         }
     }
 
+    // Other users should see the citizen's new avatar
+    if cav_changed {
+        notify_cav_changed(changed_info.id, database, client_manager);
+    }
+
     let mut response = AWPacket::new(PacketType::CitizenChangeResult);
     log::trace!("Change citizen: {:?}", rc);
     response.add_int(VarID::ReasonCode, rc as i32);
@@ -186,9 +254,19 @@ pub fn citizen_change(client: &Client, packet: &AWPacket, database: &Database) {
 fn modify_citizen(
     original: &CitizenQuery,
     changed: &CitizenQuery,
+    multi_login: Option<u32>,
     database: &Database,
     admin: bool,
+    trial: &TrialConfig,
 ) -> Result<(), ReasonCode> {
+    // Trial citizens may be kept from setting a privilege password
+    if !admin
+        && !changed.priv_pass.is_empty()
+        && !trial.allows_privilege_password(original.trial != 0)
+    {
+        return Err(ReasonCode::Unauthorized);
+    }
+
     // Find any citizens with the same name as the new name
     if let Ok(matching_cit) = database.citizen_by_name(&changed.name) {
         // If someone already has the name, it needs to be the same user
@@ -201,9 +279,19 @@ fn modify_citizen(
         id: original.id,
         changed: 0,
         name: changed.name.clone(),
-        password: changed.password.clone(),
+        // Passwords are never sent to browsers, so an empty one is left unchanged.
+        // The same goes for privilege passwords below.
+        password: if changed.password.is_empty() {
+            original.password.clone()
+        } else {
+            password::prepare_password(&changed.password)?
+        },
         email: changed.email.clone(),
-        priv_pass: changed.priv_pass.clone(),
+        priv_pass: if changed.priv_pass.is_empty() {
+            original.priv_pass.clone()
+        } else {
+            password::prepare_privilege_password(&changed.priv_pass)?
+        },
         comment: if admin {
             changed.comment.clone()
         } else {
@@ -238,6 +326,10 @@ fn modify_citizen(
         },
         privacy: changed.privacy,
         trial: if admin { changed.trial } else { original.trial },
+        multi_login: match (admin, multi_login) {
+            (true, Some(x)) => x,
+            _ => original.multi_login,
+        },
     };
 
     database
@@ -278,9 +370,7 @@ fn citizen_info_vars(
             AWPacketVar::Byte(VarID::BetaUser, citizen.beta as u8),
             AWPacketVar::Byte(VarID::CitizenEnabled, citizen.enabled as u8),
             AWPacketVar::Uint(VarID::CitizenPrivacy, citizen.privacy),
-            AWPacketVar::String(VarID::CitizenPassword, citizen.password.clone()),
             AWPacketVar::String(VarID::CitizenEmail, citizen.email.clone()),
-            AWPacketVar::String(VarID::CitizenPrivilegePassword, citizen.priv_pass.clone()),
             AWPacketVar::Uint(VarID::CitizenImmigration, citizen.immigration),
         ]);
     }
@@ -289,87 +379,88 @@ fn citizen_info_vars(
         vars.extend(vec![
             AWPacketVar::String(VarID::CitizenComment, citizen.comment.clone()),
             AWPacketVar::Uint(VarID::IdentifyUserIP, citizen.last_address),
+            AWPacketVar::Byte(VarID::CitizenMultiLogin, citizen.multi_login as u8),
         ]);
     }
 
     vars
 }
 
+/// Citizen fields sent by a browser when adding or changing a citizen.
+#[derive(AWPacketData)]
+struct CitizenFields {
+    #[aw(var = CitizenName)]
+    name: String,
+    #[aw(var = CitizenNumber)]
+    id: u32,
+    #[aw(var = CitizenEmail)]
+    email: String,
+    #[aw(var = CitizenPrivilegePassword)]
+    priv_pass: String,
+    #[aw(var = CitizenExpiration)]
+    expiration: u32,
+    #[aw(var = CitizenBotLimit)]
+    bot_limit: u32,
+    #[aw(var = BetaUser)]
+    beta: u32,
+    #[aw(var = CitizenEnabled)]
+    enabled: u32,
+    #[aw(var = CitizenComment)]
+    comment: String,
+    #[aw(var = CitizenPassword)]
+    password: String,
+    #[aw(var = CitizenURL)]
+    url: String,
+    #[aw(var = CAVTemplate)]
+    cav_template: u32,
+    #[aw(var = CAVEnabled)]
+    cav_enabled: u32,
+    #[aw(var = CitizenPrivacy)]
+    privacy: u32,
+    #[aw(var = TrialUser)]
+    trial: u32,
+}
+
 fn citizen_from_packet(packet: &AWPacket) -> Result<CitizenQuery, String> {
-    let username = packet
-        .get_string(VarID::CitizenName)
-        .ok_or_else(|| "No citizen name".to_string())?;
-    let citizen_id = packet
-        .get_uint(VarID::CitizenNumber)
-        .ok_or_else(|| "No citizen number".to_string())?;
-    let email = packet
-        .get_string(VarID::CitizenEmail)
-        .ok_or_else(|| "No citizen email".to_string())?;
-    let priv_pass = packet
-        .get_string(VarID::CitizenPrivilegePassword)
-        .ok_or_else(|| "No citizen privilege password".to_string())?;
-    let expiration = packet
-        .get_uint(VarID::CitizenExpiration)
-        .ok_or_else(|| "No citizen expiration".to_string())?;
-    let bot_limit = packet
-        .get_uint(VarID::CitizenBotLimit)
-        .ok_or_else(|| "No citizen bot limit".to_string())?;
-    let beta = packet
-        .get_uint(VarID::BetaUser)
-        .ok_or_else(|| "No citizen beta user".to_string())?;
-    let enabled = packet
-        .get_uint(VarID::CitizenEnabled)
-        .ok_or_else(|| "No citizen enabled".to_string())?;
-    let comment = packet
-        .get_string(VarID::CitizenComment)
-        .ok_or_else(|| "No citizen comment".to_string())?;
-    let password = packet
-        .get_string(VarID::CitizenPassword)
-        .ok_or_else(|| "No citizen password".to_string())?;
-    let url = packet
-        .get_string(VarID::CitizenURL)
-        .ok_or_else(|| "No citizen url".to_string())?;
-    let cav_template = packet
-        .get_uint(VarID::CAVTemplate)
-        .ok_or_else(|| "No citizen cav template".to_string())?;
-    let cav_enabled = packet
-        .get_uint(VarID::CAVEnabled)
-        .ok_or_else(|| "No citizen cav enabled".to_string())?;
-    let privacy = packet
-        .get_uint(VarID::CitizenPrivacy)
-        .ok_or_else(|| "No citizen privacy".to_string())?;
-    let trial = packet
-        .get_uint(VarID::TrialUser)
-        .ok_or_else(|| "No citizen trial".to_string())?;
+    let fields = CitizenFields::try_from(packet)?;
 
     Ok(CitizenQuery {
-        id: citizen_id,
+        id: fields.id,
         changed: 0,
-        name: username,
-        password,
-        email,
-        priv_pass,
-        comment,
-        url,
+        name: fields.name,
+        password: fields.password,
+        email: fields.email,
+        priv_pass: fields.priv_pass,
+        comment: fields.comment,
+        url: fields.url,
         immigration: 0,
-        expiration,
+        expiration: fields.expiration,
         last_login: 0,
         last_address: 0,
         total_time: 0,
-        bot_limit,
-        beta,
-        cav_enabled,
-        cav_template,
-        enabled,
-        privacy,
-        trial,
+        bot_limit: fields.bot_limit,
+        beta: fields.beta,
+        cav_enabled: fields.cav_enabled,
+        cav_template: fields.cav_template,
+        enabled: fields.enabled,
+        privacy: fields.privacy,
+        trial: fields.trial,
+        multi_login: 0,
     })
 }
 
-pub fn citizen_add(client: &Client, packet: &AWPacket, database: &Database) {
+pub fn citizen_add(client: &Client, packet: &AWPacket, database: &Database, scripts: &Scripts) {
     let mut response = AWPacket::new(PacketType::CitizenChangeResult);
-    let rc = match try_add_citizen(client, packet, database) {
+    let rc = match try_add_citizen(client, packet, database, scripts) {
         Ok(new_cit) => {
+            audit::record(
+                database,
+                &client.actor(),
+                "citizen_add",
+                &citizen_target(new_cit.id),
+                serde_json::Value::Null,
+                citizen_json(&new_cit),
+            );
             response.add_uint(VarID::CitizenNumber, new_cit.id);
             response.add_string(VarID::CitizenName, new_cit.name);
 
@@ -388,6 +479,7 @@ fn try_add_citizen(
     client: &Client,
     packet: &AWPacket,
     database: &Database,
+    scripts: &Scripts,
 ) -> Result<CitizenQuery, ReasonCode> {
     let id = packet
         .get_uint(VarID::CitizenNumber)
@@ -438,10 +530,11 @@ fn try_add_citizen(
         enabled,
         privacy: 0,
         trial,
+        multi_login: 0,
     };
 
-    // Client needs to be an admin
-    if !client.has_admin_permissions() {
+    // Client needs to be a citizen admin
+    if !client.has_permission(GroupRights::CITIZEN_ADMIN) {
         return Err(ReasonCode::Unauthorized);
     }
 
@@ -450,9 +543,11 @@ fn try_add_citizen(
         return Err(ReasonCode::NameAlreadyUsed);
     }
 
-    // Cannot have ID 0 - TODO: get default next ID
+    // Citizen 0 means the next free number
     if new_info.id == 0 {
-        return Err(ReasonCode::NumberAlreadyUsed);
+        new_info.id = database
+            .citizen_next_number()
+            .map_err(|_| ReasonCode::UnableToInsertCitizen)?;
     }
 
     // Can't add citizen if someone already has the citzen number
@@ -460,6 +555,11 @@ fn try_add_citizen(
         return Err(ReasonCode::NumberAlreadyUsed);
     }
 
+    // Nor if it belonged to a deleted citizen, whose rights could still refer to it
+    if database.citizen_number_retired(new_info.id) != Ok(false) {
+        return Err(ReasonCode::NumberAlreadyUsed);
+    }
+
     // Can't add citizen if the id is too large
     if new_info.id > (i32::MAX as u32) {
         return Err(ReasonCode::UnableToInsertCitizen);
@@ -467,12 +567,15 @@ fn try_add_citizen(
 
     // Unimplemented: email filter
 
+    scripts.on_register(&new_info.name, &new_info.email)?;
+
     if client.info().client_type == Some(ClientType::Bot) {
         new_info.immigration = packet.get_uint(VarID::CitizenImmigration).unwrap_or(0);
         new_info.last_login = packet.get_uint(VarID::CitizenLastLogin).unwrap_or(0);
         new_info.total_time = packet.get_uint(VarID::CitizenTotalTime).unwrap_or(0);
     }
 
+    new_info.password = password::prepare_password(&new_info.password)?;
     database
         .citizen_add(&new_info)
         .map_err(|_| ReasonCode::UnableToInsertCitizen)?;
@@ -483,3 +586,116 @@ fn try_add_citizen(
 
     Ok(result)
 }
+
+/// Handle an admin deleting a citizen account.
+pub fn citizen_delete(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) {
+    let mut response = AWPacket::new(PacketType::CitizenChangeResult);
+
+    let rc = match try_delete_citizen(client, packet, database, client_manager) {
+        Ok(citizen_id) => {
+            response.add_uint(VarID::CitizenNumber, citizen_id);
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    log::trace!("Delete citizen: {:?}", rc);
+    response.add_int(VarID::ReasonCode, rc as i32);
+
+    client.connection.send(response);
+}
+
+fn try_delete_citizen(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) -> Result<u32, ReasonCode> {
+    // Client needs to be a citizen admin
+    if !client.has_permission(GroupRights::CITIZEN_ADMIN) {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let citizen_id = packet
+        .get_uint(VarID::CitizenNumber)
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+
+    if !client.may_manage_citizen(citizen_id, database) {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let citizen = remove_citizen(citizen_id, database, client_manager)?;
+
+    log::info!(
+        "Citizen {} (#{}) was deleted by {}",
+        citizen.name,
+        citizen.id,
+        client.addr.ip()
+    );
+    audit::record(
+        database,
+        &client.actor(),
+        "citizen_delete",
+        &citizen_target(citizen.id),
+        citizen_json(&citizen),
+        serde_json::Value::Null,
+    );
+
+    Ok(citizen.id)
+}
+
+/// Delete a citizen along with everything stored for them, and disconnect anyone
+/// logged in to the account.
+pub fn remove_citizen(
+    citizen_id: u32,
+    database: &Database,
+    client_manager: &ClientManager,
+) -> Result<CitizenQuery, ReasonCode> {
+    // The administrator account must always exist
+    if citizen_id == 1 {
+        return Err(ReasonCode::UnableToDeleteCitizen);
+    }
+
+    let citizen = database
+        .citizen_by_number(citizen_id)
+        .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+    database
+        .citizen_delete(citizen.id)
+        .map_err(|_| ReasonCode::UnableToDeleteCitizen)?;
+
+    // Nothing is left behind, even though the number is never given out again
+    let cleanups = [
+        ("avatars", database.cav_delete_all(citizen.id)),
+        (
+            "group memberships",
+            database.group_remove_citizen(citizen.id),
+        ),
+        ("credits", database.credit_delete(citizen.id)),
+        ("telegrams", database.telegram_delete_all(citizen.id)),
+        ("contacts", database.contact_delete_all(citizen.id)),
+        ("tokens", database.token_delete_all(citizen.id)),
+        ("profile", database.profile_delete_all(citizen.id)),
+    ];
+    for (what, result) in cleanups {
+        if result.is_err() {
+            log::warn!("Could not delete the {what} of citizen #{}", citizen.id);
+        }
+    }
+
+    // Anyone still logged in to the account is disconnected
+    for other_client in client_manager.clients() {
+        if let Some(Entity::Player(info)) = &other_client.info().entity {
+            if info.citizen_id == Some(citizen.id) {
+                other_client.kill();
+            }
+        }
+    }
+
+    Ok(citizen)
+}
@@ -0,0 +1,41 @@
+use crate::{
+    client::{Client, ClientManager},
+    database::group::GroupRights,
+};
+use aw_core::*;
+
+/// An admin sends a message to one session's chat window, or to everyone's
+/// if no session is given, such as to warn of a restart.
+pub fn console_message(client: &Client, packet: &AWPacket, client_manager: &ClientManager) {
+    let rc = match try_console_message(client, packet, client_manager) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    let mut response = AWPacket::new(PacketType::ConsoleMessage);
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_console_message(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+) -> Result<(), ReasonCode> {
+    if !client.has_permission(GroupRights::BROADCAST) {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let message = packet
+        .get_string(VarID::ConsoleMessage)
+        .filter(|x| !x.is_empty())
+        .ok_or(ReasonCode::MessageLengthBad)?;
+    let session_id = packet.get_int(VarID::SessionID).map(|x| x as u16);
+
+    let count = client_manager.send_console_message_to(session_id, &message)?;
+    log::info!(
+        "{} sent a console message to {count} users: {message:?}",
+        client.addr.ip()
+    );
+    Ok(())
+}
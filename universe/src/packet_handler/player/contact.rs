@@ -136,20 +136,152 @@ fn try_add_contact(
     Ok((citizen_id, contact_citizen.id))
 }
 
-pub fn set_afk(client: &Client, packet: &AWPacket) {
-    if let Some(Entity::Player(player)) = &mut client.info_mut().entity {
-        if player.citizen_id.is_none() {
-            return;
+/// Handle a citizen changing the options of a contact, or their default
+/// options when the contact is 0.
+pub fn contact_change(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) {
+    let mut response = AWPacket::new(PacketType::ContactChange);
+
+    let rc = match try_contact_change(client, packet, database) {
+        Ok(citizen_id) => {
+            // The change may hide or reveal this citizen's status to others
+            update_contacts_of_user(citizen_id, database, client_manager);
+            ReasonCode::Success
         }
+        Err(x) => x,
+    };
 
-        let afk_status = match packet.get_uint(VarID::AFKStatus) {
-            Some(x) => x,
-            None => return,
-        };
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
 
-        let is_afk = afk_status != 0;
-        player.afk = is_afk;
-        log::info!("{:?} AFK: {:?}", player.username, player.afk);
+fn try_contact_change(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+) -> Result<u32, ReasonCode> {
+    // Must be a player
+    let player_info = match &client.info().entity {
+        Some(Entity::Player(x)) => x.clone(),
+        _ => return Err(ReasonCode::NotLoggedIn),
+    };
+
+    // Must be logged in as a citizen
+    let citizen_id = match player_info.citizen_id {
+        Some(x) => x,
+        None => return Err(ReasonCode::NotLoggedIn),
+    };
+
+    let contact_id = packet
+        .get_uint(VarID::ContactListCitizenID)
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+
+    let contact_options = packet
+        .get_uint(VarID::ContactListOptions)
+        .ok_or(ReasonCode::UnableToSetContact)?;
+
+    // Only contacts already on the list can be changed, apart from the defaults
+    if contact_id != 0 && database.contact_get(citizen_id, contact_id).is_err() {
+        return Err(ReasonCode::NoSuchCitizen);
+    }
+
+    database
+        .contact_set(citizen_id, contact_id, contact_options)
+        .map_err(|_| ReasonCode::UnableToSetContact)?;
+
+    Ok(citizen_id)
+}
+
+/// Handle a citizen removing someone from their contact list.
+pub fn contact_delete(client: &Client, packet: &AWPacket, database: &Database) {
+    let rc = match try_contact_delete(client, packet, database) {
+        Ok(_) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    let mut response = AWPacket::new(PacketType::ContactDelete);
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_contact_delete(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+) -> Result<(), ReasonCode> {
+    // Must be a player
+    let player_info = match &client.info().entity {
+        Some(Entity::Player(x)) => x.clone(),
+        _ => return Err(ReasonCode::NotLoggedIn),
+    };
+
+    // Must be logged in as a citizen
+    let citizen_id = match player_info.citizen_id {
+        Some(x) => x,
+        None => return Err(ReasonCode::NotLoggedIn),
+    };
+
+    let contact_id = packet
+        .get_uint(VarID::ContactListCitizenID)
+        .filter(|x| *x != 0)
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+
+    database
+        .contact_get(citizen_id, contact_id)
+        .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+    database
+        .contact_delete(citizen_id, contact_id)
+        .map_err(|_| ReasonCode::UnableToSetContact)
+}
+
+pub fn set_afk(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    database: &Database,
+) {
+    let player = match &mut client.info_mut().entity {
+        Some(Entity::Player(player)) => {
+            if player.citizen_id.is_none() {
+                return;
+            }
+
+            let afk_status = match packet.get_uint(VarID::AFKStatus) {
+                Some(x) => x,
+                None => return,
+            };
+
+            // 1 means away and 2 means do not disturb
+            let is_afk = afk_status != 0;
+            let is_dnd = afk_status == 2;
+            // Going from away to do not disturb is a change too
+            let changed = player.afk != is_afk || player.dnd != is_dnd;
+            player.afk = is_afk;
+            player.dnd = is_dnd;
+            log::info!(
+                "{:?} AFK: {:?} DND: {:?}",
+                player.username,
+                player.afk,
+                player.dnd
+            );
+
+            if !changed {
+                return;
+            }
+            player.clone()
+        }
+        _ => return,
+    };
+
+    // Let everyone else see the user is away, or back
+    PlayerInfo::send_update_to_all(&player, client_manager, database);
+    if let Some(citizen_id) = player.citizen_id {
+        update_contacts_of_user(citizen_id, database, client_manager);
     }
 }
 
@@ -224,7 +356,12 @@ fn try_contact_confirm(
     Ok(())
 }
 
-pub fn user_list(client: &Client, packet: &AWPacket, client_manager: &ClientManager) {
+pub fn user_list(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    database: &Database,
+) {
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Current time is before the unix epoch.")
@@ -239,7 +376,7 @@ pub fn user_list(client: &Client, packet: &AWPacket, client_manager: &ClientMana
         return;
     }
 
-    PlayerInfo::send_updates_to_one(&client_manager.get_player_infos(), client);
+    PlayerInfo::send_updates_to_one(&client_manager.get_player_infos(), client, database);
 }
 
 pub fn contact_list(
@@ -365,13 +502,14 @@ fn contact_name_world_state(
     };
 
     username = contact_citizen.name;
+    let location_allowed = database.contact_location_allowed(contact.contact, contact.citizen);
 
     let mut status = match client_manager.get_client_by_citizen_id(contact.contact) {
         Some(client) => match &client.info().entity {
             Some(Entity::Player(player)) => match player.state {
                 PlayerState::Offline => ContactState::Offline,
                 PlayerState::Online => {
-                    if let Some(player_world) = &player.world {
+                    if let Some(player_world) = player.world.as_ref().filter(|_| location_allowed) {
                         world = player_world.clone();
                     }
                     match player.afk {
@@ -0,0 +1,220 @@
+use crate::{
+    audit::{self, citizen_target},
+    client::{Client, Entity},
+    config::CreditConfig,
+    database::{group::GroupRights, CitizenDB, CreditDB, Database, GroupDB},
+    events::{EventQueue, UniverseEvent},
+    ClientType,
+};
+use aw_core::*;
+use serde_json::json;
+
+/// Get a citizen's credit balance. Citizens may only see their own, while
+/// credit admins and their bots may see anyone's.
+pub fn credit_query(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    config: &CreditConfig,
+) {
+    let mut response = AWPacket::new(PacketType::CreditQuery);
+
+    let rc = match try_credit_query(client, packet, database, config, &mut response) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_credit_query(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    config: &CreditConfig,
+    response: &mut AWPacket,
+) -> Result<(), ReasonCode> {
+    if !config.enabled {
+        return Err(ReasonCode::NotAvailable);
+    }
+
+    let own_id = match &client.info().entity {
+        Some(Entity::Player(info)) => info.citizen_id,
+        _ => None,
+    };
+
+    let citizen_id = match (packet.get_uint(VarID::CitizenNumber), own_id) {
+        (Some(id), _) => id,
+        (None, Some(id)) => id,
+        (None, None) => return Err(ReasonCode::NoSuchCitizen),
+    };
+
+    if own_id != Some(citizen_id) && !may_manage_credits(client, database) {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    response.add_uint(VarID::CitizenNumber, citizen_id);
+    response.add_int(
+        VarID::CreditBalance,
+        clamp_balance(database.credit_balance(citizen_id)?),
+    );
+
+    Ok(())
+}
+
+/// Credit or debit a citizen's balance. Only credit admins and their bots may
+/// do this, and every adjustment is recorded in the ledger.
+pub fn credit_change(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    config: &CreditConfig,
+    events: &EventQueue,
+) {
+    let mut response = AWPacket::new(PacketType::CreditChange);
+
+    let rc = match try_credit_change(client, packet, database, config, events, &mut response) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_credit_change(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    config: &CreditConfig,
+    events: &EventQueue,
+    response: &mut AWPacket,
+) -> Result<(), ReasonCode> {
+    if !config.enabled {
+        return Err(ReasonCode::NotAvailable);
+    }
+
+    if !may_manage_credits(client, database) {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let citizen_id = packet
+        .get_uint(VarID::CitizenNumber)
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+    let amount = packet
+        .get_int(VarID::CreditAmount)
+        .ok_or(ReasonCode::InvalidArgument)?;
+    let reason = packet.get_string(VarID::CreditReason).unwrap_or_default();
+
+    database
+        .citizen_by_number(citizen_id)
+        .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+    let actor = client.actor();
+    let balance = database.credit_adjust(
+        citizen_id,
+        amount.into(),
+        config.allow_negative,
+        &actor,
+        &reason,
+    )?;
+
+    log::info!(
+        "{actor} adjusted the credits of citizen {citizen_id} by {amount} to {balance} ({reason})"
+    );
+    audit::record(
+        database,
+        &actor,
+        "credit_adjust",
+        &citizen_target(citizen_id),
+        json!({ "balance": balance - i64::from(amount) }),
+        json!({ "balance": balance, "reason": reason }),
+    );
+    events.publish(UniverseEvent::CreditChange {
+        citizen_id,
+        amount: amount.into(),
+        balance,
+        actor,
+        reason,
+    });
+
+    response.add_uint(VarID::CitizenNumber, citizen_id);
+    response.add_int(VarID::CreditBalance, clamp_balance(balance));
+
+    Ok(())
+}
+
+/// Whether a client may see and change anyone's credits. Bots may if the
+/// citizen running them may.
+fn may_manage_credits(client: &Client, database: &Database) -> bool {
+    if client.has_permission(GroupRights::CREDIT_ADMIN) {
+        return true;
+    }
+
+    let owner_id = match (&client.info().client_type, &client.info().entity) {
+        (Some(ClientType::Bot), Some(Entity::Player(bot))) => bot.privilege_id,
+        _ => None,
+    };
+    owner_id.is_some_and(|owner_id| {
+        database
+            .group_rights_of_citizen(owner_id)
+            .intersects(GroupRights::ADMIN | GroupRights::CREDIT_ADMIN)
+    })
+}
+
+/// Balances are stored as 64 bits but sent as 32.
+fn clamp_balance(balance: i64) -> i32 {
+    balance.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldServerInfo;
+
+    #[test]
+    pub fn test_may_manage_credits() {
+        let database = Database::unreachable();
+
+        let credit_admin = Client::loopback();
+        credit_admin.info_mut().entity = Some(Entity::new_citizen(
+            5,
+            None,
+            1,
+            0,
+            "Banker",
+            credit_admin.addr.ip(),
+            GroupRights::CREDIT_ADMIN,
+        ));
+        assert!(may_manage_credits(&credit_admin, &database));
+
+        let citizen = Client::loopback();
+        citizen.info_mut().entity = Some(Entity::new_citizen(
+            6,
+            None,
+            2,
+            0,
+            "Citizen",
+            citizen.addr.ip(),
+            GroupRights::CITIZEN_ADMIN,
+        ));
+        assert!(!may_manage_credits(&citizen, &database));
+
+        // The owner's rights cannot be checked, so the bot is refused
+        let bot = Client::loopback();
+        bot.info_mut().client_type = Some(ClientType::Bot);
+        bot.info_mut().entity = Some(Entity::new_bot(5, 3, 0, "Bank", bot.addr.ip()));
+        assert!(!may_manage_credits(&bot, &database));
+
+        let world_server = Client::loopback();
+        world_server.info_mut().client_type = Some(ClientType::World);
+        world_server.info_mut().entity = Some(Entity::WorldServer(WorldServerInfo {
+            build: 0,
+            version: None,
+            server_port: 6670,
+            worlds: Vec::new(),
+        }));
+        assert!(!may_manage_credits(&world_server, &database));
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    audit,
+    client::{Client, ClientManager},
+    database::{eject::EjectQuery, group::GroupRights, Database, EjectDB},
+};
+use aw_core::*;
+
+use super::{ip_to_num, ipv4_representation};
+
+/// Addresses are sent over the wire in the same byte order as `ip_to_num` produces.
+fn num_to_ip(num: u32) -> Ipv4Addr {
+    Ipv4Addr::from(num.to_le_bytes())
+}
+
+/// The /64 network of an IPv6 address, as its first and last address.
+fn ipv6_network(ip: Ipv6Addr) -> (Ipv6Addr, Ipv6Addr) {
+    let network = u128::from(ip) & !u128::from(u64::MAX);
+    (
+        Ipv6Addr::from(network),
+        Ipv6Addr::from(network | u128::from(u64::MAX)),
+    )
+}
+
+/// The real addresses behind an address from the protocol. A stand-in for IPv6
+/// clients is resolved to the network of a connected client it stands for.
+fn resolve_address(address: Ipv4Addr, client_manager: &ClientManager) -> (IpAddr, Option<IpAddr>) {
+    let ipv6 = client_manager
+        .clients()
+        .iter()
+        .filter_map(|client| match client.addr.ip().to_canonical() {
+            IpAddr::V6(ip) => Some(ip),
+            IpAddr::V4(_) => None,
+        })
+        .find(|ip| ipv4_representation(IpAddr::V6(*ip)) == address);
+
+    match ipv6 {
+        Some(ip) => {
+            let (start, end) = ipv6_network(ip);
+            (IpAddr::V6(start), Some(IpAddr::V6(end)))
+        }
+        None => (IpAddr::V4(address), None),
+    }
+}
+
+/// What an audit entry about an ejection is about.
+fn eject_target(address: impl std::fmt::Display) -> String {
+    format!("eject:{address}")
+}
+
+fn eject_json(eject: &EjectQuery) -> Value {
+    json!({
+        "address": eject.address.to_string(),
+        "address_end": eject.address_end.map(|end| end.to_string()),
+        "expiration": eject.expiration,
+        "creation": eject.creation,
+        "comment": eject.comment,
+    })
+}
+
+fn add_ejection_vars(response: &mut AWPacket, eject: &EjectQuery) {
+    // IPv6 networks are shown as their stand-in alone
+    response.add_uint(VarID::EjectionAddress, ip_to_num(eject.address));
+    response.add_uint(
+        VarID::EjectionAddressEnd,
+        eject
+            .address_end
+            .filter(IpAddr::is_ipv4)
+            .map(ip_to_num)
+            .unwrap_or(0),
+    );
+    response.add_uint(VarID::EjectionExpiration, eject.expiration);
+    response.add_uint(VarID::EjectionCreation, eject.creation);
+    response.add_string(VarID::EjectionComment, eject.comment.clone());
+}
+
+/// An admin bans an address, or a range of addresses, from the universe.
+pub fn eject_add(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) {
+    let mut response = AWPacket::new(PacketType::EjectAdd);
+
+    let rc = match try_eject_add(client, packet, database, client_manager) {
+        Ok(eject) => {
+            // Anyone already connected from the banned addresses is disconnected
+            for other_client in client_manager.clients() {
+                if eject.covers(other_client.addr.ip()) {
+                    log::info!("Disconnecting ejected client {}", other_client.addr.ip());
+                    other_client.kill();
+                }
+            }
+
+            add_ejection_vars(&mut response, &eject);
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_eject_add(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) -> Result<EjectQuery, ReasonCode> {
+    if !client.has_permission(GroupRights::EJECT_ADMIN) {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs() as u32;
+
+    let address = packet
+        .get_uint(VarID::EjectionAddress)
+        .map(num_to_ip)
+        .ok_or(ReasonCode::NoSuchEjection)?;
+
+    let address_end = match packet.get_uint(VarID::EjectionAddressEnd).unwrap_or(0) {
+        0 => None,
+        end => Some(num_to_ip(end)).filter(|end| *end != address),
+    };
+
+    if matches!(address_end, Some(end) if end < address) {
+        return Err(ReasonCode::NoSuchEjection);
+    }
+
+    let expiration = packet.get_uint(VarID::EjectionExpiration).unwrap_or(0);
+    if expiration != 0 && expiration <= now {
+        return Err(ReasonCode::EjectionExpired);
+    }
+
+    let (address, address_end) = match address_end {
+        Some(end) => (IpAddr::V4(address), Some(IpAddr::V4(end))),
+        None => resolve_address(address, client_manager),
+    };
+
+    let mut eject = EjectQuery {
+        id: 0,
+        address,
+        address_end,
+        expiration,
+        creation: now,
+        comment: packet
+            .get_string(VarID::EjectionComment)
+            .unwrap_or_default(),
+    };
+
+    eject.id = database.eject_add(&eject)?;
+    log::info!(
+        "{} ejected {} (until {})",
+        client.addr.ip(),
+        address,
+        expiration
+    );
+    audit::record(
+        database,
+        &client.actor(),
+        "eject_add",
+        &eject_target(address),
+        Value::Null,
+        eject_json(&eject),
+    );
+
+    Ok(eject)
+}
+
+/// An admin lifts the ejection starting at an address.
+pub fn eject_delete(client: &Client, packet: &AWPacket, database: &Database) {
+    let rc = if !client.has_permission(GroupRights::EJECT_ADMIN) {
+        ReasonCode::Unauthorized
+    } else {
+        match packet.get_uint(VarID::EjectionAddress).map(num_to_ip) {
+            Some(address) => {
+                let before = database.eject_lookup(address);
+                match database.eject_delete(address) {
+                    Ok(()) => {
+                        audit::record(
+                            database,
+                            &client.actor(),
+                            "eject_delete",
+                            &eject_target(address),
+                            before.as_ref().map(eject_json).unwrap_or_default(),
+                            Value::Null,
+                        );
+                        ReasonCode::Success
+                    }
+                    Err(x) => x,
+                }
+            }
+            None => ReasonCode::NoSuchEjection,
+        }
+    };
+
+    let mut response = AWPacket::new(PacketType::EjectDelete);
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+pub fn eject_lookup(client: &Client, packet: &AWPacket, database: &Database) {
+    send_eject_lookup(client, packet, database, PacketType::EjectLookup);
+}
+
+pub fn eject_next(client: &Client, packet: &AWPacket, database: &Database) {
+    send_eject_lookup(client, packet, database, PacketType::EjectNext);
+}
+
+pub fn eject_prev(client: &Client, packet: &AWPacket, database: &Database) {
+    send_eject_lookup(client, packet, database, PacketType::EjectPrev);
+}
+
+fn send_eject_lookup(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    packet_type: PacketType,
+) {
+    let mut response = AWPacket::new(packet_type);
+
+    let eject = if !client.has_permission(GroupRights::EJECT_ADMIN) {
+        Err(ReasonCode::Unauthorized)
+    } else {
+        let address = num_to_ip(packet.get_uint(VarID::EjectionAddress).unwrap_or(0));
+        match packet_type {
+            PacketType::EjectNext => database.eject_next(address),
+            PacketType::EjectPrev => database.eject_prev(address),
+            _ => database.eject_lookup(address),
+        }
+    };
+
+    let rc = match eject {
+        Ok(eject) => {
+            add_ejection_vars(&mut response, &eject);
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_ipv6_network() {
+        let (start, end) = ipv6_network("2001:db8:1:2:3:4:5:6".parse().unwrap());
+        assert_eq!(start, "2001:db8:1:2::".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(
+            end,
+            "2001:db8:1:2:ffff:ffff:ffff:ffff"
+                .parse::<Ipv6Addr>()
+                .unwrap()
+        );
+
+        // Everything in one network has the same stand-in
+        let a = ipv4_representation("2001:db8:1:2::1".parse().unwrap());
+        let b = ipv4_representation("2001:db8:1:2:ffff::1".parse().unwrap());
+        let c = ipv4_representation("2001:db8:1:3::1".parse().unwrap());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(a.octets()[0] & 0xF0, 0xF0);
+    }
+}
@@ -0,0 +1,305 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    audit,
+    client::{Client, ClientManager, Entity},
+    database::{
+        group::{GroupQuery, GroupRights},
+        CitizenDB, ContactDB, Database, GroupDB, TelegramDB,
+    },
+};
+use aw_core::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use serde_json::{json, Value};
+
+use super::send_telegram_update_available;
+
+/// Longest allowed group name, matching the size of the Name column.
+const MAX_GROUP_NAME_LEN: usize = 50;
+
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupAction {
+    Create = 0,
+    Delete = 1,
+    AddMember = 2,
+    RemoveMember = 3,
+    SetRights = 4,
+}
+
+/// Look up a group by name, or list the groups a citizen belongs to.
+/// Available to citizens, bots and world servers.
+pub fn group_query(client: &Client, packet: &AWPacket, database: &Database) {
+    let mut response = AWPacket::new(PacketType::GroupQuery);
+
+    let rc = match try_group_query(client, packet, database, &mut response) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_group_query(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    response: &mut AWPacket,
+) -> Result<(), ReasonCode> {
+    match &client.info().entity {
+        Some(Entity::Player(info)) if info.citizen_id.is_some() => {}
+        Some(Entity::WorldServer(_)) => {}
+        _ => return Err(ReasonCode::NotLoggedIn),
+    }
+
+    if let Some(name) = packet.get_string(VarID::GroupName) {
+        let group = database.group_by_name(&name)?;
+        add_group_vars(response, &group);
+        for member in database.group_members(group.id)? {
+            response.add_uint(VarID::GroupMember, member);
+        }
+    } else if let Some(citizen_id) = packet.get_uint(VarID::CitizenNumber) {
+        response.add_uint(VarID::CitizenNumber, citizen_id);
+        for group in database.groups_of_citizen(citizen_id)? {
+            add_group_vars(response, &group);
+        }
+    } else {
+        return Err(ReasonCode::InvalidArgument);
+    }
+
+    Ok(())
+}
+
+fn add_group_vars(response: &mut AWPacket, group: &GroupQuery) {
+    response.add_uint(VarID::GroupID, group.id);
+    response.add_string(VarID::GroupName, group.name.clone());
+    response.add_uint(VarID::GroupOwner, group.owner);
+    response.add_uint(VarID::GroupRights, group.rights.bits());
+}
+
+/// Send a telegram to every member of a group.
+/// Only members, the group owner and admins may do this. Members who would not
+/// accept a telegram from the sender are skipped.
+pub fn group_telegram(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) {
+    let rc = match try_group_telegram(client, packet, database) {
+        Ok(recipients) => {
+            for citizen_id in recipients {
+                if let Some(target_client) = client_manager.get_client_by_citizen_id(citizen_id) {
+                    send_telegram_update_available(target_client, database);
+                }
+            }
+
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    let mut response = AWPacket::new(PacketType::GroupTelegram);
+    response.add_int(VarID::ReasonCode, rc as i32);
+
+    client.connection.send(response);
+}
+
+fn try_group_telegram(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+) -> Result<Vec<u32>, ReasonCode> {
+    let citizen_id = player_citizen_id(client)?;
+
+    let name = packet
+        .get_string(VarID::GroupName)
+        .ok_or(ReasonCode::InvalidArgument)?;
+
+    let message = packet
+        .get_string(VarID::TelegramMessage)
+        .ok_or(ReasonCode::UnableToSendTelegram)?;
+    if message.is_empty() {
+        return Err(ReasonCode::MessageLengthBad);
+    }
+
+    let group = database.group_by_name(&name)?;
+    let members = database.group_members(group.id)?;
+
+    if !(client.has_admin_permissions()
+        || group.owner == citizen_id
+        || members.contains(&citizen_id))
+    {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs() as u32;
+
+    let recipients: Vec<u32> = members
+        .into_iter()
+        .filter(|&id| id != citizen_id)
+        .filter(|&id| {
+            database.contact_telegrams_allowed(citizen_id, id)
+                && database.contact_telegrams_allowed(id, citizen_id)
+        })
+        .collect();
+
+    for &target in &recipients {
+        database
+            .telegram_add(target, citizen_id, now, &message)
+            .map_err(|_| ReasonCode::UnableToSendTelegram)?;
+    }
+
+    Ok(recipients)
+}
+
+/// Create, delete or change a group.
+/// Any citizen may create a group and becomes its owner. The owner may
+/// delete the group and manage its members, while only admins may change
+/// the rights a group grants, or who is in a group which grants any.
+pub fn group_change(client: &Client, packet: &AWPacket, database: &Database) {
+    let mut response = AWPacket::new(PacketType::GroupChange);
+
+    let rc = match try_group_change(client, packet, database, &mut response) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_group_change(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    response: &mut AWPacket,
+) -> Result<(), ReasonCode> {
+    let citizen_id = player_citizen_id(client)?;
+    let admin = client.has_admin_permissions();
+
+    let action = packet
+        .get_byte(VarID::GroupAction)
+        .and_then(GroupAction::from_u8)
+        .ok_or(ReasonCode::InvalidArgument)?;
+
+    let name = packet
+        .get_string(VarID::GroupName)
+        .ok_or(ReasonCode::InvalidArgument)?;
+
+    if action == GroupAction::Create {
+        if name.is_empty() || name.len() > MAX_GROUP_NAME_LEN {
+            return Err(ReasonCode::InvalidArgument);
+        }
+        if database.group_by_name(&name).is_ok() {
+            return Err(ReasonCode::NameAlreadyUsed);
+        }
+        let id = database.group_add(&name, citizen_id)?;
+        database.group_add_member(id, citizen_id)?;
+        response.add_uint(VarID::GroupID, id);
+        audit::record(
+            database,
+            &client.actor(),
+            "group_create",
+            &group_target(id),
+            Value::Null,
+            json!({ "name": name, "owner": citizen_id }),
+        );
+        return Ok(());
+    }
+
+    let group = database.group_by_name(&name)?;
+    let owner = admin || group.owner == citizen_id;
+    response.add_uint(VarID::GroupID, group.id);
+
+    match action {
+        GroupAction::Create => unreachable!(),
+        GroupAction::Delete => {
+            if !owner {
+                return Err(ReasonCode::Unauthorized);
+            }
+            database.group_delete(group.id)?;
+            audit::record(
+                database,
+                &client.actor(),
+                "group_delete",
+                &group_target(group.id),
+                json!({ "name": group.name, "owner": group.owner }),
+                Value::Null,
+            );
+            Ok(())
+        }
+        GroupAction::AddMember | GroupAction::RemoveMember => {
+            let member = packet
+                .get_uint(VarID::CitizenNumber)
+                .ok_or(ReasonCode::NoSuchCitizen)?;
+
+            // Joining a group with rights is the same as being given them
+            let manager = if group.rights.is_empty() {
+                owner
+            } else {
+                admin
+            };
+
+            // Members may always remove themselves
+            if !(manager || action == GroupAction::RemoveMember && member == citizen_id) {
+                return Err(ReasonCode::Unauthorized);
+            }
+
+            let audit_action = if action == GroupAction::AddMember {
+                database
+                    .citizen_by_number(member)
+                    .map_err(|_| ReasonCode::NoSuchCitizen)?;
+                database.group_add_member(group.id, member)?;
+                "group_add_member"
+            } else {
+                database.group_remove_member(group.id, member)?;
+                "group_remove_member"
+            };
+            audit::record(
+                database,
+                &client.actor(),
+                audit_action,
+                &group_target(group.id),
+                Value::Null,
+                json!({ "citizen": member }),
+            );
+            Ok(())
+        }
+        GroupAction::SetRights => {
+            if !admin {
+                return Err(ReasonCode::Unauthorized);
+            }
+            let rights = packet
+                .get_uint(VarID::GroupRights)
+                .ok_or(ReasonCode::InvalidArgument)?;
+            let rights = GroupRights::from_bits_truncate(rights);
+            database.group_set_rights(group.id, rights)?;
+            audit::record(
+                database,
+                &client.actor(),
+                "group_set_rights",
+                &group_target(group.id),
+                json!({ "rights": group.rights.bits() }),
+                json!({ "rights": rights.bits() }),
+            );
+            Ok(())
+        }
+    }
+}
+
+/// What an audit entry about a group is about.
+fn group_target(group_id: u32) -> String {
+    format!("group:{group_id}")
+}
+
+fn player_citizen_id(client: &Client) -> Result<u32, ReasonCode> {
+    match &client.info().entity {
+        Some(Entity::Player(info)) => info.citizen_id.ok_or(ReasonCode::NotLoggedIn),
+        _ => Err(ReasonCode::NotLoggedIn),
+    }
+}
@@ -0,0 +1,337 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    accounts,
+    client::{Client, Entity},
+    config::{Config, UniverseConfig},
+    database::attrib::Attribute,
+    database::citizen::CitizenQuery,
+    database::{password, Database},
+    database::{AttribDB, CitizenDB},
+    email::Mailer,
+    events::UniverseEvent,
+    packet_handler::{send_console_message, HandlerContext},
+    scripting::Scripts,
+};
+use aw_core::*;
+
+/// When citizens recently immigrated, by the address they came from, so one
+/// address cannot create citizens without end.
+#[derive(Default)]
+pub struct ImmigrationLimit {
+    by_ip: RefCell<HashMap<IpAddr, Vec<Instant>>>,
+}
+
+impl ImmigrationLimit {
+    /// Whether another citizen may immigrate from an address.
+    fn allows(&self, ip: IpAddr, config: &UniverseConfig) -> bool {
+        if config.immigrations_per_ip == 0 {
+            return true;
+        }
+        let window = Duration::from_secs(config.immigration_window_secs);
+        self.by_ip.borrow().get(&ip).map_or(0, |times| {
+            times.iter().filter(|time| time.elapsed() < window).count()
+        }) < config.immigrations_per_ip as usize
+    }
+
+    fn record(&self, ip: IpAddr) {
+        self.by_ip
+            .borrow_mut()
+            .entry(ip)
+            .or_default()
+            .push(Instant::now());
+    }
+
+    /// Forget immigrations which no longer count against their address.
+    pub fn expire(&self, config: &UniverseConfig) {
+        let window = Duration::from_secs(config.immigration_window_secs);
+        self.by_ip.borrow_mut().retain(|_, times| {
+            times.retain(|time| time.elapsed() < window);
+            !times.is_empty()
+        });
+    }
+}
+
+/// Handle a new user creating their own citizen account.
+pub fn immigrate(ctx: &HandlerContext, client: &Client, packet: &AWPacket) {
+    let config = ctx.config;
+    let mut response = AWPacket::new(PacketType::Immigrate);
+
+    // Verification needs both a way to send the link and a page for it to lead to
+    let mailer = ctx
+        .mailer
+        .filter(|_| config.accounts.verify_email && config.accounts.enabled);
+
+    let result = try_immigrate(
+        client,
+        packet,
+        ctx.database,
+        config,
+        ctx.scripts,
+        mailer,
+        ctx.immigration_limit,
+    );
+    let rc = match result {
+        Ok(citizen) => {
+            log::info!(
+                "{} immigrated as {} (#{})",
+                client.addr.ip(),
+                citizen.name,
+                citizen.id
+            );
+            ctx.events.publish(UniverseEvent::Register {
+                ip: client.addr.ip(),
+                name: citizen.name.clone(),
+                citizen_id: citizen.id,
+            });
+            response.add_uint(VarID::CitizenNumber, citizen.id);
+            response.add_string(VarID::CitizenName, citizen.name);
+
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_immigrate(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    config: &Config,
+    scripts: &Scripts,
+    mailer: Option<&Mailer>,
+    limit: &ImmigrationLimit,
+) -> Result<CitizenQuery, ReasonCode> {
+    let open = database
+        .attrib_get()
+        .ok()
+        .and_then(|mut values| values.remove(&Attribute::RegistrationMethod))
+        .is_some_and(|value| value == "Y");
+    if !open {
+        return Err(ReasonCode::ImmigrationNotAllowed);
+    }
+
+    // World servers have no business creating citizens
+    if let Some(Entity::WorldServer(_)) = client.info().entity {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    if !limit.allows(client.addr.ip(), &config.universe) {
+        log::info!(
+            "Refusing immigration from {}, which has immigrated too often",
+            client.addr.ip()
+        );
+        return Err(ReasonCode::NotYet);
+    }
+
+    let name = packet
+        .get_string(VarID::CitizenName)
+        .ok_or(ReasonCode::NameTooShort)?;
+    let password = packet
+        .get_string(VarID::CitizenPassword)
+        .ok_or(ReasonCode::PasswordTooShort)?;
+    let email = packet
+        .get_string(VarID::CitizenEmail)
+        .ok_or(ReasonCode::EmailTooShort)?;
+
+    check_valid_citizen_name(&name)?;
+    check_valid_password(&password)?;
+    check_valid_email(&email)?;
+
+    if database.citizen_by_name(&name).is_ok() {
+        return Err(ReasonCode::NameAlreadyUsed);
+    }
+
+    scripts.on_register(&name, &email)?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs() as u32;
+
+    let citizen = CitizenQuery {
+        id: database
+            .citizen_next_number()
+            .map_err(|_| ReasonCode::UnableToInsertCitizen)?,
+        changed: 0,
+        name,
+        password: password::prepare_password(&password)?,
+        email,
+        priv_pass: String::default(),
+        comment: String::default(),
+        url: String::default(),
+        immigration: now,
+        expiration: 0,
+        last_login: 0,
+        last_address: 0,
+        total_time: 0,
+        bot_limit: 0,
+        beta: 0,
+        cav_enabled: 0,
+        cav_template: 0,
+        enabled: mailer.is_none() as u32,
+        privacy: 0,
+        trial: 0,
+        multi_login: 0,
+    };
+
+    database
+        .citizen_add(&citizen)
+        .map_err(|_| ReasonCode::UnableToInsertCitizen)?;
+
+    let citizen = database
+        .citizen_by_name(&citizen.name)
+        .map_err(|_| ReasonCode::UnableToInsertCitizen)?;
+
+    if let Some(mailer) = mailer {
+        // A citizen who can never be enabled is taken back out, so the name is free to try again
+        if let Err(err) = accounts::send_verification(&citizen, database, mailer, &config.accounts)
+        {
+            log::warn!(
+                "Could not send a verification email to {}: {err:?}",
+                citizen.name
+            );
+            if let Err(err) = database.citizen_discard(citizen.id) {
+                log::warn!(
+                    "Could not remove unverifiable citizen {} (#{}): {err:?}",
+                    citizen.name,
+                    citizen.id
+                );
+            }
+            return Err(ReasonCode::UnableToMailBackNumber);
+        }
+        send_console_message(
+            client,
+            "Check your email for a link to confirm your address before logging in.",
+        );
+    }
+
+    limit.record(client.addr.ip());
+
+    Ok(citizen)
+}
+
+/// Check that a name is acceptable for a new citizen.
+pub fn check_valid_citizen_name(name: &str) -> Result<(), ReasonCode> {
+    let length = name.chars().count();
+
+    if length < 2 {
+        return Err(ReasonCode::NameTooShort);
+    }
+
+    if length > 16 {
+        return Err(ReasonCode::NameTooLong);
+    }
+
+    if name.starts_with(' ') {
+        return Err(ReasonCode::NameContainsInvalidBlank);
+    }
+
+    if name.ends_with(' ') {
+        return Err(ReasonCode::NameEndsWithBlank);
+    }
+
+    // Bots and tourists are told apart from citizens by brackets and quotes
+    if !name.chars().all(|c| c.is_alphanumeric() || c == ' ') {
+        return Err(ReasonCode::NameContainsNonalphanumericChar);
+    }
+
+    Ok(())
+}
+
+/// Check that a password is acceptable for a new citizen.
+pub fn check_valid_password(password: &str) -> Result<(), ReasonCode> {
+    if password.len() < 4 {
+        return Err(ReasonCode::PasswordTooShort);
+    }
+
+    if password.len() > 12 {
+        return Err(ReasonCode::PasswordTooLong);
+    }
+
+    Ok(())
+}
+
+/// Check that an email address looks deliverable.
+pub fn check_valid_email(email: &str) -> Result<(), ReasonCode> {
+    if email.len() < 6 {
+        return Err(ReasonCode::EmailTooShort);
+    }
+
+    if email.len() > 50 {
+        return Err(ReasonCode::EmailTooLong);
+    }
+
+    if email.starts_with(' ') {
+        return Err(ReasonCode::EmailStartsWithBlank);
+    }
+
+    if email.ends_with(' ') {
+        return Err(ReasonCode::EmailEndsWithBlank);
+    }
+
+    if !email
+        .chars()
+        .all(|c| c.is_ascii_graphic() && !matches!(c, '"' | '(' | ')' | ',' | ';' | '<' | '>'))
+    {
+        return Err(ReasonCode::EmailContainsInvalidChar);
+    }
+
+    let (local, domain) = email.split_once('@').ok_or(ReasonCode::EmailMissingAt)?;
+    if local.is_empty() || domain.contains('@') {
+        return Err(ReasonCode::EmailMissingAt);
+    }
+
+    if !domain.contains('.') || domain.starts_with('.') || domain.ends_with('.') {
+        return Err(ReasonCode::EmailMissingDot);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_immigration_limit() {
+        let config = UniverseConfig {
+            immigrations_per_ip: 2,
+            ..Default::default()
+        };
+        let limit = ImmigrationLimit::default();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+
+        for _ in 0..2 {
+            assert!(limit.allows(ip, &config));
+            limit.record(ip);
+        }
+        assert!(!limit.allows(ip, &config));
+        assert!(limit.allows(other, &config));
+
+        // Immigrations outside the window no longer count
+        let config = UniverseConfig {
+            immigration_window_secs: 0,
+            ..config
+        };
+        limit.expire(&config);
+        assert!(limit.allows(ip, &config));
+
+        let unlimited = UniverseConfig {
+            immigrations_per_ip: 0,
+            ..Default::default()
+        };
+        limit.record(ip);
+        limit.record(ip);
+        assert!(limit.allows(ip, &unlimited));
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    client::{Client, ClientManager, Entity},
+    config::UniverseConfig,
+    database::{CitizenDB, ContactDB, Database, TelegramDB},
+};
+use aw_core::*;
+
+use super::{ip_to_num, join::locate_world};
+
+/// A citizen invites another citizen to where they are. An online invitee is
+/// sent the inviter's world and position, and an offline one gets a telegram.
+pub fn invite(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+    config: &UniverseConfig,
+) {
+    let rc = match try_invite(client, packet, database, client_manager, config) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    let mut response = AWPacket::new(PacketType::Invite);
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_invite(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+    config: &UniverseConfig,
+) -> Result<(), ReasonCode> {
+    let (citizen_id, username, world_name, world_address) = match &client.info().entity {
+        Some(Entity::Player(info)) => match info.citizen_id {
+            Some(citizen_id) => (
+                citizen_id,
+                info.username.clone(),
+                info.world.clone(),
+                info.world_address,
+            ),
+            None => return Err(ReasonCode::NotLoggedIn),
+        },
+        _ => return Err(ReasonCode::NotLoggedIn),
+    };
+
+    let world_name = world_name.ok_or(ReasonCode::WorldNotSet)?;
+    let world =
+        locate_world(client_manager, &world_name, world_address).ok_or(ReasonCode::NoSuchWorld)?;
+
+    let target_id = packet
+        .get_uint(VarID::JoinCitizenNumber)
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+    if target_id == citizen_id {
+        return Err(ReasonCode::JoinRefused);
+    }
+    let target_citizen = database
+        .citizen_by_number(target_id)
+        .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+    if !database.contact_invites_allowed(target_id, citizen_id) {
+        return Err(ReasonCode::JoinRefused);
+    }
+
+    let target = match client_manager.get_client_by_citizen_id(target_id) {
+        Some(x) => x,
+        None => {
+            if !database.contact_telegrams_allowed(target_id, citizen_id) {
+                return Err(ReasonCode::TelegramBlocked);
+            }
+
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Current time is before the unix epoch.")
+                .as_secs() as u32;
+            let message = format!("{username} invited you to join them in {}.", world.name);
+            database
+                .telegram_add(target_citizen.id, citizen_id, now, &message)
+                .map_err(|_| ReasonCode::UnableToSendTelegram)?;
+
+            log::info!(
+                "Left an invitation from {username} for {}",
+                target_citizen.name
+            );
+            return Ok(());
+        }
+    };
+
+    let mut invitation = AWPacket::new(PacketType::Invite);
+    invitation.add_uint(VarID::JoinCitizenNumber, citizen_id);
+    invitation.add_string(VarID::JoinCitizenName, username);
+    invitation.add_string(VarID::JoinWorld, world.name.clone());
+    invitation.add_int(VarID::JoinX, packet.get_int(VarID::JoinX).unwrap_or(0));
+    invitation.add_int(VarID::JoinY, packet.get_int(VarID::JoinY).unwrap_or(0));
+    invitation.add_int(VarID::JoinZ, packet.get_int(VarID::JoinZ).unwrap_or(0));
+    invitation.add_int(VarID::JoinYaw, packet.get_int(VarID::JoinYaw).unwrap_or(0));
+    invitation.add_uint(
+        VarID::WorldAddress,
+        ip_to_num(config.world_server_address(world.ip)),
+    );
+    invitation.add_uint(VarID::WorldPort, world.port as u32);
+    target.connection.send(invitation);
+
+    Ok(())
+}
@@ -0,0 +1,212 @@
+use crate::{
+    client::{Client, ClientManager, Entity},
+    config::UniverseConfig,
+    database::{ContactDB, Database},
+    world::World,
+};
+use aw_core::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use std::net::SocketAddr;
+
+use super::ip_to_num;
+
+/// Why a join request was declined, sent as JoinDeclineReason.
+#[derive(FromPrimitive, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeclineReason {
+    Refused = 0,
+    Busy = 1,
+    DoNotDisturb = 2,
+}
+
+/// A citizen asks to join another citizen. The request is passed on to the
+/// target, whose browser answers with JoinReply.
+pub fn join(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+    config: &UniverseConfig,
+) {
+    let mut response = AWPacket::new(PacketType::Join);
+
+    let rc = match try_join(client, packet, database, client_manager, config) {
+        Ok(None) => ReasonCode::Success,
+        // The decline is the only answer the requester gets
+        Ok(Some((target_id, reason))) => {
+            send_decline(client, target_id, reason);
+            return;
+        }
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+/// Pass a join request on, or give the target and reason if it is declined for them.
+fn try_join(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+    config: &UniverseConfig,
+) -> Result<Option<(u32, DeclineReason)>, ReasonCode> {
+    let (citizen_id, username) = match &client.info().entity {
+        Some(Entity::Player(info)) => match info.citizen_id {
+            Some(citizen_id) => (citizen_id, info.username.clone()),
+            None => return Err(ReasonCode::NotLoggedIn),
+        },
+        _ => return Err(ReasonCode::NotLoggedIn),
+    };
+
+    let target_id = packet
+        .get_uint(VarID::JoinCitizenNumber)
+        .ok_or(ReasonCode::NoSuchCitizen)?;
+
+    if target_id == citizen_id {
+        return Err(ReasonCode::JoinRefused);
+    }
+
+    let target = client_manager
+        .get_client_by_citizen_id(target_id)
+        .ok_or(ReasonCode::NoSuchSession)?;
+
+    if !database.contact_joins_allowed(target_id, citizen_id) {
+        return Err(ReasonCode::JoinRefused);
+    }
+
+    if let Some(Entity::Player(target_info)) = &mut target.info_mut().entity {
+        // There is nowhere to send the requester until the target enters a world
+        if target_info.world.is_none() {
+            return Err(ReasonCode::WorldNotSet);
+        }
+
+        if target_info.dnd && !config.allow_joins_when_dnd {
+            return Ok(Some((target_id, DeclineReason::DoNotDisturb)));
+        }
+
+        if !target_info.join_requests.contains(&citizen_id) {
+            target_info.join_requests.push(citizen_id);
+        }
+    }
+
+    let mut request = AWPacket::new(PacketType::Join);
+    request.add_uint(VarID::JoinCitizenNumber, citizen_id);
+    request.add_string(VarID::JoinCitizenName, username);
+    target.connection.send(request);
+
+    Ok(None)
+}
+
+/// A citizen answers a join request. If they accepted, the requester is told
+/// where to find them, including the address of the world server hosting them.
+pub fn join_reply(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    config: &UniverseConfig,
+) {
+    let requester_id = match packet.get_uint(VarID::JoinCitizenNumber) {
+        Some(x) => x,
+        None => return,
+    };
+
+    let (citizen_id, world_name, world_address) = {
+        let mut info = client.info_mut();
+        let player = match &mut info.entity {
+            Some(Entity::Player(player)) => player,
+            _ => return,
+        };
+
+        // Only answer requests which were actually made
+        let request_index = player
+            .join_requests
+            .iter()
+            .position(|&id| id == requester_id);
+        match request_index {
+            Some(i) => {
+                player.join_requests.remove(i);
+            }
+            None => {
+                log::info!(
+                    "Client {} replied to a join request which was never made",
+                    client.addr.ip()
+                );
+                return;
+            }
+        }
+
+        match player.citizen_id {
+            Some(citizen_id) => (citizen_id, player.world.clone(), player.world_address),
+            None => return,
+        }
+    };
+
+    let requester = match client_manager.get_client_by_citizen_id(requester_id) {
+        Some(x) => x,
+        None => return,
+    };
+
+    let mut response = AWPacket::new(PacketType::JoinReply);
+    response.add_uint(VarID::JoinCitizenNumber, citizen_id);
+
+    let accepted = packet
+        .get_int(VarID::ReasonCode)
+        .map(|rc| rc == ReasonCode::Success as i32)
+        .unwrap_or(false);
+
+    let world = world_name.and_then(|name| locate_world(client_manager, &name, world_address));
+
+    if !accepted {
+        let reason = packet
+            .get_byte(VarID::JoinDeclineReason)
+            .and_then(DeclineReason::from_u8)
+            .unwrap_or(DeclineReason::Refused);
+        send_decline(requester, citizen_id, reason);
+        return;
+    }
+
+    let rc = match world {
+        None => ReasonCode::NoSuchWorld,
+        Some(world) => {
+            response.add_string(VarID::JoinWorld, world.name.clone());
+            response.add_int(VarID::JoinX, packet.get_int(VarID::JoinX).unwrap_or(0));
+            response.add_int(VarID::JoinY, packet.get_int(VarID::JoinY).unwrap_or(0));
+            response.add_int(VarID::JoinZ, packet.get_int(VarID::JoinZ).unwrap_or(0));
+            response.add_int(VarID::JoinYaw, packet.get_int(VarID::JoinYaw).unwrap_or(0));
+            response.add_uint(
+                VarID::WorldAddress,
+                ip_to_num(config.world_server_address(world.ip)),
+            );
+            response.add_uint(VarID::WorldPort, world.port as u32);
+
+            ReasonCode::Success
+        }
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    requester.connection.send(response);
+}
+
+/// Find the world a player is in, on the same server as them if the world has replicas.
+pub(super) fn locate_world(
+    client_manager: &ClientManager,
+    name: &str,
+    address: Option<SocketAddr>,
+) -> Option<World> {
+    client_manager
+        .get_world_replicas(name)
+        .into_iter()
+        .find(|world| Some(SocketAddr::new(world.ip, world.port)) == address)
+        .or_else(|| client_manager.get_world_by_name(name))
+}
+
+/// Tell a requester that a citizen declined to be joined.
+fn send_decline(requester: &Client, citizen_id: u32, reason: DeclineReason) {
+    let mut response = AWPacket::new(PacketType::JoinReply);
+    response.add_uint(VarID::JoinCitizenNumber, citizen_id);
+    response.add_byte(VarID::JoinDeclineReason, reason as u8);
+    response.add_int(VarID::ReasonCode, ReasonCode::JoinRefused as i32);
+    requester.connection.send(response);
+}
@@ -0,0 +1,58 @@
+use serde_json::{json, Value};
+
+use crate::{
+    audit,
+    client::{Client, ClientManager},
+    database::{group::GroupRights, Database},
+};
+use aw_core::*;
+
+/// An admin disconnects another user's session, optionally giving a reason
+/// which is shown to them first.
+pub fn session_kick(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    database: &Database,
+) {
+    let mut response = AWPacket::new(PacketType::SessionKick);
+
+    let rc = match try_session_kick(client, packet, client_manager, database) {
+        Ok(session_id) => {
+            response.add_int(VarID::SessionID, session_id as i32);
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_session_kick(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    database: &Database,
+) -> Result<u16, ReasonCode> {
+    if !client.has_permission(GroupRights::EJECT_ADMIN) {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let session_id = packet
+        .get_int(VarID::SessionID)
+        .ok_or(ReasonCode::NoSuchSession)? as u16;
+    let reason = packet.get_string(VarID::KickReason).unwrap_or_default();
+
+    let kicked = client_manager.kick_session(session_id, &reason)?;
+    audit::record(
+        database,
+        &client.actor(),
+        "kick",
+        &kicked.actor(),
+        Value::Null,
+        json!({ "session": session_id, "reason": reason }),
+    );
+
+    Ok(session_id)
+}
@@ -1,10 +1,13 @@
 use crate::{
-    client::{Client, Entity},
+    audit::{self, license_json, license_target},
+    client::{Client, ClientManager, Entity},
+    database::group::GroupRights,
     database::license::LicenseQuery,
     database::Database,
     database::LicenseDB,
 };
 use aw_core::*;
+use serde_json::Value;
 
 pub fn license_add(client: &Client, packet: &AWPacket, database: &Database) {
     let mut p = AWPacket::new(PacketType::LicenseChangeResult);
@@ -19,8 +22,8 @@ pub fn license_add(client: &Client, packet: &AWPacket, database: &Database) {
         None => return,
     };
 
-    if !client.has_admin_permissions() {
-        log::trace!("Failed to add license due to lack of admin permissions");
+    if !client.has_permission(GroupRights::WORLD_ADMIN) {
+        log::trace!("Failed to add license due to lack of world admin permissions");
         p.add_int(VarID::ReasonCode, ReasonCode::Unauthorized as i32);
         client.connection.send(p);
         return;
@@ -56,6 +59,15 @@ pub fn license_add(client: &Client, packet: &AWPacket, database: &Database) {
         return;
     }
 
+    audit::record(
+        database,
+        &client.actor(),
+        "license_add",
+        &license_target(&lic.name),
+        Value::Null,
+        license_json(&lic),
+    );
+
     p.add_int(VarID::ReasonCode, ReasonCode::Success as i32);
     client.connection.send(p);
 }
@@ -86,8 +98,8 @@ fn send_license_lookup(
 ) {
     let mut p = AWPacket::new(PacketType::LicenseResult);
 
-    // Only admins should be able to query for world licenses
-    if !client.has_admin_permissions() {
+    // Only world admins should be able to query for world licenses
+    if !client.has_permission(GroupRights::WORLD_ADMIN) {
         p.add_int(VarID::ReasonCode, ReasonCode::Unauthorized as i32);
         client.connection.send(p);
         return;
@@ -109,7 +121,7 @@ fn send_license_lookup(
     let rc = match license_result {
         Ok(lic) => {
             // Attach world license info to packet
-            let vars = license_to_vars(&lic, client.has_admin_permissions());
+            let vars = license_to_vars(&lic, client.has_permission(GroupRights::WORLD_ADMIN));
 
             for v in vars {
                 p.add_var(v);
@@ -131,8 +143,8 @@ fn send_license_lookup(
 pub fn license_change(client: &Client, packet: &AWPacket, database: &Database) {
     let mut p = AWPacket::new(PacketType::LicenseResult);
 
-    // Only admins should be able change world licenses
-    if !client.has_admin_permissions() {
+    // Only world admins should be able change world licenses
+    if !client.has_permission(GroupRights::WORLD_ADMIN) {
         p.add_int(VarID::ReasonCode, ReasonCode::Unauthorized as i32);
         client.connection.send(p);
         return;
@@ -186,8 +198,17 @@ pub fn license_change(client: &Client, packet: &AWPacket, database: &Database) {
         return;
     }
 
+    audit::record(
+        database,
+        &client.actor(),
+        "license_change",
+        &license_target(&new_lic.name),
+        license_json(&original_lic),
+        license_json(&new_lic),
+    );
+
     if let Ok(lic) = database.license_by_name(&changed_lic.name) {
-        let vars = license_to_vars(&lic, client.has_admin_permissions());
+        let vars = license_to_vars(&lic, client.has_permission(GroupRights::WORLD_ADMIN));
 
         for v in vars {
             p.add_var(v);
@@ -226,6 +247,67 @@ fn license_to_vars(lic: &LicenseQuery, admin: bool) -> Vec<AWPacketVar> {
     result
 }
 
+pub fn license_delete(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) {
+    let rc = match try_license_delete(client, packet, database, client_manager) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    let mut p = AWPacket::new(PacketType::LicenseChangeResult);
+    p.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(p);
+}
+
+fn try_license_delete(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+) -> Result<(), ReasonCode> {
+    // Only world admins should be able to delete world licenses
+    if !client.has_permission(GroupRights::WORLD_ADMIN) {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let world_name = packet
+        .get_string(VarID::WorldStartWorldName)
+        .ok_or(ReasonCode::NoSuchLicense)?;
+
+    let lic = database
+        .license_by_name(&world_name)
+        .map_err(|_| ReasonCode::NoSuchLicense)?;
+
+    // The universe cannot make a world server stop a world, so it has to be stopped first
+    if !client_manager.get_world_replicas(&lic.name).is_empty() {
+        return Err(ReasonCode::WorldRunning);
+    }
+
+    database
+        .license_delete(&lic.name)
+        .map_err(|_| ReasonCode::UnableToDeleteName)?;
+
+    log::info!(
+        "World license {:?} was deleted by {}",
+        lic.name,
+        client.addr.ip()
+    );
+    audit::record(
+        database,
+        &client.actor(),
+        "license_delete",
+        &license_target(&lic.name),
+        license_json(&lic),
+        Value::Null,
+    );
+
+    Ok(())
+}
+
 fn check_valid_world_name(name: &str) -> Result<(), ReasonCode> {
     if name.len() < 2 {
         return Err(ReasonCode::NameTooShort);
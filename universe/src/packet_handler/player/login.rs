@@ -1,17 +1,26 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::{
+    attributes,
     client::{ClientManager, Entity},
-    database::{citizen::CitizenQuery, Database},
+    config::{
+        BrowserConfig, Config, ExpirationConfig, LoginThrottleConfig, NewsConfig, UniverseConfig,
+    },
+    database::{citizen::CitizenQuery, group::GroupRights, CitizenDB, Database, GroupDB, StatsDB},
+    events::UniverseEvent,
+    packet_handler::HandlerContext,
     player::{PlayerInfo, PlayerState},
-    universe_license::LicenseGenerator,
+    protocol::ProtocolVersion,
     Client, ClientType,
 };
 use aw_core::{AWPacket, AWPacketVar, PacketType, ReasonCode, VarID};
 use num_traits::FromPrimitive;
 
-use super::{send_telegram_update_available, update_contacts_of_user};
+use super::{ip_to_num, send_telegram_update_available, update_contacts_of_user};
+use crate::packet_handler::{send_console_message, send_reason_message};
 
 /// Represents the credentials obtained during handling of the Login packet.
-struct LoginCredentials {
+pub struct LoginCredentials {
     pub user_type: Option<ClientType>,
     pub username: Option<String>,
     pub password: Option<String>,
@@ -38,21 +47,80 @@ impl LoginCredentials {
 }
 
 /// Handle a client attempting to log in.
-pub fn login(
-    client: &Client,
-    packet: &AWPacket,
-    client_manager: &ClientManager,
-    license_generator: &LicenseGenerator,
-    database: &Database,
-) {
-    let _client_version = packet.get_int(VarID::BrowserVersion);
+pub fn login(ctx: &HandlerContext, client: &Client, packet: &AWPacket) {
+    let (config, database, client_manager) = (ctx.config, ctx.database, ctx.client_manager);
+    let browser_version = packet.get_int(VarID::BrowserVersion);
     let browser_build = packet.get_int(VarID::BrowserBuild);
 
+    let protocol = ProtocolVersion::negotiate(browser_version, browser_build);
+    client.set_protocol(protocol);
+    log::debug!(
+        "Login from {} using the {protocol} protocol (version {browser_version:?}, build {browser_build:?})",
+        client.addr.ip()
+    );
+    if !protocol.is_supported() {
+        log::info!(
+            "{} is logging in with a browser newer than 5.2 (version {browser_version:?}, build {browser_build:?}), which is not supported",
+            client.addr.ip()
+        );
+    }
+
     let credentials = LoginCredentials::from_packet(packet);
 
     let mut response = AWPacket::new(PacketType::Login);
+    let mut show_news = false;
+    let mut grace_remaining = None;
+
+    // Only citizens' names are throttled, since tourist names are not guessed at
+    let citizen_name = match credentials.user_type {
+        Some(ClientType::UnspecifiedHuman) => credentials
+            .username
+            .as_deref()
+            .filter(|name| !name.starts_with('"')),
+        _ => None,
+    };
+
+    let validation = check_login_challenge(client, packet, browser_build, &config.universe)
+        .and_then(|_| check_browser_build(client, &credentials, browser_build, &config.browsers))
+        .and_then(|_| {
+            ctx.login_throttle
+                .check(client.addr.ip(), citizen_name, &config.login_throttle)
+        })
+        .and_then(|_| validate_login(client, &credentials, client_manager, database, config))
+        .and_then(|user| {
+            let name = match &user {
+                Some(citizen) => citizen.name.clone(),
+                None => credentials.username.clone().unwrap_or_default(),
+            };
+            ctx.scripts
+                .on_login(&name, user.as_ref().map(|c| c.id), client.addr.ip())?;
+            Ok(user)
+        });
 
-    let rc = match validate_login(client, &credentials, client_manager, database) {
+    match &validation {
+        Ok(user) => {
+            ctx.login_throttle
+                .record_success(client.addr.ip(), citizen_name);
+            if let (Some(citizen), Some(_)) = (user, citizen_name) {
+                clear_failed_logins(database, citizen.id, &config.login_throttle);
+            }
+        }
+        Err(
+            ReasonCode::InvalidPassword
+            | ReasonCode::NoSuchCitizen
+            | ReasonCode::ActingPasswordInvalid
+            | ReasonCode::NoSuchActingCitizen,
+        ) => {
+            ctx.login_throttle
+                .record_failure(client.addr.ip(), citizen_name);
+            if let Some(name) = citizen_name {
+                record_failed_login(database, name, &config.login_throttle);
+            }
+        }
+        Err(_) => {}
+    }
+
+    let rc = match validation {
         // Successful login
         Ok(user) => {
             match (user, credentials.user_type) {
@@ -67,8 +135,24 @@ pub fn login(
                         browser_build.unwrap_or(0),
                         &citizen.name,
                         client.addr.ip(),
+                        database.group_rights_of_citizen(citizen.id),
                     ));
 
+                    if let Some(Entity::Player(player)) = &mut client.info_mut().entity {
+                        player.trial = citizen.trial != 0;
+                    }
+
+                    if citizen.multi_login == 0 {
+                        client_manager.replace_sessions_of(citizen.id, client);
+                    }
+
+                    // Attributes only admins may see were left out before logging in
+                    if client.has_permission(GroupRights::ATTRIBUTE_ADMIN)
+                        && !protocol.expects_attributes_after_login()
+                    {
+                        attributes::send_attributes(client, database);
+                    }
+
                     // Update the user's friends to tell them this user is online
                     update_contacts_of_user(citizen.id, database, client_manager);
 
@@ -79,7 +163,18 @@ pub fn login(
                     response.add_uint(VarID::CitizenPrivacy, citizen.privacy);
                     response.add_uint(VarID::CAVEnabled, citizen.cav_enabled);
 
-                    // TODO: update login time and last address
+                    show_news = should_show_news(&config.news, Some(citizen.last_login));
+
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .expect("Current time is before the unix epoch.")
+                        .as_secs() as u32;
+                    grace_remaining = config.expiration.grace_remaining(citizen.expiration, now);
+                    if let Err(x) =
+                        database.citizen_record_login(citizen.id, now, ip_to_num(client.addr.ip()))
+                    {
+                        log::warn!("Could not record login of citizen {}: {x:?}", citizen.id);
+                    }
                 }
                 // Promote to tourist
                 (None, Some(ClientType::UnspecifiedHuman)) => {
@@ -88,12 +183,25 @@ pub fn login(
                     client.info_mut().entity = Some(Entity::new_tourist(
                         client_manager.create_session_id(),
                         browser_build.unwrap_or(0),
-                        &credentials.username.unwrap_or_default(),
+                        &tourist_name(credentials.username.as_deref().unwrap_or_default()),
                         client.addr.ip(),
                     ));
+
+                    show_news = should_show_news(&config.news, None);
                 }
-                (_, Some(ClientType::Bot)) => {
-                    todo!();
+                // Bots are always owned by a citizen
+                (Some(owner), Some(ClientType::Bot)) => {
+                    client.info_mut().client_type = Some(ClientType::Bot);
+
+                    client.info_mut().entity = Some(Entity::new_bot(
+                        owner.id,
+                        client_manager.create_session_id(),
+                        browser_build.unwrap_or(0),
+                        &credentials.username.clone().unwrap_or_default(),
+                        client.addr.ip(),
+                    ));
+
+                    response.add_uint(VarID::CitizenNumber, owner.id);
                 }
                 _ => {
                     panic!("Got an OK login validation that wasn't a citizen, tourist, or bot. Should be impossible.");
@@ -102,7 +210,13 @@ pub fn login(
             ReasonCode::Success
         }
         // Failed, either because of incorrect credentials or because the client is of the wrong type
-        Err(reason) => reason,
+        Err(reason) => {
+            send_reason_message(client, config, reason);
+            if reason == ReasonCode::MustUpgrade && !config.browsers.upgrade_url.is_empty() {
+                response.add_string(VarID::UpgradeURL, config.browsers.upgrade_url.clone());
+            }
+            reason
+        }
     };
 
     // Inform the client of their displayed username and their new session ID
@@ -111,18 +225,136 @@ pub fn login(
         response.add_int(VarID::SessionID, info.session_id as i32);
     }
 
+    let (name, citizen_id) = match &client.info().entity {
+        Some(Entity::Player(info)) => (info.username.clone(), info.citizen_id),
+        _ => (credentials.username.clone().unwrap_or_default(), None),
+    };
+    ctx.events.publish(UniverseEvent::login(
+        client.addr.ip(),
+        &name,
+        citizen_id,
+        rc,
+    ));
+
     // Add license data (Specific to the IP/port binding that the client sees!)
     response.add_data(
         VarID::UniverseLicense,
-        license_generator.create_license_data(browser_build.unwrap_or(0)),
+        ctx.license_generator
+            .create_license_data(browser_build.unwrap_or(0)),
     );
 
     response.add_int(VarID::ReasonCode, rc as i32);
     client.connection.send(response);
-    PlayerInfo::send_updates_to_all(&client_manager.get_player_infos(), client_manager);
+
+    if rc == ReasonCode::Success && protocol.expects_attributes_after_login() {
+        attributes::send_attributes(client, database);
+    }
+
+    // The new user gets the whole list, and everyone else only hears about them
+    let new_player = match &client.info().entity {
+        Some(Entity::Player(player)) => Some(player.clone()),
+        _ => None,
+    };
+    if let Some(player) = new_player {
+        *client.user_list.borrow_mut() = Default::default();
+        PlayerInfo::send_updates_to_one(&client_manager.get_player_infos(), client, database);
+        PlayerInfo::send_update_to_all(&player, client_manager, database);
+    }
 
     // Inform the client of new telegrams if they are available
     send_telegram_update_available(client, database);
+
+    if show_news && config.news.applies_to_build(browser_build.unwrap_or(0)) {
+        send_news_url(client, &config.news, database);
+    }
+
+    // Citizens in their grace period are reminded to renew
+    if let Some(remaining) = grace_remaining {
+        let days = remaining.div_ceil(86400);
+        send_console_message(
+            client,
+            &format!(
+                "Your citizenship has expired. Renew it within {days} day(s) to keep logging in."
+            ),
+        );
+    }
+}
+
+/// Decides whether the news page should be opened for a user, given the last
+/// time they logged in (None for tourists, who have no login history).
+fn should_show_news(news: &NewsConfig, last_login: Option<u32>) -> bool {
+    if !news.only_when_changed {
+        return true;
+    }
+
+    match last_login {
+        Some(last_login) => last_login < news.last_changed,
+        None => true,
+    }
+}
+
+/// Tell the client's browser to open the universe's news page.
+fn send_news_url(client: &Client, news: &NewsConfig, database: &Database) {
+    database.stats_url_sent(&news.url).ok();
+
+    let mut packet = AWPacket::new(PacketType::URL);
+    packet.add_string(VarID::URLName, news.url.clone());
+    packet.add_string(VarID::URLTarget, String::new());
+    client.connection.send(packet);
+}
+
+/// Make sure a login was made on this connection rather than replayed from
+/// another, by checking it returns the challenge this connection was given.
+fn check_login_challenge(
+    client: &Client,
+    packet: &AWPacket,
+    browser_build: Option<i32>,
+    config: &UniverseConfig,
+) -> Result<(), ReasonCode> {
+    let minimum_build = config.login_challenge_minimum_build;
+    if minimum_build == 0 {
+        return Ok(());
+    }
+
+    match packet.get_data(VarID::LoginChallenge) {
+        Some(challenge) if challenge == client.login_challenge => Ok(()),
+        Some(_) => {
+            log::warn!(
+                "Login from {} returned the wrong challenge",
+                client.addr.ip()
+            );
+            Err(ReasonCode::Imposter)
+        }
+        None if browser_build.unwrap_or(0) >= minimum_build => {
+            log::info!("Login from {} did not return a challenge", client.addr.ip());
+            Err(ReasonCode::Imposter)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Refuse browsers whose build is too old or not one of those allowed.
+/// Bots report SDK builds rather than browser builds, so are not checked.
+fn check_browser_build(
+    client: &Client,
+    credentials: &LoginCredentials,
+    browser_build: Option<i32>,
+    config: &BrowserConfig,
+) -> Result<(), ReasonCode> {
+    if credentials.user_type != Some(ClientType::UnspecifiedHuman) {
+        return Ok(());
+    }
+
+    let build = browser_build.unwrap_or(0);
+    if !config.allows_build(build) {
+        log::info!(
+            "Refusing login from {} using browser build {build}",
+            client.addr.ip()
+        );
+        return Err(ReasonCode::MustUpgrade);
+    }
+
+    Ok(())
 }
 
 /// Validates a client's login credentials.
@@ -135,11 +367,36 @@ fn validate_login(
     credentials: &LoginCredentials,
     client_manager: &ClientManager,
     database: &Database,
+    config: &Config,
 ) -> Result<Option<CitizenQuery>, ReasonCode> {
+    let max_clients = config.universe.max_clients;
+    if max_clients != 0 && client_manager.player_count() >= max_clients {
+        log::info!(
+            "Refusing login from {} because the universe is full",
+            client.addr.ip()
+        );
+        return Err(ReasonCode::UniverseFull);
+    }
+
     match credentials.user_type {
-        Some(ClientType::Bot) => todo!(),
+        Some(ClientType::Bot) => client_manager
+            .check_bot(
+                database,
+                client,
+                &credentials.username,
+                credentials.privilege_id,
+                &credentials.privilege_password,
+                &config.trial,
+            )
+            .and_then(|owner| {
+                if citizenship_expired(&owner, &config.expiration) {
+                    log::info!("Refusing bot login for expired citizen {}", owner.id);
+                    return Err(ReasonCode::CitizenshipExpired);
+                }
+                Ok(Some(owner))
+            }),
         Some(ClientType::UnspecifiedHuman) => {
-            validate_human_login(client, credentials, client_manager, database)
+            validate_human_login(client, credentials, client_manager, database, config)
         }
         _ => Err(ReasonCode::NoSuchCitizen),
     }
@@ -154,6 +411,7 @@ fn validate_human_login(
     credentials: &LoginCredentials,
     client_manager: &ClientManager,
     database: &Database,
+    config: &Config,
 ) -> Result<Option<CitizenQuery>, ReasonCode> {
     let username = credentials
         .username
@@ -162,21 +420,104 @@ fn validate_human_login(
 
     // A user is a tourist if they have quotes around their name
     if username.starts_with('"') {
-        client_manager.check_tourist(username)?;
+        client_manager.check_tourist(username, &config.tourists)?;
         Ok(None)
     } else {
-        let cit = client_manager.check_citizen(
-            database,
-            client,
-            &credentials.username,
-            &credentials.password,
-            credentials.privilege_id,
-            &credentials.privilege_password,
-        )?;
+        check_not_locked(database, username, &config.login_throttle)?;
+        let cit = client_manager.check_citizen(database, client, credentials, config)?;
+
+        if citizenship_expired(&cit, &config.expiration) {
+            if !config.expiration.expired_as_tourist {
+                log::info!("Refusing login to expired citizen {}", cit.id);
+                return Err(ReasonCode::CitizenshipExpired);
+            }
+
+            log::info!("Letting expired citizen {} in as a tourist", cit.id);
+            client_manager.check_tourist(&tourist_name(username), &config.tourists)?;
+            return Ok(None);
+        }
+
         Ok(Some(cit))
     }
 }
 
+/// The name a human is shown by as a tourist. Expired citizens logging in as
+/// tourists give their citizen name, which does not have the quotes yet.
+fn tourist_name(username: &str) -> String {
+    if username.starts_with('"') {
+        username.to_string()
+    } else {
+        format!("\"{username}\"")
+    }
+}
+
+/// Whether a citizen has expired and their grace period is over.
+fn citizenship_expired(citizen: &CitizenQuery, config: &ExpirationConfig) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current time is before the unix epoch.")
+        .as_secs() as u32;
+
+    config.grace_remaining(citizen.expiration, now) == Some(0)
+}
+
+/// Refuse logins to a citizen account which has been locked after too many wrong passwords.
+fn check_not_locked(
+    database: &Database,
+    name: &str,
+    config: &LoginThrottleConfig,
+) -> Result<(), ReasonCode> {
+    if !config.enabled || config.lock_after == 0 {
+        return Ok(());
+    }
+
+    let citizen = match database.citizen_by_name(name) {
+        Ok(x) => x,
+        // Let the usual checks report on names which don't exist
+        Err(_) => return Ok(()),
+    };
+
+    if database.citizen_failed_logins(citizen.id)? >= config.lock_after {
+        log::info!("Refusing login to locked citizen {}", citizen.id);
+        return Err(ReasonCode::CitizenDisabled);
+    }
+
+    Ok(())
+}
+
+/// Count a wrong password against a citizen, locking them out once it reaches the limit.
+fn record_failed_login(database: &Database, name: &str, config: &LoginThrottleConfig) {
+    if !config.enabled || config.lock_after == 0 {
+        return;
+    }
+
+    let citizen = match database.citizen_by_name(name) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    match database.citizen_record_failed_login(citizen.id) {
+        Ok(failed) if failed == config.lock_after => {
+            log::warn!("Locked citizen {} after {failed} failed logins", citizen.id)
+        }
+        Ok(_) => {}
+        Err(x) => log::warn!(
+            "Could not record failed login of citizen {}: {x:?}",
+            citizen.id
+        ),
+    }
+}
+
+fn clear_failed_logins(database: &Database, citizen_id: u32, config: &LoginThrottleConfig) {
+    if !config.enabled || config.lock_after == 0 {
+        return;
+    }
+
+    if let Err(x) = database.citizen_clear_failed_logins(citizen_id) {
+        log::warn!("Could not clear failed logins of citizen {citizen_id}: {x:?}");
+    }
+}
+
 pub fn heartbeat(client: &Client) {
     log::info!("Received heartbeat from {}", client.addr.ip());
 }
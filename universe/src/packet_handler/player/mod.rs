@@ -19,33 +19,108 @@ pub use attribute::*;
 mod world;
 pub use world::*;
 
+mod join;
+pub use join::*;
+
+mod invite;
+pub use invite::*;
+
+mod presence;
+pub use presence::*;
+
+mod url;
+pub use url::*;
+
+mod cav;
+pub use cav::*;
+
+mod profile;
+pub use profile::*;
+
+mod group;
+pub use group::*;
+
+mod credit;
+pub use credit::*;
+
+mod immigrate;
+pub use immigrate::*;
+
+mod xfer;
+pub use xfer::*;
+
+mod eject;
+pub use eject::*;
+
+mod kick;
+pub use kick::*;
+
+mod teleport;
+pub use teleport::*;
+
+mod botgram;
+pub use botgram::*;
+
+mod console_message;
+pub use console_message::*;
+
 use std::{
-    net::IpAddr,
+    net::{IpAddr, Ipv4Addr},
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
     client::{Client, ClientManager},
+    database::Database,
     player::PlayerInfo,
 };
 use aw_core::*;
 
+/// Round trip times are shown by the console's `list`, the admin interface's
+/// `/clients` and `/metrics`, so each heartbeat is only logged for debugging.
 pub fn heartbeat(client: &Client) {
-    log::info!("Received heartbeat from {}", client.addr.ip());
+    match client.rtt() {
+        Some(rtt) => log::debug!(
+            "Received heartbeat from {} (rtt {} ms)",
+            client.addr.ip(),
+            rtt.as_millis()
+        ),
+        None => log::debug!("Received heartbeat from {}", client.addr.ip()),
+    }
+}
+
+/// The IPv4 address used to represent a client in the protocol, which has no room
+/// for IPv6. IPv4-mapped addresses are unwrapped, and the /64 network of other IPv6
+/// addresses is folded into the reserved 240.0.0.0/4 block. Such a stand-in is only
+/// for showing; ejecting one ejects the real network of the client behind it.
+pub fn ipv4_representation(ip: IpAddr) -> Ipv4Addr {
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => v4,
+        IpAddr::V6(v6) => {
+            let folded = v6.octets()[..8]
+                .chunks_exact(4)
+                .map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]]))
+                .fold(0, |acc, word| acc ^ word);
+            Ipv4Addr::from(0xF000_0000 | (folded & 0x0FFF_FFFF))
+        }
+    }
 }
 
 pub fn ip_to_num(ip: IpAddr) -> u32 {
     let mut res: u32 = 0;
-    if let std::net::IpAddr::V4(v4) = ip {
-        for octet in v4.octets().iter().rev() {
-            res <<= 8;
-            res |= *octet as u32;
-        }
+    for octet in ipv4_representation(ip).octets().iter().rev() {
+        res <<= 8;
+        res |= *octet as u32;
     }
     res
 }
 
-pub fn user_list(client: &Client, packet: &AWPacket, client_manager: &ClientManager) {
+pub fn user_list(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    database: &Database,
+) {
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Current time is before the unix epoch.")
@@ -60,5 +135,5 @@ pub fn user_list(client: &Client, packet: &AWPacket, client_manager: &ClientMana
         return;
     }
 
-    PlayerInfo::send_updates_to_one(&client_manager.get_player_infos(), client);
+    PlayerInfo::send_updates_to_one(&client_manager.get_player_infos(), client, database);
 }
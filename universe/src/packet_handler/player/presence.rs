@@ -0,0 +1,114 @@
+use crate::{
+    client::{Client, ClientManager, Entity},
+    database::{CitizenDB, ContactDB, Database},
+    presence_store::PresenceStore,
+};
+use aw_core::*;
+
+/// Tell a player or bot whether a citizen is online, which world they are in
+/// and whether they are away, as far as the citizen's privacy settings allow.
+pub fn presence_query(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+    presence_store: Option<&PresenceStore>,
+) {
+    let mut response = AWPacket::new(PacketType::PresenceQuery);
+
+    let rc = match try_presence_query(
+        client,
+        packet,
+        database,
+        client_manager,
+        presence_store,
+        &mut response,
+    ) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_presence_query(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    client_manager: &ClientManager,
+    presence_store: Option<&PresenceStore>,
+    response: &mut AWPacket,
+) -> Result<(), ReasonCode> {
+    // Tourists have no contact settings, so they are treated as strangers
+    let requester_id = match &client.info().entity {
+        Some(Entity::Player(info)) => info.citizen_id.unwrap_or(0),
+        _ => return Err(ReasonCode::NotLoggedIn),
+    };
+
+    let citizen = if let Some(number) = packet.get_uint(VarID::CitizenNumber) {
+        database.citizen_by_number(number)
+    } else if let Some(name) = packet.get_string(VarID::CitizenName) {
+        database.citizen_by_name(&name)
+    } else {
+        return Err(ReasonCode::NoSuchCitizen);
+    }
+    .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+    response.add_uint(VarID::CitizenNumber, citizen.id);
+    response.add_string(VarID::CitizenName, citizen.name.clone());
+
+    let admin = client.has_admin_permissions();
+    if !admin && !database.contact_status_allowed(citizen.id, requester_id) {
+        // Hidden citizens always appear offline
+        response.add_byte(VarID::PresenceOnline, 0);
+        return Ok(());
+    }
+
+    let target = match client_manager.get_client_by_citizen_id(citizen.id) {
+        Some(x) => x,
+        None => {
+            // The citizen may be connected to another universe process
+            let remote = presence_store.and_then(|store| store.find_remote_citizen(citizen.id));
+            match remote {
+                Some(entry) => {
+                    response.add_byte(VarID::PresenceOnline, 1);
+                    response.add_byte(VarID::PresenceAFK, entry.afk as u8);
+                    if admin || database.contact_location_allowed(citizen.id, requester_id) {
+                        if let Some(world) = &entry.world {
+                            response.add_string(VarID::PresenceWorld, world.clone());
+                        }
+                    }
+                }
+                None => response.add_byte(VarID::PresenceOnline, 0),
+            }
+            return Ok(());
+        }
+    };
+
+    response.add_byte(VarID::PresenceOnline, 1);
+
+    if let Some(Entity::Player(player)) = &target.info().entity {
+        response.add_byte(VarID::PresenceAFK, player.afk as u8);
+
+        if admin || database.contact_location_allowed(citizen.id, requester_id) {
+            if let Some(world) = &player.world {
+                response.add_string(VarID::PresenceWorld, world.clone());
+            }
+        }
+    }
+
+    if admin {
+        let traffic = target.connection.traffic();
+        response.add_uint(
+            VarID::PresenceBytesSent,
+            traffic.bytes_sent().min(u32::MAX as u64) as u32,
+        );
+        response.add_uint(
+            VarID::PresenceBytesReceived,
+            traffic.bytes_received().min(u32::MAX as u64) as u32,
+        );
+    }
+
+    Ok(())
+}
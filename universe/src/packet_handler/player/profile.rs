@@ -0,0 +1,93 @@
+use crate::{
+    audit::{self, citizen_target, profile_field_json},
+    client::{Client, Entity},
+    config::ProfileConfig,
+    database::{CitizenDB, Database, ProfileDB},
+};
+use aw_core::*;
+use serde_json::{json, Value};
+
+/// Get a citizen's profile fields, and optionally set one first.
+/// Only fields listed as public are shown to other citizens.
+pub fn citizen_profile(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    config: &ProfileConfig,
+) {
+    let mut response = AWPacket::new(PacketType::CitizenProfile);
+
+    let rc = match try_citizen_profile(client, packet, database, config, &mut response) {
+        Ok(()) => ReasonCode::Success,
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_citizen_profile(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+    config: &ProfileConfig,
+    response: &mut AWPacket,
+) -> Result<(), ReasonCode> {
+    let own_id = match &client.info().entity {
+        Some(Entity::Player(info)) => info.citizen_id,
+        _ => return Err(ReasonCode::NotLoggedIn),
+    };
+    let admin = client.has_admin_permissions();
+
+    let citizen_id = match (packet.get_uint(VarID::CitizenNumber), own_id) {
+        (Some(id), _) => id,
+        (None, Some(id)) => id,
+        (None, None) => return Err(ReasonCode::NoSuchCitizen),
+    };
+    database
+        .citizen_by_number(citizen_id)
+        .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+    let own_profile = own_id == Some(citizen_id);
+
+    if let Some(name) = packet.get_string(VarID::ProfileFieldName) {
+        if !(admin || own_profile && config.editable_fields.contains(&name)) {
+            return Err(ReasonCode::Unauthorized);
+        }
+
+        let before = profile_field_json(database, citizen_id, &name);
+        let (action, after) = match packet.get_string(VarID::ProfileFieldValue) {
+            Some(value) if !value.is_empty() => {
+                config.check_field(&name, &value)?;
+                database.profile_set(citizen_id, &name, &value)?;
+                ("profile_update", json!({ name: value }))
+            }
+            _ => {
+                database.profile_delete(citizen_id, &name)?;
+                ("profile_delete", Value::Null)
+            }
+        };
+
+        // Citizens editing their own profile are not administrative actions
+        if !own_profile {
+            audit::record(
+                database,
+                &client.actor(),
+                action,
+                &citizen_target(citizen_id),
+                before,
+                after,
+            );
+        }
+    }
+
+    response.add_uint(VarID::CitizenNumber, citizen_id);
+    for field in database.profile_get_all(citizen_id)? {
+        if admin || own_profile || config.public_fields.contains(&field.name) {
+            response.add_string(VarID::ProfileFieldName, field.name);
+            response.add_string(VarID::ProfileFieldValue, field.value);
+        }
+    }
+
+    Ok(())
+}
@@ -1,10 +1,12 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
-    client::{Client, ClientManager, Entity},
+    client::{Client, ClientManager, ClientType, Entity},
     database::CitizenDB,
     database::Database,
     database::{telegram::TelegramQuery, ContactDB, TelegramDB},
+    events::{EventQueue, UniverseEvent},
+    scripting::Scripts,
 };
 use aw_core::*;
 
@@ -13,8 +15,10 @@ pub fn telegram_send(
     packet: &AWPacket,
     database: &Database,
     client_manager: &ClientManager,
+    scripts: &Scripts,
+    events: &EventQueue,
 ) {
-    let rc = match try_send_telegram_from_packet(client, packet, database) {
+    let rc = match try_send_telegram_from_packet(client, packet, database, scripts, events) {
         Ok(citizen_id) => {
             // Alert recipient of new telegram
             if let Some(target_client) = client_manager.get_client_by_citizen_id(citizen_id) {
@@ -36,6 +40,8 @@ fn try_send_telegram_from_packet(
     client: &Client,
     packet: &AWPacket,
     database: &Database,
+    scripts: &Scripts,
+    events: &EventQueue,
 ) -> Result<u32, ReasonCode> {
     // Must be a player
     let player_info = match &client.info().entity {
@@ -43,10 +49,11 @@ fn try_send_telegram_from_packet(
         _ => return Err(ReasonCode::NotLoggedIn),
     };
 
-    // Must be logged in as a citizen
-    let citizen_id = match player_info.citizen_id {
-        Some(x) => x,
-        None => return Err(ReasonCode::NotLoggedIn),
+    // Must be logged in as a citizen, or be a bot sending on behalf of its owner
+    let citizen_id = match (player_info.citizen_id, client.info().client_type) {
+        (Some(x), _) => x,
+        (None, Some(ClientType::Bot)) => player_info.effective_privilege(),
+        (None, _) => return Err(ReasonCode::NotLoggedIn),
     };
 
     // TODO: aw_citizen_privacy
@@ -58,6 +65,9 @@ fn try_send_telegram_from_packet(
     let message = packet
         .get_string(VarID::TelegramMessage)
         .ok_or(ReasonCode::UnableToSendTelegram)?;
+    if message.is_empty() {
+        return Err(ReasonCode::MessageLengthBad);
+    }
 
     let target_citizen = database
         .citizen_by_name(&username_to)
@@ -69,6 +79,8 @@ fn try_send_telegram_from_packet(
         return Err(ReasonCode::TelegramBlocked);
     }
 
+    let message = scripts.on_telegram(&player_info.username, &target_citizen.name, message)?;
+
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Current time is before the unix epoch.")
@@ -78,6 +90,11 @@ fn try_send_telegram_from_packet(
         .telegram_add(target_citizen.id, citizen_id, now, &message)
         .map_err(|_| ReasonCode::UnableToSendTelegram)?;
 
+    events.publish(UniverseEvent::Telegram {
+        from: player_info.username.clone(),
+        to: target_citizen.name.clone(),
+    });
+
     Ok(target_citizen.id)
 }
 
@@ -0,0 +1,86 @@
+use crate::{
+    client::{Client, ClientManager, Entity},
+    config::UniverseConfig,
+};
+use aw_core::*;
+
+use super::ip_to_num;
+
+/// Send a user to a world and position on behalf of an admin, or of a bot
+/// acting for the citizen being sent. Without a world the user is moved within
+/// the world they are in.
+pub fn teleport(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    config: &UniverseConfig,
+) {
+    let mut response = AWPacket::new(PacketType::Teleport);
+
+    let rc = match try_teleport(client, packet, client_manager, config) {
+        Ok(session_id) => {
+            response.add_int(VarID::SessionID, session_id as i32);
+            ReasonCode::Success
+        }
+        Err(x) => x,
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_teleport(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    config: &UniverseConfig,
+) -> Result<u16, ReasonCode> {
+    let caller_privilege = match &client.info().entity {
+        Some(Entity::Player(info)) => info.effective_privilege(),
+        _ => return Err(ReasonCode::NotLoggedIn),
+    };
+
+    let session_id = packet
+        .get_int(VarID::SessionID)
+        .ok_or(ReasonCode::NoSuchSession)? as u16;
+    let target = client_manager
+        .get_client_by_session_id(session_id)
+        .ok_or(ReasonCode::NoSuchSession)?;
+
+    let target_citizen = match &target.info().entity {
+        Some(Entity::Player(info)) => info.citizen_id,
+        _ => None,
+    };
+    let allowed = client.has_admin_permissions()
+        || (caller_privilege != 0 && target_citizen == Some(caller_privilege));
+    if !allowed {
+        return Err(ReasonCode::Unauthorized);
+    }
+
+    let mut teleport = AWPacket::new(PacketType::Teleport);
+    if let Some(world_name) = packet
+        .get_string(VarID::JoinWorld)
+        .filter(|x| !x.is_empty())
+    {
+        let world = client_manager
+            .get_world_by_name(&world_name)
+            .ok_or(ReasonCode::NoSuchWorld)?;
+        teleport.add_string(VarID::JoinWorld, world.name.clone());
+        teleport.add_uint(
+            VarID::WorldAddress,
+            ip_to_num(config.world_server_address(world.ip)),
+        );
+        teleport.add_uint(VarID::WorldPort, world.port as u32);
+    }
+    teleport.add_int(VarID::JoinX, packet.get_int(VarID::JoinX).unwrap_or(0));
+    teleport.add_int(VarID::JoinY, packet.get_int(VarID::JoinY).unwrap_or(0));
+    teleport.add_int(VarID::JoinZ, packet.get_int(VarID::JoinZ).unwrap_or(0));
+    teleport.add_int(VarID::JoinYaw, packet.get_int(VarID::JoinYaw).unwrap_or(0));
+    target.connection.send(teleport);
+
+    log::info!(
+        "Teleported session {session_id} on behalf of {}",
+        client.addr.ip()
+    );
+    Ok(session_id)
+}
@@ -0,0 +1,21 @@
+use crate::{
+    client::{Client, Entity},
+    database::{Database, StatsDB},
+};
+use aw_core::*;
+
+/// Count a click on a URL the universe sent. Only the URL is recorded, not who clicked it.
+pub fn url_click(client: &Client, packet: &AWPacket, database: &Database) {
+    if !matches!(client.info().entity, Some(Entity::Player(_))) {
+        return;
+    }
+
+    let url = match packet.get_string(VarID::URLName) {
+        Some(x) if !x.is_empty() && x.len() <= 255 => x,
+        _ => return,
+    };
+
+    if database.stats_url_clicked(&url).is_err() {
+        log::warn!("Could not record click on {url:?}");
+    }
+}
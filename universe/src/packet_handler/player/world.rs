@@ -2,6 +2,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
     client::{Client, ClientManager, Entity},
+    config::UniverseConfig,
     world::World,
 };
 use aw_core::*;
@@ -28,10 +29,27 @@ pub fn world_list(client: &Client, packet: &AWPacket, client_manager: &ClientMan
         return;
     }
 
-    World::send_updates_to_one(&client_manager.get_world_infos(), client);
+    // World servers only report their user counts periodically, so prefer the
+    // universe's own count of identified players when it is higher.
+    let worlds = client_manager
+        .get_world_infos()
+        .into_iter()
+        .map(|mut world| {
+            let tracked = client_manager.count_players_in_world(&world.name);
+            world.user_count = world.user_count.max(tracked);
+            world
+        })
+        .collect::<Vec<World>>();
+
+    World::send_updates_to_one(&worlds, client);
 }
 
-pub fn world_lookup(client: &Client, packet: &AWPacket, client_manager: &ClientManager) {
+pub fn world_lookup(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    config: &UniverseConfig,
+) {
     let world_name = match packet.get_string(VarID::WorldStartWorldName) {
         Some(x) => x,
         None => return,
@@ -42,6 +60,15 @@ pub fn world_lookup(client: &Client, packet: &AWPacket, client_manager: &ClientM
     p.add_string(VarID::WorldStartWorldName, world_name.clone());
 
     match client_manager.get_world_by_name(&world_name) {
+        // The lookup sends the player to this replica, so it is the one which must have room
+        Some(world) if world.is_full(client_manager.count_players_in_replica(&world)) => {
+            log::info!(
+                "Refusing entry to {} for {} because it is full",
+                world.name,
+                client.addr.ip()
+            );
+            p.add_int(VarID::ReasonCode, ReasonCode::WorldFull as i32);
+        }
         Some(world) => {
             let mut client_info = client.info_mut();
             if let Some(Entity::Player(info)) = &mut client_info.entity {
@@ -53,10 +80,14 @@ pub fn world_lookup(client: &Client, packet: &AWPacket, client_manager: &ClientM
                 nonce.copy_from_slice(&rand_bytes[0..255]);
                 info.nonce = Some(nonce);
 
-                p.add_uint(VarID::WorldAddress, ip_to_num(world.ip));
+                p.add_uint(
+                    VarID::WorldAddress,
+                    ip_to_num(config.world_server_address(world.ip)),
+                );
                 p.add_uint(VarID::WorldPort, world.port as u32);
                 p.add_uint(VarID::WorldLicenseUsers, world.max_users);
                 p.add_uint(VarID::WorldLicenseRange, world.world_size);
+                p.add_byte(VarID::WorldLoad, world.load());
                 p.add_data(VarID::WorldUserNonce, nonce.to_vec());
 
                 p.add_int(VarID::ReasonCode, ReasonCode::Success as i32);
@@ -0,0 +1,141 @@
+use crate::{
+    client::{Client, Entity},
+    database::{CavDB, CitizenDB, Database},
+    packet_handler::HandlerContext,
+    xfer::{Chunk, XferKind},
+};
+use aw_core::*;
+use num_traits::FromPrimitive;
+
+use super::{notify_cav_changed, store_cav_definition};
+
+/// Handle a chunk of an upload, or a request for a chunk of a download.
+pub fn xfer(ctx: &HandlerContext, client: &Client, packet: &AWPacket) {
+    let mut response = AWPacket::new(PacketType::XferReply);
+
+    let id = packet.get_uint(VarID::XferID).unwrap_or(0);
+    response.add_uint(VarID::XferID, id);
+
+    let rc = match packet.get_data(VarID::XferData) {
+        Some(chunk) => match try_upload(ctx, client, packet, id, &chunk) {
+            Ok(received) => {
+                response.add_uint(VarID::XferOffset, received as u32);
+                ReasonCode::Success
+            }
+            Err((received, rc)) => {
+                response.add_uint(VarID::XferOffset, received as u32);
+                rc
+            }
+        },
+        None => match try_download(client, packet, ctx.database) {
+            Ok(payload) => {
+                let offset =
+                    (packet.get_uint(VarID::XferOffset).unwrap_or(0) as usize).min(payload.len());
+                let end = (offset + ctx.config.xfer.chunk_size).min(payload.len());
+
+                response.add_uint(VarID::XferOffset, offset as u32);
+                response.add_uint(VarID::XferTotalSize, payload.len() as u32);
+                response.add_data(VarID::XferData, payload[offset..end].to_vec());
+
+                ReasonCode::Success
+            }
+            Err(x) => x,
+        },
+    };
+
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+fn try_upload(
+    ctx: &HandlerContext,
+    client: &Client,
+    packet: &AWPacket,
+    id: u32,
+    data: &[u8],
+) -> Result<usize, (usize, ReasonCode)> {
+    let owner = match &client.info().entity {
+        Some(Entity::Player(info)) => info.citizen_id.ok_or((0, ReasonCode::NotLoggedIn))?,
+        _ => return Err((0, ReasonCode::NotLoggedIn)),
+    };
+
+    let kind = packet
+        .get_uint(VarID::XferKind)
+        .and_then(XferKind::from_u32)
+        .ok_or((0, ReasonCode::InvalidArgument))?;
+
+    let total = packet
+        .get_uint(VarID::XferTotalSize)
+        .ok_or((0, ReasonCode::InvalidArgument))? as usize;
+
+    // Each kind of payload has its own limit too
+    if kind == XferKind::Cav && total > ctx.config.cav.max_definition_size {
+        return Err((0, ReasonCode::TooManyBytes));
+    }
+
+    let offset = packet.get_uint(VarID::XferOffset).unwrap_or(0) as usize;
+
+    let chunk = Chunk {
+        id,
+        kind,
+        total,
+        offset,
+        data,
+    };
+    let (received, completed) = ctx.transfers.receive(owner, &chunk, &ctx.config.xfer)?;
+
+    if let Some(upload) = completed {
+        match upload.kind {
+            XferKind::Cav => {
+                let citizen_id = store_cav_definition(client, packet, upload.data, ctx.database)
+                    .map_err(|rc| (received, rc))?;
+                notify_cav_changed(citizen_id, ctx.database, ctx.client_manager);
+            }
+        }
+    }
+
+    Ok(received)
+}
+
+fn try_download(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+) -> Result<Vec<u8>, ReasonCode> {
+    if client.info().entity.is_none() {
+        return Err(ReasonCode::NotLoggedIn);
+    }
+
+    let kind = packet
+        .get_uint(VarID::XferKind)
+        .and_then(XferKind::from_u32)
+        .ok_or(ReasonCode::InvalidArgument)?;
+
+    match kind {
+        XferKind::Cav => {
+            let citizen_id = match packet.get_uint(VarID::CAVCitizen) {
+                Some(id) => id,
+                None => match &client.info().entity {
+                    Some(Entity::Player(info)) => {
+                        info.citizen_id.ok_or(ReasonCode::NoSuchCitizen)?
+                    }
+                    _ => return Err(ReasonCode::NoSuchCitizen),
+                },
+            };
+
+            let citizen = database
+                .citizen_by_number(citizen_id)
+                .map_err(|_| ReasonCode::NoSuchCitizen)?;
+
+            if citizen.cav_enabled == 0 {
+                return Err(ReasonCode::NoSuchCav);
+            }
+
+            let template = packet
+                .get_uint(VarID::CAVTemplate)
+                .unwrap_or(citizen.cav_template);
+
+            Ok(database.cav_get(citizen_id, template)?.definition)
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Per-connection packet rate limiting
+use std::time::Instant;
+
+use aw_core::{AWPacket, PacketType};
+
+use crate::{client::Client, config::RateLimitConfig};
+
+use super::{HandlerContext, HookAction, PacketHook};
+
+/// Packets which touch the database or send large responses.
+const EXPENSIVE_PACKETS: &[PacketType] = &[
+    PacketType::Login,
+    PacketType::UserList,
+    PacketType::CitizenLookupByName,
+    PacketType::CitizenLookupByNumber,
+    PacketType::CitizenNext,
+    PacketType::CitizenPrev,
+    PacketType::LicenseByName,
+    PacketType::ContactAdd,
+    PacketType::TelegramSend,
+    PacketType::Botgram,
+    PacketType::WorldLookup,
+    PacketType::Immigrate,
+];
+
+/// A token bucket which refills continuously up to its capacity.
+#[derive(Debug)]
+pub struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// Packets dropped since the connection was made
+    dropped: u32,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f64) -> Self {
+        Self {
+            tokens: capacity,
+            last_refill: Instant::now(),
+            dropped: 0,
+        }
+    }
+
+    /// Take `cost` tokens if there are enough, after refilling for the time passed.
+    fn try_take(&mut self, cost: f64, rate: f64, capacity: f64) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.last_refill = now;
+
+        if self.tokens >= cost {
+            self.tokens -= cost;
+            true
+        } else {
+            self.dropped += 1;
+            false
+        }
+    }
+}
+
+/// Drops packets from connections which send them faster than the configured rate,
+/// and disconnects those which keep it up. World servers are not limited.
+pub struct RateLimitHook;
+
+impl PacketHook for RateLimitHook {
+    fn before(&self, ctx: &HandlerContext, client: &Client, packet: &AWPacket) -> HookAction {
+        let config: &RateLimitConfig = &ctx.config.rate_limit;
+        if !config.enabled || client.info().entity.as_ref().is_some_and(|e| e.is_world()) {
+            return HookAction::Continue;
+        }
+
+        let cost = if EXPENSIVE_PACKETS.contains(&packet.get_opcode()) {
+            config.expensive_cost
+        } else {
+            1.0
+        };
+
+        let mut bucket = client.packet_budget.borrow_mut();
+        let bucket = bucket.get_or_insert_with(|| TokenBucket::new(config.burst));
+        if bucket.try_take(cost, config.packets_per_sec, config.burst) {
+            return HookAction::Continue;
+        }
+
+        if config.disconnect_after != 0 && bucket.dropped >= config.disconnect_after {
+            log::warn!(
+                "Disconnecting {} for flooding ({} packets dropped)",
+                client.addr.ip(),
+                bucket.dropped
+            );
+            client.kill();
+        } else {
+            log::debug!(
+                "Throttling {:?} from {}",
+                packet.get_opcode(),
+                client.addr.ip()
+            );
+        }
+
+        HookAction::Drop
+    }
+}
@@ -0,0 +1,105 @@
+//! Dispatch of incoming packets to their handlers
+use std::collections::HashMap;
+
+use crate::{
+    client::{Client, ClientManager},
+    config::Config,
+    database::Database,
+    email::Mailer,
+    events::EventQueue,
+    login_throttle::LoginThrottle,
+    packet_handler::ImmigrationLimit,
+    presence_store::PresenceStore,
+    scripting::Scripts,
+    universe_license::LicenseGenerator,
+    xfer::Transfers,
+};
+use aw_core::{AWPacket, PacketType};
+
+/// Universe state which is made available to every packet handler.
+pub struct HandlerContext<'a> {
+    pub config: &'a Config,
+    pub database: &'a Database,
+    pub client_manager: &'a ClientManager,
+    pub license_generator: &'a LicenseGenerator,
+    pub scripts: &'a Scripts,
+    pub events: &'a EventQueue,
+    pub presence_store: Option<&'a PresenceStore>,
+    pub transfers: &'a Transfers,
+    pub login_throttle: &'a LoginThrottle,
+    pub immigration_limit: &'a ImmigrationLimit,
+    pub mailer: Option<&'a Mailer>,
+}
+
+/// A function which handles one type of packet.
+pub type PacketHandler = fn(&HandlerContext<'_>, &Client, &AWPacket);
+
+/// What should happen to a packet after a hook has seen it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookAction {
+    /// Keep processing the packet normally.
+    Continue,
+    /// Stop processing the packet. Its handler and any later hooks will not run.
+    Drop,
+}
+
+/// Code which runs around every packet handler, such as logging or rate limiting.
+pub trait PacketHook {
+    /// Called before a packet is handled.
+    fn before(&self, _ctx: &HandlerContext, _client: &Client, _packet: &AWPacket) -> HookAction {
+        HookAction::Continue
+    }
+
+    /// Called after a packet has been handled.
+    fn after(&self, _ctx: &HandlerContext, _client: &Client, _packet: &AWPacket) {}
+}
+
+/// Maps packet types to handlers and runs hooks around them.
+#[derive(Default)]
+pub struct PacketRegistry {
+    handlers: HashMap<PacketType, PacketHandler>,
+    hooks: Vec<Box<dyn PacketHook>>,
+    fallback: Option<PacketHandler>,
+}
+
+impl PacketRegistry {
+    /// Set the handler for a packet type, replacing any existing one.
+    pub fn register(&mut self, opcode: PacketType, handler: PacketHandler) {
+        if self.handlers.insert(opcode, handler).is_some() {
+            log::debug!("Replaced handler for {opcode:?}");
+        }
+    }
+
+    /// Add a hook which runs around every packet. Hooks run in the order they were added.
+    pub fn add_hook(&mut self, hook: Box<dyn PacketHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Set the handler used for packets which have no registered handler.
+    pub fn set_fallback(&mut self, handler: PacketHandler) {
+        self.fallback = Some(handler);
+    }
+
+    /// Run a packet through the hooks and its handler.
+    pub fn dispatch(&self, ctx: &HandlerContext, client: &Client, packet: &AWPacket) {
+        for hook in &self.hooks {
+            if hook.before(ctx, client, packet) == HookAction::Drop {
+                log::debug!("Hook dropped packet {:?}", packet.get_opcode());
+                return;
+            }
+        }
+
+        match self
+            .handlers
+            .get(&packet.get_opcode())
+            .or(self.fallback.as_ref())
+        {
+            Some(handler) => handler(ctx, client, packet),
+            None => log::info!("Unhandled packet {packet:?}"),
+        }
+
+        for hook in &self.hooks {
+            hook.after(ctx, client, packet);
+        }
+    }
+}
@@ -2,7 +2,9 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
     client::{Client, ClientManager, Entity},
+    config::UniverseConfig,
     database::{attrib::Attribute, license::LicenseQuery, AttribDB, Database, LicenseDB},
+    events::{EventQueue, UniverseEvent},
     world::{World, WorldRating, WorldStatus},
 };
 use aw_core::{AWPacket, AWPacketVar, PacketType, ReasonCode, VarID};
@@ -13,6 +15,8 @@ pub fn world_start(
     packet: &AWPacket,
     database: &Database,
     client_manager: &ClientManager,
+    config: &UniverseConfig,
+    events: &EventQueue,
 ) {
     let (world_build, world_port) = match &client.info().entity {
         Some(Entity::WorldServer(info)) => (info.build, info.server_port),
@@ -55,8 +59,13 @@ pub fn world_start(
         }
     };
 
-    // Don't let clients start a world twice
-    if client_manager.get_world_by_name(&lic.name).is_some() {
+    // Don't let clients start a world twice, unless it may run on several servers
+    let running_here = match &client.info().entity {
+        Some(Entity::WorldServer(info)) => info.get_world(&lic.name).is_some(),
+        _ => false,
+    };
+    let running_elsewhere = client_manager.get_world_by_name(&lic.name).is_some();
+    if running_here || (running_elsewhere && !config.allow_world_replicas) {
         p.add_int(VarID::ReasonCode, ReasonCode::WorldAlreadyStarted as i32);
         client.connection.send(p);
         return;
@@ -90,11 +99,24 @@ pub fn world_start(
 
     client.connection.send(p);
 
+    events.publish(UniverseEvent::WorldStart {
+        ip: client.addr.ip(),
+        name: new_world.name.clone(),
+    });
+
     // Send update about new world to all players
-    World::send_update_to_all(&new_world, client_manager);
+    let listing = client_manager
+        .get_world_listing(&new_world.name)
+        .unwrap_or(new_world);
+    World::send_update_to_all(&listing, client_manager);
 }
 
-pub fn world_stop(client: &Client, packet: &AWPacket, client_manager: &ClientManager) {
+pub fn world_stop(
+    client: &Client,
+    packet: &AWPacket,
+    client_manager: &ClientManager,
+    events: &EventQueue,
+) {
     let world_name = match packet.get_string(VarID::WorldStartWorldName) {
         Some(x) => x,
         None => return,
@@ -127,10 +149,19 @@ pub fn world_stop(client: &Client, packet: &AWPacket, client_manager: &ClientMan
         false => ReasonCode::NoSuchWorld,
     };
 
-    // Remove world from clients' world list
+    // Remove world from clients' world list, unless it is still running elsewhere
     if let Some(mut removed_world) = removed_world {
-        removed_world.status = WorldStatus::Hidden;
-        World::send_update_to_all(&removed_world, client_manager);
+        events.publish(UniverseEvent::WorldStop {
+            ip: client.addr.ip(),
+            name: removed_world.name.clone(),
+        });
+        match client_manager.get_world_listing(&removed_world.name) {
+            Some(listing) => World::send_update_to_all(&listing, client_manager),
+            None => {
+                removed_world.status = WorldStatus::Hidden;
+                World::send_update_to_all(&removed_world, client_manager);
+            }
+        }
     }
 
     let mut p = AWPacket::new(PacketType::WorldStop);
@@ -230,5 +261,8 @@ pub fn world_stats_update(client: &Client, packet: &AWPacket, client_manager: &C
         return;
     };
 
-    World::send_update_to_all(&world, client_manager);
+    let listing = client_manager
+        .get_world_listing(&world.name)
+        .unwrap_or(world);
+    World::send_update_to_all(&listing, client_manager);
 }
@@ -2,8 +2,10 @@ use crate::{
     client::{Client, ClientManager, Entity},
     database::Database,
     packet_handler::update_contacts_of_user,
+    player::PlayerInfo,
 };
 use aw_core::{AWPacket, PacketType, ReasonCode, VarID};
+use std::net::SocketAddr;
 
 pub fn identify(
     client: &Client,
@@ -13,42 +15,24 @@ pub fn identify(
 ) {
     let mut p = AWPacket::new(PacketType::Identify);
 
-    let world_name = match packet.get_string(VarID::WorldStartWorldName) {
-        Some(x) => x,
-        None => {
-            log::info!("Failed to identify player because no world name was provided");
-            return;
-        }
-    };
-
-    let nonce = match packet.get_data(VarID::WorldUserNonce) {
-        Some(x) => x,
-        None => {
-            log::info!("Failed to identify player because no user nonce was provided");
-            return;
-        }
-    };
-
-    let session_id = match packet.get_int(VarID::SessionID) {
-        Some(x) => x,
-        None => {
-            log::info!("Failed to identify player because no session id was provided");
-            return;
+    let (world_name, nonce, session_id, player_ip, player_port) = match (
+        packet.get_string(VarID::WorldStartWorldName),
+        packet.get_data(VarID::WorldUserNonce),
+        packet.get_int(VarID::SessionID),
+        packet.get_uint(VarID::IdentifyUserIP),
+        packet.get_int(VarID::PlayerPort),
+    ) {
+        (Some(world_name), Some(nonce), Some(session_id), Some(player_ip), Some(player_port)) => {
+            (world_name, nonce, session_id, player_ip, player_port)
         }
-    };
-
-    let player_ip = match packet.get_uint(VarID::IdentifyUserIP) {
-        Some(x) => x,
-        None => {
-            log::info!("Failed to identify player because no user ip was provided");
-            return;
-        }
-    };
-
-    let player_port = match packet.get_int(VarID::PlayerPort) {
-        Some(x) => x,
-        None => {
-            log::info!("Failed to identify player because no port was provided");
+        _ => {
+            log::info!("Failed to identify player because the request was incomplete");
+            // The world server still needs an answer, or the user would wait on it forever
+            if let Some(session_id) = packet.get_int(VarID::SessionID) {
+                p.add_int(VarID::SessionID, session_id);
+            }
+            p.add_int(VarID::ReasonCode, ReasonCode::InvalidRequest as i32);
+            client.connection.send(p);
             return;
         }
     };
@@ -60,6 +44,9 @@ pub fn identify(
                 log::info!(
                     "Failed to identify player because the world server does not own the world"
                 );
+                p.add_int(VarID::SessionID, session_id);
+                p.add_int(VarID::ReasonCode, ReasonCode::NoSuchWorld as i32);
+                client.connection.send(p);
                 return;
             }
         },
@@ -67,12 +54,37 @@ pub fn identify(
     };
 
     let mut rc = ReasonCode::NoSuchSession;
-    let mut changed_cit_id: Option<u32> = None;
+    let mut changed_player: Option<PlayerInfo> = None;
+
+    // Don't count the user against the limit if they are already in the world
+    let mut tracked_users = client_manager.count_players_in_replica(&world);
+    if let Some(user_client) = client_manager.get_client_by_session_id(session_id as u16) {
+        if let Some(Entity::Player(user_ent)) = &user_client.info().entity {
+            if let Some(user_world) = &user_ent.world {
+                if user_world.eq_ignore_ascii_case(&world.name)
+                    && user_ent.world_address == Some(SocketAddr::new(world.ip, world.port))
+                {
+                    tracked_users = tracked_users.saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    if world.is_full(tracked_users) {
+        log::info!(
+            "Refusing to identify session {session_id} because {} is full",
+            world.name
+        );
+        p.add_int(VarID::SessionID, session_id);
+        p.add_int(VarID::ReasonCode, ReasonCode::WorldFull as i32);
+        client.connection.send(p);
+        return;
+    }
 
     if let Some(user_client) = client_manager.get_client_by_session_id(session_id as u16) {
         if let Some(Entity::Player(user_ent)) = &mut user_client.info_mut().entity {
             if let Some(user_nonce) = user_ent.nonce {
-                if user_nonce.to_vec() == nonce {
+                if user_nonce[..] == nonce[..] {
                     // Not currently checking IP address or port
                     p.add_string(VarID::WorldStartWorldName, world_name);
                     p.add_int(VarID::SessionID, session_id);
@@ -83,9 +95,10 @@ pub fn identify(
                     p.add_string(VarID::LoginUsername, user_ent.username.clone());
                     p.add_uint(VarID::PrivilegeUserID, user_ent.effective_privilege());
 
-                    user_ent.world = Some(world.name);
+                    user_ent.world = Some(world.name.clone());
+                    user_ent.world_address = Some(SocketAddr::new(world.ip, world.port));
 
-                    changed_cit_id = user_ent.citizen_id;
+                    changed_player = Some(user_ent.clone());
 
                     rc = ReasonCode::Success;
                 }
@@ -93,12 +106,21 @@ pub fn identify(
         }
     }
 
+    if rc != ReasonCode::Success {
+        log::info!("Could not identify session {session_id} for {}", world.name);
+        p.add_int(VarID::SessionID, session_id);
+    }
     p.add_int(VarID::ReasonCode, rc as i32);
 
     client.connection.send(p);
 
-    if let Some(citizen_id) = changed_cit_id {
-        // Update the user's friends to tell them this user is in a new world
-        update_contacts_of_user(citizen_id, database, client_manager);
+    if let Some(player) = changed_player {
+        // Show the user's new world in everyone's user list
+        PlayerInfo::send_update_to_all(&player, client_manager, database);
+
+        if let Some(citizen_id) = player.citizen_id {
+            // Update the user's friends to tell them this user is in a new world
+            update_contacts_of_user(citizen_id, database, client_manager);
+        }
     }
 }
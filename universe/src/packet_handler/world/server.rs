@@ -1,33 +1,153 @@
 use crate::{
-    client::{Client, ClientType, Entity},
+    client::{Client, ClientManager, ClientType, Entity},
+    config::UniverseConfig,
+    database::{Database, LicenseDB},
+    packet_handler::send_console_message,
     world::{WorldServerInfo, WorldStatus},
 };
-use aw_core::{AWPacket, VarID};
+use aw_core::{AWPacket, PacketType, ReasonCode, VarID};
 
-pub fn world_server_start(client: &Client, packet: &AWPacket) {
+pub fn world_server_start(
+    client: &Client,
+    packet: &AWPacket,
+    config: &UniverseConfig,
+    client_manager: &ClientManager,
+) {
+    register_world_server(client, packet, config, client_manager).ok();
+}
+
+/// Handle a world server authenticating with the universe. This registers the
+/// server the same way as WorldServerStart once it proves it holds a world
+/// license, and tells it whether it was accepted.
+pub fn server_login(
+    client: &Client,
+    packet: &AWPacket,
+    config: &UniverseConfig,
+    database: &Database,
+    client_manager: &ClientManager,
+) {
+    let rc = match check_license(client, packet, database)
+        .and_then(|_| register_world_server(client, packet, config, client_manager))
+    {
+        Ok(()) => ReasonCode::Success,
+        Err(reason) => reason,
+    };
+
+    let mut response = AWPacket::new(PacketType::ServerLogin);
+    response.add_int(VarID::ReasonCode, rc as i32);
+    client.connection.send(response);
+}
+
+/// Check the name and password of one of the world licenses the server hosts.
+fn check_license(
+    client: &Client,
+    packet: &AWPacket,
+    database: &Database,
+) -> Result<(), ReasonCode> {
+    let name = packet
+        .get_string(VarID::WorldStartWorldName)
+        .ok_or(ReasonCode::InvalidWorld)?;
+    let password = packet
+        .get_string(VarID::WorldLicensePassword)
+        .ok_or(ReasonCode::InvalidPassword)?;
+
+    let license = database
+        .license_by_name(&name)
+        .map_err(|_| ReasonCode::InvalidWorld)?;
+
+    if license.password != password {
+        log::warn!(
+            "World server {} gave the wrong password for {name}.",
+            client.addr.ip()
+        );
+        client.kill();
+        return Err(ReasonCode::InvalidPassword);
+    }
+
+    Ok(())
+}
+
+/// Make a client into a world server, after which it may start worlds.
+fn register_world_server(
+    client: &Client,
+    packet: &AWPacket,
+    config: &UniverseConfig,
+    client_manager: &ClientManager,
+) -> Result<(), ReasonCode> {
     if let Some(client_type) = client.info().client_type {
         log::warn!(
             "A client who already has type {:?} tried to start a world server.",
             client_type
         );
-        return;
+        return Err(ReasonCode::AlreadyStarted);
+    }
+
+    if !config.world_server_allowed(client.addr.ip()) {
+        log::warn!(
+            "Rejected world server from {} because it is not in the allowlist.",
+            client.addr.ip()
+        );
+        client.kill();
+        return Err(ReasonCode::Unauthorized);
     }
 
-    let _browser_version = packet.get_int(VarID::BrowserVersion);
-    let world_build = packet.get_int(VarID::WorldBuild);
-    let world_port = packet.get_int(VarID::WorldPort);
+    let browser_version = packet.get_int(VarID::BrowserVersion);
+    let world_build = packet
+        .get_int(VarID::WorldBuild)
+        .ok_or(ReasonCode::InvalidRequest)?;
+    let world_port = packet.get_int(VarID::WorldPort).ok_or(ReasonCode::NoPort)?;
 
-    if let (Some(world_build), Some(world_port)) = (world_build, world_port) {
-        let client_entity = Entity::WorldServer(WorldServerInfo {
-            build: world_build,
-            server_port: world_port as u16,
-            worlds: Vec::new(),
-        });
+    if config.world_server_outdated(world_build) {
+        warn_outdated_world_server(client, world_build, config, client_manager);
+        if config.refuse_outdated_world_servers {
+            client.kill();
+            return Err(ReasonCode::ServerOutdated);
+        }
+    }
+
+    let client_entity = Entity::WorldServer(WorldServerInfo {
+        build: world_build,
+        version: browser_version,
+        server_port: world_port as u16,
+        worlds: Vec::new(),
+    });
+
+    client.info_mut().client_type = Some(ClientType::World);
+    client.info_mut().entity = Some(client_entity);
+
+    log::info!(
+        "World server {} connected (build {world_build}).",
+        client.addr.ip()
+    );
+
+    Ok(())
+}
 
-        client.info_mut().client_type = Some(ClientType::World);
-        client.info_mut().entity = Some(client_entity);
+/// Tell the log and any online admins that an old world server is connecting.
+fn warn_outdated_world_server(
+    client: &Client,
+    world_build: i32,
+    config: &UniverseConfig,
+    client_manager: &ClientManager,
+) {
+    let action = if config.refuse_outdated_world_servers {
+        "refused"
+    } else {
+        "allowed"
+    };
+    let message = format!(
+        "World server {} is running build {world_build}, older than the minimum of {}, and was {action}.",
+        client.addr.ip(),
+        config.minimum_world_server_build
+    );
+    log::warn!("{message}");
 
-        log::info!("World server {} connected.", client.addr.ip());
+    for admin in client_manager
+        .clients()
+        .iter()
+        .filter(|c| c.has_admin_permissions())
+    {
+        send_console_message(admin, &message);
     }
 }
 
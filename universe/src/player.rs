@@ -1,15 +1,21 @@
 use std::{
-    net::IpAddr,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use aw_core::{AWPacket, AWPacketGroup, PacketType, VarID};
 
 use crate::{
     client::{ClientManager, Entity},
+    database::{contact::ContactOptions, group::GroupRights, ContactDB, Database},
+    packet_handler::ip_to_num,
     Client,
 };
 
+/// User list state of a player who is away, numbered the same as in contact lists
+const USER_LIST_STATE_AFK: u8 = 3;
+
 #[derive(Debug, Clone, Copy)]
 pub enum PlayerState {
     Offline = 0,
@@ -25,9 +31,23 @@ pub struct PlayerInfo {
     pub username: String,
     pub nonce: Option<[u8; 255]>, // AW4 worlds allow 256 bytes, AW5 worlds allow 255 bytes
     pub world: Option<String>,
+    /// Address of the world server the player was identified by
+    pub world_address: Option<SocketAddr>,
     pub ip: IpAddr,
     pub state: PlayerState,
     pub afk: bool,
+    /// Do not disturb mode, which declines join requests automatically
+    pub dnd: bool,
+    /// Citizens who have asked to join this player and are waiting for a reply
+    pub join_requests: Vec<u32>,
+    /// Rights granted through the groups the citizen belongs to
+    pub group_rights: GroupRights,
+    /// When the player logged in
+    pub session_start: Instant,
+    /// Whether the player has been told their session is about to end
+    pub session_warned: bool,
+    /// Whether the citizen is on a trial, which may limit how long they stay
+    pub trial: bool,
 }
 
 impl PlayerInfo {
@@ -41,6 +61,14 @@ impl PlayerInfo {
         }
     }
 
+    /// State shown for the player in other users' lists.
+    fn list_state(&self) -> u8 {
+        match (self.state, self.afk) {
+            (PlayerState::Online, true) => USER_LIST_STATE_AFK,
+            (state, _) => state as u8,
+        }
+    }
+
     pub fn make_list_packet(&self, to_admin: bool) -> AWPacket {
         let mut p = AWPacket::new(PacketType::UserList);
 
@@ -48,9 +76,8 @@ impl PlayerInfo {
         // p.add_string(VarID::UserList178, format!("178"));
         p.add_string(VarID::UserListName, self.username.clone());
 
-        // ID is supposed to be an ID relating to the user list so it can
-        // be updated when a user changes state, but using the session id
-        // for this is convenient for now.
+        // Entries are updated in place by ID, so it has to stay the same for the
+        // whole session. Session IDs do, and are unique among those online.
         p.add_int(VarID::UserListID, self.session_id.into());
 
         p.add_uint(VarID::UserListCitizenID, self.citizen_id.unwrap_or(0));
@@ -58,7 +85,7 @@ impl PlayerInfo {
         if to_admin {
             p.add_uint(VarID::UserListAddress, ip_to_num(self.ip));
         }
-        p.add_byte(VarID::UserListState, self.state as u8);
+        p.add_byte(VarID::UserListState, self.list_state());
 
         if let Some(world_name) = &self.world {
             p.add_string(VarID::UserListWorldName, world_name.clone());
@@ -67,17 +94,16 @@ impl PlayerInfo {
         p
     }
 
-    fn make_packet_groups(players: &[PlayerInfo], to_admin: bool) -> Vec<AWPacketGroup> {
+    fn list_entry(&self, to_admin: bool) -> (u16, AWPacket) {
+        (self.session_id, self.make_list_packet(to_admin))
+    }
+
+    fn make_packet_groups(player_packets: Vec<AWPacket>) -> Vec<AWPacketGroup> {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("Current time is before the unix epoch.")
             .as_secs();
 
-        let player_packets = players
-            .iter()
-            .map(|x| x.make_list_packet(to_admin))
-            .collect::<Vec<AWPacket>>();
-
         // Group packets into larger transmissions for efficiency
         let mut groups: Vec<AWPacketGroup> = Vec::new();
         let mut group = AWPacketGroup::new();
@@ -112,55 +138,245 @@ impl PlayerInfo {
         groups
     }
 
-    pub fn send_updates_to_some(players: &[PlayerInfo], clients: &[Client]) {
-        let groups_normal = PlayerInfo::make_packet_groups(players, false);
-        let groups_admin = PlayerInfo::make_packet_groups(players, true);
+    pub fn send_updates_to_some(players: &[PlayerInfo], clients: &[Client], database: &Database) {
+        let entries_normal: Vec<(u16, AWPacket)> =
+            players.iter().map(|x| x.list_entry(false)).collect();
+        let entries_admin: Vec<(u16, AWPacket)> =
+            players.iter().map(|x| x.list_entry(true)).collect();
+        let privacy = StatusPrivacy::load(players, database);
 
         // Send update to target players
         for client in clients {
-            if let Some(Entity::Player(_)) = client.info().entity {
-                // Only send the groups with IP addresses to admins.
-                if client.has_admin_permissions() {
-                    for group in &groups_admin {
-                        client.connection.send_group(group.clone());
-                    }
-                } else {
-                    for group in &groups_normal {
-                        client.connection.send_group(group.clone());
-                    }
-                }
+            let viewer = match &client.info().entity {
+                Some(Entity::Player(info)) => info.citizen_id,
+                _ => continue,
+            };
+
+            // Only send the entries with IP addresses to admins.
+            let entries: Vec<&(u16, AWPacket)> = if client.has_admin_permissions() {
+                entries_admin.iter().collect()
+            } else {
+                entries_normal
+                    .iter()
+                    .zip(players)
+                    .filter(|(_, player)| privacy.allows(player, viewer))
+                    .map(|(entry, _)| entry)
+                    .collect()
+            };
+            if entries.is_empty() {
+                continue;
+            }
+
+            let mut view = client.user_list.borrow_mut();
+            for (id, packet) in &entries {
+                view.record(*id, packet);
+            }
+
+            let packets = entries.into_iter().map(|(_, packet)| packet.clone());
+            for group in PlayerInfo::make_packet_groups(packets.collect()) {
+                client.connection.send_group(group);
             }
         }
     }
 
-    pub fn send_updates_to_all(players: &[PlayerInfo], client_manager: &ClientManager) {
-        PlayerInfo::send_updates_to_some(players, client_manager.clients());
+    pub fn send_updates_to_all(
+        players: &[PlayerInfo],
+        client_manager: &ClientManager,
+        database: &Database,
+    ) {
+        PlayerInfo::send_updates_to_some(players, client_manager.clients(), database);
     }
 
-    pub fn send_update_to_all(player: &PlayerInfo, client_manager: &ClientManager) {
-        PlayerInfo::send_updates_to_all(&[player.clone()], client_manager);
+    pub fn send_update_to_all(
+        player: &PlayerInfo,
+        client_manager: &ClientManager,
+        database: &Database,
+    ) {
+        PlayerInfo::send_updates_to_all(&[player.clone()], client_manager, database);
     }
 
-    pub fn send_updates_to_one(players: &[PlayerInfo], target_client: &Client) {
-        let groups = if target_client.has_admin_permissions() {
-            PlayerInfo::make_packet_groups(players, true)
+    /// Send a client the entries of the user list which have changed since it was
+    /// last sent them, so a client which has just logged in gets the whole list.
+    pub fn send_updates_to_one(
+        players: &[PlayerInfo],
+        target_client: &Client,
+        database: &Database,
+    ) {
+        let current = if target_client.has_admin_permissions() {
+            players.iter().map(|x| x.list_entry(true)).collect()
         } else {
-            PlayerInfo::make_packet_groups(players, false)
+            let viewer = match &target_client.info().entity {
+                Some(Entity::Player(info)) => info.citizen_id,
+                _ => None,
+            };
+            let privacy = StatusPrivacy::load(players, database);
+            players
+                .iter()
+                .filter(|player| privacy.allows(player, viewer))
+                .map(|x| x.list_entry(false))
+                .collect()
         };
 
-        for group in groups {
-            target_client.connection.send_group(group.clone());
+        let changes = target_client.user_list.borrow_mut().changes(current);
+        for group in PlayerInfo::make_packet_groups(changes) {
+            target_client.connection.send_group(group);
         }
     }
 }
 
-fn ip_to_num(ip: IpAddr) -> u32 {
-    let mut res: u32 = 0;
-    if let std::net::IpAddr::V4(v4) = ip {
-        for octet in v4.octets().iter().rev() {
-            res <<= 8;
-            res |= *octet as u32;
+/// The user list entries a client has been sent, by ID, so that when it asks for
+/// the list again it only has to be sent what has changed.
+#[derive(Debug, Default)]
+pub struct UserListView {
+    entries: HashMap<u16, AWPacket>,
+}
+
+impl UserListView {
+    /// Note an entry sent to the client. Offline entries leave its list.
+    fn record(&mut self, id: u16, packet: &AWPacket) {
+        if packet.get_byte(VarID::UserListState) == Some(PlayerState::Offline as u8) {
+            self.entries.remove(&id);
+        } else {
+            self.entries.insert(id, packet.clone());
+        }
+    }
+
+    /// The entries of `current` which differ from those last sent, along with
+    /// offline entries for any sent before which are no longer in it.
+    fn changes(&mut self, current: Vec<(u16, AWPacket)>) -> Vec<AWPacket> {
+        let ids: HashSet<u16> = current.iter().map(|(id, _)| *id).collect();
+        let gone: Vec<u16> = self
+            .entries
+            .keys()
+            .filter(|id| !ids.contains(id))
+            .copied()
+            .collect();
+
+        let mut changes = Vec::new();
+        for id in gone {
+            if let Some(entry) = self.entries.remove(&id) {
+                changes.push(offline_entry(&entry));
+            }
         }
+
+        for (id, packet) in current {
+            if self.entries.get(&id) != Some(&packet) {
+                self.record(id, &packet);
+                changes.push(packet);
+            }
+        }
+
+        changes
+    }
+}
+
+/// A copy of a user list entry showing the player as offline.
+fn offline_entry(entry: &AWPacket) -> AWPacket {
+    let mut p = AWPacket::new(PacketType::UserList);
+    for var in entry.get_vars() {
+        if var.get_var_id() != VarID::UserListState {
+            p.add_var(var.clone());
+        }
+    }
+    p.add_byte(VarID::UserListState, PlayerState::Offline as u8);
+    p
+}
+
+/// The contact options of the citizens in a user list update, so players who
+/// hide their online status can be left out of the lists sent to others.
+struct StatusPrivacy {
+    /// Default options and per-contact options of each citizen
+    options: HashMap<u32, (ContactOptions, HashMap<u32, ContactOptions>)>,
+}
+
+impl StatusPrivacy {
+    fn load(players: &[PlayerInfo], database: &Database) -> Self {
+        let mut options = HashMap::new();
+
+        for citizen_id in players.iter().filter_map(|player| player.citizen_id) {
+            if options.contains_key(&citizen_id) {
+                continue;
+            }
+
+            let mut default = ContactOptions::default();
+            let mut contacts = HashMap::new();
+            for contact in database.contact_get_all(citizen_id) {
+                if contact.contact == 0 {
+                    default = contact.options;
+                } else {
+                    contacts.insert(contact.contact, contact.options);
+                }
+            }
+            options.insert(citizen_id, (default, contacts));
+        }
+
+        Self { options }
+    }
+
+    /// Whether a player lets a viewer (a citizen, or None for tourists) see their status.
+    fn allows(&self, player: &PlayerInfo, viewer: Option<u32>) -> bool {
+        let citizen_id = match player.citizen_id {
+            Some(x) => x,
+            None => return true,
+        };
+        if viewer == Some(citizen_id) {
+            return true;
+        }
+
+        match self.options.get(&citizen_id) {
+            Some((default, contacts)) => viewer
+                .and_then(|viewer| contacts.get(&viewer))
+                .unwrap_or(default)
+                .is_status_allowed(),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, state: PlayerState) -> AWPacket {
+        let mut p = AWPacket::new(PacketType::UserList);
+        p.add_string(VarID::UserListName, name.to_string());
+        p.add_byte(VarID::UserListState, state as u8);
+        p
+    }
+
+    #[test]
+    pub fn test_user_list_changes() {
+        let mut view = UserListView::default();
+        let alice = entry("Alice", PlayerState::Online);
+        let bob = entry("Bob", PlayerState::Online);
+
+        // Everything is new at first, and nothing has changed straight after
+        let changes = view.changes(vec![(1, alice.clone()), (2, bob.clone())]);
+        assert_eq!(changes, vec![alice.clone(), bob.clone()]);
+        assert!(view
+            .changes(vec![(1, alice.clone()), (2, bob.clone())])
+            .is_empty());
+
+        // Entries pushed to the client do not need sending again
+        let mut bob_away = entry("Bob", PlayerState::Online);
+        bob_away.add_string(VarID::UserListWorldName, "AW".to_string());
+        view.record(2, &bob_away);
+        assert!(view
+            .changes(vec![(1, alice.clone()), (2, bob_away.clone())])
+            .is_empty());
+
+        // Players who have gone are sent as offline
+        let changes = view.changes(vec![(2, bob_away.clone())]);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].get_string(VarID::UserListName).unwrap(), "Alice");
+        assert_eq!(
+            changes[0].get_byte(VarID::UserListState),
+            Some(PlayerState::Offline as u8)
+        );
+        assert!(view.changes(vec![(2, bob_away.clone())]).is_empty());
+
+        // Offline updates already pushed are not repeated
+        view.record(2, &entry("Bob", PlayerState::Offline));
+        assert!(view.changes(Vec::new()).is_empty());
     }
-    res
 }
@@ -0,0 +1,266 @@
+//! A record of who is online shared through Redis, so several universe
+//! processes (or web services alongside the universe) see the same picture.
+//!
+//! Each process stores a snapshot of its own players under
+//! `<prefix>:presence:<instance>` which expires unless refreshed, and lists
+//! itself in the `<prefix>:instances` set.
+//!
+//! Redis is only talked to from a background thread, so a slow or unreachable
+//! store never stalls the main loop.
+use std::time::{Duration, Instant};
+#[cfg(feature = "redis-presence")]
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{ClientManager, Entity},
+    config::{PresenceConfig, UniverseConfig},
+    events::{TimedEvent, UniverseEvent},
+};
+
+/// One player as seen by the shared store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceEntry {
+    pub name: String,
+    pub citizen_id: Option<u32>,
+    pub world: Option<String>,
+    pub afk: bool,
+}
+
+pub struct PresenceStore {
+    /// Snapshots of this process's players for the worker to publish
+    #[cfg(feature = "redis-presence")]
+    snapshots: Sender<Vec<PresenceEntry>>,
+    /// Players on other processes, as fetched by the worker
+    #[cfg(feature = "redis-presence")]
+    fetched: Receiver<Vec<PresenceEntry>>,
+    refresh: Duration,
+    last_sync: Option<Instant>,
+    changed: bool,
+    /// Players connected to other universe processes
+    remote: Vec<PresenceEntry>,
+}
+
+impl PresenceStore {
+    /// Connect to the store if it is enabled.
+    pub fn connect(config: &PresenceConfig, universe: &UniverseConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let instance = if config.instance_id.is_empty() {
+            universe.advertised_address().to_string()
+        } else {
+            config.instance_id.clone()
+        };
+        let refresh = Duration::from_secs(config.refresh_secs.max(1));
+
+        #[cfg(feature = "redis-presence")]
+        {
+            let conn = match redis::Client::open(config.redis_url.as_str())
+                .and_then(|client| client.get_connection())
+            {
+                Ok(x) => x,
+                Err(err) => {
+                    log::error!(
+                        "Could not connect to presence store {}: {err}",
+                        config.redis_url
+                    );
+                    return None;
+                }
+            };
+            log::info!(
+                "Sharing presence through {} as {instance}",
+                config.redis_url
+            );
+
+            let (snapshots, worker_snapshots) = mpsc::channel();
+            let (worker_fetched, fetched) = mpsc::channel();
+            let worker = Worker {
+                conn,
+                instance,
+                prefix: config.key_prefix.clone(),
+                // Snapshots outlive a few missed refreshes before they are forgotten
+                ttl: refresh.as_secs() * 3,
+            };
+            thread::spawn(move || worker.run(worker_snapshots, worker_fetched));
+
+            Some(Self {
+                snapshots,
+                fetched,
+                refresh,
+                last_sync: None,
+                changed: true,
+                remote: Vec::new(),
+            })
+        }
+
+        #[cfg(not(feature = "redis-presence"))]
+        {
+            // Only this process's players are known, so nobody is ever found remotely
+            log::warn!(
+                "Not sharing presence as {instance}: built without the redis-presence feature"
+            );
+            Some(Self {
+                refresh,
+                last_sync: None,
+                changed: true,
+                remote: Vec::new(),
+            })
+        }
+    }
+
+    /// Note any events which change who is online, so the store is updated promptly.
+    pub fn observe_events(&mut self, events: &[TimedEvent]) {
+        self.changed |= events.iter().any(|event| {
+            matches!(
+                event.event,
+                UniverseEvent::Login { .. } | UniverseEvent::Disconnect { .. }
+            )
+        });
+    }
+
+    /// Take in what the worker last fetched, and hand it this process's players
+    /// to publish if it is time to.
+    pub fn sync(&mut self, client_manager: &ClientManager) {
+        self.receive();
+
+        let due = self.last_sync.is_none_or(|x| x.elapsed() >= self.refresh);
+        if !self.changed && !due {
+            return;
+        }
+        self.changed = false;
+        self.last_sync = Some(Instant::now());
+
+        let local: Vec<PresenceEntry> = client_manager
+            .clients()
+            .iter()
+            .filter_map(|client| match &client.info().entity {
+                Some(Entity::Player(player)) => Some(PresenceEntry {
+                    name: player.username.clone(),
+                    citizen_id: player.citizen_id,
+                    world: player.world.clone(),
+                    afk: player.afk,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        self.publish(local);
+    }
+
+    /// A citizen who is online through another universe process.
+    pub fn find_remote_citizen(&self, citizen_id: u32) -> Option<&PresenceEntry> {
+        self.remote
+            .iter()
+            .find(|entry| entry.citizen_id == Some(citizen_id))
+    }
+
+    #[cfg(feature = "redis-presence")]
+    fn receive(&mut self) {
+        while let Ok(remote) = self.fetched.try_recv() {
+            self.remote = remote;
+        }
+    }
+
+    #[cfg(not(feature = "redis-presence"))]
+    fn receive(&mut self) {}
+
+    #[cfg(feature = "redis-presence")]
+    fn publish(&self, local: Vec<PresenceEntry>) {
+        if self.snapshots.send(local).is_err() {
+            log::warn!("Presence store worker has stopped");
+        }
+    }
+
+    #[cfg(not(feature = "redis-presence"))]
+    fn publish(&self, _local: Vec<PresenceEntry>) {}
+}
+
+/// Talks to Redis on behalf of the main loop.
+#[cfg(feature = "redis-presence")]
+struct Worker {
+    conn: redis::Connection,
+    instance: String,
+    prefix: String,
+    ttl: u64,
+}
+
+#[cfg(feature = "redis-presence")]
+impl Worker {
+    /// Publish each snapshot and send back everyone else's players, until the
+    /// store is dropped.
+    fn run(mut self, snapshots: Receiver<Vec<PresenceEntry>>, fetched: Sender<Vec<PresenceEntry>>) {
+        while let Ok(mut local) = snapshots.recv() {
+            // Only the newest snapshot matters if the store has fallen behind
+            while let Ok(newer) = snapshots.try_recv() {
+                local = newer;
+            }
+
+            match self.exchange(&local) {
+                Ok(remote) => {
+                    if fetched.send(remote).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => log::warn!("Could not update presence store: {err}"),
+            }
+        }
+    }
+
+    fn exchange(&mut self, local: &[PresenceEntry]) -> Result<Vec<PresenceEntry>, String> {
+        let instances_key = format!("{}:instances", self.prefix);
+        let own_key = self.presence_key(&self.instance);
+        let snapshot = serde_json::to_string(local).map_err(|err| err.to_string())?;
+
+        redis::pipe()
+            .cmd("SET")
+            .arg(&own_key)
+            .arg(snapshot)
+            .arg("EX")
+            .arg(self.ttl)
+            .ignore()
+            .cmd("SADD")
+            .arg(&instances_key)
+            .arg(&self.instance)
+            .ignore()
+            .query::<()>(&mut self.conn)
+            .map_err(|err| err.to_string())?;
+
+        let instances: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(&instances_key)
+            .query(&mut self.conn)
+            .map_err(|err| err.to_string())?;
+
+        let mut remote = Vec::new();
+        for instance in instances.iter().filter(|x| **x != self.instance) {
+            let snapshot: Option<String> = redis::cmd("GET")
+                .arg(self.presence_key(instance))
+                .query(&mut self.conn)
+                .map_err(|err| err.to_string())?;
+
+            match snapshot {
+                Some(snapshot) => match serde_json::from_str::<Vec<PresenceEntry>>(&snapshot) {
+                    Ok(entries) => remote.extend(entries),
+                    Err(err) => log::warn!("Bad presence snapshot from {instance}: {err}"),
+                },
+                // The process has stopped refreshing, so it is gone
+                None => redis::cmd("SREM")
+                    .arg(&instances_key)
+                    .arg(instance)
+                    .query::<()>(&mut self.conn)
+                    .map_err(|err| err.to_string())?,
+            }
+        }
+
+        Ok(remote)
+    }
+
+    fn presence_key(&self, instance: &str) -> String {
+        format!("{}:presence:{instance}", self.prefix)
+    }
+}
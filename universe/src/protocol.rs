@@ -0,0 +1,55 @@
+//! Which revision of the protocol a browser speaks.
+//!
+//! The universe was written against 4.1 browsers. Later browsers report a
+//! `BrowserVersion` when logging in; those that don't are treated as 4.x.
+//! 5.1 and 5.2 browsers are supported. Browsers after 5.2 use a different
+//! handshake which is not implemented, so they are only recognised to say why
+//! they cannot log in.
+
+/// Newest 5.1 build known to this universe (the Vortex browser). 5.1 and 5.2
+/// both report version 5, so builds are what tell them apart.
+const LAST_AW51_BUILD: i32 = 1217;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+    #[default]
+    Aw4,
+    Aw51,
+    Aw52,
+    /// 6.x and later
+    Unsupported,
+}
+
+impl ProtocolVersion {
+    /// Work out the protocol from the version and build a browser logs in with.
+    pub fn negotiate(browser_version: Option<i32>, browser_build: Option<i32>) -> Self {
+        match browser_version {
+            None | Some(..=4) => Self::Aw4,
+            Some(5) if browser_build.unwrap_or(0) <= LAST_AW51_BUILD => Self::Aw51,
+            Some(5) => Self::Aw52,
+            Some(_) => Self::Unsupported,
+        }
+    }
+
+    pub fn is_supported(self) -> bool {
+        self != Self::Unsupported
+    }
+
+    /// Whether the browser waits for the universe attributes again once its
+    /// login has been accepted, rather than using those sent on connecting.
+    pub fn expects_attributes_after_login(self) -> bool {
+        self >= Self::Aw52
+    }
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Aw4 => "4.x",
+            Self::Aw51 => "5.1",
+            Self::Aw52 => "5.2",
+            Self::Unsupported => "6.x or later",
+        };
+        f.write_str(name)
+    }
+}
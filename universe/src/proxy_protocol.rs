@@ -0,0 +1,261 @@
+//! Reading PROXY protocol headers, sent by load balancers such as HAProxy and
+//! NGINX to pass on the address of the client they are forwarding.
+
+use std::{
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    time::{Duration, Instant},
+};
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Longest version 1 header allowed, including the trailing CRLF
+const V1_MAX_LEN: usize = 107;
+
+/// Longest header of either version accepted. Version 2 headers may carry extra
+/// TLVs after the addresses, but never this many.
+const MAX_HEADER_LEN: usize = 1024;
+
+/// A connection from a proxy whose header has not all arrived yet.
+pub struct PendingConnection {
+    pub stream: TcpStream,
+    pub peer: SocketAddr,
+    accepted: Instant,
+}
+
+impl PendingConnection {
+    pub fn new(stream: TcpStream, peer: SocketAddr) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            peer,
+            accepted: Instant::now(),
+        })
+    }
+
+    /// Whether the whole header should have arrived by now.
+    pub fn expired(&self, timeout: Duration) -> bool {
+        self.accepted.elapsed() >= timeout
+    }
+
+    /// Take the header off the stream without waiting for it. Returns `None` until
+    /// the whole header has arrived, and then the client address it gives, if any.
+    pub fn poll(&mut self) -> io::Result<Option<Option<SocketAddr>>> {
+        let mut buf = [0u8; MAX_HEADER_LEN];
+        let len = match self.stream.peek(&mut buf) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        match parse_header(&buf[..len])? {
+            Some((client, header_len)) => {
+                // The header has already arrived, so this does not wait
+                self.stream.read_exact(&mut buf[..header_len])?;
+                self.stream.set_nonblocking(false)?;
+                Ok(Some(client))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Parse a version 1 or version 2 PROXY header from the start of `data`.
+/// Returns `None` if more data is needed, or else the original client address
+/// (`None` if the proxy did not supply one, such as for its own health checks)
+/// and the length of the header.
+pub fn parse_header(data: &[u8]) -> io::Result<Option<(Option<SocketAddr>, usize)>> {
+    if data.len() < V2_SIGNATURE.len() {
+        let v1_start = &b"PROXY "[..data.len().min(6)];
+        if V2_SIGNATURE.starts_with(data) || data.starts_with(v1_start) {
+            return Ok(None);
+        }
+        return Err(invalid("missing PROXY header"));
+    }
+
+    if data.starts_with(&V2_SIGNATURE) {
+        parse_v2(data)
+    } else if data.starts_with(b"PROXY ") {
+        parse_v1(data)
+    } else {
+        Err(invalid("missing PROXY header"))
+    }
+}
+
+fn parse_v1(data: &[u8]) -> io::Result<Option<(Option<SocketAddr>, usize)>> {
+    let searched = &data[..data.len().min(V1_MAX_LEN)];
+    let end = match searched.windows(2).position(|x| x == b"\r\n") {
+        Some(end) => end,
+        None if data.len() >= V1_MAX_LEN => return Err(invalid("PROXY header is too long")),
+        None => return Ok(None),
+    };
+
+    let line =
+        std::str::from_utf8(&data[..end]).map_err(|_| invalid("PROXY header is not text"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+
+    let client = match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => None,
+        ["PROXY", "TCP4" | "TCP6", source, _destination, source_port, _destination_port] => {
+            let ip: IpAddr = source
+                .parse()
+                .map_err(|_| invalid("bad source address in PROXY header"))?;
+            let port: u16 = source_port
+                .parse()
+                .map_err(|_| invalid("bad source port in PROXY header"))?;
+            Some(SocketAddr::new(ip, port))
+        }
+        _ => return Err(invalid("malformed PROXY header")),
+    };
+
+    Ok(Some((client, end + 2)))
+}
+
+fn parse_v2(data: &[u8]) -> io::Result<Option<(Option<SocketAddr>, usize)>> {
+    let Some(&[version_command, family, len_high, len_low]) = data.get(12..16) else {
+        return Ok(None);
+    };
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    // The payload always has to be taken in full, so the client's data starts cleanly after it
+    let header_len = 16 + u16::from_be_bytes([len_high, len_low]) as usize;
+    if header_len > MAX_HEADER_LEN {
+        return Err(invalid("PROXY header is too long"));
+    }
+    let Some(payload) = data.get(16..header_len) else {
+        return Ok(None);
+    };
+
+    // LOCAL connections are made by the proxy itself
+    if version_command & 0x0F == 0 {
+        return Ok(Some((None, header_len)));
+    }
+
+    let client = match family >> 4 {
+        // AF_INET
+        1 if payload.len() >= 12 => {
+            let ip = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+            let port = u16::from_be_bytes([payload[8], payload[9]]);
+            Some(SocketAddr::new(IpAddr::V4(ip), port))
+        }
+        // AF_INET6
+        2 if payload.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&payload[..16]);
+            let port = u16::from_be_bytes([payload[32], payload[33]]);
+            Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port))
+        }
+        1 | 2 => return Err(invalid("PROXY header is too short for its addresses")),
+        _ => None,
+    };
+
+    Ok(Some((client, header_len)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v2_header(command: u8, family: u8, payload: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        header.extend_from_slice(payload);
+        header
+    }
+
+    #[test]
+    pub fn test_v1() {
+        let data = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 6670\r\nclient data";
+        let (client, len) = parse_header(data).unwrap().unwrap();
+        assert_eq!(client, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(&data[len..], b"client data");
+
+        let data = b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 6670\r\n";
+        let (client, len) = parse_header(data).unwrap().unwrap();
+        assert_eq!(client, Some("[2001:db8::1]:4000".parse().unwrap()));
+        assert_eq!(len, data.len());
+
+        let (client, _) = parse_header(b"PROXY UNKNOWN\r\n").unwrap().unwrap();
+        assert_eq!(client, None);
+    }
+
+    #[test]
+    pub fn test_v1_incomplete() {
+        let data = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 6670\r\n";
+        for end in 0..data.len() {
+            assert!(parse_header(&data[..end]).unwrap().is_none(), "{end}");
+        }
+    }
+
+    #[test]
+    pub fn test_v1_invalid() {
+        assert!(parse_header(b"GET / HTTP/1.1\r\n").is_err());
+        assert!(parse_header(b"GET").is_err());
+        assert!(parse_header(b"PROXY TCP4 nonsense\r\n").is_err());
+        assert!(parse_header(b"PROXY TCP4 192.0.2.1 198.51.100.1 99999 6670\r\n").is_err());
+
+        let mut long = b"PROXY ".to_vec();
+        long.resize(V1_MAX_LEN, b'1');
+        assert!(parse_header(&long).is_err());
+    }
+
+    #[test]
+    pub fn test_v2() {
+        let mut payload = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        payload.extend_from_slice(&56324u16.to_be_bytes());
+        payload.extend_from_slice(&6670u16.to_be_bytes());
+        let mut data = v2_header(1, 0x11, &payload);
+        let header_len = data.len();
+        data.extend_from_slice(b"client data");
+
+        let (client, len) = parse_header(&data).unwrap().unwrap();
+        assert_eq!(client, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(len, header_len);
+
+        for end in 0..header_len {
+            assert!(parse_header(&data[..end]).unwrap().is_none(), "{end}");
+        }
+    }
+
+    #[test]
+    pub fn test_v2_ipv6() {
+        let source: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let mut payload = source.octets().to_vec();
+        payload.extend_from_slice(&[0; 16]);
+        payload.extend_from_slice(&4000u16.to_be_bytes());
+        payload.extend_from_slice(&6670u16.to_be_bytes());
+
+        let (client, _) = parse_header(&v2_header(1, 0x21, &payload))
+            .unwrap()
+            .unwrap();
+        assert_eq!(client, Some("[2001:db8::1]:4000".parse().unwrap()));
+    }
+
+    #[test]
+    pub fn test_v2_local_and_invalid() {
+        // Health checks from the proxy itself carry no address
+        let (client, len) = parse_header(&v2_header(0, 0, &[])).unwrap().unwrap();
+        assert_eq!(client, None);
+        assert_eq!(len, 16);
+
+        // Too short for an IPv4 address
+        assert!(parse_header(&v2_header(1, 0x11, &[0; 4])).is_err());
+
+        // Wrong version
+        let mut data = v2_header(1, 0x11, &[0; 12]);
+        data[12] = 0x11;
+        assert!(parse_header(&data).is_err());
+
+        // Longer than any header accepted
+        assert!(parse_header(&v2_header(1, 0x11, &[0; MAX_HEADER_LEN])).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Operator-supplied Lua hooks for server events.
+//!
+//! A script may define any of these global functions:
+//!
+//! * `on_login(name, citizen_id, ip)` - `citizen_id` is nil for tourists
+//! * `on_register(name, email)`
+//! * `on_telegram(from, to, message)`
+//! * `on_attribute_change(attribute_id, value)`
+//!
+//! Returning nothing or `true` allows the event. Returning `false` refuses it,
+//! and returning a number refuses it with that reason code. `on_telegram` and
+//! `on_attribute_change` may also return a string to replace the message or value.
+//! If a hook fails or returns anything else, the event is refused.
+//!
+//! Scripting is only available when built with the `scripting` feature. The
+//! server will not start if a script is configured but cannot be loaded.
+use std::net::IpAddr;
+
+use aw_core::{ReasonCode, VarID};
+
+use crate::config::ScriptingConfig;
+
+pub struct Scripts {
+    #[cfg(feature = "scripting")]
+    lua: Option<mlua::Lua>,
+}
+
+impl Scripts {
+    /// Load the script named in the config, if there is one.
+    pub fn load(config: &ScriptingConfig) -> Result<Self, String> {
+        #[cfg(feature = "scripting")]
+        {
+            let lua = match &config.script {
+                Some(path) => {
+                    let lua = mlua::Lua::new();
+                    let source = std::fs::read_to_string(path)
+                        .map_err(|err| format!("Could not read script {path}: {err}"))?;
+                    lua.load(&source)
+                        .set_name(path)
+                        .exec()
+                        .map_err(|err| format!("Could not load script {path}: {err}"))?;
+                    log::info!("Loaded script {path}");
+                    Some(lua)
+                }
+                None => None,
+            };
+
+            Ok(Self { lua })
+        }
+
+        #[cfg(not(feature = "scripting"))]
+        {
+            if let Some(path) = &config.script {
+                return Err(format!(
+                    "Cannot run script {path}: built without the scripting feature"
+                ));
+            }
+
+            Ok(Self {})
+        }
+    }
+
+    pub fn on_login(
+        &self,
+        name: &str,
+        citizen_id: Option<u32>,
+        ip: IpAddr,
+    ) -> Result<(), ReasonCode> {
+        self.call("on_login", (name, citizen_id, ip.to_string()))
+            .map(|_| ())
+    }
+
+    pub fn on_register(&self, name: &str, email: &str) -> Result<(), ReasonCode> {
+        self.call("on_register", (name, email)).map(|_| ())
+    }
+
+    /// Returns the message which should be delivered.
+    pub fn on_telegram(&self, from: &str, to: &str, message: String) -> Result<String, ReasonCode> {
+        let replacement = self.call("on_telegram", (from, to, message.as_str()))?;
+        Ok(replacement.unwrap_or(message))
+    }
+
+    /// Returns the value which should be stored.
+    pub fn on_attribute_change(&self, id: VarID, value: String) -> Result<String, ReasonCode> {
+        let replacement = self.call("on_attribute_change", (id as u16, value.as_str()))?;
+        Ok(replacement.unwrap_or(value))
+    }
+
+    /// Run a hook, giving a replacement string if it returned one, or the reason
+    /// the event is refused.
+    #[cfg(feature = "scripting")]
+    fn call(
+        &self,
+        hook: &str,
+        args: impl for<'lua> mlua::IntoLuaMulti<'lua>,
+    ) -> Result<Option<String>, ReasonCode> {
+        use mlua::Value;
+        use num_traits::FromPrimitive;
+
+        let lua = match &self.lua {
+            Some(lua) => lua,
+            None => return Ok(None),
+        };
+
+        let function = match lua.globals().get::<_, Option<mlua::Function>>(hook) {
+            Ok(Some(function)) => function,
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                log::error!("Script hook {hook} is not a function: {err}");
+                return Err(ReasonCode::Unauthorized);
+            }
+        };
+
+        match function.call::<_, Value>(args) {
+            Ok(Value::Nil) | Ok(Value::Boolean(true)) => Ok(None),
+            Ok(Value::Boolean(false)) => Err(ReasonCode::Unauthorized),
+            Ok(Value::Integer(rc)) => {
+                Err(ReasonCode::from_i64(rc).unwrap_or(ReasonCode::Unauthorized))
+            }
+            Ok(Value::String(s)) => Ok(Some(s.to_string_lossy().into_owned())),
+            Ok(other) => {
+                log::error!(
+                    "Script hook {hook} returned unexpected {}",
+                    other.type_name()
+                );
+                Err(ReasonCode::Unauthorized)
+            }
+            Err(err) => {
+                log::error!("Script hook {hook} failed: {err}");
+                Err(ReasonCode::Unauthorized)
+            }
+        }
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn call<T>(&self, _hook: &str, _args: T) -> Result<Option<String>, ReasonCode> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,216 @@
+//! Running as a Windows service, with log output going to the Event Log.
+//!
+//! The service runs from the folder its executable is in, so `universe.toml`
+//! belongs alongside it.
+use std::{
+    ffi::OsString,
+    sync::{atomic::Ordering, OnceLock},
+    time::Duration,
+};
+
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+use windows_sys::Win32::System::EventLog::{
+    RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+    EVENTLOG_WARNING_TYPE,
+};
+
+use crate::{config, digest, UniverseServer};
+
+const SERVICE_NAME: &str = "AWUniverse";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+/// Log level from the command line, for the service's main function to pick up
+static LOG_LEVEL: OnceLock<log::LevelFilter> = OnceLock::new();
+
+/// Register the service to start automatically, running this executable.
+pub fn install() -> windows_service::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+
+    let info = ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: "Active Worlds Universe".into(),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe().map_err(windows_service::Error::Winapi)?,
+        launch_arguments: vec!["--service".into()],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+
+    let service = manager.create_service(&info, ServiceAccess::CHANGE_CONFIG)?;
+    service.set_description(
+        "Central server which Active Worlds browsers, bots and worlds connect to",
+    )?;
+    Ok(())
+}
+
+pub fn uninstall() -> windows_service::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(SERVICE_NAME, ServiceAccess::DELETE)?;
+    service.delete()
+}
+
+/// Hand control to the service manager, which calls back into `service_main`.
+pub fn run(level: log::LevelFilter) -> windows_service::Result<()> {
+    LOG_LEVEL.get_or_init(|| level);
+
+    if let Ok(exe) = std::env::current_exe() {
+        if let Some(folder) = exe.parent() {
+            std::env::set_current_dir(folder).ok();
+        }
+    }
+
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    let level = LOG_LEVEL.get().copied().unwrap_or(log::LevelFilter::Info);
+
+    let config = match config::Config::get() {
+        Ok(config) => config,
+        Err(err) => {
+            init_event_log(level);
+            log::error!("Could not get universe configuration: {err}");
+            return;
+        }
+    };
+
+    // A log file is still used if one is configured, and the Event Log otherwise
+    if config.logging.file.is_empty() {
+        init_event_log(level);
+    } else {
+        crate::init_logging(level, &config.logging);
+    }
+
+    let mut universe = match UniverseServer::new(config) {
+        Ok(x) => x,
+        Err(err) => {
+            log::error!("Could not create universe: {err}");
+            return;
+        }
+    };
+
+    let shutdown = universe.shutdown_handle();
+    let handler = move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            shutdown.store(true, Ordering::Relaxed);
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    };
+
+    let status = match service_control_handler::register(SERVICE_NAME, handler) {
+        Ok(x) => x,
+        Err(err) => {
+            log::error!("Could not register with the service manager: {err}");
+            return;
+        }
+    };
+
+    set_state(&status, ServiceState::Running);
+    universe.run();
+    set_state(&status, ServiceState::Stopped);
+}
+
+fn set_state(status: &ServiceStatusHandle, state: ServiceState) {
+    let controls_accepted = match state {
+        ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        _ => ServiceControlAccept::empty(),
+    };
+
+    let result = status.set_service_status(ServiceStatus {
+        service_type: SERVICE_TYPE,
+        current_state: state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    });
+    if let Err(err) = result {
+        log::warn!("Could not report service state {state:?}: {err}");
+    }
+}
+
+fn init_event_log(level: log::LevelFilter) {
+    let source = wide(SERVICE_NAME);
+    // SAFETY: the source name is a valid null-terminated wide string
+    let handle = unsafe { RegisterEventSourceW(std::ptr::null(), source.as_ptr()) };
+    if handle.is_null() {
+        return;
+    }
+
+    let logger = EventLogLogger {
+        handle: handle as isize,
+        level,
+    };
+    log::set_max_level(level);
+    log::set_boxed_logger(Box::new(digest::ErrorCountingLogger(logger)))
+        .expect("Logger was already initialized");
+}
+
+/// Writes log records to the Windows Event Log.
+struct EventLogLogger {
+    /// Event source handle, kept as an integer so the logger can be shared between threads
+    handle: isize,
+    level: log::LevelFilter,
+}
+
+impl log::Log for EventLogLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let event_type = match record.level() {
+            log::Level::Error => EVENTLOG_ERROR_TYPE,
+            log::Level::Warn => EVENTLOG_WARNING_TYPE,
+            _ => EVENTLOG_INFORMATION_TYPE,
+        };
+        let message = wide(&format!("{}: {}", record.target(), record.args()));
+        let strings = [message.as_ptr()];
+
+        // SAFETY: the handle came from RegisterEventSourceW and the message outlives the call
+        unsafe {
+            ReportEventW(
+                self.handle as _,
+                event_type,
+                0,
+                0,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Encode a string as null-terminated UTF-16 for Windows.
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
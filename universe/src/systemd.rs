@@ -0,0 +1,140 @@
+//! Running as a systemd service: readiness and watchdog notifications for
+//! `Type=notify` units, and listeners handed over by socket activation.
+//! Everything here does nothing when not started by systemd.
+use std::{
+    net::TcpListener,
+    time::{Duration, Instant},
+};
+
+/// Sends state changes to systemd over the socket named in `NOTIFY_SOCKET`.
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: Option<std::os::unix::net::UnixDatagram>,
+    /// How often the watchdog must be pinged, if it is enabled for the unit
+    watchdog_interval: Option<Duration>,
+    last_ping: Instant,
+}
+
+impl Notifier {
+    pub fn from_env() -> Self {
+        // Ping at half the timeout, as systemd recommends
+        let watchdog_interval = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|_| watchdog_is_ours())
+            .map(|usec| Duration::from_micros(usec) / 2);
+
+        Self {
+            #[cfg(unix)]
+            socket: std::env::var_os("NOTIFY_SOCKET").and_then(|path| {
+                connect(&path)
+                    .map_err(|err| log::warn!("Could not connect to NOTIFY_SOCKET: {err}"))
+                    .ok()
+            }),
+            watchdog_interval,
+            last_ping: Instant::now(),
+        }
+    }
+
+    /// Tell systemd the universe has started and is accepting connections.
+    pub fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    /// Tell systemd the universe is shutting down.
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    /// Ping the watchdog if it is due. Called from the main loop, so a hung
+    /// loop gets the service restarted.
+    pub fn service_watchdog(&mut self) {
+        if let Some(interval) = self.watchdog_interval {
+            if self.last_ping.elapsed() >= interval {
+                self.last_ping = Instant::now();
+                self.notify("WATCHDOG=1");
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn notify(&self, state: &str) {
+        if let Some(socket) = &self.socket {
+            if let Err(err) = socket.send(state.as_bytes()) {
+                log::warn!("Could not notify systemd of {state}: {err}");
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn notify(&self, _state: &str) {}
+}
+
+#[cfg(unix)]
+fn connect(path: &std::ffi::OsStr) -> std::io::Result<std::os::unix::net::UnixDatagram> {
+    use std::os::unix::{ffi::OsStrExt, net::UnixDatagram};
+
+    let socket = UnixDatagram::unbound()?;
+
+    // Names starting with @ are in the abstract namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.connect_addr(&addr)?;
+        return Ok(socket);
+    }
+
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+/// Whether variables systemd set for a process are meant for this one, rather than
+/// inherited from a parent.
+fn watchdog_is_ours() -> bool {
+    match std::env::var("WATCHDOG_PID") {
+        Ok(pid) => pid.parse() == Ok(std::process::id()),
+        Err(_) => true,
+    }
+}
+
+/// Listeners passed in by systemd socket activation, if the universe was started that way.
+#[cfg(unix)]
+pub fn activated_listeners() -> Vec<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    /// The first descriptor systemd passes sockets in
+    const LISTEN_FDS_START: i32 = 3;
+
+    let pid_matches = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<i32>().ok())
+        .unwrap_or(0);
+    if !pid_matches || count <= 0 {
+        return Vec::new();
+    }
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .filter_map(|fd| {
+            // SAFETY: systemd hands these descriptors to this process to own,
+            // and nothing else in the universe uses them.
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            match listener.set_nonblocking(true) {
+                Ok(()) => Some(listener),
+                Err(err) => {
+                    log::warn!("Could not use socket {fd} from systemd: {err}");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+pub fn activated_listeners() -> Vec<TcpListener> {
+    Vec::new()
+}
@@ -1,61 +1,434 @@
 use aw_core::*;
 
 use crate::{
-    client::{Client, ClientManager},
+    accounts::AccountServer,
+    admin::{AdminContext, AdminServer},
+    announcements::Announcements,
+    bus::EventBus,
+    client::{Client, ClientManager, Entity},
     config,
-    database::Database,
-    packet_handler,
+    console::Console,
+    database::{Database, EjectDB},
+    digest::DailyDigest,
+    email::{self, Mailer},
+    events::{EventQueue, UniverseEvent},
+    logging::{self, LogContext},
+    login_throttle::LoginThrottle,
+    metrics::Metrics,
+    packet_handler::{self, HandlerContext, ImmigrationLimit, PacketRegistry},
+    presence_store::PresenceStore,
+    proxy_protocol::PendingConnection,
+    scripting::Scripts,
+    systemd,
     universe_license::LicenseGenerator,
+    webhooks::Webhooks,
+    xfer::Transfers,
 };
-use std::net::{SocketAddrV4, TcpListener};
+use num_traits::FromPrimitive;
+use socket2::{Domain, SockRef, Socket, TcpKeepalive, Type};
+use std::{
+    net::{SocketAddr, SocketAddrV4, SocketAddrV6, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How long a proxy has to send the whole PROXY header on a new connection
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(2);
 
 pub struct UniverseServer {
-    config: config::UniverseConfig,
+    config: config::Config,
     license_generator: LicenseGenerator,
     client_manager: ClientManager,
     database: Database,
-    listener: TcpListener,
+    listeners: Vec<TcpListener>,
+    packet_registry: PacketRegistry,
+    scripts: Scripts,
+    mailer: Option<Mailer>,
+    last_telegram_email: Instant,
+    last_stats_log: Instant,
+    events: EventQueue,
+    admin: Option<AdminServer>,
+    accounts: Option<AccountServer>,
+    digest: DailyDigest,
+    announcements: Announcements,
+    event_bus: Option<EventBus>,
+    webhooks: Option<Webhooks>,
+    presence_store: Option<PresenceStore>,
+    transfers: Transfers,
+    login_throttle: LoginThrottle,
+    immigration_limit: ImmigrationLimit,
+    /// Connections from proxies still waiting for their PROXY header
+    pending_proxied: Vec<PendingConnection>,
+    /// Set when the process is asked to stop
+    shutdown: Arc<AtomicBool>,
+    systemd: systemd::Notifier,
+    console: Option<Console>,
+    metrics: Metrics,
 }
 
 impl UniverseServer {
     pub fn new(config: config::Config) -> Result<Self, String> {
-        let database = Database::new(config.mysql, &config.universe)?;
-        let ip = SocketAddrV4::new(config.universe.ip, config.universe.port);
-        let listener = TcpListener::bind(&ip).unwrap();
-        listener.set_nonblocking(true).unwrap();
+        let database = Database::new(config.mysql.clone(), &config.universe)?;
+        Self::with_database(config, database)
+    }
+
+    /// Run on a database which is already open, rather than the configured one.
+    pub fn with_database(config: config::Config, database: Database) -> Result<Self, String> {
+        aw_core::encoding::set_wire_codepage(config.universe.codepage);
+        if config.network.packet_group_size != 0 {
+            aw_core::set_group_target_len(config.network.packet_group_size);
+        }
+        apply_header_config(&config.headers);
+        if config.network.proxy_protocol && config.network.trusted_proxies.is_empty() {
+            return Err(
+                "proxy_protocol needs the proxies to be listed in trusted_proxies".to_string(),
+            );
+        }
+        let listeners = bind_listeners(&config.universe)?;
+        let scripts = Scripts::load(&config.scripting)?;
+        let mailer = Mailer::new(&config.email);
+        let admin = AdminServer::new(&config.admin);
+        let accounts = AccountServer::new(&config.accounts);
+        let digest = DailyDigest::new(&config.digest);
+        let announcements = Announcements::new(&config.announcements);
+        let event_bus = EventBus::new(&config.event_bus);
+        let webhooks = Webhooks::new(&config.webhooks);
+        let presence_store = PresenceStore::connect(&config.presence, &config.universe);
+        let console = Console::new(&config.console);
+        let license_generator = LicenseGenerator::new(&config.universe.advertised_address());
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let flag = shutdown.clone();
+        if let Err(err) = ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed)) {
+            log::warn!("Could not handle shutdown signals: {err}");
+        }
 
         Ok(Self {
-            config: config.universe,
-            license_generator: LicenseGenerator::new(&ip),
+            config,
+            license_generator,
             client_manager: Default::default(),
             database,
-            listener,
+            listeners,
+            packet_registry: default_registry(),
+            scripts,
+            mailer,
+            last_telegram_email: Instant::now(),
+            last_stats_log: Instant::now(),
+            events: EventQueue::default(),
+            admin,
+            accounts,
+            digest,
+            announcements,
+            event_bus,
+            webhooks,
+            presence_store,
+            transfers: Transfers::default(),
+            login_throttle: LoginThrottle::default(),
+            immigration_limit: ImmigrationLimit::default(),
+            pending_proxied: Vec::new(),
+            shutdown,
+            systemd: systemd::Notifier::from_env(),
+            console,
+            metrics: Metrics::default(),
         })
     }
 
+    /// A flag which stops the universe once set, as Ctrl-C does.
+    pub fn shutdown_handle(&self) -> Arc<AtomicBool> {
+        self.shutdown.clone()
+    }
+
     pub fn run(&mut self) {
-        log::info!(
-            "Starting universe on {}:{}",
-            self.config.ip,
-            self.config.port
-        );
-        loop {
+        for listener in &self.listeners {
+            if let Ok(addr) = listener.local_addr() {
+                log::info!("Starting universe on {addr}");
+            }
+        }
+        self.systemd.ready();
+        while !self.shutdown.load(Ordering::Relaxed) {
             self.accept_new_clients();
             self.service_clients();
-            self.client_manager.remove_dead_clients(&self.database);
-            self.client_manager.send_heartbeats();
+            self.client_manager
+                .remove_dead_clients(&self.database, &self.events);
+            self.client_manager
+                .send_heartbeats(self.config.network.missed_heartbeat_limit);
+            self.client_manager.expire_sessions(&self.config);
+            self.transfers.expire(&self.config.xfer);
+            self.login_throttle.expire(&self.config.login_throttle);
+            self.immigration_limit.expire(&self.config.universe);
+            self.email_offline_telegrams();
+            self.log_stats();
+            self.service_admin();
+            self.send_digest();
+            self.announcements.send_due(&self.client_manager);
+            self.systemd.service_watchdog();
+        }
+
+        self.shut_down();
+    }
+
+    /// Tell everyone the universe is stopping and disconnect them once their
+    /// queued packets have been sent, recording how long they were online.
+    fn shut_down(&mut self) {
+        log::info!("Shutting down");
+        self.systemd.stopping();
+
+        let message = &self.config.shutdown.message;
+        if !message.is_empty() {
+            self.client_manager
+                .send_console_message_to(None, message)
+                .ok();
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(self.config.shutdown.drain_secs);
+        while Instant::now() < deadline
+            && self
+                .client_manager
+                .clients()
+                .iter()
+                .any(|client| client.connection.queued_len() != 0)
+        {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        for client in self.client_manager.clients() {
+            client.kill();
+        }
+        self.client_manager
+            .remove_dead_clients(&self.database, &self.events);
+
+        // Pass the disconnections on to anything watching events
+        self.service_admin();
+    }
+
+    fn service_admin(&mut self) {
+        let events = self.events.take();
+        self.digest.observe_events(&events);
+        self.metrics.observe_events(&events);
+        if let Some(event_bus) = &self.event_bus {
+            event_bus.publish(&events);
+        }
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.publish(&events);
+        }
+        if let Some(presence_store) = &mut self.presence_store {
+            presence_store.observe_events(&events);
+            presence_store.sync(&self.client_manager);
+        }
+        if let Some(admin) = &mut self.admin {
+            let mut ctx = AdminContext {
+                queue: &self.events,
+                database: &self.database,
+                client_manager: &self.client_manager,
+                config: &self.config,
+                metrics: &self.metrics,
+                announcements: &mut self.announcements,
+                mailer: self.mailer.as_ref(),
+            };
+            admin.service(&events, &mut ctx);
+        }
+        if let Some(console) = &self.console {
+            console.service(&self.database, &self.client_manager);
+        }
+        if let Some(accounts) = &mut self.accounts {
+            accounts.service(&self.database, self.mailer.as_ref(), &self.config.accounts);
+        }
+    }
+
+    fn send_digest(&mut self) {
+        let players = self
+            .client_manager
+            .clients()
+            .iter()
+            .filter(|c| c.info().entity.as_ref().is_some_and(|e| e.is_player()))
+            .count();
+        self.digest.observe_users(players as u32);
+        self.digest.send_if_due(
+            &self.config.digest,
+            &self.database,
+            &self.client_manager,
+            self.mailer.as_ref(),
+        );
+    }
+
+    fn log_stats(&mut self) {
+        let interval = self.config.network.stats_log_secs;
+        if interval == 0 || self.last_stats_log.elapsed() < Duration::from_secs(interval) {
+            return;
+        }
+        self.last_stats_log = Instant::now();
+
+        let stats = GroupStats::get();
+        log::info!(
+            "Packet groups: {} sent, {} packets, {} bytes (average {}), {} bytes unused, {} splits",
+            stats.groups_sent,
+            stats.packets_sent,
+            stats.bytes_sent,
+            stats.average_group_len(),
+            stats.bytes_wasted,
+            stats.splits
+        );
+
+        let (sent, received) =
+            self.client_manager
+                .clients()
+                .iter()
+                .fold((0, 0), |(sent, received), client| {
+                    let traffic = client.connection.traffic();
+                    (
+                        sent + traffic.bytes_sent(),
+                        received + traffic.bytes_received(),
+                    )
+                });
+        log::info!(
+            "Connected clients: {} bytes sent, {} bytes received",
+            sent,
+            received
+        );
+
+        for seen in aw_core::observed_headers() {
+            log::info!(
+                "Headers {:?} {:?}: header_0={} header_1={} ({} times)",
+                seen.direction,
+                seen.opcode,
+                seen.header_0,
+                seen.header_1,
+                seen.count
+            );
+        }
+    }
+
+    fn email_offline_telegrams(&mut self) {
+        let interval = self.config.email.telegram_interval_secs;
+        let mailer = match &self.mailer {
+            Some(mailer) if interval != 0 => mailer,
+            _ => return,
+        };
+
+        if self.last_telegram_email.elapsed() < Duration::from_secs(interval) {
+            return;
         }
+        self.last_telegram_email = Instant::now();
+
+        email::forward_offline_telegrams(
+            mailer,
+            &self.database,
+            &self.client_manager,
+            &self.config.accounts,
+        );
     }
 
     fn accept_new_clients(&mut self) {
-        while let Ok((stream, addr)) = self.listener.accept() {
-            let client = Client::new(AWConnection::new(AWProtocol::new(stream)), addr);
+        let mut accepted = Vec::new();
+        for listener in &self.listeners {
+            while let Ok((stream, addr)) = listener.accept() {
+                if !self.config.network.proxy_protocol {
+                    accepted.push((stream, addr));
+                    continue;
+                }
+
+                let proxy = addr.ip().to_canonical();
+                if !self.config.network.trusted_proxies.contains(&proxy) {
+                    log::warn!("Refusing connection from {proxy}, which is not a trusted proxy");
+                    continue;
+                }
+                match PendingConnection::new(stream, addr) {
+                    Ok(pending) => self.pending_proxied.push(pending),
+                    Err(err) => log::warn!("Could not accept connection from {proxy}: {err}"),
+                }
+            }
+        }
+        accepted.extend(self.take_proxied());
+
+        for (stream, addr) in accepted {
+            // Treat IPv4 clients reaching an IPv6 socket the same as any other IPv4 client
+            let addr = SocketAddr::new(addr.ip().to_canonical(), addr.port());
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Current time is before the unix epoch.")
+                .as_secs() as u32;
+
+            if let Some(eject) = self.database.eject_active_for(addr.ip(), now) {
+                log::info!(
+                    "Refusing connection from ejected address {} ({})",
+                    addr.ip(),
+                    eject.id
+                );
+                continue;
+            }
+
+            let limit = self.config.universe.max_connections_per_ip;
+            if limit != 0
+                && !self
+                    .config
+                    .universe
+                    .world_server_allowlist
+                    .contains(&addr.ip())
+                && self.client_manager.connections_from(addr.ip()) >= limit
+            {
+                log::info!(
+                    "Refusing connection from {} which already has {limit} open",
+                    addr.ip()
+                );
+                continue;
+            }
+
+            if let Err(err) = apply_socket_options(&stream, &self.config.network) {
+                log::warn!("Could not apply socket options for {}: {err}", addr.ip());
+            }
+
+            let mut protocol = AWProtocol::new(stream);
+            protocol.set_send_rate_limit(Some(self.config.network.client_send_rate));
+            if self.config.network.malformed_limit != 0 {
+                protocol.set_malformed_limit(self.config.network.malformed_limit);
+            }
+
+            let connection =
+                AWConnection::with_queue_limits(protocol, self.config.network.queue_limits());
+            let client = Client::new(connection, addr);
+            self.events
+                .publish(UniverseEvent::Connect { ip: addr.ip() });
             self.client_manager.add_client(client);
         }
     }
 
+    /// Take the connections whose PROXY headers have arrived, along with the address
+    /// of the client behind each. Those which have not sent one in time are dropped.
+    fn take_proxied(&mut self) -> Vec<(TcpStream, SocketAddr)> {
+        let mut ready = Vec::new();
+        for mut pending in std::mem::take(&mut self.pending_proxied) {
+            let proxy = pending.peer.ip().to_canonical();
+            match pending.poll() {
+                Ok(Some(client)) => {
+                    let addr = client.unwrap_or(pending.peer);
+                    ready.push((pending.stream, addr));
+                }
+                Ok(None) if pending.expired(PROXY_HEADER_TIMEOUT) => {
+                    log::warn!("No PROXY header from {proxy} in time");
+                }
+                Ok(None) => self.pending_proxied.push(pending),
+                Err(err) => log::warn!("Bad PROXY header from {proxy}: {err}"),
+            }
+        }
+        ready
+    }
+
     fn service_clients(&mut self) {
         for client in self.client_manager.clients() {
+            if client.connection.is_stalled() {
+                log::warn!(
+                    "Disconnecting {} because it is not reading ({} messages queued, {} dropped)",
+                    client.addr.ip(),
+                    client.connection.queued_len(),
+                    client.connection.dropped_count()
+                );
+                client.kill();
+                continue;
+            }
+
             let messages = client.connection.recv();
             self.handle_messages(messages, client);
         }
@@ -80,90 +453,394 @@ impl UniverseServer {
     }
 
     fn handle_packet(&self, packet: &AWPacket, client: &Client) {
+        let (session_id, citizen_id) = match &client.info().entity {
+            Some(Entity::Player(player)) => (Some(player.session_id), player.citizen_id),
+            _ => (None, None),
+        };
+        logging::set_context(Some(LogContext {
+            ip: client.addr.ip(),
+            session_id,
+            citizen_id,
+        }));
+
         log::debug!("Handling packet {packet:?}");
-        match packet.get_opcode() {
-            PacketType::PublicKeyRequest => packet_handler::public_key_request(client),
-            PacketType::StreamKeyResponse => {
-                packet_handler::stream_key_response(client, packet, &self.database)
-            }
-            PacketType::PublicKeyResponse => packet_handler::public_key_response(client, packet),
-            PacketType::Login => packet_handler::login(
-                client,
-                packet,
-                &self.client_manager,
-                &self.license_generator,
-                &self.database,
-            ),
-            PacketType::Heartbeat => packet_handler::heartbeat(client),
-            PacketType::WorldServerStart => packet_handler::world_server_start(client, packet),
-            PacketType::UserList => packet_handler::user_list(client, packet, &self.client_manager),
-            PacketType::AttributeChange => packet_handler::attribute_change(
-                client,
-                packet,
-                &self.database,
-                &self.client_manager,
-            ),
-            PacketType::CitizenNext => packet_handler::citizen_next(client, packet, &self.database),
-            PacketType::CitizenPrev => packet_handler::citizen_prev(client, packet, &self.database),
-            PacketType::CitizenLookupByName => {
-                packet_handler::citizen_lookup_by_name(client, packet, &self.database)
-            }
-            PacketType::CitizenLookupByNumber => {
-                packet_handler::citizen_lookup_by_number(client, packet, &self.database)
-            }
-            PacketType::CitizenChange => {
-                packet_handler::citizen_change(client, packet, &self.database)
-            }
-            PacketType::LicenseAdd => packet_handler::license_add(client, packet, &self.database),
-            PacketType::LicenseByName => {
-                packet_handler::license_by_name(client, packet, &self.database)
-            }
-            PacketType::LicenseNext => packet_handler::license_next(client, packet, &self.database),
-            PacketType::LicensePrev => packet_handler::license_prev(client, packet, &self.database),
-            PacketType::LicenseChange => {
-                packet_handler::license_change(client, packet, &self.database)
-            }
-            PacketType::WorldStart => {
-                packet_handler::world_start(client, packet, &self.database, &self.client_manager)
-            }
-            PacketType::WorldStop => {
-                packet_handler::world_stop(client, packet, &self.client_manager)
-            }
-            PacketType::WorldList => {
-                packet_handler::world_list(client, packet, &self.client_manager)
-            }
-            PacketType::WorldLookup => {
-                packet_handler::world_lookup(client, packet, &self.client_manager)
-            }
-            PacketType::Identify => {
-                packet_handler::identify(client, packet, &self.client_manager, &self.database)
-            }
-            PacketType::WorldStatsUpdate => {
-                packet_handler::world_stats_update(client, packet, &self.client_manager)
-            }
-            PacketType::CitizenAdd => packet_handler::citizen_add(client, packet, &self.database),
-            PacketType::ContactAdd => {
-                packet_handler::contact_add(client, packet, &self.database, &self.client_manager)
-            }
-            PacketType::TelegramSend => {
-                packet_handler::telegram_send(client, packet, &self.database, &self.client_manager)
-            }
-            PacketType::TelegramGet => {
-                packet_handler::telegram_get(client, packet, &self.database);
+        client.record_heartbeat_response();
+        self.metrics.record_packet(packet.get_opcode());
+
+        let ctx = HandlerContext {
+            config: &self.config,
+            database: &self.database,
+            client_manager: &self.client_manager,
+            license_generator: &self.license_generator,
+            scripts: &self.scripts,
+            events: &self.events,
+            presence_store: self.presence_store.as_ref(),
+            transfers: &self.transfers,
+            login_throttle: &self.login_throttle,
+            immigration_limit: &self.immigration_limit,
+            mailer: self.mailer.as_ref(),
+        };
+
+        self.packet_registry.dispatch(&ctx, client, packet);
+        logging::set_context(None);
+    }
+}
+
+/// Set up header observation and overrides from the config.
+fn apply_header_config(config: &config::HeaderConfig) {
+    aw_core::set_header_observation(config.observe);
+
+    for (key, header_override) in &config.overrides {
+        let opcode = match key.parse::<i16>().ok().and_then(PacketType::from_i16) {
+            Some(x) => x,
+            None => {
+                log::warn!("Ignoring header override for unknown packet type {key:?}");
+                continue;
             }
-            PacketType::SetAFK => packet_handler::set_afk(client, packet),
-            PacketType::ContactConfirm => packet_handler::contact_confirm(
+        };
+
+        log::info!("Overriding headers for {opcode:?}: {header_override:?}");
+        aw_core::set_header_override(
+            opcode,
+            HeaderOverride {
+                header_0: header_override.header_0,
+                header_1: header_override.header_1,
+            },
+        );
+    }
+}
+
+/// Create a registry with the handlers for every packet the universe understands.
+fn default_registry() -> PacketRegistry {
+    let mut r = PacketRegistry::default();
+    r.add_hook(Box::new(packet_handler::RateLimitHook));
+
+    r.register(PacketType::PublicKeyRequest, |ctx, client, _| {
+        packet_handler::public_key_request(client, &ctx.config.universe)
+    });
+    r.register(PacketType::StreamKeyResponse, |ctx, client, packet| {
+        packet_handler::stream_key_response(client, packet, ctx.database)
+    });
+    r.register(PacketType::PublicKeyResponse, |_, client, packet| {
+        packet_handler::public_key_response(client, packet)
+    });
+    r.register(PacketType::Login, packet_handler::login);
+    r.register(PacketType::Heartbeat, |_, client, _| {
+        packet_handler::heartbeat(client)
+    });
+    r.register(PacketType::WorldServerStart, |ctx, client, packet| {
+        packet_handler::world_server_start(client, packet, &ctx.config.universe, ctx.client_manager)
+    });
+    r.register(PacketType::ServerLogin, |ctx, client, packet| {
+        packet_handler::server_login(
+            client,
+            packet,
+            &ctx.config.universe,
+            ctx.database,
+            ctx.client_manager,
+        )
+    });
+    r.register(PacketType::UserList, |ctx, client, packet| {
+        packet_handler::user_list(client, packet, ctx.client_manager, ctx.database)
+    });
+    r.register(PacketType::AttributeChange, |ctx, client, packet| {
+        packet_handler::attribute_change(
+            client,
+            packet,
+            ctx.database,
+            ctx.client_manager,
+            ctx.scripts,
+            ctx.events,
+        )
+    });
+    r.register(PacketType::CitizenNext, |ctx, client, packet| {
+        packet_handler::citizen_next(client, packet, ctx.database)
+    });
+    r.register(PacketType::CitizenPrev, |ctx, client, packet| {
+        packet_handler::citizen_prev(client, packet, ctx.database)
+    });
+    r.register(PacketType::CitizenLookupByName, |ctx, client, packet| {
+        packet_handler::citizen_lookup_by_name(client, packet, ctx.database)
+    });
+    r.register(PacketType::CitizenLookupByNumber, |ctx, client, packet| {
+        packet_handler::citizen_lookup_by_number(client, packet, ctx.database)
+    });
+    r.register(PacketType::CitizenChange, |ctx, client, packet| {
+        packet_handler::citizen_change(
+            client,
+            packet,
+            ctx.database,
+            ctx.client_manager,
+            &ctx.config.trial,
+        )
+    });
+    r.register(PacketType::CitizenDelete, |ctx, client, packet| {
+        packet_handler::citizen_delete(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::LicenseAdd, |ctx, client, packet| {
+        packet_handler::license_add(client, packet, ctx.database)
+    });
+    r.register(PacketType::LicenseByName, |ctx, client, packet| {
+        packet_handler::license_by_name(client, packet, ctx.database)
+    });
+    r.register(PacketType::LicenseNext, |ctx, client, packet| {
+        packet_handler::license_next(client, packet, ctx.database)
+    });
+    r.register(PacketType::LicensePrev, |ctx, client, packet| {
+        packet_handler::license_prev(client, packet, ctx.database)
+    });
+    r.register(PacketType::LicenseChange, |ctx, client, packet| {
+        packet_handler::license_change(client, packet, ctx.database)
+    });
+    r.register(PacketType::LicenseDelete, |ctx, client, packet| {
+        packet_handler::license_delete(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::WorldStart, |ctx, client, packet| {
+        packet_handler::world_start(
+            client,
+            packet,
+            ctx.database,
+            ctx.client_manager,
+            &ctx.config.universe,
+            ctx.events,
+        )
+    });
+    r.register(PacketType::WorldStop, |ctx, client, packet| {
+        packet_handler::world_stop(client, packet, ctx.client_manager, ctx.events)
+    });
+    r.register(PacketType::WorldList, |ctx, client, packet| {
+        packet_handler::world_list(client, packet, ctx.client_manager)
+    });
+    r.register(PacketType::WorldLookup, |ctx, client, packet| {
+        packet_handler::world_lookup(client, packet, ctx.client_manager, &ctx.config.universe)
+    });
+    r.register(PacketType::Identify, |ctx, client, packet| {
+        packet_handler::identify(client, packet, ctx.client_manager, ctx.database)
+    });
+    r.register(PacketType::WorldStatsUpdate, |ctx, client, packet| {
+        packet_handler::world_stats_update(client, packet, ctx.client_manager)
+    });
+    r.register(PacketType::CitizenAdd, |ctx, client, packet| {
+        packet_handler::citizen_add(client, packet, ctx.database, ctx.scripts)
+    });
+    r.register(PacketType::Immigrate, packet_handler::immigrate);
+    r.register(PacketType::ContactAdd, |ctx, client, packet| {
+        packet_handler::contact_add(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::ContactChange, |ctx, client, packet| {
+        packet_handler::contact_change(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::ContactDelete, |ctx, client, packet| {
+        packet_handler::contact_delete(client, packet, ctx.database)
+    });
+    r.register(PacketType::TelegramSend, |ctx, client, packet| {
+        packet_handler::telegram_send(
+            client,
+            packet,
+            ctx.database,
+            ctx.client_manager,
+            ctx.scripts,
+            ctx.events,
+        )
+    });
+    r.register(PacketType::TelegramGet, |ctx, client, packet| {
+        packet_handler::telegram_get(client, packet, ctx.database)
+    });
+    r.register(PacketType::SetAFK, |ctx, client, packet| {
+        packet_handler::set_afk(client, packet, ctx.client_manager, ctx.database)
+    });
+    r.register(PacketType::ContactConfirm, |ctx, client, packet| {
+        packet_handler::contact_confirm(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::ContactList, |ctx, client, packet| {
+        packet_handler::contact_list(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::Join, |ctx, client, packet| {
+        packet_handler::join(
+            client,
+            packet,
+            ctx.database,
+            ctx.client_manager,
+            &ctx.config.universe,
+        )
+    });
+    r.register(PacketType::JoinReply, |ctx, client, packet| {
+        packet_handler::join_reply(client, packet, ctx.client_manager, &ctx.config.universe)
+    });
+    r.register(PacketType::Invite, |ctx, client, packet| {
+        packet_handler::invite(
+            client,
+            packet,
+            ctx.database,
+            ctx.client_manager,
+            &ctx.config.universe,
+        )
+    });
+    r.register(PacketType::ConsoleMessage, |ctx, client, packet| {
+        packet_handler::console_message(client, packet, ctx.client_manager)
+    });
+    r.register(PacketType::Botgram, |ctx, client, packet| {
+        packet_handler::botgram(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::Teleport, |ctx, client, packet| {
+        packet_handler::teleport(client, packet, ctx.client_manager, &ctx.config.universe)
+    });
+    r.register(PacketType::PresenceQuery, |ctx, client, packet| {
+        packet_handler::presence_query(
+            client,
+            packet,
+            ctx.database,
+            ctx.client_manager,
+            ctx.presence_store,
+        )
+    });
+    r.register(PacketType::URLClick, |ctx, client, packet| {
+        packet_handler::url_click(client, packet, ctx.database)
+    });
+    r.register(
+        PacketType::WorldCAVDefinitionChange,
+        |ctx, client, packet| {
+            packet_handler::cav_change(
                 client,
                 packet,
-                &self.database,
-                &self.client_manager,
-            ),
-            PacketType::ContactList => {
-                packet_handler::contact_list(client, packet, &self.database, &self.client_manager)
-            }
-            _ => {
-                log::info!("Unhandled packet {packet:?}");
-            }
-        }
+                ctx.database,
+                ctx.client_manager,
+                &ctx.config.cav,
+            )
+        },
+    );
+    r.register(PacketType::WorldCAV, |ctx, client, packet| {
+        packet_handler::world_cav(client, packet, ctx.database)
+    });
+    r.register(PacketType::CavDelete, |ctx, client, packet| {
+        packet_handler::cav_delete(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::Xfer, packet_handler::xfer);
+    r.register(PacketType::CavTemplateByNumber, |ctx, client, packet| {
+        packet_handler::cav_template_by_number(client, packet, ctx.database)
+    });
+    r.register(PacketType::CavTemplateNext, |ctx, client, packet| {
+        packet_handler::cav_template_next(client, packet, ctx.database)
+    });
+    r.register(PacketType::CavTemplateChange, |ctx, client, packet| {
+        packet_handler::cav_template_change(client, packet, ctx.database)
+    });
+    r.register(PacketType::CavTemplateDelete, |ctx, client, packet| {
+        packet_handler::cav_template_delete(client, packet, ctx.database)
+    });
+
+    r.register(PacketType::EjectAdd, |ctx, client, packet| {
+        packet_handler::eject_add(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::EjectDelete, |ctx, client, packet| {
+        packet_handler::eject_delete(client, packet, ctx.database)
+    });
+    r.register(PacketType::EjectLookup, |ctx, client, packet| {
+        packet_handler::eject_lookup(client, packet, ctx.database)
+    });
+    r.register(PacketType::EjectNext, |ctx, client, packet| {
+        packet_handler::eject_next(client, packet, ctx.database)
+    });
+    r.register(PacketType::EjectPrev, |ctx, client, packet| {
+        packet_handler::eject_prev(client, packet, ctx.database)
+    });
+    r.register(PacketType::SessionKick, |ctx, client, packet| {
+        packet_handler::session_kick(client, packet, ctx.client_manager, ctx.database)
+    });
+
+    r.register(PacketType::CitizenProfile, |ctx, client, packet| {
+        packet_handler::citizen_profile(client, packet, ctx.database, &ctx.config.profile)
+    });
+    r.register(PacketType::GroupQuery, |ctx, client, packet| {
+        packet_handler::group_query(client, packet, ctx.database)
+    });
+    r.register(PacketType::GroupTelegram, |ctx, client, packet| {
+        packet_handler::group_telegram(client, packet, ctx.database, ctx.client_manager)
+    });
+    r.register(PacketType::GroupChange, |ctx, client, packet| {
+        packet_handler::group_change(client, packet, ctx.database)
+    });
+    r.register(PacketType::CreditQuery, |ctx, client, packet| {
+        packet_handler::credit_query(client, packet, ctx.database, &ctx.config.credits)
+    });
+    r.register(PacketType::CreditChange, |ctx, client, packet| {
+        packet_handler::credit_change(
+            client,
+            packet,
+            ctx.database,
+            &ctx.config.credits,
+            ctx.events,
+        )
+    });
+
+    r
+}
+
+/// Take the listeners systemd passed in with socket activation, or otherwise
+/// listen on the configured addresses.
+fn bind_listeners(config: &config::UniverseConfig) -> Result<Vec<TcpListener>, String> {
+    let activated = systemd::activated_listeners();
+    if !activated.is_empty() {
+        log::info!("Using {} sockets from systemd", activated.len());
+        return Ok(activated);
+    }
+
+    let ip = SocketAddrV4::new(config.ip, config.port);
+    let listener =
+        TcpListener::bind(ip).map_err(|err| format!("Could not listen on {ip}: {err}"))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|err| format!("Could not listen on {ip}: {err}"))?;
+    let mut listeners = vec![listener];
+    if let Some(ipv6) = config.ipv6 {
+        let addr = SocketAddrV6::new(ipv6, config.port, 0, 0);
+        let listener =
+            bind_ipv6_listener(addr).map_err(|err| format!("Could not listen on {addr}: {err}"))?;
+        listeners.push(listener);
     }
+    Ok(listeners)
+}
+
+/// Listen on an IPv6 address only, leaving IPv4 to the main listener even when both
+/// are bound to the unspecified address.
+fn bind_ipv6_listener(addr: SocketAddrV6) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, None)?;
+    socket.set_only_v6(true)?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::V6(addr).into())?;
+    socket.listen(128)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Apply the configured socket tuning to a newly accepted connection.
+fn apply_socket_options(stream: &TcpStream, config: &config::NetworkConfig) -> std::io::Result<()> {
+    let socket = SockRef::from(stream);
+
+    if config.keepalive_secs != 0 {
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(config.keepalive_secs));
+        socket.set_tcp_keepalive(&keepalive)?;
+    }
+
+    if config.read_timeout_secs != 0 {
+        stream.set_read_timeout(Some(Duration::from_secs(config.read_timeout_secs)))?;
+    }
+
+    if config.write_timeout_secs != 0 {
+        stream.set_write_timeout(Some(Duration::from_secs(config.write_timeout_secs)))?;
+    }
+
+    if config.recv_buffer_size != 0 {
+        socket.set_recv_buffer_size(config.recv_buffer_size)?;
+    }
+
+    if config.send_buffer_size != 0 {
+        socket.set_send_buffer_size(config.send_buffer_size)?;
+    }
+
+    if config.nodelay {
+        stream.set_nodelay(true)?;
+    }
+
+    Ok(())
 }
@@ -56,6 +56,48 @@ pub struct World {
 }
 
 impl World {
+    /// Whether the world has reached its licensed user limit. The larger of
+    /// the world server's reported count and the universe's own count of
+    /// players in the world is used, in case the world server is not
+    /// enforcing the limit itself. Both counts have to cover the same servers
+    /// as the limit: one replica's, or all of them after `merge_replicas`.
+    pub fn is_full(&self, tracked_users: u32) -> bool {
+        if self.max_users == 0 {
+            return false;
+        }
+
+        self.user_count.max(tracked_users) >= self.max_users
+    }
+
+    /// How full the world is as a percentage of its user limit, as a hint for
+    /// spreading users across servers. Worlds without a limit report 0.
+    pub fn load(&self) -> u8 {
+        if self.max_users == 0 {
+            return 0;
+        }
+
+        (self.user_count.saturating_mul(100) / self.max_users).min(100) as u8
+    }
+
+    /// Combine the copies of a world running on different servers into one
+    /// entry for world listings.
+    pub fn merge_replicas(replicas: &[World]) -> Option<World> {
+        let mut merged = replicas.first()?.clone();
+
+        for replica in &replicas[1..] {
+            merged.user_count += replica.user_count;
+            merged.max_users = match (merged.max_users, replica.max_users) {
+                (0, _) | (_, 0) => 0,
+                (a, b) => a + b,
+            };
+            if let WorldStatus::Permitted = replica.status {
+                merged.status = WorldStatus::Permitted;
+            }
+        }
+
+        Some(merged)
+    }
+
     pub fn make_list_packet(&self) -> AWPacket {
         let mut p = AWPacket::new(PacketType::WorldList);
 
@@ -63,6 +105,7 @@ impl World {
         p.add_byte(VarID::WorldListStatus, self.status as u8);
         p.add_uint(VarID::WorldListUsers, self.user_count);
         p.add_byte(VarID::WorldListRating, self.rating as u8);
+        p.add_byte(VarID::WorldListLoad, self.load());
 
         p
     }
@@ -144,6 +187,8 @@ impl World {
 #[derive(Debug)]
 pub struct WorldServerInfo {
     pub build: i32,
+    /// Browser version the world server reported, if any
+    pub version: Option<i32>,
     pub server_port: u16,
     pub worlds: Vec<World>,
 }
@@ -0,0 +1,220 @@
+//! Transfers of payloads too large for a single packet, such as custom avatar
+//! definitions.
+//!
+//! A client uploads with Xfer packets carrying a transfer ID of its choosing,
+//! the total size, and a chunk with its offset. Each chunk is answered with an
+//! XferReply giving how many bytes have been received, so an interrupted
+//! upload can be resumed from there, even after reconnecting. An Xfer without
+//! data asks for a chunk of a payload to be downloaded instead.
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use aw_core::ReasonCode;
+use num_derive::FromPrimitive;
+
+use crate::config::XferConfig;
+
+/// What a transfer's payload is.
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XferKind {
+    Cav = 1,
+}
+
+/// One piece of an upload, as sent by the client.
+pub struct Chunk<'a> {
+    /// Chosen by the client to tell its uploads apart
+    pub id: u32,
+    pub kind: XferKind,
+    /// Size of the whole payload, which is only a claim until every byte arrives
+    pub total: usize,
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+/// An upload which has been completed.
+pub struct CompletedUpload {
+    pub kind: XferKind,
+    pub data: Vec<u8>,
+}
+
+struct Upload {
+    kind: XferKind,
+    total: usize,
+    data: Vec<u8>,
+    last_activity: Instant,
+}
+
+/// Unfinished uploads, keyed by the uploading citizen and their transfer ID.
+#[derive(Default)]
+pub struct Transfers {
+    uploads: RefCell<HashMap<(u32, u32), Upload>>,
+}
+
+impl Transfers {
+    /// Add a chunk to an upload, starting it if needed. Returns the number of
+    /// bytes received so far, and the payload once every byte has arrived.
+    pub fn receive(
+        &self,
+        owner: u32,
+        chunk: &Chunk,
+        config: &XferConfig,
+    ) -> Result<(usize, Option<CompletedUpload>), (usize, ReasonCode)> {
+        if chunk.total > config.max_size {
+            return Err((0, ReasonCode::TooManyBytes));
+        }
+        if chunk.data.len() > config.chunk_size {
+            return Err((chunk.offset, ReasonCode::TooManyBytes));
+        }
+
+        let mut uploads = self.uploads.borrow_mut();
+        let key = (owner, chunk.id);
+        if !uploads.contains_key(&key)
+            && uploads.keys().filter(|(x, _)| *x == owner).count() >= config.max_uploads
+        {
+            return Err((0, ReasonCode::TooManyBytes));
+        }
+
+        // The buffer grows as data arrives rather than trusting the claimed total
+        let upload = uploads.entry(key).or_insert_with(|| Upload {
+            kind: chunk.kind,
+            total: chunk.total,
+            data: Vec::new(),
+            last_activity: Instant::now(),
+        });
+
+        // A transfer ID being reused for something else starts over
+        if upload.kind != chunk.kind || upload.total != chunk.total {
+            *upload = Upload {
+                kind: chunk.kind,
+                total: chunk.total,
+                data: Vec::new(),
+                last_activity: Instant::now(),
+            };
+        }
+
+        let received = upload.data.len();
+        if chunk.offset != received {
+            return Err((received, ReasonCode::InvalidArgument));
+        }
+        if received + chunk.data.len() > upload.total {
+            return Err((received, ReasonCode::TooManyBytes));
+        }
+
+        upload.data.extend_from_slice(chunk.data);
+        upload.last_activity = Instant::now();
+        let received = upload.data.len();
+
+        if received < upload.total {
+            return Ok((received, None));
+        }
+
+        let upload = uploads
+            .remove(&key)
+            .expect("Upload disappeared while being completed.");
+        Ok((
+            received,
+            Some(CompletedUpload {
+                kind: upload.kind,
+                data: upload.data,
+            }),
+        ))
+    }
+
+    /// Forget uploads which have not progressed for a while.
+    pub fn expire(&self, config: &XferConfig) {
+        let timeout = Duration::from_secs(config.timeout_secs);
+        self.uploads
+            .borrow_mut()
+            .retain(|_, upload| upload.last_activity.elapsed() < timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: u32, total: usize, offset: usize, data: &[u8]) -> Chunk<'_> {
+        Chunk {
+            id,
+            kind: XferKind::Cav,
+            total,
+            offset,
+            data,
+        }
+    }
+
+    fn config() -> XferConfig {
+        XferConfig {
+            max_size: 8,
+            chunk_size: 4,
+            timeout_secs: 600,
+            max_uploads: 2,
+        }
+    }
+
+    #[test]
+    pub fn test_upload() {
+        let transfers = Transfers::default();
+        let config = config();
+
+        let (received, completed) = transfers
+            .receive(2, &chunk(1, 6, 0, b"abcd"), &config)
+            .unwrap();
+        assert_eq!(received, 4);
+        assert!(completed.is_none());
+
+        // Chunks must follow on from what was received
+        assert_eq!(
+            transfers.receive(2, &chunk(1, 6, 0, b"ef"), &config).err(),
+            Some((4, ReasonCode::InvalidArgument))
+        );
+
+        let (received, completed) = transfers
+            .receive(2, &chunk(1, 6, 4, b"ef"), &config)
+            .unwrap();
+        assert_eq!(received, 6);
+        assert_eq!(completed.unwrap().data, b"abcdef");
+    }
+
+    #[test]
+    pub fn test_upload_limits() {
+        let transfers = Transfers::default();
+        let config = config();
+
+        // Larger than allowed in total, or in one chunk
+        assert_eq!(
+            transfers.receive(2, &chunk(1, 9, 0, b"a"), &config).err(),
+            Some((0, ReasonCode::TooManyBytes))
+        );
+        assert_eq!(
+            transfers
+                .receive(2, &chunk(1, 8, 0, b"abcde"), &config)
+                .err(),
+            Some((0, ReasonCode::TooManyBytes))
+        );
+
+        // More than the total claimed
+        transfers
+            .receive(2, &chunk(1, 5, 0, b"abcd"), &config)
+            .unwrap();
+        assert_eq!(
+            transfers.receive(2, &chunk(1, 5, 4, b"ef"), &config).err(),
+            Some((4, ReasonCode::TooManyBytes))
+        );
+
+        // Too many uploads at once, which only counts the citizen's own
+        transfers
+            .receive(2, &chunk(2, 5, 0, b"a"), &config)
+            .unwrap();
+        assert_eq!(
+            transfers.receive(2, &chunk(3, 5, 0, b"a"), &config).err(),
+            Some((0, ReasonCode::TooManyBytes))
+        );
+        transfers
+            .receive(3, &chunk(3, 5, 0, b"a"), &config)
+            .unwrap();
+    }
+}